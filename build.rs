@@ -41,7 +41,7 @@ fn main() {
             .render(&mut File::create(out.join("ouch.1")).unwrap())
             .unwrap();
 
-        for subcmd in cmd.get_subcommands() {
+        for subcmd in cmd.get_subcommands().filter(|subcmd| !subcmd.is_hide_set()) {
             let name = format!("ouch-{}", subcmd.get_name());
             Man::new(subcmd.clone().name(&name))
                 .render(&mut File::create(out.join(format!("{name}.1"))).unwrap())
@@ -0,0 +1,304 @@
+//! Contains Ar-specific listing and unpacking functions
+//!
+//! Used by static libraries (`.a`) and Debian packages (`.deb`), whose members are flat files.
+//! Long names are read in both the GNU and the BSD variants, and symbol tables are skipped.
+//! Creating ar archives isn't supported.
+
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+use std::{
+    io::{self, prelude::*},
+    path::{Path, PathBuf},
+};
+
+use filetime_creation::{set_file_mtime, FileTime};
+use fs_err as fs;
+
+use crate::{
+    archive::{EntrySelection, SizeLimit},
+    error::FinalError,
+    list::FileInArchive,
+    utils::{
+        self,
+        interrupt::Writing,
+        logger::{file_info, Verbosity},
+        Bytes,
+    },
+    Error,
+};
+
+pub const MAGIC: &[u8; 8] = b"!<arch>\n";
+const HEADER_SIZE: usize = 60;
+/// Default mode of members whose header leaves it blank
+const DEFAULT_MODE: u32 = 0o644;
+
+/// A member of the archive, read from its header
+struct Member {
+    name: String,
+    size: u64,
+    mode: u32,
+    mtime: i64,
+}
+
+/// Reader of the members of an ar archive, the data of the current member is read through its
+/// `Read` implementation
+struct ArReader<R> {
+    reader: R,
+    /// Data of the current member that wasn't read yet
+    remaining: u64,
+    /// Whether the current member is followed by a padding byte, data is aligned to 2 bytes
+    padded: bool,
+    /// The GNU table of the long names, referenced by names like "/42"
+    long_names: Vec<u8>,
+}
+
+fn invalid_archive(detail: impl ToString) -> Error {
+    FinalError::with_title("Invalid ar archive")
+        .detail(detail.to_string())
+        .into()
+}
+
+impl<R: Read> ArReader<R> {
+    fn new(mut reader: R) -> crate::Result<Self> {
+        let mut magic = [0; MAGIC.len()];
+        reader
+            .read_exact(&mut magic)
+            .map_err(|_| invalid_archive("Missing the '!<arch>' signature"))?;
+        if &magic != MAGIC {
+            return Err(invalid_archive("Missing the '!<arch>' signature"));
+        }
+
+        Ok(Self {
+            reader,
+            remaining: 0,
+            padded: false,
+            long_names: vec![],
+        })
+    }
+
+    /// Skip what's left of the current member, and read the header of the next file, `None` at
+    /// the end of the archive
+    fn next_member(&mut self) -> crate::Result<Option<Member>> {
+        loop {
+            let skipped = self.remaining + u64::from(self.padded);
+            io::copy(&mut (&mut self.reader).take(skipped), &mut io::sink())?;
+            self.remaining = 0;
+            self.padded = false;
+
+            let mut header = [0; HEADER_SIZE];
+            match read_up_to(&mut self.reader, &mut header)? {
+                0 => return Ok(None),
+                HEADER_SIZE => {}
+                _ => return Err(invalid_archive("Unexpected end of the archive")),
+            }
+            if &header[58..] != b"`\n" {
+                return Err(invalid_archive("Corrupted member header"));
+            }
+
+            let field = |range: std::ops::Range<usize>| String::from_utf8_lossy(&header[range]).trim_end().to_string();
+            let size = field(48..58)
+                .parse::<u64>()
+                .map_err(|_| invalid_archive("Invalid member size"))?;
+            let mode = u32::from_str_radix(&field(40..48), 8).unwrap_or(DEFAULT_MODE);
+            let mtime = field(16..28).parse().unwrap_or(0);
+            self.remaining = size;
+            self.padded = size % 2 == 1;
+
+            let name = field(0..16);
+            let name = match name.as_str() {
+                // Symbol tables of the GNU variant
+                "/" | "/SYM64/" => continue,
+                "//" => {
+                    let mut long_names = vec![];
+                    self.read_to_end(&mut long_names)?;
+                    self.long_names = long_names;
+                    continue;
+                }
+                _ => self.member_name(&name)?,
+            };
+            // Symbol tables of the BSD variant
+            if name.starts_with("__.SYMDEF") {
+                continue;
+            }
+
+            return Ok(Some(Member {
+                name,
+                size: self.remaining,
+                mode,
+                mtime,
+            }));
+        }
+    }
+
+    /// The full name of the member whose header has the name `name`
+    fn member_name(&mut self, name: &str) -> crate::Result<String> {
+        // GNU long names are an offset into the table, where they end with "/\n"
+        if let Some(offset) = name.strip_prefix('/').and_then(|offset| offset.parse::<usize>().ok()) {
+            let names = self
+                .long_names
+                .get(offset..)
+                .ok_or_else(|| invalid_archive("Invalid long name offset"))?;
+            let end = names.iter().position(|&byte| byte == b'\n').unwrap_or(names.len());
+            return Ok(String::from_utf8_lossy(&names[..end]).trim_end_matches('/').to_string());
+        }
+
+        // BSD long names are at the start of the data, counted in its size
+        if let Some(len) = name.strip_prefix("#1/").and_then(|len| len.parse::<u64>().ok()) {
+            if len > self.remaining {
+                return Err(invalid_archive("Invalid long name size"));
+            }
+            let mut bytes = vec![0; len as usize];
+            self.read_exact(&mut bytes)?;
+            return Ok(String::from_utf8_lossy(&bytes).trim_end_matches('\0').to_string());
+        }
+
+        // GNU names end with a '/', so that they can have spaces
+        Ok(name.strip_suffix('/').unwrap_or(name).to_string())
+    }
+}
+
+impl<R: Read> Read for ArReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(usize::try_from(self.remaining).unwrap_or(usize::MAX));
+        let read = self.reader.read(&mut buf[..len])?;
+        if read == 0 && len > 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "ar member is truncated"));
+        }
+        self.remaining -= read as u64;
+        Ok(read)
+    }
+}
+
+/// Fill `buf` unless the reader ends first, returns how many bytes were read
+fn read_up_to(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(filled)
+}
+
+/// Unpacks the archive given by `reader` into the folder given by `output_folder`, only the
+/// members that `selection` extracts. Assumes that output_folder is empty
+pub fn unpack_archive(
+    reader: Box<dyn Read>,
+    output_folder: &Path,
+    selection: &EntrySelection,
+    size_limit: &SizeLimit,
+    preserve_permissions: bool,
+    preserve_mtime: bool,
+    verbosity: Verbosity,
+) -> crate::Result<usize> {
+    assert!(output_folder.read_dir().expect("dir exists").count() == 0);
+
+    let mut archive = ArReader::new(reader)?;
+    let mut files_unpacked = 0;
+
+    while let Some(member) = archive.next_member()? {
+        if !selection.extracts(Path::new(&member.name), false) {
+            continue;
+        }
+        let file_path = utils::safe_join(output_folder, Path::new(&member.name))?;
+        if selection.was_extracted(Path::new(&member.name), member.size, Some(member.mtime)) {
+            continue;
+        }
+        size_limit.add(Path::new(&member.name), member.size)?;
+
+        // Static libraries can have members with the same name, the last one is kept
+        let writing = Writing::start(&file_path);
+        let mut output_file = fs::File::create(&file_path)?;
+        io::copy(&mut archive, &mut output_file)?;
+        writing.finish();
+        set_member_metadata(&file_path, &member, preserve_permissions, preserve_mtime)?;
+
+        // This is printed for every file in the archive and has little
+        // importance for most users, but would generate lots of
+        // spoken text for users using screen readers, braille displays
+        // and so on
+        file_info(verbosity, || {
+            format!(
+                "{:?} extracted. ({})",
+                utils::strip_cur_dir(&file_path),
+                Bytes::new(member.size),
+            )
+        });
+
+        files_unpacked += 1;
+    }
+
+    Ok(files_unpacked)
+}
+
+/// Restore the permissions and, if `preserve_mtime` is set, the modification time stored in the member header
+// `preserve_permissions` is only used on Unix
+#[cfg_attr(not(unix), allow(unused_variables))]
+fn set_member_metadata(
+    path: &Path,
+    member: &Member,
+    preserve_permissions: bool,
+    preserve_mtime: bool,
+) -> crate::Result<()> {
+    #[cfg(unix)]
+    {
+        let mode = utils::extracted_mode(member.mode & 0o7777, false, preserve_permissions);
+        fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    }
+
+    if preserve_mtime {
+        set_file_mtime(path, FileTime::from_unix_time(member.mtime, 0))?;
+    }
+
+    Ok(())
+}
+
+/// Reads every member of the archive given by `reader` without writing them anywhere, failing
+/// if the archive or the formats it's compressed with are corrupted.
+pub fn test_archive(reader: Box<dyn Read>) -> crate::Result<usize> {
+    let mut archive = ArReader::new(reader)?;
+    let mut files_tested = 0;
+
+    while archive.next_member()?.is_some() {
+        io::copy(&mut archive, &mut io::sink())?;
+        files_tested += 1;
+    }
+
+    // The checksums of compression formats are only checked once their end is reached
+    io::copy(&mut archive.reader, &mut io::sink())?;
+
+    Ok(files_tested)
+}
+
+/// List contents of `reader`, returning a vector of archive entries
+pub fn list_archive(reader: impl Read) -> impl Iterator<Item = crate::Result<FileInArchive>> {
+    let mut archive = Some(ArReader::new(reader));
+
+    std::iter::from_fn(move || {
+        let member = match archive.as_mut()? {
+            Ok(archive) => archive.next_member().transpose()?,
+            Err(_) => Err(archive.take()?.err()?),
+        };
+        if member.is_err() {
+            archive = None;
+        }
+
+        Some(member.map(|member| FileInArchive {
+            path: PathBuf::from(member.name),
+            is_dir: false,
+            size: Some(member.size),
+            compressed_size: None,
+            modified: Some(member.mtime),
+            mode: Some(member.mode & 0o7777),
+        }))
+    })
+}
+
+pub fn no_compression() -> Error {
+    Error::UnsupportedFormat {
+        reason: "Creating ar archives is not supported, they can only be listed and extracted.".into(),
+    }
+}
@@ -0,0 +1,382 @@
+//! Contains Cpio-specific building and unpacking functions
+//!
+//! Only the SVR4 `newc` format is supported, as it's the one used by initramfs and RPM.
+
+#[cfg(unix)]
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::{
+    env,
+    io::{self, prelude::*},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+use cpio::{newc, NewcBuilder, NewcReader};
+use filetime_creation::{set_file_mtime, FileTime};
+use fs_err as fs;
+use same_file::Handle;
+
+use crate::{
+    archive::{log_compressing, skip_error, EntryBase, EntrySelection, Reproducible, SizeLimit, SkippedFiles},
+    error::FinalError,
+    list::FileInArchive,
+    utils::{
+        self,
+        interrupt::Writing,
+        logger::{file_info, warning, Verbosity},
+        progress::Progress,
+        Bytes, EscapedPathDisplay, FileVisibilityPolicy,
+    },
+};
+
+/// Mask for the file type bits of a cpio mode
+const MODE_FILE_TYPE_MASK: u32 = 0o170000;
+const MODE_DIRECTORY: u32 = 0o040000;
+const MODE_REGULAR: u32 = 0o100000;
+const MODE_SYMLINK: u32 = 0o120000;
+
+/// Unpacks the archive given by `reader` into the folder given by `output_folder`, only the
+/// entries that `selection` extracts. Assumes that output_folder is empty
+pub fn unpack_archive(
+    mut reader: Box<dyn Read>,
+    output_folder: &Path,
+    selection: &EntrySelection,
+    size_limit: &SizeLimit,
+    preserve_permissions: bool,
+    preserve_mtime: bool,
+    verbosity: Verbosity,
+) -> crate::Result<usize> {
+    assert!(output_folder.read_dir().expect("dir exists").count() == 0);
+
+    let mut files_unpacked = 0;
+    // Directory metadata is applied at the end, otherwise writing their children
+    // would update their mtime, or fail because of missing write permissions
+    let mut directories = vec![];
+
+    loop {
+        let mut entry_reader = NewcReader::new(reader)?;
+        let entry = entry_reader.entry().clone();
+        if entry.is_trailer() {
+            break;
+        }
+
+        let is_dir = entry.mode() & MODE_FILE_TYPE_MASK == MODE_DIRECTORY;
+        if !selection.extracts(Path::new(entry.name()), is_dir) {
+            reader = entry_reader.finish()?;
+            continue;
+        }
+        let file_path = utils::safe_join(output_folder, Path::new(entry.name()))?;
+        // The root entry "." is created by tools like `find . | cpio -o`
+        if file_path == output_folder {
+            reader = entry_reader.finish()?;
+            continue;
+        }
+
+        let mode = entry.mode();
+        match mode & MODE_FILE_TYPE_MASK {
+            MODE_DIRECTORY => {
+                fs::create_dir_all(&file_path)?;
+                reader = entry_reader.finish()?;
+                directories.push((file_path.clone(), entry.clone()));
+            }
+            MODE_SYMLINK => {
+                let mut target = String::new();
+                entry_reader.read_to_string(&mut target)?;
+                reader = entry_reader.finish()?;
+
+                #[cfg(unix)]
+                std::os::unix::fs::symlink(target, &file_path)?;
+                #[cfg(not(unix))]
+                {
+                    warning(format!(
+                        "Skipping symlink '{}', not supported on this platform",
+                        entry.name()
+                    ));
+                    continue;
+                }
+            }
+            MODE_REGULAR => {
+                let size = entry.file_size().into();
+                if selection.was_extracted(Path::new(entry.name()), size, Some(entry.mtime().into())) {
+                    reader = entry_reader.finish()?;
+                    continue;
+                }
+                size_limit.add(Path::new(entry.name()), size)?;
+                let writing = Writing::start(&file_path);
+                let mut output_file = fs::File::create(&file_path)?;
+                io::copy(&mut entry_reader, &mut output_file)?;
+                reader = entry_reader.finish()?;
+                writing.finish();
+            }
+            _ => {
+                warning(format!(
+                    "Skipping special file '{}', not supported by ouch",
+                    entry.name()
+                ));
+                reader = entry_reader.finish()?;
+                continue;
+            }
+        }
+
+        if mode & MODE_FILE_TYPE_MASK == MODE_REGULAR {
+            set_entry_metadata(&file_path, &entry, preserve_permissions, preserve_mtime)?;
+        }
+
+        // This is printed for every file in the archive and has little
+        // importance for most users, but would generate lots of
+        // spoken text for users using screen readers, braille displays
+        // and so on
+        file_info(verbosity, || {
+            format!(
+                "{:?} extracted. ({})",
+                utils::strip_cur_dir(&file_path),
+                Bytes::new(entry.file_size().into()),
+            )
+        });
+
+        files_unpacked += 1;
+    }
+
+    for (path, entry) in directories.iter().rev() {
+        set_entry_metadata(path, entry, preserve_permissions, preserve_mtime)?;
+    }
+
+    Ok(files_unpacked)
+}
+
+/// Restore the permissions and, if `preserve_mtime` is set, the modification time stored in the entry header
+// `preserve_permissions` is only used on Unix
+#[cfg_attr(not(unix), allow(unused_variables))]
+fn set_entry_metadata(
+    path: &Path,
+    entry: &newc::Entry,
+    preserve_permissions: bool,
+    preserve_mtime: bool,
+) -> crate::Result<()> {
+    #[cfg(unix)]
+    {
+        let is_dir = entry.mode() & MODE_FILE_TYPE_MASK == MODE_DIRECTORY;
+        let mode = utils::extracted_mode(entry.mode() & 0o7777, is_dir, preserve_permissions);
+        fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    }
+
+    if preserve_mtime {
+        set_file_mtime(path, FileTime::from_unix_time(entry.mtime().into(), 0))?;
+    }
+
+    Ok(())
+}
+
+/// Reads every entry of the archive given by `reader` without writing them anywhere, failing
+/// if the archive or the formats it's compressed with are corrupted.
+pub fn test_archive(mut reader: Box<dyn Read>) -> crate::Result<usize> {
+    let mut files_tested = 0;
+
+    loop {
+        let mut entry_reader = NewcReader::new(reader)?;
+        if entry_reader.entry().is_trailer() {
+            reader = entry_reader.finish()?;
+            break;
+        }
+        io::copy(&mut entry_reader, &mut io::sink())?;
+        reader = entry_reader.finish()?;
+        files_tested += 1;
+    }
+
+    // The checksums of compression formats are only checked once their end is reached,
+    // which can be past the end of the cpio archive
+    io::copy(&mut reader, &mut io::sink())?;
+
+    Ok(files_tested)
+}
+
+/// List contents of `reader`, returning a vector of archive entries
+pub fn list_archive(reader: impl Read + Send + 'static) -> impl Iterator<Item = crate::Result<FileInArchive>> {
+    struct Files(Receiver<crate::Result<FileInArchive>>);
+    impl Iterator for Files {
+        type Item = crate::Result<FileInArchive>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            self.0.recv().ok()
+        }
+    }
+
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut reader = reader;
+        loop {
+            let entry_reader = match NewcReader::new(reader) {
+                Ok(entry_reader) => entry_reader,
+                Err(err) => {
+                    let _ = tx.send(Err(err.into()));
+                    break;
+                }
+            };
+            let entry = entry_reader.entry();
+            if entry.is_trailer() {
+                break;
+            }
+
+            let file_in_archive = FileInArchive {
+                path: entry.name().into(),
+                is_dir: entry.mode() & MODE_FILE_TYPE_MASK == MODE_DIRECTORY,
+                size: Some(entry.file_size().into()),
+                compressed_size: None,
+                modified: Some(entry.mtime().into()),
+                mode: Some(entry.mode() & 0o7777),
+            };
+            tx.send(Ok(file_in_archive)).unwrap();
+
+            reader = match entry_reader.finish() {
+                Ok(reader) => reader,
+                Err(err) => {
+                    let _ = tx.send(Err(err.into()));
+                    break;
+                }
+            };
+        }
+    });
+
+    Files(rx)
+}
+
+/// Compresses the archives given by `input_filenames` into the file given previously to `writer`.
+///
+/// With `reproducible`, the owners are 0, the modification times fixed and the permissions
+/// normalized.
+///
+/// With `skip_errors`, files and directories that can't be read are left out.
+#[allow(clippy::too_many_arguments)]
+pub fn build_archive_from_paths<W>(
+    input_filenames: &[PathBuf],
+    output_path: &Path,
+    mut writer: W,
+    file_visibility_policy: FileVisibilityPolicy,
+    entry_base: &EntryBase,
+    reproducible: Option<Reproducible>,
+    skip_errors: Option<&SkippedFiles>,
+    progress: &Progress,
+    verbosity: Verbosity,
+) -> crate::Result<W>
+where
+    W: Write,
+{
+    let output_handle = Handle::from_path(output_path);
+    // cpio entries are identified by their inode number, so it needs to be unique in the archive
+    let mut inode = 0;
+
+    for filename in input_filenames {
+        let previous_location = utils::cd_into_entry_base(filename, entry_base)?;
+        let filename = entry_base.relative_path(filename)?;
+
+        for entry in file_visibility_policy.build_walker(filename) {
+            let Some(entry) = skip_error(entry, skip_errors)? else {
+                continue;
+            };
+            let path = entry.path();
+            // The input is walked from "." when it's the base directory, which has no entry itself
+            let path = path.strip_prefix(".").unwrap_or(path);
+            if path.as_os_str().is_empty() {
+                continue;
+            }
+
+            // If the output_path is the same as the input file, warn the user and skip the input (in order to avoid compression recursion)
+            if let Ok(handle) = &output_handle {
+                if matches!(Handle::from_path(path), Ok(x) if &x == handle) {
+                    warning(format!(
+                        "The output file and the input file are the same: `{}`, skipping...",
+                        output_path.display()
+                    ));
+
+                    continue;
+                }
+            }
+
+            // This is printed for every file in `input_filenames` and has
+            // little importance for most users, but would generate lots of
+            // spoken text for users using screen readers, braille displays
+            // and so on
+            log_compressing(path, path, verbosity);
+
+            let metadata = match path.metadata() {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    if e.kind() == std::io::ErrorKind::NotFound && utils::is_symlink(path) {
+                        // This path is for a broken symlink
+                        // We just ignore it
+                        continue;
+                    }
+                    return Err(e.into());
+                }
+            };
+
+            let entry_name = path.to_str().ok_or_else(|| {
+                FinalError::with_title("Cpio requires that all entry names are valid UTF-8")
+                    .detail(format!("File at '{path:?}' has a non-UTF-8 name"))
+            })?;
+
+            inode += 1;
+            let builder = header_builder(entry_name, &metadata, reproducible).ino(inode);
+
+            if metadata.is_dir() {
+                writer = builder.write(writer, 0).finish()?;
+            } else {
+                let file_size = u32::try_from(metadata.len()).map_err(|_| {
+                    FinalError::with_title("Could not create archive")
+                        .detail(format!(
+                            "File at '{}' is too big for cpio",
+                            EscapedPathDisplay::new(path)
+                        ))
+                        .detail("The newc format is limited to 4 GiB per file")
+                })?;
+
+                let Some(file) = skip_error(fs::File::open(path), skip_errors)? else {
+                    continue;
+                };
+                let mut entry_writer = builder.write(writer, file_size);
+                io::copy(&mut progress.wrap_read(file), &mut entry_writer)?;
+                writer = entry_writer.finish()?;
+            }
+        }
+        env::set_current_dir(previous_location)?;
+    }
+
+    Ok(newc::trailer(writer)?)
+}
+
+/// Build the newc header of an entry, storing its mode, ownership and modification time, or
+/// their normalized values if `reproducible` is set
+fn header_builder(entry_name: &str, metadata: &std::fs::Metadata, reproducible: Option<Reproducible>) -> NewcBuilder {
+    let mtime = match reproducible {
+        Some(reproducible) => reproducible.mtime as u32,
+        None => metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+            .map_or(0, |duration| duration.as_secs() as u32),
+    };
+
+    let builder = NewcBuilder::new(entry_name).mtime(mtime);
+
+    // Every entry gets an unique inode, so they can't be hardlinks of each other
+    let builder = builder.nlink(if metadata.is_dir() { 2 } else { 1 });
+
+    #[cfg(unix)]
+    let builder = match reproducible {
+        Some(_) => {
+            let permissions = Reproducible::mode(metadata.is_dir(), metadata.mode());
+            builder.mode(metadata.mode() & !0o7777 | permissions).uid(0).gid(0)
+        }
+        None => builder.mode(metadata.mode()).uid(metadata.uid()).gid(metadata.gid()),
+    };
+
+    #[cfg(not(unix))]
+    let builder = if metadata.is_dir() {
+        builder.mode(MODE_DIRECTORY | 0o755)
+    } else {
+        builder.mode(MODE_REGULAR | 0o644)
+    };
+
+    builder
+}
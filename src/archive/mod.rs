@@ -1,5 +1,7 @@
 //! Archive compression algorithms
 
+pub mod ar;
+pub mod cpio;
 #[cfg(feature = "unrar")]
 pub mod rar;
 #[cfg(not(feature = "unrar"))]
@@ -7,3 +9,350 @@ pub mod rar_stub;
 pub mod sevenz;
 pub mod tar;
 pub mod zip;
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashSet,
+    error::Error,
+    ffi::OsString,
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use filetime_creation::FileTime;
+use ignore::overrides::Override;
+
+use crate::{
+    error::FinalError,
+    utils::{
+        self,
+        logger::{file_info, info_accessible, warning, Verbosity},
+        nice_directory_display, Bytes, EscapedPathDisplay,
+    },
+};
+
+/// What the names of archive entries are relative to, set by `--base-dir`, `--change-dir` and `--absolute-paths`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum EntryBase {
+    /// The parent directory of each input, so the inputs are at the root of the archive
+    #[default]
+    Parent,
+    /// A directory that has every input in it
+    Dir(PathBuf),
+    /// The root of the filesystem, entries have the whole paths of the inputs, without their leading '/'
+    Root,
+}
+
+impl EntryBase {
+    /// Directory that the names of the entries of `input` are relative to, `input` being
+    /// canonicalized
+    pub fn dir_of<'a>(&'a self, input: &'a Path) -> crate::Result<&'a Path> {
+        match self {
+            Self::Parent => input.parent().ok_or(crate::Error::CompressingRootFolder),
+            Self::Dir(dir) => Ok(dir),
+            // Unwrap safety:
+            //   canonicalized paths are absolute, the last of their ancestors is the root.
+            Self::Root => Ok(input.ancestors().last().unwrap()),
+        }
+    }
+
+    /// Name stored in the archive for `path`, found by walking `input`, or `None` for the base
+    /// directory itself, which has no entry
+    pub fn entry_name<'p>(&self, input: &Path, path: &'p Path) -> crate::Result<Option<&'p Path>> {
+        // Unwrap safety:
+        //   every path walked is inside of `input`, so it starts with its base.
+        let entry_name = path.strip_prefix(self.dir_of(input)?).unwrap();
+        Ok(Some(entry_name).filter(|name| !name.as_os_str().is_empty()))
+    }
+
+    /// Path of `input` relative to [`Self::dir_of`], or "." if it's that directory
+    pub fn relative_path<'a>(&'a self, input: &'a Path) -> crate::Result<&'a Path> {
+        // Unwrap safety:
+        //   inputs outside of `--base-dir` were rejected.
+        let relative = input.strip_prefix(self.dir_of(input)?).unwrap();
+        Ok(if relative.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            relative
+        })
+    }
+}
+
+/// The archive entries to extract, picked by `--include` and `--entry`, every entry is extracted if
+/// neither was given. Unpackers check each entry with [`Self::extracts`] before writing it, and
+/// each file with [`Self::was_extracted`] for `--skip-existing`
+#[derive(Default)]
+pub struct EntrySelection<'a> {
+    include: Option<&'a Override>,
+    /// Sanitized paths of the entries at the positions given to `--entry`
+    entry_paths: Option<&'a HashSet<PathBuf>>,
+    extracted_output: Option<ExtractedOutput>,
+    skipped: Cell<usize>,
+    existing: Cell<usize>,
+    /// First components of the paths of the files skipped by [`Self::was_extracted`]
+    existing_roots: RefCell<HashSet<OsString>>,
+}
+
+/// Where the files of an archive were written by an earlier extraction, to keep them with
+/// `--skip-existing`
+pub struct ExtractedOutput {
+    /// Directories the files may have been written to, with how many leading components were
+    /// removed from their paths, which one depends on the roots of the whole archive
+    pub destinations: Vec<(PathBuf, usize)>,
+    /// Whether the modification times, restored by the earlier extraction, are compared too
+    pub compare_mtime: bool,
+}
+
+impl<'a> EntrySelection<'a> {
+    pub fn new(include: Option<&'a Override>, entry_paths: Option<&'a HashSet<PathBuf>>) -> Self {
+        Self {
+            include,
+            entry_paths,
+            ..Self::default()
+        }
+    }
+
+    /// Also skip the files already written to `output`, see [`Self::was_extracted`]
+    pub fn skip_existing(self, output: ExtractedOutput) -> Self {
+        Self {
+            extracted_output: Some(output),
+            ..self
+        }
+    }
+
+    pub fn picks_entries(&self) -> bool {
+        self.include.is_some() || self.entry_paths.is_some()
+    }
+
+    /// Whether the entry named `name` is extracted, because it's picked by either flag or inside
+    /// of a picked directory, the files among the others are counted by [`Self::skipped`]
+    pub fn extracts(&self, name: &Path, is_dir: bool) -> bool {
+        // Unsafe paths are extracted, so they fail when they're joined to the output directory
+        let Ok(path) = utils::sanitize_entry_path(name) else {
+            return true;
+        };
+        if !self.picks_entries() || path.as_os_str().is_empty() {
+            return true;
+        }
+
+        let picked = path
+            .ancestors()
+            .take_while(|ancestor| !ancestor.as_os_str().is_empty())
+            .any(|ancestor| self.picks(ancestor, is_dir || ancestor != path));
+        if !picked && !is_dir {
+            self.skipped.set(self.skipped.get() + 1);
+        }
+        picked
+    }
+
+    /// How many files weren't extracted so far
+    pub fn skipped(&self) -> usize {
+        self.skipped.get()
+    }
+
+    /// Whether the file named `name`, of `size` bytes and modified at `mtime` in seconds since the
+    /// Unix epoch if known, was already written by an earlier extraction with the same size and
+    /// modification time, so it's skipped. These files are counted by [`Self::existing`]
+    pub fn was_extracted(&self, name: &Path, size: u64, mtime: Option<i64>) -> bool {
+        let (Some(output), Ok(path)) = (&self.extracted_output, utils::sanitize_entry_path(name)) else {
+            return false;
+        };
+
+        let is_same_file = |destination: &Path| {
+            fs::symlink_metadata(destination).is_ok_and(|metadata| {
+                metadata.is_file()
+                    && metadata.len() == size
+                    && (!output.compare_mtime
+                        || mtime.is_none_or(|mtime| {
+                            FileTime::from_last_modification_time(&metadata).unix_seconds() == mtime
+                        }))
+            })
+        };
+        let destination = output
+            .destinations
+            .iter()
+            .map(|(dir, strip_components)| (dir, path.components().skip(*strip_components).collect::<PathBuf>()))
+            .filter(|(_, stripped)| !stripped.as_os_str().is_empty())
+            .map(|(dir, stripped)| dir.join(stripped))
+            .find(|destination| is_same_file(destination));
+        let Some(destination) = destination else {
+            return false;
+        };
+
+        info_accessible(format!(
+            "Skipped {}, it was already extracted.",
+            nice_directory_display(&destination)
+        ));
+        self.existing.set(self.existing.get() + 1);
+        if let Some(root) = path.components().next() {
+            self.existing_roots.borrow_mut().insert(root.as_os_str().to_owned());
+        }
+        true
+    }
+
+    /// How many files were skipped because they were already extracted
+    pub fn existing(&self) -> usize {
+        self.existing.get()
+    }
+
+    /// Names of the roots of the archive that files already extracted are in, they might not be
+    /// extracted again at all
+    pub fn existing_roots(&self) -> HashSet<OsString> {
+        self.existing_roots.borrow().clone()
+    }
+
+    /// Whether the entry at `path`, relative to the root of the archive, is picked by either flag
+    fn picks(&self, path: &Path, is_dir: bool) -> bool {
+        self.include
+            .is_some_and(|include| include.matched(path, is_dir).is_whitelist())
+            || self.entry_paths.is_some_and(|paths| paths.contains(path))
+    }
+}
+
+/// How much the files extracted from an archive may add up to when no limit is given, the
+/// default of `--max-decompressed-size`
+pub const DEFAULT_MAX_UNPACKED_SIZE: u64 = 100 << 30;
+
+/// The most the files decompressed from `archive_path` may add up to, given by
+/// `--max-decompressed-size`, so a decompression bomb can't fill the disk. Unpackers count each
+/// file with [`Self::add`] before writing it, or copy it with [`Self::copy`] if its size isn't known
+pub struct SizeLimit<'a> {
+    archive_path: &'a Path,
+    max_size: u64,
+    unpacked: Cell<u64>,
+}
+
+impl<'a> SizeLimit<'a> {
+    pub fn new(archive_path: &'a Path, max_size: u64) -> Self {
+        Self {
+            archive_path,
+            max_size,
+            unpacked: Cell::new(0),
+        }
+    }
+
+    /// Count the `size` bytes of the file `name`, failing if the files go over the limit with it
+    pub fn add(&self, name: &Path, size: u64) -> crate::Result<()> {
+        let unpacked = self.unpacked.get();
+        if size > self.max_size - unpacked {
+            return Err(self.error(name, unpacked == 0));
+        }
+        self.unpacked.set(unpacked + size);
+        Ok(())
+    }
+
+    /// Copy the file `name` from `reader` to `writer`, failing once the files go over the limit
+    /// with it, for files whose size is only known after they're decompressed
+    pub fn copy(&self, name: &Path, reader: &mut impl Read, writer: &mut impl Write) -> crate::Result<u64> {
+        // Reading one byte past what's left is enough to tell the limit was exceeded
+        let left = self.max_size - self.unpacked.get();
+        let copied = io::copy(&mut reader.take(left.saturating_add(1)), writer)?;
+        self.add(name, copied)?;
+        Ok(copied)
+    }
+
+    /// Error for the file `name` going over the limit, `alone` if it did so by itself
+    fn error(&self, name: &Path, alone: bool) -> crate::Error {
+        let name = EscapedPathDisplay::new(name);
+        let detail = if alone {
+            format!("'{name}' decompresses into more than {}", Bytes::new(self.max_size))
+        } else {
+            format!(
+                "The files decompress into more than {} in total, the limit was reached while extracting '{name}'",
+                Bytes::new(self.max_size)
+            )
+        };
+        FinalError::with_title(format!(
+            "Stopped decompressing '{}', it's larger than the limit",
+            EscapedPathDisplay::new(self.archive_path)
+        ))
+        .detail(detail)
+        .detail("Files that decompress into far more than their compressed size can be made to fill the disk")
+        .hint("If the file is trusted, raise the limit with --max-decompressed-size")
+        .into()
+    }
+}
+
+/// Metadata stored in archive entries instead of the files' own by `--reproducible`, so the same
+/// inputs always give the same archive
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Reproducible {
+    /// Modification time of every entry, in seconds since the Unix epoch
+    pub mtime: u64,
+}
+
+impl Reproducible {
+    /// Permissions of an entry whose file has `mode`: 0o755 for directories and files executable
+    /// by their owner, 0o644 for the rest, like `tar::HeaderMode::Deterministic`
+    pub fn mode(is_dir: bool, mode: u32) -> u32 {
+        if is_dir || mode & 0o100 != 0 {
+            0o755
+        } else {
+            0o644
+        }
+    }
+}
+
+/// Log that the file at `path` is being added to an archive as `name`, with its size at `-vv`
+pub fn log_compressing(name: &Path, path: &Path, verbosity: Verbosity) {
+    file_info(verbosity, || {
+        let metadata = (verbosity == Verbosity::VeryVerbose)
+            .then(|| fs::symlink_metadata(path).ok())
+            .flatten()
+            .filter(|metadata| metadata.is_file());
+        match metadata {
+            Some(metadata) => format!(
+                "Compressing '{}' ({}).",
+                EscapedPathDisplay::new(name),
+                Bytes::new(metadata.len())
+            ),
+            None => format!("Compressing '{}'.", EscapedPathDisplay::new(name)),
+        }
+    });
+}
+
+/// Count of the files left out of archives by `--skip-errors`, because they couldn't be read,
+/// shared by the archives compressed at the same time
+#[derive(Debug, Default)]
+pub struct SkippedFiles(AtomicUsize);
+
+impl SkippedFiles {
+    /// Leave out the file that couldn't be read because of `err`, warning about it
+    pub fn skip(&self, err: impl Error) {
+        // Errors of `fs_err` name the file, and keep why it failed in their source
+        let reason = match err.source() {
+            Some(source) => format!("{err}: {source}"),
+            None => err.to_string(),
+        };
+        warning(format!("Skipped a file that couldn't be read: {reason}"));
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// How many files were left out so far
+    pub fn count(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Add the files left out of the archive counted by `other`
+    pub fn merge(&self, other: SkippedFiles) {
+        self.0.fetch_add(other.count(), Ordering::Relaxed);
+    }
+}
+
+/// The value of `result`, or `None` if it failed and `skip_errors` is given, so the file it was
+/// for is left out instead of failing the whole archive
+pub fn skip_error<T, E>(result: Result<T, E>, skip_errors: Option<&SkippedFiles>) -> crate::Result<Option<T>>
+where
+    E: Error + Into<crate::Error>,
+{
+    match (result, skip_errors) {
+        (Ok(value), _) => Ok(Some(value)),
+        (Err(err), Some(skipped)) => {
+            skipped.skip(err);
+            Ok(None)
+        }
+        (Err(err), None) => Err(err.into()),
+    }
+}
@@ -2,13 +2,29 @@
 
 use std::path::Path;
 
+use time::OffsetDateTime;
 use unrar::Archive;
 
-use crate::{error::Error, list::FileInArchive, utils::logger::info};
+use crate::{
+    archive::{EntrySelection, SizeLimit},
+    error::Error,
+    list::FileInArchive,
+    utils::{
+        self,
+        interrupt::Writing,
+        logger::{file_info, Verbosity},
+    },
+};
 
-/// Unpacks the archive given by `archive_path` into the folder given by `output_folder`.
-/// Assumes that output_folder is empty
-pub fn unpack_archive(archive_path: &Path, output_folder: &Path, quiet: bool) -> crate::Result<usize> {
+/// Unpacks the archive given by `archive_path` into the folder given by `output_folder`, only the
+/// files that `selection` extracts. Assumes that output_folder is empty
+pub fn unpack_archive(
+    archive_path: &Path,
+    output_folder: &Path,
+    selection: &EntrySelection,
+    size_limit: &SizeLimit,
+    verbosity: Verbosity,
+) -> crate::Result<usize> {
     assert!(output_folder.read_dir().expect("dir exists").count() == 0);
 
     let mut archive = Archive::new(archive_path).open_for_processing()?;
@@ -16,16 +32,22 @@ pub fn unpack_archive(archive_path: &Path, output_folder: &Path, quiet: bool) ->
 
     while let Some(header) = archive.read_header()? {
         let entry = header.entry();
-        archive = if entry.is_file() {
-            if !quiet {
-                info(format!(
-                    "{} extracted. ({})",
-                    entry.filename.display(),
-                    entry.unpacked_size
-                ));
+        archive = if entry.is_file() && selection.extracts(&entry.filename, false) {
+            let file_path = utils::safe_join(output_folder, &entry.filename)?;
+            // The times are stored in local time, so only the size is compared
+            if selection.was_extracted(&entry.filename, entry.unpacked_size, None) {
+                archive = header.skip()?;
+                continue;
             }
+            size_limit.add(&entry.filename, entry.unpacked_size)?;
+            file_info(verbosity, || {
+                format!("{} extracted. ({})", entry.filename.display(), entry.unpacked_size)
+            });
             unpacked += 1;
-            header.extract_with_base(output_folder)?
+            let writing = Writing::start(&file_path);
+            let archive = header.extract_with_base(output_folder)?;
+            writing.finish();
+            archive
         } else {
             header.skip()?
         };
@@ -34,6 +56,24 @@ pub fn unpack_archive(archive_path: &Path, output_folder: &Path, quiet: bool) ->
     Ok(unpacked)
 }
 
+/// Reads every file of the archive given by `archive_path` without writing them anywhere, failing
+/// if their checksums don't match their contents.
+pub fn test_archive(archive_path: &Path) -> crate::Result<usize> {
+    let mut archive = Archive::new(archive_path).open_for_processing()?;
+    let mut files_tested = 0;
+
+    while let Some(header) = archive.read_header()? {
+        archive = if header.entry().is_file() {
+            files_tested += 1;
+            header.test()?
+        } else {
+            header.skip()?
+        };
+    }
+
+    Ok(files_tested)
+}
+
 /// List contents of `archive_path`, returning a vector of archive entries
 pub fn list_archive(archive_path: &Path) -> impl Iterator<Item = crate::Result<FileInArchive>> {
     Archive::new(archive_path)
@@ -42,9 +82,21 @@ pub fn list_archive(archive_path: &Path) -> impl Iterator<Item = crate::Result<F
         .map(|item| {
             let item = item?;
             let is_dir = item.is_directory();
-            let path = item.filename;
+            // Stored in the MS-DOS format, like in zip archives
+            let modified = zip::DateTime::try_from_msdos((item.file_time >> 16) as u16, item.file_time as u16)
+                .ok()
+                .and_then(|time| OffsetDateTime::try_from(time).ok())
+                .map(OffsetDateTime::unix_timestamp);
 
-            Ok(FileInArchive { path, is_dir })
+            Ok(FileInArchive {
+                path: item.filename,
+                is_dir,
+                size: Some(item.unpacked_size),
+                compressed_size: None,
+                modified,
+                // The attributes depend on the system the archive was made on
+                mode: None,
+            })
         })
 }
 
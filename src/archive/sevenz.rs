@@ -4,26 +4,38 @@ use std::{
     env,
     io::{self, Read, Seek, Write},
     path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 use fs_err as fs;
 use same_file::Handle;
 
 use crate::{
+    archive::{log_compressing, skip_error, EntryBase, EntrySelection, SizeLimit, SkippedFiles},
     error::FinalError,
+    list::FileInArchive,
     utils::{
-        self, cd_into_same_dir_as,
-        logger::{info, warning},
-        Bytes, EscapedPathDisplay, FileVisibilityPolicy,
+        self, cd_into_entry_base,
+        interrupt::Writing,
+        logger::{file_info, warning, Verbosity},
+        progress::Progress,
+        Bytes, FileVisibilityPolicy,
     },
 };
 
+/// Set in the attributes of entries whose high 16 bits are a Unix mode
+const UNIX_EXTENSION_FLAG: u32 = 0x8000;
+
+#[allow(clippy::too_many_arguments)]
 pub fn compress_sevenz<W>(
     files: &[PathBuf],
     output_path: &Path,
     writer: W,
     file_visibility_policy: FileVisibilityPolicy,
-    quiet: bool,
+    entry_base: &EntryBase,
+    skip_errors: Option<&SkippedFiles>,
+    progress: &Progress,
+    verbosity: Verbosity,
 ) -> crate::Result<W>
 where
     W: Write + Seek,
@@ -32,15 +44,19 @@ where
     let output_handle = Handle::from_path(output_path);
 
     for filename in files {
-        let previous_location = cd_into_same_dir_as(filename)?;
-
-        // Unwrap safety:
-        //   paths should be canonicalized by now, and the root directory rejected.
-        let filename = filename.file_name().unwrap();
+        let previous_location = cd_into_entry_base(filename, entry_base)?;
+        let filename = entry_base.relative_path(filename)?;
 
         for entry in file_visibility_policy.build_walker(filename) {
-            let entry = entry?;
+            let Some(entry) = skip_error(entry, skip_errors)? else {
+                continue;
+            };
             let path = entry.path();
+            // The input is walked from "." when it's the base directory, which has no entry itself
+            let path = path.strip_prefix(".").unwrap_or(path);
+            if path.as_os_str().is_empty() {
+                continue;
+            }
 
             // If the output_path is the same as the input file, warn the user and skip the input (in order to avoid compression recursion)
             if let Ok(handle) = &output_handle {
@@ -58,9 +74,7 @@ where
             // little importance for most users, but would generate lots of
             // spoken text for users using screen readers, braille displays
             // and so on
-            if !quiet {
-                info(format!("Compressing '{}'.", EscapedPathDisplay::new(path)));
-            }
+            log_compressing(path, path, verbosity);
 
             let metadata = match path.metadata() {
                 Ok(metadata) => metadata,
@@ -79,14 +93,17 @@ where
                     .detail(format!("File at '{path:?}' has a non-UTF-8 name"))
             })?;
 
-            let entry = sevenz_rust::SevenZArchiveEntry::from_path(path, entry_name.to_owned());
             let entry_data = if metadata.is_dir() {
                 None
             } else {
-                Some(fs::File::open(path)?)
+                let Some(file) = skip_error(fs::File::open(path), skip_errors)? else {
+                    continue;
+                };
+                Some(progress.wrap_read(file))
             };
+            let entry = sevenz_rust::SevenZArchiveEntry::from_path(path, entry_name.to_owned());
 
-            writer.push_archive_entry::<fs::File>(entry, entry_data)?;
+            writer.push_archive_entry(entry, entry_data)?;
         }
 
         env::set_current_dir(previous_location)?;
@@ -96,61 +113,153 @@ where
     Ok(bytes)
 }
 
-pub fn decompress_sevenz<R>(reader: R, output_path: &Path, quiet: bool) -> crate::Result<usize>
+/// Unpacks the archive given by `reader` into the folder given by `output_path`, only the entries
+/// that `selection` extracts
+pub fn decompress_sevenz<R>(
+    reader: R,
+    output_path: &Path,
+    selection: &EntrySelection,
+    size_limit: &SizeLimit,
+    preserve_mtime: bool,
+    verbosity: Verbosity,
+) -> crate::Result<usize>
 where
     R: Read + Seek,
 {
     let mut count: usize = 0;
-    sevenz_rust::decompress_with_extract_fn(reader, output_path, |entry, reader, path| {
-        count += 1;
+    // The error of an unsafe entry, or one over the size limit, sevenz_rust errors can't hold it
+    let mut entry_error = None;
+    let result = sevenz_rust::decompress_with_extract_fn(reader, output_path, |entry, reader, _| {
+        if !selection.extracts(Path::new(entry.name()), entry.is_directory()) {
+            // The entries of solid blocks are decompressed one after the other, the data of the
+            // next one only starts after this one's
+            io::copy(reader, &mut io::sink())?;
+            return Ok(true);
+        }
         // Manually handle writing all files from 7z archive, due to library exluding empty files
         use std::io::BufWriter;
 
         use filetime_creation as ft;
 
-        let file_path = output_path.join(entry.name());
-
-        if entry.is_directory() {
-            if !quiet {
-                info(format!(
-                    "File {} extracted to \"{}\"",
-                    entry.name(),
-                    file_path.display()
-                ));
-            }
-            if !path.exists() {
-                fs::create_dir_all(path)?;
+        let file_path = match utils::safe_join(output_path, Path::new(entry.name())) {
+            Ok(path) => path,
+            Err(err) => {
+                entry_error = Some(err);
+                return Err(sevenz_rust::Error::other("unsafe entry path"));
             }
-        } else {
-            if !quiet {
-                info(format!(
-                    "{:?} extracted. ({})",
-                    file_path.display(),
-                    Bytes::new(entry.size())
-                ));
+        };
+        let mtime = entry
+            .has_last_modified_date
+            .then(|| ft::FileTime::from_system_time(entry.last_modified_date().into()).unix_seconds());
+        if !entry.is_directory() && selection.was_extracted(Path::new(entry.name()), entry.size(), mtime) {
+            io::copy(reader, &mut io::sink())?;
+            return Ok(true);
+        }
+        if !entry.is_directory() {
+            if let Err(err) = size_limit.add(Path::new(entry.name()), entry.size()) {
+                entry_error = Some(err);
+                return Err(sevenz_rust::Error::other("entry over the size limit"));
             }
+        }
+        count += 1;
 
-            if let Some(parent) = path.parent() {
-                if !parent.exists() {
-                    fs::create_dir_all(parent)?;
-                }
+        if entry.is_directory() {
+            file_info(verbosity, || {
+                format!("File {} extracted to \"{}\"", entry.name(), file_path.display())
+            });
+            if !file_path.exists() {
+                fs::create_dir_all(&file_path)?;
             }
+        } else {
+            file_info(verbosity, || {
+                format!("{:?} extracted. ({})", file_path.display(), Bytes::new(entry.size()))
+            });
 
-            let file = fs::File::create(path)?;
+            let writing = Writing::start(&file_path);
+            let file = fs::File::create(&file_path)?;
             let mut writer = BufWriter::new(file);
             io::copy(reader, &mut writer)?;
+            writer.flush()?;
+            writing.finish();
 
-            ft::set_file_handle_times(
-                writer.get_ref().file(),
-                Some(ft::FileTime::from_system_time(entry.access_date().into())),
-                Some(ft::FileTime::from_system_time(entry.last_modified_date().into())),
-                Some(ft::FileTime::from_system_time(entry.creation_date().into())),
-            )
-            .unwrap_or_default();
+            if preserve_mtime {
+                ft::set_file_handle_times(
+                    writer.get_ref().file(),
+                    Some(ft::FileTime::from_system_time(entry.access_date().into())),
+                    Some(ft::FileTime::from_system_time(entry.last_modified_date().into())),
+                    Some(ft::FileTime::from_system_time(entry.creation_date().into())),
+                )
+                .unwrap_or_default();
+            }
         }
 
         Ok(true)
-    })?;
+    });
+
+    if let Some(err) = entry_error {
+        return Err(err);
+    }
+    result?;
 
     Ok(count)
 }
+
+/// Reads every entry of the archive given by `reader` without writing them anywhere, failing
+/// if their CRC32 doesn't match their contents.
+pub fn test_archive<R>(mut reader: R) -> crate::Result<usize>
+where
+    R: Read + Seek,
+{
+    let reader_len = reader.seek(io::SeekFrom::End(0))?;
+    reader.rewind()?;
+
+    let mut archive = sevenz_rust::SevenZReader::new(reader, reader_len, sevenz_rust::Password::empty())?;
+    let mut files_tested = 0;
+    archive.for_each_entries(|_, reader| {
+        io::copy(reader, &mut io::sink())?;
+        files_tested += 1;
+        Ok(true)
+    })?;
+
+    Ok(files_tested)
+}
+
+/// List contents of `archive`, returning a vector of archive entries
+///
+/// Only the archive headers are read, entries are not decompressed.
+pub fn list_archive<R>(mut reader: R) -> crate::Result<impl Iterator<Item = crate::Result<FileInArchive>>>
+where
+    R: Read + Seek,
+{
+    let reader_len = reader.seek(io::SeekFrom::End(0))?;
+    reader.rewind()?;
+
+    let archive = sevenz_rust::Archive::read(&mut reader, reader_len, &[])?;
+
+    let files = archive.files.into_iter().map(|entry| {
+        let modified = entry
+            .has_last_modified_date
+            .then(|| {
+                SystemTime::from(entry.last_modified_date())
+                    .duration_since(UNIX_EPOCH)
+                    .ok()
+            })
+            .flatten()
+            .map(|duration| duration.as_secs() as i64);
+        // The high bits of the attributes hold the Unix mode, when the archive was made on Unix
+        let mode = (entry.has_windows_attributes && entry.windows_attributes & UNIX_EXTENSION_FLAG != 0)
+            .then_some((entry.windows_attributes >> 16) & 0o7777);
+
+        // Solid archives compress many entries together, so there's no compressed size per entry
+        Ok(FileInArchive {
+            path: entry.name().into(),
+            is_dir: entry.is_directory(),
+            size: Some(entry.size()),
+            compressed_size: None,
+            modified,
+            mode,
+        })
+    });
+
+    Ok(files)
+}
@@ -1,56 +1,355 @@
 //! Contains Tar-specific building and unpacking functions
 
+#[cfg(unix)]
 use std::{
-    env,
-    io::prelude::*,
+    collections::{hash_map::Entry, HashMap},
+    ffi::{CString, OsStr},
+    fs::Permissions,
+    os::unix::{
+        ffi::OsStrExt,
+        fs::{lchown, MetadataExt, PermissionsExt},
+    },
+    sync::Once,
+};
+use std::{
+    io::{self, prelude::*},
     path::{Path, PathBuf},
     sync::mpsc::{self, Receiver},
     thread,
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 use fs_err as fs;
 use same_file::Handle;
 
 use crate::{
+    archive::{log_compressing, skip_error, EntryBase, EntrySelection, Reproducible, SizeLimit, SkippedFiles},
     error::FinalError,
     list::FileInArchive,
     utils::{
         self,
-        logger::{info, warning},
+        interrupt::Writing,
+        logger::{file_info, warning, Verbosity},
+        progress::Progress,
         Bytes, EscapedPathDisplay, FileVisibilityPolicy,
     },
 };
 
-/// Unpacks the archive given by `archive` into the folder given by `into`.
-/// Assumes that output_folder is empty
-pub fn unpack_archive(reader: Box<dyn Read>, output_folder: &Path, quiet: bool) -> crate::Result<usize> {
+/// Who owns the extracted files
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Ownership {
+    /// The user extracting them
+    #[default]
+    Current,
+    /// The user and group stored in the headers, looked up by name, or by ID if the name doesn't exist
+    ByName,
+    /// The user and group IDs stored in the headers, ignoring the names
+    Numeric,
+}
+
+/// Unpacks the archive given by `archive` into the folder given by `into`, only the entries that
+/// `selection` extracts. Assumes that output_folder is empty
+///
+/// On Unix, the permissions stored in the headers are restored if `preserve_permissions` is set,
+/// and the owners following `ownership`, which requires root.
+/// The modification times are restored if `preserve_mtime` is set, and on Unix the extended
+/// attributes stored in PAX headers if `xattrs` is set, see [`restore_xattrs`].
+// `preserve_permissions`, `ownership` and `xattrs` are only used on Unix
+#[cfg_attr(not(unix), allow(unused_variables))]
+#[allow(clippy::too_many_arguments)]
+pub fn unpack_archive(
+    reader: Box<dyn Read>,
+    output_folder: &Path,
+    selection: &EntrySelection,
+    size_limit: &SizeLimit,
+    preserve_permissions: bool,
+    preserve_mtime: bool,
+    ownership: Ownership,
+    xattrs: bool,
+    verbosity: Verbosity,
+) -> crate::Result<usize> {
     assert!(output_folder.read_dir().expect("dir exists").count() == 0);
     let mut archive = tar::Archive::new(reader);
+    archive.set_preserve_mtime(preserve_mtime);
+    #[cfg(unix)]
+    let mut owners = Owners::new(ownership);
 
     let mut files_unpacked = 0;
     for file in archive.entries()? {
         let mut file = file?;
+        if !selection.extracts(&file.path()?, file.header().entry_type().is_dir()) {
+            continue;
+        }
+        let file_path = utils::safe_join(output_folder, &file.path()?)?;
+        if is_file(file.header()) {
+            let mtime = file.header().mtime().ok().map(|mtime| mtime as i64);
+            if selection.was_extracted(&file.path()?, file.size(), mtime) {
+                continue;
+            }
+            size_limit.add(&file.path()?, file.size())?;
+        }
+
+        // Only regular files can be left incomplete
+        let writing = is_file(file.header()).then(|| Writing::start(&file_path));
+        // Also skips entries that would be written through symlinks pointing outside of `output_folder`
+        if !file.unpack_in(output_folder)? {
+            continue;
+        }
+
+        #[cfg(unix)]
+        {
+            let entry_type = file.header().entry_type();
+            // Symlinks are skipped, setting their permissions would change their target's
+            if is_file(file.header()) || entry_type.is_dir() {
+                if xattrs {
+                    restore_xattrs(&mut file, &file_path)?;
+                }
+                // Setuid, setgid and sticky bits are dropped, like the tar crate does
+                let mode =
+                    utils::extracted_mode(file.header().mode()? & 0o777, entry_type.is_dir(), preserve_permissions);
+                fs::set_permissions(&file_path, Permissions::from_mode(mode))?;
+            }
 
-        file.unpack_in(output_folder)?;
+            // After the permissions, changing the owner clears the setuid and setgid bits anyway
+            if let Some((uid, gid)) = owners.owner_of(file.header())? {
+                lchown(&file_path, Some(uid), Some(gid))?;
+            }
+        }
 
         // This is printed for every file in the archive and has little
         // importance for most users, but would generate lots of
         // spoken text for users using screen readers, braille displays
         // and so on
-        if !quiet {
-            info(format!(
+        file_info(verbosity, || {
+            format!(
                 "{:?} extracted. ({})",
-                utils::strip_cur_dir(&output_folder.join(file.path()?)),
+                utils::strip_cur_dir(&file_path),
                 Bytes::new(file.size()),
-            ));
+            )
+        });
 
-            files_unpacked += 1;
+        if let Some(writing) = writing {
+            writing.finish();
         }
+        files_unpacked += 1;
     }
 
     Ok(files_unpacked)
 }
 
+/// Prefix of the keys of the PAX records that store extended attributes, followed by their name
+#[cfg(unix)]
+const XATTR_PREFIX: &[u8] = b"SCHILY.xattr.";
+
+/// Set the extended attributes stored in the PAX records of `entry` on its extracted file at
+/// `path`, warning once and going on if they can't be.
+///
+/// Leaves `path` writable by its owner, which attributes in the `user` namespace need, the caller
+/// sets its permissions afterwards.
+#[cfg(unix)]
+fn restore_xattrs(entry: &mut tar::Entry<impl Read>, path: &Path) -> io::Result<()> {
+    let Some(extensions) = entry.pax_extensions()? else {
+        return Ok(());
+    };
+    let mut attributes = vec![];
+    for extension in extensions {
+        let extension = extension?;
+        if let Some(name) = extension.key_bytes().strip_prefix(XATTR_PREFIX) {
+            attributes.push((OsStr::from_bytes(name).to_owned(), extension.value_bytes().to_vec()));
+        }
+    }
+    if attributes.is_empty() {
+        return Ok(());
+    }
+
+    let mode = fs::metadata(path)?.permissions().mode();
+    fs::set_permissions(path, Permissions::from_mode(mode | 0o200))?;
+    for (name, value) in attributes {
+        if let Err(err) = xattr::set(path, &name, &value) {
+            warn_xattrs_once("restore", path, &err);
+        }
+    }
+    Ok(())
+}
+
+/// PAX records storing the extended attributes of `path`, empty if it has none, warning once and
+/// going on if they can't be read
+#[cfg(unix)]
+fn xattr_records(path: &Path) -> Vec<u8> {
+    let names = match xattr::list(path) {
+        Ok(names) => names,
+        Err(err) => {
+            warn_xattrs_once("read", path, &err);
+            return vec![];
+        }
+    };
+
+    let mut records = vec![];
+    for name in names {
+        match xattr::get(path, &name) {
+            Ok(Some(value)) => records.extend(pax_record(&[XATTR_PREFIX, name.as_bytes()].concat(), &value)),
+            // Removed since it was listed
+            Ok(None) => {}
+            Err(err) => warn_xattrs_once("read", path, &err),
+        }
+    }
+    records
+}
+
+/// A PAX record, "<length> <key>=<value>\n", where the length counts its own digits
+#[cfg(unix)]
+fn pax_record(key: &[u8], value: &[u8]) -> Vec<u8> {
+    let rest = key.len() + value.len() + 3;
+    let mut length = rest + rest.to_string().len();
+    // One more digit when counting the digits carries over, like 99 + 2 = 101
+    if length.to_string().len() > rest.to_string().len() {
+        length += 1;
+    }
+
+    let mut record = format!("{length} ").into_bytes();
+    record.extend_from_slice(key);
+    record.push(b'=');
+    record.extend_from_slice(value);
+    record.push(b'\n');
+    record
+}
+
+/// Append a PAX extended header with the extended attributes of `path`, which apply to the entry
+/// appended next, unless it has none
+#[cfg(unix)]
+fn append_xattrs<W: Write>(builder: &mut tar::Builder<W>, path: &Path) -> io::Result<()> {
+    let records = xattr_records(path);
+    if records.is_empty() {
+        return Ok(());
+    }
+
+    let mut header = tar::Header::new_ustar();
+    header.set_path("PaxHeader")?;
+    header.set_entry_type(tar::EntryType::XHeader);
+    header.set_mode(0o644);
+    header.set_size(records.len() as u64);
+    header.set_cksum();
+    builder.append(&header, records.as_slice())
+}
+
+/// Extended attributes aren't stored on platforms without them, see [`check::check_xattrs_options`]
+///
+/// [`check::check_xattrs_options`]: crate::check::check_xattrs_options
+#[cfg(not(unix))]
+fn append_xattrs<W: Write>(_builder: &mut tar::Builder<W>, _path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Warn that extended attributes couldn't be read or restored, only the first time, they usually
+/// fail for every file of an unsupported filesystem
+#[cfg(unix)]
+fn warn_xattrs_once(action: &str, path: &Path, err: &io::Error) {
+    static WARNED: Once = Once::new();
+    WARNED.call_once(|| {
+        warning(format!(
+            "Could not {action} the extended attributes of '{}', skipping them: {err}. Other failures won't be reported.",
+            EscapedPathDisplay::new(path)
+        ));
+    });
+}
+
+/// Finds the owner of extracted entries, remembering the IDs of the names that were looked up
+#[cfg(unix)]
+struct Owners {
+    ownership: Ownership,
+    users: HashMap<Vec<u8>, Option<u32>>,
+    groups: HashMap<Vec<u8>, Option<u32>>,
+}
+
+#[cfg(unix)]
+impl Owners {
+    fn new(ownership: Ownership) -> Self {
+        Self {
+            ownership,
+            users: HashMap::new(),
+            groups: HashMap::new(),
+        }
+    }
+
+    /// The user and group IDs to give to the entry of `header`, `None` if it keeps the current user
+    fn owner_of(&mut self, header: &tar::Header) -> io::Result<Option<(u32, u32)>> {
+        if self.ownership == Ownership::Current {
+            return Ok(None);
+        }
+
+        let id = |id: u64| {
+            u32::try_from(id).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "owner ID is too large"))
+        };
+        let (mut uid, mut gid) = (id(header.uid()?)?, id(header.gid()?)?);
+
+        if self.ownership == Ownership::ByName {
+            if let Some(name) = header.username_bytes().filter(|name| !name.is_empty()) {
+                let user = self.users.entry(name.to_vec()).or_insert_with(|| user_id(name));
+                uid = user.unwrap_or(uid);
+            }
+            if let Some(name) = header.groupname_bytes().filter(|name| !name.is_empty()) {
+                let group = self.groups.entry(name.to_vec()).or_insert_with(|| group_id(name));
+                gid = group.unwrap_or(gid);
+            }
+        }
+
+        Ok(Some((uid, gid)))
+    }
+}
+
+/// Size of the buffer holding the strings of the user and group entries being looked up
+#[cfg(unix)]
+const LOOKUP_BUFFER_SIZE: usize = 16 * 1024;
+
+/// ID of the user called `name` on this system, if there's one
+#[cfg(unix)]
+fn user_id(name: &[u8]) -> Option<u32> {
+    let name = CString::new(name).ok()?;
+    let mut buffer = vec![0; LOOKUP_BUFFER_SIZE];
+    // Safety: an all-zero `passwd` is valid, it only holds integers and null pointers
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut result = std::ptr::null_mut();
+    // Safety: the pointers are valid for the whole call, and `buffer.len()` is the size of `buffer`
+    let code = unsafe {
+        libc::getpwnam_r(
+            name.as_ptr(),
+            &mut passwd,
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &mut result,
+        )
+    };
+    (code == 0 && !result.is_null()).then_some(passwd.pw_uid)
+}
+
+/// ID of the group called `name` on this system, if there's one
+#[cfg(unix)]
+fn group_id(name: &[u8]) -> Option<u32> {
+    let name = CString::new(name).ok()?;
+    let mut buffer = vec![0; LOOKUP_BUFFER_SIZE];
+    // Safety: an all-zero `group` is valid, it only holds integers and null pointers
+    let mut group: libc::group = unsafe { std::mem::zeroed() };
+    let mut result = std::ptr::null_mut();
+    // Safety: the pointers are valid for the whole call, and `buffer.len()` is the size of `buffer`
+    let code = unsafe {
+        libc::getgrnam_r(
+            name.as_ptr(),
+            &mut group,
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &mut result,
+        )
+    };
+    (code == 0 && !result.is_null()).then_some(group.gr_gid)
+}
+
+/// Whether the owner of files can be changed, which requires root
+#[cfg(unix)]
+pub fn can_change_owner() -> bool {
+    // Safety: geteuid has no preconditions and can't fail
+    unsafe { libc::geteuid() == 0 }
+}
+
 /// List contents of `archive`, returning a vector of archive entries
 pub fn list_archive(
     mut archive: tar::Archive<impl Read + Send + 'static>,
@@ -70,8 +369,16 @@ pub fn list_archive(
             let file_in_archive = (|| {
                 let file = file?;
                 let path = file.path()?.into_owned();
-                let is_dir = file.header().entry_type().is_dir();
-                Ok(FileInArchive { path, is_dir })
+                let header = file.header();
+                let is_dir = header.entry_type().is_dir();
+                Ok(FileInArchive {
+                    path,
+                    is_dir,
+                    size: header.size().ok(),
+                    compressed_size: None,
+                    modified: header.mtime().ok().map(|mtime| mtime as i64),
+                    mode: header.mode().ok().map(|mode| mode & 0o7777),
+                })
             })();
             tx.send(file_in_archive).unwrap();
         }
@@ -80,30 +387,145 @@ pub fn list_archive(
     Files(rx)
 }
 
+/// Reads every entry of the archive given by `reader` without writing them anywhere, failing
+/// if the archive or the formats it's compressed with are corrupted.
+pub fn test_archive(reader: Box<dyn Read>) -> crate::Result<usize> {
+    let mut archive = tar::Archive::new(reader);
+    let mut files_tested = 0;
+
+    for file in archive.entries()? {
+        io::copy(&mut file?, &mut io::sink())?;
+        files_tested += 1;
+    }
+
+    // The checksums of compression formats are only checked once their end is reached,
+    // which can be past the end of the tar archive
+    io::copy(&mut archive.into_inner(), &mut io::sink())?;
+
+    Ok(files_tested)
+}
+
+/// Writes a tar archive with a single regular file called `name`, with `size` bytes read from
+/// `contents`, used to nest other archives in a tar archive, like "archive.zip.tar".
+///
+/// The entry is modified now, unless `reproducible` fixes its modification time.
+pub fn build_archive_from_reader<W>(
+    name: &Path,
+    size: u64,
+    contents: impl Read,
+    writer: W,
+    reproducible: Option<Reproducible>,
+) -> crate::Result<W>
+where
+    W: Write,
+{
+    let mut builder = tar::Builder::new(writer);
+
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Regular);
+    header.set_size(size);
+    header.set_mode(0o644);
+    let mtime = reproducible.map_or_else(
+        || {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs()
+        },
+        |reproducible| reproducible.mtime,
+    );
+    header.set_mtime(mtime);
+    builder.append_data(&mut header, name, contents)?;
+
+    Ok(builder.into_inner()?)
+}
+
+/// Open the tar archive at `path` to add entries to it, positioned over the blocks marking its end.
+///
+/// Also returns the position where the new entries start, to [`truncate_archive`] it back to.
+pub fn open_for_append(path: &Path) -> crate::Result<(fs::File, u64)> {
+    let invalid_archive = |err: io::Error| {
+        FinalError::with_title(format!("Cannot append to '{}'", EscapedPathDisplay::new(path)))
+            .detail("It isn't a valid tar archive")
+            .detail(format!("Error: {err}."))
+    };
+
+    let mut archive = tar::Archive::new(io::BufReader::new(fs::File::open(path)?));
+    let mut end = 0;
+    for entry in archive.entries().map_err(invalid_archive)? {
+        let entry = entry.map_err(invalid_archive)?;
+        // Contents are padded to whole blocks
+        end = entry.raw_file_position() + entry.size().next_multiple_of(512);
+    }
+
+    let mut file = fs::OpenOptions::new().read(true).write(true).open(path)?;
+    file.seek(io::SeekFrom::Start(end))?;
+    Ok((file, end))
+}
+
+/// Drop the entries of the tar archive at `path` that start at `end` or after it, undoing
+/// what was appended to it.
+pub fn truncate_archive(path: &Path, end: u64) -> crate::Result<()> {
+    let file = fs::OpenOptions::new().write(true).open(path)?;
+    file.set_len(end)?;
+    // Extended with zeros, which are the two blocks marking the end of the archive
+    file.set_len(end + 2 * 512)?;
+    Ok(())
+}
+
 /// Compresses the archives given by `input_filenames` into the file given previously to `writer`.
+///
+/// Entries are named relative to the parent directory of each input, or the base given by
+/// `entry_base`, without changing the current directory, so archives can be built concurrently.
+///
+/// Headers use the GNU format, whose extensions store paths and link targets longer than 100
+/// bytes, sizes over 8 GiB and large ids, which `tar::Archive` reads back when unpacking.
+///
+/// On Unix, files linked several times in the inputs are stored once, followed by hard link
+/// entries, which are recreated as hard links when unpacking.
+///
+/// With `reproducible`, the owners are 0, the modification times fixed and the permissions
+/// normalized, see [`entry_header`].
+///
+/// With `sparse`, files with holes are stored as GNU sparse entries, with only the regions that
+/// have data, see [`data_regions`], unless they're too large for one, see [`SPARSE_SIZE_LIMIT`].
+///
+/// With `xattrs`, the extended attributes of files and directories are stored in a PAX header
+/// before their entry, with the `SCHILY.xattr.` keys GNU tar uses.
+///
+/// With `skip_errors`, files and directories that can't be read are left out, see [`skip_error`].
+#[allow(clippy::too_many_arguments)]
 pub fn build_archive_from_paths<W>(
     input_filenames: &[PathBuf],
     output_path: &Path,
     writer: W,
     file_visibility_policy: FileVisibilityPolicy,
-    quiet: bool,
+    entry_base: &EntryBase,
+    reproducible: Option<Reproducible>,
+    sparse: bool,
+    xattrs: bool,
+    skip_errors: Option<&SkippedFiles>,
+    progress: &Progress,
+    verbosity: Verbosity,
 ) -> crate::Result<W>
 where
     W: Write,
 {
     let mut builder = tar::Builder::new(writer);
     let output_handle = Handle::from_path(output_path);
+    // Entry names of the files with several hard links, by device and inode
+    #[cfg(unix)]
+    let mut hard_links: HashMap<(u64, u64), PathBuf> = HashMap::new();
 
     for filename in input_filenames {
-        let previous_location = utils::cd_into_same_dir_as(filename)?;
-
-        // Unwrap safety:
-        //   paths should be canonicalized by now, and the root directory rejected.
-        let filename = filename.file_name().unwrap();
-
         for entry in file_visibility_policy.build_walker(filename) {
-            let entry = entry?;
+            let Some(entry) = skip_error(entry, skip_errors)? else {
+                continue;
+            };
             let path = entry.path();
+            let Some(entry_name) = entry_base.entry_name(filename, path)? else {
+                continue;
+            };
 
             // If the output_path is the same as the input file, warn the user and skip the input (in order to avoid compression recursion)
             if let Ok(handle) = &output_handle {
@@ -121,14 +543,21 @@ where
             // little importance for most users, but would generate lots of
             // spoken text for users using screen readers, braille displays
             // and so on
-            if !quiet {
-                info(format!("Compressing '{}'.", EscapedPathDisplay::new(path)));
-            }
+            log_compressing(entry_name, path, verbosity);
 
-            if path.is_dir() {
-                builder.append_dir(path, path)?;
+            if entry.path_is_symlink() && !file_visibility_policy.follows_links() {
+                // Stored as a link to the same target, instead of a copy of it
+                let mut header = entry_header(&fs::symlink_metadata(path)?, reproducible);
+                builder.append_link(&mut header, entry_name, fs::read_link(path)?)?;
+            } else if path.is_dir() {
+                if xattrs {
+                    append_xattrs(&mut builder, path)?;
+                }
+                // Same header as `Builder::append_dir`
+                let mut header = entry_header(&fs::metadata(path)?, reproducible);
+                builder.append_data(&mut header, entry_name, io::empty())?;
             } else {
-                let mut file = match fs::File::open(path) {
+                let file = match fs::File::open(path) {
                     Ok(f) => f,
                     Err(e) => {
                         if e.kind() == std::io::ErrorKind::NotFound && utils::is_symlink(path) {
@@ -136,18 +565,339 @@ where
                             // We just ignore it
                             continue;
                         }
-                        return Err(e.into());
+                        match skip_errors {
+                            Some(skipped) => {
+                                skipped.skip(e);
+                                continue;
+                            }
+                            None => return Err(e.into()),
+                        }
+                    }
+                };
+
+                let metadata = file.metadata()?;
+
+                // Files with several hard links are stored once, the other links point at that entry
+                #[cfg(unix)]
+                if metadata.nlink() > 1 {
+                    match hard_links.entry((metadata.dev(), metadata.ino())) {
+                        Entry::Occupied(first) => {
+                            let mut header = entry_header(&metadata, reproducible);
+                            header.set_entry_type(tar::EntryType::Link);
+                            header.set_size(0);
+                            builder.append_link(&mut header, entry_name, first.get())?;
+                            // Counted in the total, but never read
+                            progress.inc(metadata.len());
+                            continue;
+                        }
+                        Entry::Vacant(vacant) => {
+                            vacant.insert(entry_name.to_path_buf());
+                        }
+                    }
+                }
+
+                if xattrs {
+                    append_xattrs(&mut builder, path)?;
+                }
+                // Same header as `Builder::append_file`, but the contents are read through `progress`
+                let mut header = entry_header(&metadata, reproducible);
+                let regions = match sparse {
+                    true => data_regions(&file, metadata.len())?,
+                    false => None,
+                };
+                let regions = match regions {
+                    Some(_) if metadata.len() >= SPARSE_SIZE_LIMIT => {
+                        warning(format!(
+                            "'{}' is too large for a sparse entry, it's stored whole, holes included",
+                            EscapedPathDisplay::new(path)
+                        ));
+                        None
                     }
+                    regions => regions,
                 };
-                builder.append_file(path, file.file_mut()).map_err(|err| {
+                let appended = match regions {
+                    Some(regions) => {
+                        let extensions = set_sparse_header(&mut header, &regions, metadata.len());
+                        // The holes aren't read
+                        progress.inc(metadata.len() - regions.iter().map(|(_, length)| length).sum::<u64>());
+                        let contents = RegionReader::new(file.file(), &regions);
+                        builder.append_data(
+                            &mut header,
+                            entry_name,
+                            io::Cursor::new(extensions).chain(progress.wrap_read(contents)),
+                        )
+                    }
+                    None => builder.append_data(&mut header, entry_name, progress.wrap_read(file)),
+                };
+                appended.map_err(|err| {
                     FinalError::with_title("Could not create archive")
                         .detail("Unexpected error while trying to read file")
                         .detail(format!("Error: {err}."))
                 })?;
             }
         }
-        env::set_current_dir(previous_location)?;
     }
 
     Ok(builder.into_inner()?)
 }
+
+/// Whether the entry of `header` is a regular file, sparse or not
+fn is_file(header: &tar::Header) -> bool {
+    let entry_type = header.entry_type();
+    entry_type.is_file() || entry_type.is_gnu_sparse()
+}
+
+/// Sparse entries store their offsets and sizes in 11 octal digits, which `tar::Archive` reads
+/// back, so larger files are stored whole, 8 GiB
+const SPARSE_SIZE_LIMIT: u64 = 1 << 33;
+
+/// Regions of `file` that have data, as offsets and lengths, or `None` if it has no holes, or the
+/// filesystem can't tell where its holes are.
+///
+/// The regions are widened to multiples of 512 bytes, the tar block size, as every region but the
+/// last must be when unpacking.
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_vendor = "apple"
+))]
+fn data_regions(file: &fs::File, len: u64) -> io::Result<Option<Vec<(u64, u64)>>> {
+    use std::os::fd::AsRawFd;
+
+    let seek = |offset: u64, whence| {
+        // Safety: the descriptor of `file` stays open for the whole call, which only moves its offset
+        match unsafe { libc::lseek(file.as_raw_fd(), offset as libc::off_t, whence) } {
+            -1 => Err(io::Error::last_os_error()),
+            position => Ok(position as u64),
+        }
+    };
+
+    let mut regions: Vec<(u64, u64)> = vec![];
+    let mut offset = 0;
+    while offset < len {
+        let start = match seek(offset, libc::SEEK_DATA) {
+            Ok(start) => start,
+            // Only holes after `offset`
+            Err(err) if err.raw_os_error() == Some(libc::ENXIO) => break,
+            // Holes aren't supported by the filesystem
+            Err(err) if err.raw_os_error() == Some(libc::EINVAL) => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let end = seek(start, libc::SEEK_HOLE)?;
+
+        let start = start / 512 * 512;
+        let end = end.div_ceil(512).saturating_mul(512).min(len);
+        match regions.last_mut() {
+            Some((last_offset, last_length)) if *last_offset + *last_length >= start => {
+                *last_length = end - *last_offset;
+            }
+            _ => regions.push((start, end - start)),
+        }
+        offset = end;
+    }
+
+    // The file is read from the start again
+    seek(0, libc::SEEK_SET)?;
+
+    let has_holes = regions.iter().map(|(_, length)| length).sum::<u64>() < len;
+    Ok(has_holes.then_some(regions))
+}
+
+/// Where the holes are can't be found on this platform, files are always stored whole
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_vendor = "apple"
+)))]
+fn data_regions(_file: &fs::File, _len: u64) -> io::Result<Option<Vec<(u64, u64)>>> {
+    Ok(None)
+}
+
+/// Turn `header` into the GNU sparse header of a file of `real_size` bytes with data in
+/// `regions`, returning the extension blocks to write right after it, for the regions that don't
+/// fit in the header
+fn set_sparse_header(header: &mut tar::Header, regions: &[(u64, u64)], real_size: u64) -> Vec<u8> {
+    fn octal_into(dst: &mut [u8; 12], value: u64) {
+        dst[..11].copy_from_slice(format!("{value:011o}").as_bytes());
+    }
+
+    // The file ends with a hole when the last region ends before it, which is marked by an empty
+    // region at its end
+    let mut blocks = regions.to_vec();
+    if blocks.last().map_or(0, |(offset, length)| offset + length) < real_size {
+        blocks.push((real_size, 0));
+    }
+    let (in_header, in_extensions) = blocks.split_at(blocks.len().min(4));
+
+    header.set_entry_type(tar::EntryType::GNUSparse);
+    header.set_size(regions.iter().map(|(_, length)| length).sum());
+    // Unwrap safety:
+    //   the headers are created with `Header::new_gnu`.
+    let gnu = header.as_gnu_mut().unwrap();
+    for (sparse, &(offset, length)) in gnu.sparse.iter_mut().zip(in_header) {
+        octal_into(&mut sparse.offset, offset);
+        octal_into(&mut sparse.numbytes, length);
+    }
+    octal_into(&mut gnu.realsize, real_size);
+    gnu.isextended[0] = u8::from(!in_extensions.is_empty());
+
+    let mut extensions = vec![];
+    let mut chunks = in_extensions.chunks(21).peekable();
+    while let Some(chunk) = chunks.next() {
+        let mut extension = tar::GnuExtSparseHeader::new();
+        for (sparse, &(offset, length)) in extension.sparse.iter_mut().zip(chunk) {
+            octal_into(&mut sparse.offset, offset);
+            octal_into(&mut sparse.numbytes, length);
+        }
+        extension.isextended[0] = u8::from(chunks.peek().is_some());
+        extensions.extend_from_slice(extension.as_bytes());
+    }
+    extensions
+}
+
+/// Reads the `regions` of a file one after the other
+struct RegionReader<'a> {
+    file: &'a std::fs::File,
+    regions: std::slice::Iter<'a, (u64, u64)>,
+    remaining: u64,
+}
+
+impl<'a> RegionReader<'a> {
+    fn new(file: &'a std::fs::File, regions: &'a [(u64, u64)]) -> Self {
+        Self {
+            file,
+            regions: regions.iter(),
+            remaining: 0,
+        }
+    }
+}
+
+impl Read for RegionReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.remaining == 0 {
+            let Some(&(offset, length)) = self.regions.next() else {
+                return Ok(0);
+            };
+            self.file.seek(io::SeekFrom::Start(offset))?;
+            self.remaining = length;
+        }
+
+        let len = buf.len().min(usize::try_from(self.remaining).unwrap_or(usize::MAX));
+        let read = self.file.read(&mut buf[..len])?;
+        if read == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "the file was truncated while it was archived",
+            ));
+        }
+        self.remaining -= read as u64;
+        Ok(read)
+    }
+}
+
+/// GNU header of an entry, filled from the `metadata` of its file, unless `reproducible` is set,
+/// then the owners are 0, the modification time is fixed and the permissions are 0o755 for
+/// directories and executables, 0o644 for other files
+fn entry_header(metadata: &std::fs::Metadata, reproducible: Option<Reproducible>) -> tar::Header {
+    let mut header = tar::Header::new_gnu();
+    match reproducible {
+        Some(reproducible) => {
+            header.set_metadata_in_mode(metadata, tar::HeaderMode::Deterministic);
+            header.set_mtime(reproducible.mtime);
+        }
+        None => header.set_metadata(metadata),
+    }
+    header
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn header(username: &str, groupname: &str) -> tar::Header {
+        let mut header = tar::Header::new_gnu();
+        header.set_uid(1234);
+        header.set_gid(5678);
+        header.set_username(username).unwrap();
+        header.set_groupname(groupname).unwrap();
+        header
+    }
+
+    #[test]
+    fn test_stored_owner_ids_are_read() {
+        let header = header("root", "no-such-group-for-ouch");
+
+        assert_eq!(Owners::new(Ownership::Current).owner_of(&header).unwrap(), None);
+        assert_eq!(
+            Owners::new(Ownership::Numeric).owner_of(&header).unwrap(),
+            Some((1234, 5678))
+        );
+        // root is user 0 everywhere, and a name that doesn't exist falls back to the stored ID
+        assert_eq!(
+            Owners::new(Ownership::ByName).owner_of(&header).unwrap(),
+            Some((0, 5678))
+        );
+    }
+
+    #[test]
+    fn test_owner_ids_are_read_after_a_round_trip() {
+        let mut builder = tar::Builder::new(vec![]);
+        let mut header = header("", "");
+        header.set_size(0);
+        builder.append_data(&mut header, "file", io::empty()).unwrap();
+        let bytes = builder.into_inner().unwrap();
+
+        let mut archive = tar::Archive::new(bytes.as_slice());
+        let entry = archive.entries().unwrap().next().unwrap().unwrap();
+        // Without names, IDs are used even when looking up by name
+        assert_eq!(
+            Owners::new(Ownership::ByName).owner_of(entry.header()).unwrap(),
+            Some((1234, 5678))
+        );
+    }
+
+    #[test]
+    fn test_sparse_entries_are_read_back_with_their_holes() {
+        // More regions than fit in the header, so extension blocks are written, and a hole at the end
+        let regions: Vec<_> = (0..30).map(|i| (i * 2048, if i == 29 { 100 } else { 512 })).collect();
+        let real_size = 29 * 2048 + 4096;
+        let contents: Vec<u8> = (0..real_size).map(|i| (i % 251) as u8 + 1).collect();
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(&contents).unwrap();
+
+        let mut header = tar::Header::new_gnu();
+        header.set_mode(0o644);
+        let extensions = set_sparse_header(&mut header, &regions, real_size);
+        assert_eq!(extensions.len(), 2 * 512);
+
+        let mut builder = tar::Builder::new(vec![]);
+        let reader = io::Cursor::new(extensions).chain(RegionReader::new(&file, &regions));
+        builder.append_data(&mut header, "file", reader).unwrap();
+        let bytes = builder.into_inner().unwrap();
+
+        let mut expected = vec![0; real_size as usize];
+        for &(offset, length) in &regions {
+            let range = offset as usize..(offset + length) as usize;
+            expected[range.clone()].copy_from_slice(&contents[range]);
+        }
+        let mut archive = tar::Archive::new(bytes.as_slice());
+        let mut entry = archive.entries().unwrap().next().unwrap().unwrap();
+        assert!(is_file(entry.header()));
+        let mut unpacked = vec![];
+        entry.read_to_end(&mut unpacked).unwrap();
+        assert_eq!(unpacked, expected);
+    }
+
+    #[test]
+    fn test_pax_records_count_their_own_length() {
+        assert_eq!(pax_record(b"key", b"value"), b"13 key=value\n");
+        // Around the lengths where the count gains a digit
+        for value_len in 80..120 {
+            let record = pax_record(b"SCHILY.xattr.user.a", &vec![b'x'; value_len]);
+            let (length, _) = std::str::from_utf8(&record).unwrap().split_once(' ').unwrap();
+            assert_eq!(length.parse::<usize>().unwrap(), record.len());
+        }
+    }
+}
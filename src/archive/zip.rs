@@ -14,76 +14,163 @@ use filetime_creation::{set_file_mtime, FileTime};
 use fs_err as fs;
 use same_file::Handle;
 use time::OffsetDateTime;
-use zip::{read::ZipFile, DateTime, ZipArchive};
+use zip::{read::ZipFile, AesMode, DateTime, ZipArchive};
 
 use crate::{
+    archive::{log_compressing, skip_error, EntryBase, EntrySelection, Reproducible, SizeLimit, SkippedFiles},
     error::FinalError,
     list::FileInArchive,
     utils::{
-        self, cd_into_same_dir_as, get_invalid_utf8_paths,
-        logger::{info, info_accessible, warning},
-        pretty_format_list_of_paths, strip_cur_dir, Bytes, EscapedPathDisplay, FileVisibilityPolicy,
+        self, ask_for_password, cd_into_entry_base, get_invalid_utf8_paths,
+        interrupt::Writing,
+        io::lock_and_flush_output_stdio,
+        logger::{file_info, info_accessible, warning, Verbosity},
+        pretty_format_list_of_paths,
+        progress::Progress,
+        strip_cur_dir, user_wants_to_continue, Bytes, EscapedPathDisplay, FileVisibilityPolicy, QuestionAction,
     },
+    QuestionPolicy,
 };
 
-/// Unpacks the archive given by `archive` into the folder given by `output_folder`.
-/// Assumes that output_folder is empty
-pub fn unpack_archive<R>(mut archive: ZipArchive<R>, output_folder: &Path, quiet: bool) -> crate::Result<usize>
+/// How many times larger than its compressed size an entry may be before it's taken for a zip bomb
+const SUSPICIOUS_RATIO: u64 = 100;
+
+/// Entries smaller than this aren't checked against `SUSPICIOUS_RATIO`, they can't fill the disk
+/// however well they compress
+const SUSPICIOUS_MIN_SIZE: u64 = 1 << 20;
+
+/// Unpacks the archive given by `archive` into the folder given by `output_folder`, only the
+/// entries that `selection` extracts. Assumes that output_folder is empty
+///
+/// If an encrypted entry is found and no `password` was given, the user is asked for the
+/// password of `archive_path`.
+///
+/// The modification times of files are restored if `preserve_mtime` is set.
+///
+/// Extraction stops with an error once the entries decompress into more than `size_limit`,
+/// counted as they're decompressed, since the sizes in the archive can be wrong. Before that, the
+/// user is asked whether to go on if the central directory says an entry decompresses into far
+/// more than its compressed size.
+// `preserve_permissions` is only used on Unix
+#[cfg_attr(not(unix), allow(unused_variables))]
+#[allow(clippy::too_many_arguments)]
+pub fn unpack_archive<R>(
+    mut archive: ZipArchive<R>,
+    archive_path: &Path,
+    output_folder: &Path,
+    selection: &EntrySelection,
+    password: Option<&str>,
+    question_policy: QuestionPolicy,
+    preserve_permissions: bool,
+    preserve_mtime: bool,
+    size_limit: &SizeLimit,
+    verbosity: Verbosity,
+) -> crate::Result<usize>
 where
     R: Read + Seek,
 {
     assert!(output_folder.read_dir().expect("dir exists").count() == 0);
 
+    if !check_compression_ratios(&mut archive, archive_path, question_policy)? {
+        return Err(crate::Error::UserDeclined);
+    }
+
     let mut unpacked_files = 0;
+    let mut password = password.map(str::to_owned);
 
     for idx in 0..archive.len() {
-        let mut file = archive.by_index(idx)?;
-        let file_path = match file.enclosed_name() {
-            Some(path) => path.to_owned(),
-            None => continue,
+        // Raw entries aren't decrypted, the password isn't asked for entries that are skipped
+        let (extracts, encrypted) = {
+            let file = archive.by_index_raw(idx)?;
+            (
+                selection.extracts(Path::new(file.name()), file.is_dir()),
+                file.encrypted(),
+            )
         };
+        if !extracts {
+            continue;
+        }
+        if password.is_none() && encrypted {
+            password = Some(ask_for_password(archive_path, question_policy)?);
+        }
 
-        let file_path = output_folder.join(file_path);
+        let mut file = match &password {
+            Some(password) => archive.by_index_decrypt(idx, password.as_bytes())?,
+            None => archive.by_index(idx)?,
+        };
+        let file_path = utils::safe_join(output_folder, Path::new(file.name()))?;
+        // Root entries like "/" have nothing to extract
+        if file_path == output_folder {
+            continue;
+        }
 
         display_zip_comment_if_exists(&file);
 
+        if file.is_symlink() {
+            let mut target = String::new();
+            file.read_to_string(&mut target)?;
+
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(&target, &file_path)?;
+            #[cfg(not(unix))]
+            {
+                warning(format!(
+                    "Skipping symlink '{}', not supported on this platform",
+                    file.name()
+                ));
+                continue;
+            }
+
+            file_info(verbosity, || {
+                format!("{:?} extracted. (symlink to {target:?})", strip_cur_dir(&file_path))
+            });
+
+            // Permissions and modification time would be applied to the target
+            unpacked_files += 1;
+            continue;
+        }
+
         match file.name().ends_with('/') {
             _is_dir @ true => {
                 // This is printed for every file in the archive and has little
                 // importance for most users, but would generate lots of
                 // spoken text for users using screen readers, braille displays
                 // and so on
-                if !quiet {
-                    info(format!("File {} extracted to \"{}\"", idx, file_path.display()));
-                }
+                file_info(verbosity, || {
+                    format!("File {} extracted to \"{}\"", idx, file_path.display())
+                });
                 fs::create_dir_all(&file_path)?;
             }
             _is_file @ false => {
-                if let Some(path) = file_path.parent() {
-                    if !path.exists() {
-                        fs::create_dir_all(path)?;
-                    }
+                let mtime = file
+                    .last_modified()
+                    .and_then(|time| OffsetDateTime::try_from(time).ok())
+                    .map(OffsetDateTime::unix_timestamp);
+                if selection.was_extracted(Path::new(file.name()), file.size(), mtime) {
+                    continue;
                 }
+
                 let file_path = strip_cur_dir(file_path.as_path());
 
                 // same reason is in _is_dir: long, often not needed text
-                if !quiet {
-                    info(format!(
-                        "{:?} extracted. ({})",
-                        file_path.display(),
-                        Bytes::new(file.size())
-                    ));
-                }
+                file_info(verbosity, || {
+                    format!("{:?} extracted. ({})", file_path.display(), Bytes::new(file.size()))
+                });
 
+                let writing = Writing::start(file_path);
                 let mut output_file = fs::File::create(file_path)?;
-                io::copy(&mut file, &mut output_file)?;
+                let name = PathBuf::from(file.name());
+                size_limit.copy(&name, &mut file, &mut output_file)?;
+                writing.finish();
 
-                set_last_modified_time(&file, file_path)?;
+                if preserve_mtime {
+                    set_last_modified_time(&file, file_path)?;
+                }
             }
         }
 
         #[cfg(unix)]
-        unix_set_permissions(&file_path, &file)?;
+        unix_set_permissions(&file_path, &file, preserve_permissions)?;
 
         unpacked_files += 1;
     }
@@ -91,6 +178,77 @@ where
     Ok(unpacked_files)
 }
 
+/// Warn about the first entry of `archive` whose sizes in the central directory look like a zip
+/// bomb's, and ask the user whether to extract it anyway
+fn check_compression_ratios<R>(
+    archive: &mut ZipArchive<R>,
+    archive_path: &Path,
+    question_policy: QuestionPolicy,
+) -> crate::Result<bool>
+where
+    R: Read + Seek,
+{
+    for idx in 0..archive.len() {
+        // Raw entries aren't decrypted, the sizes are readable without a password
+        let file = archive.by_index_raw(idx)?;
+        let ratio = file.size() / file.compressed_size().max(1);
+        if file.size() < SUSPICIOUS_MIN_SIZE || ratio <= SUSPICIOUS_RATIO {
+            continue;
+        }
+
+        // Locking necessary to guarantee that warning and question messages stay adjacent
+        let _locks = lock_and_flush_output_stdio();
+        warning(format!(
+            "The entry '{}' of '{}' decompresses into {}, {ratio} times its compressed size, like a zip bomb would",
+            file.name(),
+            EscapedPathDisplay::new(archive_path),
+            Bytes::new(file.size()),
+        ));
+        return user_wants_to_continue(archive_path, question_policy, QuestionAction::Decompression);
+    }
+    Ok(true)
+}
+
+/// Reads every entry of `archive` without writing them anywhere, failing if their CRC32 doesn't
+/// match their contents.
+///
+/// If an encrypted entry is found and no `password` was given, the user is asked for the
+/// password of `archive_path`.
+pub fn test_archive<R>(
+    mut archive: ZipArchive<R>,
+    archive_path: &Path,
+    password: Option<&str>,
+    question_policy: QuestionPolicy,
+) -> crate::Result<usize>
+where
+    R: Read + Seek,
+{
+    let mut password = password.map(str::to_owned);
+
+    for idx in 0..archive.len() {
+        if password.is_none() && archive.by_index_raw(idx)?.encrypted() {
+            password = Some(ask_for_password(archive_path, question_policy)?);
+        }
+
+        let mut file = match &password {
+            Some(password) => archive.by_index_decrypt(idx, password.as_bytes())?,
+            None => archive.by_index(idx)?,
+        };
+        io::copy(&mut file, &mut io::sink())?;
+    }
+
+    Ok(archive.len())
+}
+
+/// Comment of `archive`, if it has one
+pub fn archive_comment<R>(archive: &ZipArchive<R>) -> Option<String>
+where
+    R: Read + Seek,
+{
+    let comment = archive.comment();
+    (!comment.is_empty()).then(|| String::from_utf8_lossy(comment).into_owned())
+}
+
 /// List contents of `archive`, returning a vector of archive entries
 pub fn list_archive<R>(mut archive: ZipArchive<R>) -> impl Iterator<Item = crate::Result<FileInArchive>>
 where
@@ -109,15 +267,27 @@ where
     thread::spawn(move || {
         for idx in 0..archive.len() {
             let maybe_file_in_archive = (|| {
-                let file = match archive.by_index(idx) {
+                // Raw access skips decryption, names can be listed without a password
+                let file = match archive.by_index_raw(idx) {
                     Ok(f) => f,
                     Err(e) => return Some(Err(e.into())),
                 };
 
-                let path = file.enclosed_name()?.to_owned();
+                let path = file.enclosed_name()?;
                 let is_dir = file.is_dir();
-
-                Some(Ok(FileInArchive { path, is_dir }))
+                let modified = file
+                    .last_modified()
+                    .and_then(|time| OffsetDateTime::try_from(time).ok())
+                    .map(OffsetDateTime::unix_timestamp);
+
+                Some(Ok(FileInArchive {
+                    path,
+                    is_dir,
+                    size: Some(file.size()),
+                    compressed_size: Some(file.compressed_size()),
+                    modified,
+                    mode: file.unix_mode().map(|mode| mode & 0o7777),
+                }))
             })();
             if let Some(file_in_archive) = maybe_file_in_archive {
                 tx.send(file_in_archive).unwrap();
@@ -129,20 +299,53 @@ where
 }
 
 /// Compresses the archives given by `input_filenames` into the file given previously to `writer`.
+///
+/// If `password` is given, every file entry is encrypted with AES-256, `comment` is stored as
+/// the comment of the archive.
+///
+/// With `reproducible`, every entry has the same modification time, or 1980-01-01 if it's older
+/// than zip can store, and normalized permissions.
+///
+/// With `skip_errors`, files and directories that can't be read are left out.
+#[allow(clippy::too_many_arguments)]
 pub fn build_archive_from_paths<W>(
     input_filenames: &[PathBuf],
     output_path: &Path,
     writer: W,
     file_visibility_policy: FileVisibilityPolicy,
-    quiet: bool,
+    entry_base: &EntryBase,
+    password: Option<&str>,
+    comment: Option<&str>,
+    reproducible: Option<Reproducible>,
+    skip_errors: Option<&SkippedFiles>,
+    progress: &Progress,
+    verbosity: Verbosity,
 ) -> crate::Result<W>
 where
     W: Write + Seek,
 {
     let mut writer = zip::ZipWriter::new(writer);
+    if let Some(comment) = comment {
+        writer.set_comment(comment);
+    }
     // always use ZIP64 to allow compression of files larger than 4GB
     // the format is widely supported and the extra 20B is negligible in most cases
-    let options = zip::write::FileOptions::default().large_file(true);
+    let options = zip::write::SimpleFileOptions::default().large_file(true);
+    let options = match password {
+        Some(password) => options.with_aes_encryption(AesMode::Aes256, password),
+        None => options,
+    };
+    // Directories and symlinks are otherwise modified now
+    let fixed_mtime = reproducible.map(|reproducible| {
+        OffsetDateTime::from_unix_timestamp(reproducible.mtime as i64)
+            .ok()
+            .and_then(|time| DateTime::try_from(time).ok())
+            .unwrap_or_default()
+    });
+    let options = match fixed_mtime {
+        Some(mtime) => options.last_modified_time(mtime),
+        None => options,
+    };
     let output_handle = Handle::from_path(output_path);
 
     #[cfg(not(unix))]
@@ -163,15 +366,19 @@ where
     }
 
     for filename in input_filenames {
-        let previous_location = cd_into_same_dir_as(filename)?;
-
-        // Unwrap safety:
-        //   paths should be canonicalized by now, and the root directory rejected.
-        let filename = filename.file_name().unwrap();
+        let previous_location = cd_into_entry_base(filename, entry_base)?;
+        let filename = entry_base.relative_path(filename)?;
 
         for entry in file_visibility_policy.build_walker(filename) {
-            let entry = entry?;
+            let Some(entry) = skip_error(entry, skip_errors)? else {
+                continue;
+            };
             let path = entry.path();
+            // The input is walked from "." when it's the base directory, which has no entry itself
+            let path = path.strip_prefix(".").unwrap_or(path);
+            if path.as_os_str().is_empty() {
+                continue;
+            }
 
             // If the output_path is the same as the input file, warn the user and skip the input (in order to avoid compression recursion)
             if let Ok(handle) = &output_handle {
@@ -187,8 +394,22 @@ where
             // little importance for most users, but would generate lots of
             // spoken text for users using screen readers, braille displays
             // and so on
-            if !quiet {
-                info(format!("Compressing '{}'.", EscapedPathDisplay::new(path)));
+            log_compressing(path, path, verbosity);
+
+            let entry_name = path.to_str().ok_or_else(|| {
+                FinalError::with_title("Zip requires that all directories names are valid UTF-8")
+                    .detail(format!("File at '{path:?}' has a non-UTF-8 name"))
+            })?;
+
+            if entry.path_is_symlink() && !file_visibility_policy.follows_links() {
+                // Stored as a link to the same target, instead of a copy of it
+                let target = fs::read_link(path)?;
+                let target = target.to_str().ok_or_else(|| {
+                    FinalError::with_title("Zip requires that all symlink targets are valid UTF-8")
+                        .detail(format!("Symlink at '{path:?}' has a non-UTF-8 target"))
+                })?;
+                writer.add_symlink(entry_name, target, options)?;
+                continue;
             }
 
             let metadata = match path.metadata() {
@@ -204,12 +425,13 @@ where
             };
 
             #[cfg(unix)]
-            let options = options.unix_permissions(metadata.permissions().mode());
-
-            let entry_name = path.to_str().ok_or_else(|| {
-                FinalError::with_title("Zip requires that all directories names are valid UTF-8")
-                    .detail(format!("File at '{path:?}' has a non-UTF-8 name"))
-            })?;
+            let options = {
+                let mode = metadata.permissions().mode();
+                match reproducible {
+                    Some(_) => options.unix_permissions(Reproducible::mode(metadata.is_dir(), mode)),
+                    None => options.unix_permissions(mode),
+                }
+            };
 
             if metadata.is_dir() {
                 writer.add_directory(entry_name, options)?;
@@ -221,13 +443,16 @@ where
                     options
                 };
 
-                let mut file = fs::File::open(path)?;
+                let Some(file) = skip_error(fs::File::open(path), skip_errors)? else {
+                    continue;
+                };
 
                 // Updated last modified time
-                let last_modified_time = options.last_modified_time(get_last_modified_time(&file));
+                let last_modified_time =
+                    options.last_modified_time(fixed_mtime.unwrap_or_else(|| get_last_modified_time(&file)));
 
                 writer.start_file(entry_name, last_modified_time)?;
-                io::copy(&mut file, &mut writer)?;
+                io::copy(&mut progress.wrap_read(file), &mut writer)?;
             }
         }
 
@@ -264,9 +489,7 @@ fn get_last_modified_time(file: &fs::File) -> DateTime {
 }
 
 fn set_last_modified_time(zip_file: &ZipFile, path: &Path) -> crate::Result<()> {
-    let modification_time = zip_file.last_modified().to_time();
-
-    let Ok(time_in_seconds) = modification_time else {
+    let Some(Ok(time_in_seconds)) = zip_file.last_modified().map(OffsetDateTime::try_from) else {
         return Ok(());
     };
 
@@ -279,10 +502,11 @@ fn set_last_modified_time(zip_file: &ZipFile, path: &Path) -> crate::Result<()>
 }
 
 #[cfg(unix)]
-fn unix_set_permissions(file_path: &Path, file: &ZipFile) -> crate::Result<()> {
+fn unix_set_permissions(file_path: &Path, file: &ZipFile, preserve_permissions: bool) -> crate::Result<()> {
     use std::fs::Permissions;
 
     if let Some(mode) = file.unix_mode() {
+        let mode = utils::extracted_mode(mode, file.is_dir(), preserve_permissions);
         fs::set_permissions(file_path, Permissions::from_mode(mode))?;
     }
 
@@ -8,12 +8,20 @@ use std::{
     path::{Path, PathBuf},
 };
 
+use fs_err as fs;
+
 use crate::{
-    error::FinalError,
-    extension::{build_archive_file_suggestion, Extension, PRETTY_SUPPORTED_ALIASES, PRETTY_SUPPORTED_EXTENSIONS},
+    archive::{tar::Ownership, EntryBase},
+    error::{Error, FinalError},
+    extension::{
+        build_archive_file_suggestion, CompressionFormat, Extension, PRETTY_SUPPORTED_ALIASES,
+        PRETTY_SUPPORTED_EXTENSIONS,
+    },
     utils::{
+        is_stdio, is_symlink,
         logger::{info_accessible, warning},
-        pretty_format_list_of_paths, try_infer_extension, user_wants_to_continue, EscapedPathDisplay,
+        pretty_format_list_of_paths, sniff_unknown_content, strip_cur_dir, try_infer_extension, user_wants_to_continue,
+        EscapedPathDisplay, UnknownContent,
     },
     QuestionAction, QuestionPolicy, Result,
 };
@@ -95,15 +103,21 @@ pub fn check_for_non_archive_formats(files: &[PathBuf], formats: &[Vec<Extension
                 pretty_format_list_of_paths(&not_archives)
             ));
 
-        return Err(error.into());
+        return Err(Error::InvalidUsage { reason: error });
     }
 
     Ok(())
 }
 
 /// Show error if archive format is not the first format in the chain.
+///
+/// Tar is the exception, other archives can be nested in it, like in "archive.zip.tar".
 pub fn check_archive_formats_position(formats: &[Extension], output_path: &Path) -> Result<()> {
-    if let Some(format) = formats.iter().skip(1).find(|format| format.is_archive()) {
+    if let Some(format) = formats
+        .iter()
+        .skip(1)
+        .find(|format| format.is_archive() && format.compression_formats[0] != CompressionFormat::Tar)
+    {
         let error = FinalError::with_title(format!(
             "Cannot compress to '{}'.",
             EscapedPathDisplay::new(output_path)
@@ -121,8 +135,353 @@ pub fn check_archive_formats_position(formats: &[Extension], output_path: &Path)
             EscapedPathDisplay::new(output_path)
         ));
 
-        return Err(error.into());
+        return Err(Error::InvalidUsage { reason: error });
+    }
+    Ok(())
+}
+
+/// Check if the compression level passed to `--level` is valid for every format in the chain.
+pub fn check_compression_level(formats: &[Extension], level: i16) -> Result<()> {
+    for extension in formats {
+        for format in extension.compression_formats {
+            let Some(range) = format.compression_level_range() else {
+                continue;
+            };
+
+            if !range.contains(&level) {
+                let error = FinalError::with_title(format!("Invalid compression level '{level}' for '{extension}'."))
+                    .detail(format!(
+                        "The compression level of '{extension}' ranges from {} to {}.",
+                        range.start(),
+                        range.end()
+                    ))
+                    .hint("Use '--fast' or '--slow' to pick the lowest or highest level of every format.");
+
+                return Err(Error::InvalidUsage { reason: error });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Warn if zstd specific options were given, but no output format uses zstd.
+///
+/// `threads` is whether `--threads` was given for a single output, otherwise it also sizes the
+/// pool that compresses or decompresses each input in parallel.
+pub fn check_zstd_options(formats: &[Extension], zstd_long: bool, zstd_dict: bool, threads: bool) {
+    let uses_zstd = formats
+        .iter()
+        .any(|extension| extension.compression_formats.contains(&CompressionFormat::Zstd));
+
+    if zstd_long && !uses_zstd {
+        warning("The '--zstd-long' flag only applies to zstd, it will be ignored.".to_string());
+    }
+    if zstd_dict && !uses_zstd {
+        warning("The '--zstd-dict' flag only applies to zstd, it will be ignored.".to_string());
+    }
+    if threads && !uses_zstd {
+        warning(
+            "The '--threads' flag only applies to zstd when compressing into a single file, it will be ignored."
+                .to_string(),
+        );
+    }
+}
+
+/// Warn if a password was given, but no format uses zip, the only one that supports encryption.
+pub fn check_password_options(formats: &[Extension], has_password: bool) {
+    let uses_zip = formats
+        .iter()
+        .any(|extension| extension.compression_formats.contains(&CompressionFormat::Zip));
+
+    if has_password && !uses_zip {
+        warning("The '--password' flag only applies to zip, it will be ignored.".to_string());
+    }
+}
+
+/// Warn if a comment was given, but no format uses zip, the only one that stores it.
+pub fn check_comment_options(formats: &[Extension], has_comment: bool) {
+    let uses_zip = formats
+        .iter()
+        .any(|extension| extension.compression_formats.contains(&CompressionFormat::Zip));
+
+    if has_comment && !uses_zip {
+        warning("The '--comment' flag only applies to zip, it will be ignored.".to_string());
+    }
+}
+
+/// Warn if `--sparse` was given, but no format is tar, the only one that stores holes.
+pub fn check_sparse_options(formats: &[Extension], sparse: bool) {
+    let uses_tar = formats
+        .iter()
+        .any(|extension| extension.compression_formats.contains(&CompressionFormat::Tar));
+
+    if sparse && !uses_tar {
+        warning("The '--sparse' flag only applies to tar, it will be ignored.".to_string());
+    }
+}
+
+/// Warn if `--xattrs` was given, but no format is tar, the only one that stores extended
+/// attributes, or the platform doesn't have them.
+pub fn check_xattrs_options(formats: &[Extension], xattrs: bool) {
+    let uses_tar = formats
+        .iter()
+        .any(|extension| extension.compression_formats.contains(&CompressionFormat::Tar));
+
+    if xattrs && !uses_tar {
+        warning("The '--xattrs' flag only applies to tar, it will be ignored.".to_string());
+    } else if xattrs && !cfg!(unix) {
+        warning("Extended attributes aren't supported on this platform, '--xattrs' will be ignored.".to_string());
+    }
+}
+
+/// Warn if `--reproducible` was given, but the output is a 7z archive, whose entries keep the
+/// metadata of their files.
+pub fn check_reproducible_options(formats: &[Extension], reproducible: bool) {
+    let uses_sevenz = formats
+        .iter()
+        .any(|extension| extension.compression_formats.contains(&CompressionFormat::SevenZip));
+
+    if reproducible && uses_sevenz {
+        warning(
+            "The '--reproducible' flag doesn't apply to 7z, its entries keep the metadata of their files.".to_string(),
+        );
+    }
+}
+
+/// Warn if `--base-dir` or `--absolute-paths` was given, but no format is an archive, the only
+/// ones with entry names.
+pub fn check_entry_base_options(formats: &[Extension], entry_base: &EntryBase) {
+    let uses_archive = formats.iter().any(Extension::is_archive);
+
+    if *entry_base != EntryBase::Parent && !uses_archive {
+        warning(
+            "The '--base-dir' and '--absolute-paths' flags only apply to archives, they will be ignored.".to_string(),
+        );
+    }
+}
+
+/// Warn if `--include` was given, but no format is an archive, the only ones with entries to match.
+pub fn check_include_options(formats: &[Extension], has_include: bool) {
+    let uses_archive = formats.iter().any(Extension::is_archive);
+
+    if has_include && !uses_archive {
+        warning("The '--include' flag only applies to archives, it will be ignored.".to_string());
+    }
+}
+
+/// Check that `--entry` can be used with the inputs, which have to be files, as their entries are
+/// listed before they're extracted. Warn if no format is an archive, like with `--include`.
+pub fn check_entry_options(files: &[PathBuf], formats: &[Extension], has_entry: bool) -> Result<()> {
+    if !has_entry {
+        return Ok(());
+    }
+
+    if files.iter().any(|path| is_stdio(path)) {
+        return Err(Error::InvalidUsage {
+            reason: FinalError::with_title("Cannot pick the entries of stdin with --entry")
+                .detail(
+                    "The entries are found by listing the archive before extracting it, stdin can only be read once",
+                )
+                .hint("Save the archive to a file first, or pick the entries with --include"),
+        });
+    }
+
+    if !formats.iter().any(Extension::is_archive) {
+        warning("The '--entry' flag only applies to archives, it will be ignored.".to_string());
+    }
+
+    Ok(())
+}
+
+/// Warn about `--preserve-owner` and `--numeric-owner` when they can't be applied, returning who
+/// owns the extracted files
+pub fn check_owner_options(formats: &[Extension], ownership: Ownership) -> Ownership {
+    if ownership == Ownership::Current {
+        return ownership;
+    }
+
+    if !formats
+        .iter()
+        .any(|extension| extension.compression_formats.contains(&CompressionFormat::Tar))
+    {
+        warning("The owner of extracted files can only be restored from tar archives, it will be ignored.".to_string());
+        return Ownership::Current;
+    }
+
+    if cfg!(not(unix)) {
+        warning("The owner of extracted files can only be restored on Unix, it will be ignored.".to_string());
+        return Ownership::Current;
+    }
+
+    #[cfg(unix)]
+    if !crate::archive::tar::can_change_owner() {
+        warning(
+            "Restoring the owner of extracted files requires root, they will be owned by the current user.".to_string(),
+        );
+        return Ownership::Current;
+    }
+
+    ownership
+}
+
+/// Check that every input to compress exists, reporting all of the missing ones at once.
+pub fn check_inputs_exist(files: &[PathBuf]) -> Result<()> {
+    let missing: Vec<_> = files
+        .iter()
+        .filter(|path| !is_stdio(path) && !path.exists() && !is_symlink(path))
+        .collect();
+
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    let title = if missing.len() == 1 {
+        "An input file does not exist".to_string()
+    } else {
+        format!("{} input files do not exist", missing.len())
+    };
+
+    let mut error = FinalError::with_title(title);
+    for path in missing {
+        error = error.detail(format!("'{}'", EscapedPathDisplay::new(path)));
+    }
+
+    Err(Error::MissingInputs { reason: error })
+}
+
+/// Check that stdin, given as `-`, is the only input and is only compressed with non-archive
+/// formats, as archives need a name for each one of their entries.
+pub fn check_stdin_input(files: &[PathBuf], formats: &[Extension]) -> Result<()> {
+    if !files.iter().any(|path| is_stdio(path)) {
+        return Ok(());
+    }
+
+    let error = FinalError::with_title("Cannot compress stdin");
+    let error = if files.len() > 1 {
+        error.detail("Stdin ('-') must be the only input")
+    } else if formats.first().is_some_and(Extension::is_archive) {
+        error
+            .detail("Archive formats need a name for each of their entries, which stdin doesn't have")
+            .hint("Use a non-archive format like gz or zst, or save the input to a file first")
+    } else {
+        return Ok(());
+    };
+
+    Err(Error::InvalidUsage { reason: error })
+}
+
+/// Check that `--to-stdout` writes a single file, so a single input is decompressed, and
+/// archives have `--include` or `--entry` to pick one of their entries.
+pub fn check_to_stdout(files: &[PathBuf], formats: &[Vec<Extension>], picks_entries: bool) -> Result<()> {
+    if files.len() > 1 {
+        return Err(Error::InvalidUsage {
+            reason: FinalError::with_title("Cannot decompress multiple files to stdout")
+                .detail("Only one file can be decompressed with --to-stdout")
+                .hint("Decompress each file on its own, one after the other"),
+        });
+    }
+
+    let is_archive = formats.iter().any(|formats| formats.iter().any(Extension::is_archive));
+    if is_archive && !picks_entries {
+        let error = FinalError::with_title("Cannot write an archive to stdout")
+            .detail("Archives can hold many files, but only one can be written to stdout")
+            .hint("Pick the entry to write with --include or --entry, like '--include docs/README.md' or '--entry 3'")
+            .hint("Or use 'ouch list' to see what's inside of the archive")
+            .hint("To write the whole archive, without its compression, use --raw");
+
+        return Err(Error::InvalidUsage { reason: error });
+    }
+
+    Ok(())
+}
+
+/// Check that deleting the inputs with `--remove`, after compressing them, doesn't lose data.
+///
+/// Every file inside of the inputs must be archived, so none can be left out by filters, and
+/// `output_dir`, where the outputs are written, can't be inside of an input. It's `None` for stdout.
+pub fn check_remove_inputs(files: &[PathBuf], output_dir: Option<&Path>, files_are_filtered: bool) -> Result<()> {
+    if files_are_filtered && files.iter().any(|path| path.is_dir()) {
+        return Err(Error::InvalidUsage {
+            reason: FinalError::with_title("Cannot remove the inputs after compressing them")
+                .detail("Files left out by --hidden, --gitignore, --exclude, the size, time and depth limits or --no-recursive would be deleted with their directories")
+                .hint("Remove the inputs yourself after checking the archive"),
+        });
+    }
+
+    // The output directory might not exist yet, its closest existing ancestor is checked instead
+    let Some(output_dir) = output_dir.and_then(|output_dir| {
+        Path::new(".")
+            .join(output_dir)
+            .ancestors()
+            .find_map(|ancestor| fs::canonicalize(ancestor).ok())
+    }) else {
+        return Ok(());
+    };
+
+    if let Some(input) = files.iter().find(|path| output_dir.starts_with(path)) {
+        return Err(Error::InvalidUsage {
+            reason: FinalError::with_title(format!(
+                "Cannot remove '{}' after compressing it",
+                EscapedPathDisplay::new(input)
+            ))
+            .detail("The output is written inside of it, and would be deleted too")
+            .hint("Write the output somewhere else, or drop --remove"),
+        });
+    }
+
+    Ok(())
+}
+
+/// Check that no format needs seeking, which isn't possible when reading from stdin or writing to
+/// stdout, as they can be pipes.
+///
+/// `stream` is the name of the stream used, for the error message.
+pub fn check_streamed_formats(formats: &[Extension], stream: &str) -> Result<()> {
+    let Some(format) = formats
+        .iter()
+        .flat_map(|extension| extension.compression_formats)
+        .find(|format| matches!(format, CompressionFormat::Zip | CompressionFormat::SevenZip))
+    else {
+        return Ok(());
+    };
+
+    let format = match format {
+        CompressionFormat::Zip => "zip",
+        _ => "7z",
+    };
+
+    Err(Error::InvalidUsage {
+        reason: FinalError::with_title(format!("Cannot use {format} with {stream}"))
+            .detail(format!(
+                "{format} archives need to be seekable, which {stream} might not be"
+            ))
+            .hint("Use a file instead, or a format that supports streaming, like tar.gz"),
+    })
+}
+
+/// Check that `--append` can add entries to `output_path`, which has to be a plain tar archive,
+/// as compressed streams can't be continued without compressing them again.
+pub fn check_append(formats: &[Extension], output_path: &Path) -> Result<()> {
+    let output_name = EscapedPathDisplay::new(output_path);
+
+    if is_stdio(output_path) || output_path.is_dir() {
+        return Err(Error::InvalidUsage {
+            reason: FinalError::with_title("Cannot use --append with this output")
+                .detail("Only an existing archive file can be appended to"),
+        });
+    }
+
+    let is_plain_tar = matches!(formats, [extension] if extension.compression_formats == [CompressionFormat::Tar]);
+    if !is_plain_tar {
+        return Err(Error::InvalidUsage {
+            reason: FinalError::with_title(format!("Cannot append to '{output_name}'"))
+                .detail("Only plain '.tar' archives can be appended to")
+                .detail("Compressed archives like '.tar.gz' would have to be decompressed and compressed again")
+                .hint("Compress into a '.tar' archive to append to it later, and compress it at the end"),
+        });
     }
+
     Ok(())
 }
 
@@ -139,6 +498,18 @@ pub fn check_missing_formats_when_decompressing(files: &[PathBuf], formats: &[Ve
         return Ok(());
     }
 
+    // Their contents were already checked for a format, files with any are most likely not compressed
+    let contents: Option<Vec<_>> = files_with_broken_extension
+        .iter()
+        .map(|path| match is_stdio(path) {
+            true => None,
+            false => sniff_unknown_content(path).map(|content| (*path, content)),
+        })
+        .collect();
+    if let Some(contents) = contents {
+        return Err(not_compressed_error(&contents));
+    }
+
     let (files_with_unsupported_extensions, files_missing_extension): (Vec<&PathBuf>, Vec<&PathBuf>) =
         files_with_broken_extension
             .iter()
@@ -176,22 +547,44 @@ pub fn check_missing_formats_when_decompressing(files: &[PathBuf], formats: &[Ve
             ));
     }
 
-    Err(error.into())
+    Err(Error::InvalidUsage { reason: error })
+}
+
+/// Error for decompressing files whose contents are in no format `ouch` knows, saying what they
+/// seem to hold instead
+fn not_compressed_error(contents: &[(&PathBuf, UnknownContent)]) -> Error {
+    let title = match contents {
+        [_] => "This doesn't look like a compressed file",
+        _ => "These don't look like compressed files",
+    };
+    let mut error = FinalError::with_title(title);
+    for (path, content) in contents {
+        let content = match content {
+            UnknownContent::Text => "is plain text",
+            UnknownContent::Binary => "holds data in no format ouch knows",
+        };
+        error = error.detail(format!("'{}' {content}", EscapedPathDisplay::new(strip_cur_dir(path))));
+    }
+
+    let error = error
+        .hint("ouch decompresses archives and compressed files, like 'photos.zip' or 'notes.txt.gz'")
+        .hint("If it is compressed, pass its format to '--format', like '--format tar.gz'");
+    Error::InvalidUsage { reason: error }
 }
 
 /// Check if there is a first format when compressing, and returns it.
 pub fn check_first_format_when_compressing<'a>(formats: &'a [Extension], output_path: &Path) -> Result<&'a Extension> {
     formats.first().ok_or_else(|| {
         let output_path = EscapedPathDisplay::new(output_path);
-        FinalError::with_title(format!("Cannot compress to '{output_path}'."))
+        let reason = FinalError::with_title(format!("Cannot compress to '{output_path}'."))
             .detail("You shall supply the compression format")
             .hint("Try adding supported extensions (see --help):")
             .hint(format!("  ouch compress <FILES>... {output_path}.tar.gz"))
             .hint(format!("  ouch compress <FILES>... {output_path}.zip"))
             .hint("")
             .hint("Alternatively, you can overwrite this option by using the '--format' flag:")
-            .hint(format!("  ouch compress <FILES>... {output_path} --format tar.gz"))
-            .into()
+            .hint(format!("  ouch compress <FILES>... {output_path} --format tar.gz"));
+        Error::InvalidUsage { reason }
     })
 }
 
@@ -252,5 +645,5 @@ pub fn check_invalid_compression_with_non_archive_format(
         .hint(from_hint)
         .hint(to_hint);
 
-    Err(error.into())
+    Err(Error::InvalidUsage { reason: error })
 }
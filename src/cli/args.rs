@@ -1,11 +1,15 @@
-use std::{ffi::OsString, path::PathBuf};
+use std::{
+    ffi::OsString,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
 
 use clap::{Parser, ValueHint};
 
 // Ouch command line options (docstrings below are part of --help)
 /// A command-line utility for easily compressing and decompressing files and directories.
 ///
-/// Supported formats: tar, zip, gz, 7z, xz/lzma, bz/bz2, lz4, sz (Snappy), zst and rar.
+/// Supported formats: tar, zip, cpio, gz, 7z, xz, lzma, lz (lzip), bz/bz2, lz4, sz (Snappy), zst and rar.
 ///
 /// Repository: https://github.com/ouch-org/ouch
 #[derive(Parser, Debug, PartialEq)]
@@ -14,13 +18,28 @@ use clap::{Parser, ValueHint};
 #[allow(rustdoc::bare_urls)]
 pub struct CliArgs {
     /// Skip [Y/n] questions positively
-    #[arg(short, long, conflicts_with = "no", global = true)]
+    #[arg(
+        short,
+        long,
+        env = "OUCH_YES",
+        value_parser = clap::builder::FalseyValueParser::new(),
+        conflicts_with = "no",
+        global = true
+    )]
     pub yes: bool,
 
     /// Skip [Y/n] questions negatively
     #[arg(short, long, global = true)]
     pub no: bool,
 
+    /// Always overwrite existing output files, whatever --yes and --no say
+    #[arg(long, conflicts_with = "no_clobber", global = true)]
+    pub overwrite: bool,
+
+    /// Never overwrite existing output files, skipping them, whatever --yes and --no say
+    #[arg(long, global = true)]
+    pub no_clobber: bool,
+
     /// Activate accessibility mode, reducing visual noise
     #[arg(short = 'A', long, env = "ACCESSIBLE", global = true)]
     pub accessible: bool,
@@ -29,39 +48,102 @@ pub struct CliArgs {
     #[arg(short = 'H', long, global = true)]
     pub hidden: bool,
 
-    /// Silences output
+    /// Silences output, only errors and questions are printed
     #[arg(short = 'q', long, global = true)]
     pub quiet: bool,
 
-    /// Ignores files matched by git's ignore files
+    /// List each file as it's compressed or extracted, even in accessibility mode, instead of a
+    /// progress bar, repeat it to also print the sizes of the files compressed
+    #[arg(short = 'v', long, action = clap::ArgAction::Count, conflicts_with = "quiet", global = true)]
+    pub verbose: u8,
+
+    /// Ignores files matched by .gitignore, .ignore and the global gitignore files
     #[arg(short = 'g', long, global = true)]
     pub gitignore: bool,
 
+    /// Expand glob patterns like '*.txt' in the input paths that don't name a file, for shells
+    /// that leave them as they are. Always done on Windows
+    #[arg(long, global = true)]
+    pub glob: bool,
+
     /// Specify the format of the archive
+    ///
+    /// Raw deflate streams, with or without a zlib wrapper, have no extension and can only be
+    /// chosen here, as 'deflate' and 'zlib'
     #[arg(short, long, global = true)]
     pub format: Option<OsString>,
 
+    /// Number of threads to compress with, defaults to the number of logical CPUs
+    ///
+    /// Files compressed into a directory or with --each, and archives being decompressed, are
+    /// handled in parallel, and zstd switches to multithreaded compression when this is set
+    #[arg(
+        short = 'j',
+        long,
+        env = "OUCH_THREADS",
+        global = true,
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    pub threads: Option<u32>,
+
+    /// Ids of the flags set by their environment variable or the config file instead of the
+    /// command line
+    #[arg(skip)]
+    pub defaults: Vec<String>,
+
     // Ouch and claps subcommands
     #[command(subcommand)]
     pub cmd: Subcommand,
 }
 
+// Parsed once, boxing the options of `Compress` would only make them harder to match on
 #[derive(Parser, PartialEq, Eq, Debug)]
-#[allow(rustdoc::bare_urls)]
+#[allow(rustdoc::bare_urls, clippy::large_enum_variant)]
 pub enum Subcommand {
     /// Compress one or more files into one output file
-    #[command(visible_alias = "c")]
+    // Inputs can be omitted when given by --files-from or --files-from-stdin0
+    #[command(visible_alias = "c", allow_missing_positional = true)]
     Compress {
-        /// Files to be compressed
-        #[arg(required = true, value_hint = ValueHint::FilePath)]
+        /// Files to be compressed, or '-' to read from stdin
+        #[arg(required_unless_present_any = ["files_from", "files_from_stdin0", "each"], value_hint = ValueHint::FilePath)]
         files: Vec<PathBuf>,
 
-        /// The resulting file. Its extensions can be used to specify the compression formats
+        /// The resulting file. Its extensions can be used to specify the compression formats.
+        /// If it's a directory, each file is compressed separately into it, using --format.
+        /// If it's '-', the result is written to stdout. With --each, it's one more input
         #[arg(required = true, value_hint = ValueHint::FilePath)]
         output: PathBuf,
 
-        /// Compression level, applied to all formats
-        #[arg(short, long, group = "compression-level")]
+        /// Place the output in this directory, creating it if needed, instead of the current one
+        #[arg(long, value_hint = ValueHint::DirPath)]
+        output_dir: Option<PathBuf>,
+
+        /// Compress each input into its own archive, named after it with the extensions of
+        /// --format, like 'site1.tar.gz', in the current directory or --output-dir. Every path
+        /// given is an input
+        #[arg(long, conflicts_with_all = ["append", "split_size", "checksum"])]
+        each: bool,
+
+        /// Also compress the paths listed in this file, one per line, or read them from stdin if it's '-'
+        #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+        files_from: Option<PathBuf>,
+
+        /// Paths in --files-from are separated by NUL characters instead of newlines, like `find -print0`
+        #[arg(long, requires = "files_from")]
+        null: bool,
+
+        /// Also compress the paths read from stdin, separated by NUL characters, like
+        /// `find -print0 | ouch compress --files-from-stdin0 out.tar.gz`. Same as `--files-from - --null`
+        #[arg(long, conflicts_with_all = ["files_from", "null"])]
+        files_from_stdin0: bool,
+
+        /// Listed paths that couldn't be read, they're left out and reported after compressing
+        #[arg(skip)]
+        unreadable_listed: Vec<(PathBuf, String)>,
+
+        /// Compression level, applied to all formats, errors if out of range for any of them.
+        /// Levels from OUCH_LEVEL or the config file are clamped to the range of each format instead
+        #[arg(short, long, env = "OUCH_LEVEL", group = "compression-level")]
         level: Option<i16>,
 
         /// Fastest compression level possible,
@@ -73,17 +155,287 @@ pub enum Subcommand {
         /// conflicts with --level and --fast
         #[arg(long, group = "compression-level")]
         slow: bool,
+
+        /// Enable zstd's long distance matching, improves compression of large inputs
+        #[arg(long)]
+        zstd_long: bool,
+
+        /// Compress zstd frames with this dictionary, made by `ouch train-dict`, the same one is
+        /// needed to decompress them
+        #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+        zstd_dict: Option<PathBuf>,
+
+        /// Encrypt zip archives with this password, using AES-256
+        #[arg(short, long, conflicts_with_all = ["password_stdin", "password_file"])]
+        password: Option<String>,
+
+        /// Read the zip archive password from the first line of stdin
+        #[arg(long, conflicts_with = "password_file")]
+        password_stdin: bool,
+
+        /// Read the zip archive password from the first line of this file, keeping it out of the
+        /// process list
+        #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+        password_file: Option<PathBuf>,
+
+        /// Comment stored in zip archives, shown when listing them
+        #[arg(long, value_name = "TEXT")]
+        comment: Option<String>,
+
+        /// Skip paths matching this glob, relative to each input, can be repeated
+        #[arg(long, value_name = "GLOB")]
+        exclude: Vec<String>,
+
+        /// Skip files larger than this size, accepts the units KiB, MiB, GiB and TiB
+        #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+        max_size: Option<u64>,
+
+        /// Skip files smaller than this size, accepts the units KiB, MiB, GiB and TiB
+        #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+        min_size: Option<u64>,
+
+        /// Only compress files modified after this time, like '2024-01-01' (UTC), '2024-01-01T12:00:00+02:00',
+        /// or '7d' for a week ago, accepts the units s, m, h, d and w
+        #[arg(long, value_name = "TIME", value_parser = parse_time)]
+        newer_than: Option<SystemTime>,
+
+        /// Only go this many levels deep into the input directories, 1 compresses what's right
+        /// inside of them, without descending into their subdirectories
+        #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+        max_depth: Option<u32>,
+
+        /// Only compress the files right inside of the input directories, leaving out their
+        /// subdirectories, which --max-depth 1 would keep empty
+        #[arg(long, conflicts_with = "max_depth")]
+        no_recursive: bool,
+
+        /// Follow symlinks, compressing the files they point to instead of the links themselves
+        #[arg(long, conflicts_with_all = ["no_dereference", "skip_symlinks"])]
+        dereference: bool,
+
+        /// Store symlinks as links, the default, only tar and zip support it, other formats follow them
+        #[arg(long, conflicts_with = "skip_symlinks")]
+        no_dereference: bool,
+
+        /// Leave the symlinks found in directories out of the archive
+        #[arg(long)]
+        skip_symlinks: bool,
+
+        /// Split the output into volumes of at most this size, like 'out.tar.gz.001', 'out.tar.gz.002'...
+        ///
+        /// Accepts the units KiB, MiB, GiB and TiB, bytes if none is given
+        #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+        split_size: Option<u64>,
+
+        /// List the files that would be compressed and their total size, without writing anything
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Write the digest of the output to a sibling file, like 'archive.tar.gz.sha256'
+        #[arg(long, value_enum, value_name = "ALGORITHM")]
+        checksum: Option<ChecksumAlgorithm>,
+
+        /// Delete the input files after they were compressed successfully
+        #[arg(long)]
+        remove: bool,
+
+        /// Order of the files found in directories, 'name' makes archives reproducible
+        #[arg(long, value_enum, default_value_t = SortOrder::None)]
+        sort: SortOrder,
+
+        /// Give the same output for the same inputs, with files sorted by name unless --sort is
+        /// given, owners set to 0, normalized permissions and fixed modification times
+        #[arg(long, conflicts_with_all = ["password", "password_stdin", "password_file"])]
+        reproducible: bool,
+
+        /// Modification time of the entries with --reproducible, like '1700000000' (seconds since
+        /// the Unix epoch) or '2024-01-01', the epoch by default
+        #[arg(long, value_name = "TIMESTAMP", value_parser = parse_timestamp, requires = "reproducible")]
+        mtime: Option<u64>,
+
+        /// Name the archive entries relative to this directory, which must have every input in it,
+        /// instead of the parent of each input
+        #[arg(long, value_name = "DIR", value_hint = ValueHint::DirPath, conflicts_with = "absolute_paths")]
+        base_dir: Option<PathBuf>,
+
+        /// Find the inputs in this directory, like 'tar -C', and name the archive entries relative
+        /// to it. The output path is still relative to the current directory
+        #[arg(
+            short = 'C',
+            long,
+            value_name = "DIR",
+            value_hint = ValueHint::DirPath,
+            conflicts_with_all = ["base_dir", "absolute_paths"]
+        )]
+        change_dir: Option<PathBuf>,
+
+        /// Store the holes of sparse files, like disk images, without the zeros they read as, only
+        /// in '.tar' archives. Unpacking them recreates the holes. Files of 8 GiB or more are
+        /// stored whole, with a warning
+        #[arg(long)]
+        sparse: bool,
+
+        /// Store the extended attributes of files and directories, like POSIX ACLs on Linux, only
+        /// in '.tar' archives. Decompress with --xattrs to restore them
+        #[arg(long)]
+        xattrs: bool,
+
+        /// Name the archive entries with the whole paths of the inputs, without their leading '/'
+        #[arg(long)]
+        absolute_paths: bool,
+
+        /// Add the inputs to the end of an existing '.tar' output, instead of replacing it
+        #[arg(long, conflicts_with = "split_size")]
+        append: bool,
+
+        /// Only warn about compressing '.zip' or '.7z' chained with other formats in memory when the
+        /// inputs are larger than this size, half of the available memory by default
+        #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+        in_memory_limit: Option<u64>,
+
+        /// When the output is a plain '.tar' or '.cpio' archive, compress it too, adding '.zst' to its name
+        #[arg(long, conflicts_with = "append")]
+        auto_compress: bool,
+
+        /// With --auto-compress, pick the format with the best ratio on a sample of the inputs,
+        /// among gz, bz2, xz and zst
+        #[arg(long, requires = "auto_compress")]
+        best: bool,
+
+        /// Leave out the files that can't be read, like ones without read permission, instead of
+        /// failing, and say how many were skipped at the end. With --remove, the inputs are kept
+        /// if any file was skipped
+        #[arg(long)]
+        skip_errors: bool,
+
+        /// Run this shell command after compressing successfully, with the path of the output in
+        /// the OUCH_OUTPUT environment variable
+        #[arg(long, value_name = "CMD")]
+        on_success: Option<String>,
+
+        /// Run this shell command if compressing fails or is declined, with the path of the output
+        /// in OUCH_OUTPUT and the exit code in OUCH_EXIT_CODE
+        #[arg(long, value_name = "CMD")]
+        on_failure: Option<String>,
     },
     /// Decompresses one or more files, optionally into another folder
     #[command(visible_alias = "d")]
     Decompress {
-        /// Files to be decompressed
+        /// Files to be decompressed, or '-' to read from stdin and write single files to stdout
         #[arg(required = true, num_args = 1.., value_hint = ValueHint::FilePath)]
         files: Vec<PathBuf>,
 
         /// Place results in a directory other than the current one
-        #[arg(short = 'd', long = "dir", value_hint = ValueHint::FilePath)]
+        #[arg(short = 'd', long = "dir", visible_short_alias = 'C', value_hint = ValueHint::FilePath)]
         output_dir: Option<PathBuf>,
+
+        /// Password of encrypted zip archives, asked for when needed if not given
+        #[arg(short, long, conflicts_with = "password_file")]
+        password: Option<String>,
+
+        /// Read the password of encrypted zip archives from the first line of this file
+        #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+        password_file: Option<PathBuf>,
+
+        /// Dictionary that zstd frames were compressed with, using `--zstd-dict`
+        #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+        zstd_dict: Option<PathBuf>,
+
+        /// Don't restore the Unix permissions stored in archives, extracting files as 644
+        /// and directories as 755
+        #[arg(long)]
+        no_preserve_permissions: bool,
+
+        /// Don't restore the modification times stored in archives, extracted files get the current time
+        #[arg(long)]
+        no_preserve_mtime: bool,
+
+        /// Give the files extracted from tar archives the user and group stored in them, looked up
+        /// by name, or by ID if the name doesn't exist here, requires root
+        #[arg(long)]
+        preserve_owner: bool,
+
+        /// Like --preserve-owner, but only with the stored user and group IDs, ignoring the names
+        #[arg(long)]
+        numeric_owner: bool,
+
+        /// Restore the extended attributes stored in tar archives compressed with --xattrs
+        #[arg(long)]
+        xattrs: bool,
+
+        /// What to do with files that already exist, asks by default, or follows --overwrite,
+        /// --no-clobber, --yes and --no
+        #[arg(long, value_enum, value_name = "POLICY")]
+        on_conflict: Option<ConflictPolicy>,
+
+        /// Keep the files that already exist with the same size as in the archive, and the same
+        /// modification time unless --no-preserve-mtime is given, replacing the others, to resume an
+        /// interrupted extraction
+        #[arg(long, conflicts_with = "on_conflict")]
+        skip_existing: bool,
+
+        /// Remove N leading directories from the paths of extracted files, files with fewer are skipped
+        #[arg(long, value_name = "N", default_value_t = 0)]
+        strip_components: usize,
+
+        /// Remove the top-level directory of archives whose entries are all inside of it, like
+        /// '--strip-components 1' but only when there's a single root
+        #[arg(long, conflicts_with = "strip_components")]
+        auto_strip: bool,
+
+        /// Only extract the archive entries matching this glob, like 'docs/**' or '*.md', can be repeated
+        #[arg(long, value_name = "GLOB")]
+        include: Vec<String>,
+
+        /// Only extract the archive entry at this position, counted from 1 in the order 'ouch list'
+        /// prints them, can be repeated
+        #[arg(long, value_name = "N")]
+        entry: Vec<usize>,
+
+        /// Check each file against its sibling checksum file, like 'archive.tar.gz.sha256', before extracting
+        #[arg(long)]
+        verify_checksum: bool,
+
+        /// Delete the archives after they were decompressed successfully, unless files were skipped
+        #[arg(long)]
+        remove: bool,
+
+        /// Extract each archive into a directory named after it, even if it only holds one file
+        #[arg(long)]
+        one_dir_per_archive: bool,
+
+        /// Write the decompressed file to stdout instead of creating it, archives need --include
+        /// to pick a single entry, or --raw to write the whole archive
+        #[arg(
+            short = 'c',
+            long,
+            conflicts_with_all = ["output_dir", "on_conflict", "skip_existing", "strip_components", "auto_strip", "remove", "one_dir_per_archive"]
+        )]
+        to_stdout: bool,
+
+        /// Stop decompressing once the files written add up to more than SIZE, which guards
+        /// against decompression bombs, accepts the units KiB, MiB, GiB and TiB
+        #[arg(long, value_name = "SIZE", value_parser = parse_size, default_value = "100GiB")]
+        max_decompressed_size: u64,
+
+        /// Only undo the compression around archives, writing the archive itself instead of
+        /// extracting its files, like 'archive.tar' for 'archive.tar.gz'. With --to-stdout, the
+        /// archive is written to stdout to be piped into other tools, like 'tar'
+        #[arg(
+            long,
+            conflicts_with_all = ["include", "entry", "strip_components", "auto_strip", "one_dir_per_archive"]
+        )]
+        raw: bool,
+
+        /// Run this shell command after decompressing successfully, with the output directory, or '-'
+        /// for stdout, in the OUCH_OUTPUT environment variable
+        #[arg(long, value_name = "CMD")]
+        on_success: Option<String>,
+
+        /// Run this shell command if decompressing fails or is declined, with the output directory
+        /// in OUCH_OUTPUT and the exit code in OUCH_EXIT_CODE
+        #[arg(long, value_name = "CMD")]
+        on_failure: Option<String>,
     },
     /// List contents of an archive
     #[command(visible_aliases = ["l", "ls"])]
@@ -95,7 +447,247 @@ pub enum Subcommand {
         /// Show archive contents as a tree
         #[arg(short, long)]
         tree: bool,
+
+        /// Print the entries as a JSON array, with their path, size, compressed size (when known),
+        /// whether they are directories and modification time, ignores --tree
+        #[arg(long)]
+        json: bool,
+
+        /// Show sizes in KiB, MiB, etc, instead of bytes
+        #[arg(long)]
+        human: bool,
+
+        /// Only list the entries matching this glob, like '*.rs' or 'src/**', can be repeated
+        #[arg(long, value_name = "GLOB")]
+        filter: Vec<String>,
+
+        /// Only print the number of files and their total size, without listing them
+        #[arg(long, conflicts_with_all = ["tree", "json"])]
+        count: bool,
+
+        /// Also show the permissions and modification time (in UTC) of the entries, like 'ls -l'
+        #[arg(short, long, conflicts_with_all = ["tree", "json", "count"])]
+        long: bool,
+
+        /// Dictionary that zstd frames were compressed with, using `--zstd-dict`
+        #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+        zstd_dict: Option<PathBuf>,
+    },
+    /// Test the integrity of archives by decompressing them without writing anything
+    #[command(visible_alias = "t")]
+    Test {
+        /// Archives to be tested
+        #[arg(required = true, num_args = 1.., value_hint = ValueHint::FilePath)]
+        archives: Vec<PathBuf>,
+
+        /// Password of encrypted zip archives, asked for when needed if not given
+        #[arg(short, long, conflicts_with = "password_file")]
+        password: Option<String>,
+
+        /// Read the password of encrypted zip archives from the first line of this file
+        #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+        password_file: Option<PathBuf>,
+
+        /// Dictionary that zstd frames were compressed with, using `--zstd-dict`
+        #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+        zstd_dict: Option<PathBuf>,
     },
+    /// Convert a file into other formats, like '.tar.gz' into '.tar.zst'
+    #[command(visible_alias = "recompress")]
+    Convert {
+        /// File to be converted
+        #[arg(value_hint = ValueHint::FilePath)]
+        input: PathBuf,
+
+        /// The resulting file. Its extensions are used to specify the new compression formats
+        #[arg(value_hint = ValueHint::FilePath)]
+        output: PathBuf,
+
+        /// Compression level, applied to all formats, errors if out of range for any of them.
+        /// Levels from OUCH_LEVEL or the config file are clamped to the range of each format instead
+        #[arg(short, long, env = "OUCH_LEVEL")]
+        level: Option<i16>,
+
+        /// Dictionary that zstd frames of the input were compressed with, using `--zstd-dict`
+        #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+        zstd_dict: Option<PathBuf>,
+    },
+    /// Train a zstd dictionary on sample files, improving the compression of many small similar files
+    TrainDict {
+        /// Sample files, directories are searched recursively
+        #[arg(required = true, num_args = 1.., value_hint = ValueHint::FilePath)]
+        files: Vec<PathBuf>,
+
+        /// Where to write the dictionary
+        #[arg(short, long, value_hint = ValueHint::FilePath)]
+        output: PathBuf,
+
+        /// Maximum size of the dictionary, accepts the units KiB, MiB, GiB and TiB
+        #[arg(long, value_name = "SIZE", value_parser = parse_size, default_value = "110KiB")]
+        max_size: u64,
+    },
+    /// Compare the compression ratio and speed of formats and levels on some files
+    Benchmark {
+        /// Files to compress, without writing the results anywhere
+        #[arg(required = true, num_args = 1.., value_hint = ValueHint::FilePath)]
+        files: Vec<PathBuf>,
+
+        /// Formats to compare, separated by commas
+        #[arg(long, value_delimiter = ',', default_value = "gz,bz2,xz,zst")]
+        formats: Vec<String>,
+
+        /// Levels to compress each format with, separated by commas, defaults to the default level
+        #[arg(short, long, value_delimiter = ',', allow_negative_numbers = true)]
+        levels: Vec<i16>,
+
+        /// Print the results as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// List the supported formats, their extensions and whether they can be compressed and decompressed
+    Formats {
+        /// Print the formats as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print the completion script of a shell, like 'ouch completions zsh > _ouch'
+    Completions {
+        /// Shell to print the completions for
+        #[arg(value_enum)]
+        shell: clap_complete::Shell,
+    },
+    /// Print the man page in roff, like 'ouch man > ouch.1'
+    #[command(hide = true)]
+    Man,
+}
+
+/// What `--on-conflict` does with extracted files that already exist
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Ask whether to overwrite them, unless --overwrite, --no-clobber, --yes or --no were given
+    Ask,
+    /// Replace them
+    Overwrite,
+    /// Keep them, without extracting the new ones
+    Skip,
+    /// Extract the new ones next to them, like 'file (1).txt'
+    Rename,
+    /// Keep the ones that were already extracted, replace the others, set by --skip-existing
+    #[value(skip)]
+    SkipExisting,
+}
+
+/// Hash algorithms supported by `--checksum`
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChecksumAlgorithm {
+    Sha256,
+    Sha512,
+    Md5,
+}
+
+/// Order of the files found in directories, given to `--sort`
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortOrder {
+    /// The order the filesystem lists them in
+    #[default]
+    None,
+    /// By name, byte by byte
+    Name,
+    /// From the smallest to the largest, by name if they have the same size
+    Size,
+}
+
+/// Parse a size like `512`, `100KiB` or `2GiB` into bytes.
+fn parse_size(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let split_at = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    let (number, unit) = text.split_at(split_at);
+
+    let number: u64 = number.parse().map_err(|_| format!("invalid size '{text}'"))?;
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kib" => 1 << 10,
+        "m" | "mib" => 1 << 20,
+        "g" | "gib" => 1 << 30,
+        "t" | "tib" => 1 << 40,
+        _ => return Err(format!("unknown unit '{unit}', expected one of: B, KiB, MiB, GiB, TiB")),
+    };
+
+    match number.checked_mul(multiplier) {
+        Some(0) => Err("size must be greater than zero".to_string()),
+        Some(size) => Ok(size),
+        None => Err(format!("size '{text}' is too large")),
+    }
+}
+
+/// Parse a point in time, either a date like `2024-01-01`, a RFC 3339 date and time, or a
+/// duration before now like `7d`.
+fn parse_time(text: &str) -> Result<SystemTime, String> {
+    use time::{
+        format_description::well_known::{Iso8601, Rfc3339},
+        Date, OffsetDateTime,
+    };
+
+    let text = text.trim();
+    let split_at = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    let (number, unit) = text.split_at(split_at);
+    let unit_seconds = match unit {
+        "s" => Some(1),
+        "m" => Some(60),
+        "h" => Some(60 * 60),
+        "d" => Some(24 * 60 * 60),
+        "w" => Some(7 * 24 * 60 * 60),
+        _ => None,
+    };
+
+    if let (Ok(number), Some(unit_seconds)) = (number.parse::<u64>(), unit_seconds) {
+        return number
+            .checked_mul(unit_seconds)
+            .and_then(|seconds| SystemTime::now().checked_sub(Duration::from_secs(seconds)))
+            .ok_or_else(|| format!("time '{text}' is too far in the past"));
+    }
+
+    if let Ok(date_time) = OffsetDateTime::parse(text, &Rfc3339) {
+        return Ok(date_time.into());
+    }
+
+    // A date alone is the start of that day in UTC
+    if let Ok(date) = Date::parse(text, &Iso8601::DEFAULT) {
+        return Ok(date.midnight().assume_utc().into());
+    }
+
+    Err(format!(
+        "invalid time '{text}', expected a date like '2024-01-01', a date and time like \
+         '2024-01-01T12:00:00Z', or a duration before now like '7d'"
+    ))
+}
+
+/// Parse a timestamp into seconds since the Unix epoch, either those seconds like `1700000000`,
+/// a date like `2024-01-01` or a RFC 3339 date and time.
+fn parse_timestamp(text: &str) -> Result<u64, String> {
+    use time::{
+        format_description::well_known::{Iso8601, Rfc3339},
+        Date, OffsetDateTime,
+    };
+
+    let text = text.trim();
+    if let Ok(seconds) = text.parse() {
+        return Ok(seconds);
+    }
+
+    let date_time = if let Ok(date_time) = OffsetDateTime::parse(text, &Rfc3339) {
+        date_time
+    } else if let Ok(date) = Date::parse(text, &Iso8601::DEFAULT) {
+        // A date alone is the start of that day in UTC
+        date.midnight().assume_utc()
+    } else {
+        return Err(format!(
+            "invalid timestamp '{text}', expected seconds since the Unix epoch like '1700000000', a date \
+             like '2024-01-01' or a date and time like '2024-01-01T12:00:00Z'"
+        ));
+    };
+
+    u64::try_from(date_time.unix_timestamp()).map_err(|_| format!("timestamp '{text}' is before the Unix epoch"))
 }
 
 #[cfg(test)]
@@ -127,16 +719,44 @@ mod tests {
         CliArgs {
             yes: false,
             no: false,
+            overwrite: false,
+            no_clobber: false,
             accessible: false,
             hidden: false,
             quiet: false,
+            verbose: 0,
             gitignore: false,
+            glob: false,
             format: None,
+            threads: None,
+            defaults: vec![],
             // This is usually replaced in assertion tests
             cmd: Subcommand::Decompress {
                 // Put a crazy value here so no test can assert it unintentionally
                 files: vec!["\x00\x11\x22".into()],
                 output_dir: None,
+                password: None,
+                password_file: None,
+                zstd_dict: None,
+                no_preserve_permissions: false,
+                no_preserve_mtime: false,
+                preserve_owner: false,
+                numeric_owner: false,
+                xattrs: false,
+                on_conflict: None,
+                skip_existing: false,
+                strip_components: 0,
+                auto_strip: false,
+                include: vec![],
+                entry: vec![],
+                verify_checksum: false,
+                remove: false,
+                one_dir_per_archive: false,
+                to_stdout: false,
+                max_decompressed_size: 100 << 30,
+                raw: false,
+                on_success: None,
+                on_failure: None,
             },
         }
     }
@@ -149,6 +769,28 @@ mod tests {
                 cmd: Subcommand::Decompress {
                     files: to_paths(["file.tar.gz"]),
                     output_dir: None,
+                    password: None,
+                    password_file: None,
+                    zstd_dict: None,
+                    no_preserve_permissions: false,
+                    no_preserve_mtime: false,
+                    preserve_owner: false,
+                    numeric_owner: false,
+                    xattrs: false,
+                    on_conflict: None,
+                    skip_existing: false,
+                    strip_components: 0,
+                    auto_strip: false,
+                    include: vec![],
+                    entry: vec![],
+                    verify_checksum: false,
+                    remove: false,
+                    one_dir_per_archive: false,
+                    to_stdout: false,
+                    max_decompressed_size: 100 << 30,
+                    raw: false,
+                    on_success: None,
+                    on_failure: None,
                 },
                 ..mock_cli_args()
             }
@@ -159,6 +801,28 @@ mod tests {
                 cmd: Subcommand::Decompress {
                     files: to_paths(["file.tar.gz"]),
                     output_dir: None,
+                    password: None,
+                    password_file: None,
+                    zstd_dict: None,
+                    no_preserve_permissions: false,
+                    no_preserve_mtime: false,
+                    preserve_owner: false,
+                    numeric_owner: false,
+                    xattrs: false,
+                    on_conflict: None,
+                    skip_existing: false,
+                    strip_components: 0,
+                    auto_strip: false,
+                    include: vec![],
+                    entry: vec![],
+                    verify_checksum: false,
+                    remove: false,
+                    one_dir_per_archive: false,
+                    to_stdout: false,
+                    max_decompressed_size: 100 << 30,
+                    raw: false,
+                    on_success: None,
+                    on_failure: None,
                 },
                 ..mock_cli_args()
             }
@@ -169,6 +833,53 @@ mod tests {
                 cmd: Subcommand::Decompress {
                     files: to_paths(["a", "b", "c"]),
                     output_dir: None,
+                    password: None,
+                    password_file: None,
+                    zstd_dict: None,
+                    no_preserve_permissions: false,
+                    no_preserve_mtime: false,
+                    preserve_owner: false,
+                    numeric_owner: false,
+                    xattrs: false,
+                    on_conflict: None,
+                    skip_existing: false,
+                    strip_components: 0,
+                    auto_strip: false,
+                    include: vec![],
+                    entry: vec![],
+                    verify_checksum: false,
+                    remove: false,
+                    one_dir_per_archive: false,
+                    to_stdout: false,
+                    max_decompressed_size: 100 << 30,
+                    raw: false,
+                    on_success: None,
+                    on_failure: None,
+                },
+                ..mock_cli_args()
+            }
+        );
+        test!(
+            "ouch t a.zip b.tar.gz",
+            CliArgs {
+                cmd: Subcommand::Test {
+                    archives: to_paths(["a.zip", "b.tar.gz"]),
+                    password: None,
+                    password_file: None,
+                    zstd_dict: None,
+                },
+                ..mock_cli_args()
+            }
+        );
+
+        test!(
+            "ouch convert a.tar.gz a.tar.zst",
+            CliArgs {
+                cmd: Subcommand::Convert {
+                    input: PathBuf::from("a.tar.gz"),
+                    output: PathBuf::from("a.tar.zst"),
+                    level: None,
+                    zstd_dict: None,
                 },
                 ..mock_cli_args()
             }
@@ -180,9 +891,49 @@ mod tests {
                 cmd: Subcommand::Compress {
                     files: to_paths(["file"]),
                     output: PathBuf::from("file.tar.gz"),
+                    output_dir: None,
+                    each: false,
+                    files_from: None,
+                    null: false,
+                    files_from_stdin0: false,
+                    unreadable_listed: vec![],
                     level: None,
                     fast: false,
                     slow: false,
+                    zstd_long: false,
+                    zstd_dict: None,
+                    password: None,
+                    password_stdin: false,
+                    password_file: None,
+                    comment: None,
+                    exclude: vec![],
+                    max_size: None,
+                    min_size: None,
+                    newer_than: None,
+                    max_depth: None,
+                    no_recursive: false,
+                    append: false,
+                    in_memory_limit: None,
+                    auto_compress: false,
+                    best: false,
+                    skip_errors: false,
+                    on_success: None,
+                    on_failure: None,
+                    dereference: false,
+                    no_dereference: false,
+                    skip_symlinks: false,
+                    split_size: None,
+                    dry_run: false,
+                    checksum: None,
+                    remove: false,
+                    sort: SortOrder::None,
+                    reproducible: false,
+                    mtime: None,
+                    base_dir: None,
+                    change_dir: None,
+                    sparse: false,
+                    xattrs: false,
+                    absolute_paths: false,
                 },
                 ..mock_cli_args()
             }
@@ -193,9 +944,49 @@ mod tests {
                 cmd: Subcommand::Compress {
                     files: to_paths(["a", "b", "c"]),
                     output: PathBuf::from("archive.tar.gz"),
+                    output_dir: None,
+                    each: false,
+                    files_from: None,
+                    null: false,
+                    files_from_stdin0: false,
+                    unreadable_listed: vec![],
                     level: None,
                     fast: false,
                     slow: false,
+                    zstd_long: false,
+                    zstd_dict: None,
+                    password: None,
+                    password_stdin: false,
+                    password_file: None,
+                    comment: None,
+                    exclude: vec![],
+                    max_size: None,
+                    min_size: None,
+                    newer_than: None,
+                    max_depth: None,
+                    no_recursive: false,
+                    append: false,
+                    in_memory_limit: None,
+                    auto_compress: false,
+                    best: false,
+                    skip_errors: false,
+                    on_success: None,
+                    on_failure: None,
+                    dereference: false,
+                    no_dereference: false,
+                    skip_symlinks: false,
+                    split_size: None,
+                    dry_run: false,
+                    checksum: None,
+                    remove: false,
+                    sort: SortOrder::None,
+                    reproducible: false,
+                    mtime: None,
+                    base_dir: None,
+                    change_dir: None,
+                    sparse: false,
+                    xattrs: false,
+                    absolute_paths: false,
                 },
                 ..mock_cli_args()
             }
@@ -206,9 +997,49 @@ mod tests {
                 cmd: Subcommand::Compress {
                     files: to_paths(["a", "b", "c"]),
                     output: PathBuf::from("archive.tar.gz"),
+                    output_dir: None,
+                    each: false,
+                    files_from: None,
+                    null: false,
+                    files_from_stdin0: false,
+                    unreadable_listed: vec![],
                     level: None,
                     fast: false,
                     slow: false,
+                    zstd_long: false,
+                    zstd_dict: None,
+                    password: None,
+                    password_stdin: false,
+                    password_file: None,
+                    comment: None,
+                    exclude: vec![],
+                    max_size: None,
+                    min_size: None,
+                    newer_than: None,
+                    max_depth: None,
+                    no_recursive: false,
+                    append: false,
+                    in_memory_limit: None,
+                    auto_compress: false,
+                    best: false,
+                    skip_errors: false,
+                    on_success: None,
+                    on_failure: None,
+                    dereference: false,
+                    no_dereference: false,
+                    skip_symlinks: false,
+                    split_size: None,
+                    dry_run: false,
+                    checksum: None,
+                    remove: false,
+                    sort: SortOrder::None,
+                    reproducible: false,
+                    mtime: None,
+                    base_dir: None,
+                    change_dir: None,
+                    sparse: false,
+                    xattrs: false,
+                    absolute_paths: false,
                 },
                 ..mock_cli_args()
             }
@@ -230,15 +1061,154 @@ mod tests {
                     cmd: Subcommand::Compress {
                         files: to_paths(["a", "b", "c"]),
                         output: PathBuf::from("output"),
+                        output_dir: None,
+                        each: false,
+                        files_from: None,
+                        null: false,
+                        files_from_stdin0: false,
+                        unreadable_listed: vec![],
                         level: None,
                         fast: false,
                         slow: false,
+                        zstd_long: false,
+                        zstd_dict: None,
+                        password: None,
+                        password_stdin: false,
+                        password_file: None,
+                        comment: None,
+                        exclude: vec![],
+                        max_size: None,
+                        min_size: None,
+                        newer_than: None,
+                        max_depth: None,
+                        no_recursive: false,
+                        append: false,
+                        in_memory_limit: None,
+                        auto_compress: false,
+                        best: false,
+                        skip_errors: false,
+                        on_success: None,
+                        on_failure: None,
+                        dereference: false,
+                        no_dereference: false,
+                        skip_symlinks: false,
+                        split_size: None,
+                        dry_run: false,
+                        checksum: None,
+                        remove: false,
+                        sort: SortOrder::None,
+                        reproducible: false,
+                        mtime: None,
+                        base_dir: None,
+                        change_dir: None,
+                        sparse: false,
+                        xattrs: false,
+                        absolute_paths: false,
                     },
                     format: Some("tar.gz".into()),
                     ..mock_cli_args()
                 }
             );
         }
+
+        for input in ["ouch --threads 4 compress a b out", "ouch c a b out -j 4"] {
+            test!(
+                input,
+                CliArgs {
+                    cmd: Subcommand::Compress {
+                        files: to_paths(["a", "b"]),
+                        output: PathBuf::from("out"),
+                        output_dir: None,
+                        each: false,
+                        files_from: None,
+                        null: false,
+                        files_from_stdin0: false,
+                        unreadable_listed: vec![],
+                        level: None,
+                        fast: false,
+                        slow: false,
+                        zstd_long: false,
+                        zstd_dict: None,
+                        password: None,
+                        password_stdin: false,
+                        password_file: None,
+                        comment: None,
+                        exclude: vec![],
+                        max_size: None,
+                        min_size: None,
+                        newer_than: None,
+                        max_depth: None,
+                        no_recursive: false,
+                        append: false,
+                        in_memory_limit: None,
+                        auto_compress: false,
+                        best: false,
+                        skip_errors: false,
+                        on_success: None,
+                        on_failure: None,
+                        dereference: false,
+                        no_dereference: false,
+                        skip_symlinks: false,
+                        split_size: None,
+                        dry_run: false,
+                        checksum: None,
+                        remove: false,
+                        sort: SortOrder::None,
+                        reproducible: false,
+                        mtime: None,
+                        base_dir: None,
+                        change_dir: None,
+                        sparse: false,
+                        xattrs: false,
+                        absolute_paths: false,
+                    },
+                    threads: Some(4),
+                    ..mock_cli_args()
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("512B"), Ok(512));
+        assert_eq!(parse_size("100KiB"), Ok(100 * 1024));
+        assert_eq!(parse_size("3mib"), Ok(3 * 1024 * 1024));
+        assert_eq!(parse_size("2GiB"), Ok(2 * 1024 * 1024 * 1024));
+        assert_eq!(parse_size("1 T"), Ok(1 << 40));
+        assert!(parse_size("0MiB").is_err());
+        assert!(parse_size("GiB").is_err());
+        assert!(parse_size("1.5GiB").is_err());
+        assert!(parse_size("10XB").is_err());
+        assert!(parse_size("99999999999TiB").is_err());
+    }
+
+    #[test]
+    fn test_parse_time() {
+        let secs = |time: SystemTime| time.duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs();
+
+        assert_eq!(parse_time("2024-01-01").map(secs), Ok(1704067200));
+        assert_eq!(parse_time("2024-01-01T12:00:00Z").map(secs), Ok(1704110400));
+        assert_eq!(parse_time("2024-01-01T12:00:00+02:00").map(secs), Ok(1704103200));
+
+        let week_ago = secs(SystemTime::now()) - 7 * 24 * 60 * 60;
+        assert!(parse_time("7d").map(secs).unwrap().abs_diff(week_ago) < 60);
+        assert!(parse_time("7").is_err());
+        assert!(parse_time("7x").is_err());
+        assert!(parse_time("2024-13-01").is_err());
+        assert!(parse_time("yesterday").is_err());
+    }
+
+    #[test]
+    fn test_parse_timestamp() {
+        assert_eq!(parse_timestamp("0"), Ok(0));
+        assert_eq!(parse_timestamp("1700000000"), Ok(1700000000));
+        assert_eq!(parse_timestamp("2024-01-01"), Ok(1704067200));
+        assert_eq!(parse_timestamp("2024-01-01T12:00:00+02:00"), Ok(1704103200));
+        assert!(parse_timestamp("1960-01-01").is_err());
+        assert!(parse_timestamp("7d").is_err());
+        assert!(parse_timestamp("-1").is_err());
     }
 
     #[test]
@@ -247,5 +1217,7 @@ mod tests {
         assert!(CliArgs::try_parse_from(args_splitter("ouch c input")).is_err());
         assert!(CliArgs::try_parse_from(args_splitter("ouch d")).is_err());
         assert!(CliArgs::try_parse_from(args_splitter("ouch l")).is_err());
+        assert!(CliArgs::try_parse_from(args_splitter("ouch t")).is_err());
+        assert!(CliArgs::try_parse_from(args_splitter("ouch c a b out --threads 0")).is_err());
     }
 }
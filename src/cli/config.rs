@@ -0,0 +1,285 @@
+//! Defaults for the command line flags, from environment variables and a TOML config file.
+//!
+//! Flags given on the command line win over their environment variables, like `OUCH_THREADS`,
+//! which win over the config file, which wins over the built-in defaults. Values of a layer that
+//! conflict with a layer above it are ignored, like `OUCH_YES` when `--no` is given.
+//!
+//! Keys of the config file are the long names of the flags, like `threads = 8` for `--threads 8`.
+
+use std::{
+    env,
+    ffi::OsString,
+    mem,
+    path::{Path, PathBuf},
+};
+
+use clap::{
+    error::{ContextKind, ContextValue},
+    parser::ValueSource,
+    Arg, ArgAction, ArgMatches, Command, CommandFactory, FromArgMatches,
+};
+use fs_err as fs;
+
+use super::CliArgs;
+use crate::{
+    error::FinalError,
+    utils::{logger::warning, EscapedPathDisplay},
+};
+
+/// Environment variable with the path of the config file, overriding the default location
+const CONFIG_ENV: &str = "OUCH_CONFIG";
+
+/// Parse the command line arguments, filling in the flags that weren't given from environment
+/// variables and the config file
+pub fn parse_args_with_config() -> crate::Result<CliArgs> {
+    let mut argv: Vec<OsString> = env::args_os().collect();
+    // Only what the command line gives, to know which environment variables and keys it overrides
+    let cli_matches = match without_env(CliArgs::command(), &|_| true).try_get_matches_from(&argv) {
+        Ok(matches) => matches,
+        // Errors, `--help` and `--version` exit here, the help is printed with the environment variables
+        Err(err) => {
+            CliArgs::command().get_matches_from(&argv);
+            err.exit();
+        }
+    };
+
+    let mut built_command = CliArgs::command();
+    built_command.build();
+    // Flags of the running subcommand, global ones included
+    let scope = cli_matches
+        .subcommand_name()
+        .and_then(|name| built_command.find_subcommand(name))
+        .unwrap_or(&built_command);
+    // The flags set so far, by the command line and then by the environment variables
+    let mut given: Vec<&Arg> = scope
+        .get_arguments()
+        .filter(|arg| is_explicitly_given(&cli_matches, arg))
+        .collect();
+
+    // Variables of flags the command line gives or conflicts with are ignored
+    let mut ignored_env = vec![];
+    for arg in scope.get_arguments() {
+        let Some(name) = arg.get_env().filter(|name| env::var_os(name).is_some()) else {
+            continue;
+        };
+        if given.iter().any(|other| conflicts(scope, arg, other)) {
+            ignored_env.push(name);
+        } else {
+            given.push(arg);
+        }
+    }
+    let command = without_env(CliArgs::command(), &|arg| {
+        arg.get_env().is_some_and(|name| ignored_env.contains(&name))
+    });
+
+    let Some((config_path, config)) = read_config()? else {
+        return Ok(with_defaults(&command.get_matches_from(&argv), scope, &[]));
+    };
+
+    let mut config_args = vec![];
+    let mut config_flags: Vec<&Arg> = vec![];
+    for (key, value) in &config {
+        let long = key.replace('_', "-");
+        let Some(arg) = find_flag(scope, &long) else {
+            // Flags of other subcommands are fine, they're used when running those
+            if !built_command
+                .get_subcommands()
+                .any(|other| find_flag(other, &long).is_some())
+            {
+                warning(format!(
+                    "Unknown key '{key}' in the config file '{}', ignoring it",
+                    EscapedPathDisplay::new(&config_path)
+                ));
+            }
+            continue;
+        };
+
+        // Set or conflicting with a flag from the command line, the environment or a previous key
+        if given.iter().any(|other| conflicts(scope, arg, other)) {
+            continue;
+        }
+
+        let Some(args) = flag_args(arg, value) else {
+            warning(format!(
+                "Invalid value for '{key}' in the config file '{}', ignoring it",
+                EscapedPathDisplay::new(&config_path)
+            ));
+            continue;
+        };
+
+        if !args.is_empty() {
+            config_args.extend(args);
+            config_flags.push(arg);
+            given.push(arg);
+        }
+    }
+
+    // Options end at `--`, the positional arguments after it are left alone
+    let position = argv.iter().position(|arg| arg == "--").unwrap_or(argv.len());
+    argv.splice(position..position, config_args);
+
+    let matches = command.try_get_matches_from(&argv).map_err(|err| {
+        // Errors of the environment variables are printed by clap, like those of the command line
+        let invalid_arg = match err.get(ContextKind::InvalidArg) {
+            Some(ContextValue::String(invalid_arg)) => invalid_arg,
+            _ => err.exit(),
+        };
+        if !config_flags.iter().any(|arg| arg.to_string() == *invalid_arg) {
+            err.exit();
+        }
+
+        FinalError::with_title(format!(
+            "Invalid config file '{}'",
+            EscapedPathDisplay::new(&config_path)
+        ))
+        .detail(
+            err.to_string()
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .trim_start_matches("error: ")
+                .to_string(),
+        )
+    })?;
+
+    Ok(with_defaults(&matches, scope, &config_flags))
+}
+
+/// The arguments of `matches`, knowing which flags of `scope` were set by their environment variable
+/// or are `config_flags`, which `matches` sees as given on the command line
+fn with_defaults(matches: &ArgMatches, scope: &Command, config_flags: &[&Arg]) -> CliArgs {
+    let mut args = CliArgs::from_arg_matches(matches).unwrap_or_else(|err| err.exit());
+    args.defaults = scope
+        .get_arguments()
+        .filter(|arg| {
+            config_flags.iter().any(|flag| flag.get_id() == arg.get_id())
+                || value_source(matches, arg) == Some(ValueSource::EnvVariable)
+        })
+        .map(|arg| arg.get_id().to_string())
+        .collect();
+    args
+}
+
+/// `command` without the environment variables of the flags `strip` returns true for
+fn without_env(command: Command, strip: &impl Fn(&Arg) -> bool) -> Command {
+    let mut command = command.mut_args(|arg| if strip(&arg) { arg.env(None) } else { arg });
+    for subcommand in command.get_subcommands_mut() {
+        *subcommand = without_env(mem::take(subcommand), strip);
+    }
+    command
+}
+
+/// Check if `arg` was given on the command line or through its environment variable
+fn is_explicitly_given(matches: &ArgMatches, arg: &Arg) -> bool {
+    matches!(
+        value_source(matches, arg),
+        Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+    )
+}
+
+/// Where the value of `arg` comes from, in `matches` or the matches of its subcommand
+fn value_source(matches: &ArgMatches, arg: &Arg) -> Option<ValueSource> {
+    let id = arg.get_id().as_str();
+    // `value_source` panics for the ids of flags `matches` doesn't know about
+    let source = matches
+        .try_contains_id(id)
+        .ok()
+        .and_then(|_| matches.value_source(id))
+        .filter(|source| *source != ValueSource::DefaultValue);
+
+    source.or_else(|| matches.subcommand().and_then(|(_, matches)| value_source(matches, arg)))
+}
+
+/// Check if `arg` can't be set along with `other`, flags of `command`, because it's the same flag,
+/// one conflicts with the other, or both are in a group that only allows one of its flags
+fn conflicts(command: &Command, arg: &Arg, other: &Arg) -> bool {
+    let conflicts_with = |arg: &Arg, other: &Arg| {
+        command
+            .get_arg_conflicts_with(arg)
+            .iter()
+            .any(|conflict| conflict.get_id() == other.get_id())
+    };
+    let in_exclusive_group = command.get_groups().any(|group| {
+        !group.clone().is_multiple()
+            && group.get_args().any(|id| id == arg.get_id())
+            && group.get_args().any(|id| id == other.get_id())
+    });
+
+    arg.get_id() == other.get_id() || conflicts_with(arg, other) || conflicts_with(other, arg) || in_exclusive_group
+}
+
+/// Path of the config file, `$OUCH_CONFIG`, or `ouch/config.toml` in the user's config directory
+fn config_path() -> Option<(PathBuf, bool)> {
+    if let Some(path) = env::var_os(CONFIG_ENV).filter(|path| !path.is_empty()) {
+        return Some((path.into(), true));
+    }
+
+    let config_dir = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+    };
+
+    config_dir.map(|dir| (dir.join("ouch").join("config.toml"), false))
+}
+
+/// Read the config file, if there's one
+///
+/// A missing file is only an error if its path was given through `$OUCH_CONFIG`.
+fn read_config() -> crate::Result<Option<(PathBuf, toml::Table)>> {
+    let Some((path, explicit)) = config_path() else {
+        return Ok(None);
+    };
+
+    if !explicit && !path.exists() {
+        return Ok(None);
+    }
+
+    let contents = fs::read_to_string(&path)?;
+    let config = contents.parse::<toml::Table>().map_err(|err| {
+        FinalError::with_title(format!("Invalid config file '{}'", EscapedPathDisplay::new(&path)))
+            .detail(err.message().to_string())
+            .hint("Keys are the long names of the flags, like 'threads = 8' or 'yes = true'")
+    })?;
+
+    Ok(Some((path, config)))
+}
+
+/// Flag of `command` with the long name `long`, flags that print help or the version aren't
+/// configurable
+fn find_flag<'a>(command: &'a Command, long: &str) -> Option<&'a Arg> {
+    command.get_arguments().find(|arg| {
+        arg.get_long() == Some(long)
+            && !matches!(
+                arg.get_action(),
+                ArgAction::Help | ArgAction::HelpShort | ArgAction::HelpLong | ArgAction::Version
+            )
+    })
+}
+
+/// The command line arguments that give `value` to the flag `arg`
+fn flag_args(arg: &Arg, value: &toml::Value) -> Option<Vec<OsString>> {
+    let long = arg.get_long()?;
+
+    let scalar = |value: &toml::Value| match value {
+        toml::Value::String(string) => Some(string.clone()),
+        toml::Value::Integer(integer) => Some(integer.to_string()),
+        toml::Value::Float(float) => Some(float.to_string()),
+        toml::Value::Datetime(datetime) => Some(datetime.to_string()),
+        toml::Value::Boolean(_) | toml::Value::Array(_) | toml::Value::Table(_) => None,
+    };
+
+    match (arg.get_action(), value) {
+        (ArgAction::SetTrue, toml::Value::Boolean(true)) => Some(vec![format!("--{long}").into()]),
+        (ArgAction::SetTrue, toml::Value::Boolean(false)) => Some(vec![]),
+        (ArgAction::Append, toml::Value::Array(values)) => values
+            .iter()
+            .map(|value| Some(format!("--{long}={}", scalar(value)?).into()))
+            .collect(),
+        (ArgAction::Set | ArgAction::Append, value) => Some(vec![format!("--{long}={}", scalar(value)?).into()]),
+        _ => None,
+    }
+}
@@ -1,51 +1,376 @@
 //! CLI related functions, uses the clap argparsing definitions from `args.rs`.
 
 mod args;
+mod config;
 
 use std::{
-    io,
-    path::{Path, PathBuf},
+    io::{self, Read},
+    path::{Component, Path, PathBuf},
+    slice,
 };
 
-use clap::Parser;
 use fs_err as fs;
+use globset::GlobBuilder;
 
-pub use self::args::{CliArgs, Subcommand};
-use crate::{accessible::set_accessible, utils::FileVisibilityPolicy, QuestionPolicy};
+pub use self::args::{ChecksumAlgorithm, CliArgs, ConflictPolicy, SortOrder, Subcommand};
+use crate::{
+    accessible::set_accessible,
+    check,
+    error::{Error, FinalError},
+    utils::{self, logger, AnswerPolicy, EscapedPathDisplay, FileVisibilityPolicy, OverwritePolicy, SymlinkPolicy},
+    QuestionPolicy,
+};
 
 impl CliArgs {
-    /// A helper method that calls `clap::Parser::parse`.
-    ///
-    /// And:
-    ///   1. Make paths absolute.
-    ///   2. Checks the QuestionPolicy.
-    pub fn parse_and_validate_args() -> crate::Result<(Self, QuestionPolicy, FileVisibilityPolicy)> {
-        let mut args = Self::parse();
+    /// A helper method that calls `clap::Parser::parse`, with defaults from the config file.
+    pub fn parse_with_config() -> crate::Result<Self> {
+        let args = config::parse_args_with_config()?;
 
         set_accessible(args.accessible);
+        logger::set_quiet(args.quiet);
+
+        Ok(args)
+    }
+
+    /// Check the arguments given by [`Self::parse_with_config`], and:
+    ///   1. Make paths absolute, except for `-`, which stands for stdin.
+    ///   2. Checks the QuestionPolicy.
+    pub fn validate(self) -> crate::Result<(Self, QuestionPolicy, FileVisibilityPolicy)> {
+        let mut args = self;
+
+        // With --each, the output is one more input, the archives are written to the current directory,
+        // or to --output-dir
+        if let Subcommand::Compress {
+            files,
+            output,
+            each: true,
+            ..
+        } = &mut args.cmd
+        {
+            if args.format.is_none() {
+                return Err(Error::InvalidUsage {
+                    reason: FinalError::with_title("Cannot compress with --each without --format")
+                        .detail("The archives are named after the inputs, with the extensions of --format")
+                        .hint("Use --format to choose them, like '--format tar.gz'"),
+                });
+            }
+            files.push(std::mem::replace(output, PathBuf::from(".")));
+        }
+
+        // Position of the first listed path in the inputs, after the positional ones
+        let mut listed_start = None;
+        if let Subcommand::Compress {
+            files,
+            files_from,
+            files_from_stdin0,
+            null,
+            password_stdin,
+            ..
+        } = &mut args.cmd
+        {
+            if *files_from_stdin0 {
+                *files_from = Some(PathBuf::from("-"));
+                *null = true;
+            }
+            if let Some(list_path) = files_from {
+                if utils::is_stdio(list_path) && (*password_stdin || files.iter().any(|file| utils::is_stdio(file))) {
+                    return Err(Error::InvalidUsage {
+                        reason: FinalError::with_title("Cannot read the paths to compress from stdin")
+                            .detail("Stdin is already used for the input file or the password")
+                            .hint("Write the paths to a file and give it to --files-from instead"),
+                    });
+                }
+                listed_start = Some(files.len());
+                files.extend(read_files_from(list_path, *null)?);
+            }
+        }
+
+        if let Subcommand::Compress {
+            files,
+            change_dir: Some(change_dir),
+            ..
+        } = &mut args.cmd
+        {
+            if !change_dir.is_dir() {
+                return Err(Error::MissingInputs {
+                    reason: FinalError::with_title(format!(
+                        "Cannot change to '{}'",
+                        EscapedPathDisplay::new(change_dir)
+                    ))
+                    .detail("It isn't a directory")
+                    .hint("Give an existing directory to --change-dir"),
+                });
+            }
+            // Like `tar -C`, the inputs are found in the directory, without changing the current one
+            for file in files
+                .iter_mut()
+                .filter(|file| file.is_relative() && !utils::is_stdio(file))
+            {
+                *file = change_dir.join(&*file);
+            }
+        }
 
-        let (Subcommand::Compress { files, .. }
-        | Subcommand::Decompress { files, .. }
-        | Subcommand::List { archives: files, .. }) = &mut args.cmd;
-        *files = canonicalize_files(files)?;
+        // Shells on Windows pass the patterns as they are
+        if args.glob || cfg!(windows) {
+            match &mut args.cmd {
+                Subcommand::Compress { files, .. } => {
+                    // Listed paths are taken as they are, like tar does
+                    let listed = files.split_off(listed_start.unwrap_or(files.len()));
+                    *files = expand_globs(std::mem::take(files))?;
+                    listed_start = listed_start.map(|_| files.len());
+                    files.extend(listed);
+                }
+                Subcommand::Decompress { files, .. }
+                | Subcommand::List { archives: files, .. }
+                | Subcommand::Test { archives: files, .. }
+                | Subcommand::TrainDict { files, .. }
+                | Subcommand::Benchmark { files, .. } => *files = expand_globs(std::mem::take(files))?,
+                Subcommand::Convert { .. }
+                | Subcommand::Formats { .. }
+                | Subcommand::Completions { .. }
+                | Subcommand::Man => {}
+            }
+        }
 
-        let skip_questions_positively = match (args.yes, args.no) {
-            (false, false) => QuestionPolicy::Ask,
-            (true, false) => QuestionPolicy::AlwaysYes,
-            (false, true) => QuestionPolicy::AlwaysNo,
+        if let Subcommand::Compress {
+            files,
+            unreadable_listed,
+            ..
+        } = &mut args.cmd
+        {
+            // Like tar, listed paths that can't be read don't stop the others from being compressed
+            if let Some(listed_start) = listed_start {
+                for path in files.split_off(listed_start) {
+                    match std::fs::symlink_metadata(&path) {
+                        Ok(_) => files.push(path),
+                        Err(err) => unreadable_listed.push((path, err.to_string())),
+                    }
+                }
+            }
+            check::check_inputs_exist(files)?;
+        }
+
+        match &mut args.cmd {
+            Subcommand::Compress { files, .. }
+            | Subcommand::Decompress { files, .. }
+            | Subcommand::List { archives: files, .. }
+            | Subcommand::Test { archives: files, .. }
+            | Subcommand::TrainDict { files, .. }
+            | Subcommand::Benchmark { files, .. } => *files = canonicalize_files(files)?,
+            Subcommand::Convert { input, .. } => {
+                *input = canonicalize_files(slice::from_ref(input))?.remove(0);
+            }
+            Subcommand::Formats { .. } | Subcommand::Completions { .. } | Subcommand::Man => {}
+        }
+
+        let answers = match (args.yes, args.no) {
+            (false, false) => AnswerPolicy::Ask,
+            (true, false) => AnswerPolicy::AlwaysYes,
+            (false, true) => AnswerPolicy::AlwaysNo,
+            (true, true) => unreachable!(),
+        };
+        let overwrite = match (args.overwrite, args.no_clobber) {
+            (false, false) => OverwritePolicy::Ask,
+            (true, false) => OverwritePolicy::Always,
+            (false, true) => OverwritePolicy::Never,
             (true, true) => unreachable!(),
         };
+        let skip_questions_positively = QuestionPolicy { answers, overwrite };
+
+        let (exclude, symlinks, min_size, max_size, newer_than, max_depth, recursive, sort) = match &args.cmd {
+            Subcommand::Compress {
+                exclude,
+                dereference,
+                skip_symlinks,
+                min_size,
+                max_size,
+                newer_than,
+                max_depth,
+                no_recursive,
+                sort,
+                reproducible,
+                ..
+            } => {
+                // `--no-dereference` is the default, it's only there to override the config file
+                let symlinks = match (*dereference, *skip_symlinks) {
+                    (true, _) => SymlinkPolicy::Follow,
+                    (false, true) => SymlinkPolicy::Skip,
+                    (false, false) => SymlinkPolicy::Store,
+                };
+                // Reproducible archives need a deterministic order, by name unless another one was given
+                let sort = match (*reproducible, *sort) {
+                    (true, SortOrder::None) => SortOrder::Name,
+                    (_, sort) => sort,
+                };
+                (
+                    exclude.clone(),
+                    symlinks,
+                    *min_size,
+                    *max_size,
+                    *newer_than,
+                    *max_depth,
+                    !*no_recursive,
+                    sort,
+                )
+            }
+            Subcommand::Decompress { .. }
+            | Subcommand::List { .. }
+            | Subcommand::Test { .. }
+            | Subcommand::Convert { .. }
+            | Subcommand::TrainDict { .. }
+            | Subcommand::Benchmark { .. }
+            | Subcommand::Formats { .. }
+            | Subcommand::Completions { .. }
+            | Subcommand::Man => (
+                Vec::new(),
+                SymlinkPolicy::default(),
+                None,
+                None,
+                None,
+                None,
+                true,
+                SortOrder::default(),
+            ),
+        };
 
         let file_visibility_policy = FileVisibilityPolicy::new()
             .read_git_exclude(args.gitignore)
             .read_ignore(args.gitignore)
             .read_git_ignore(args.gitignore)
-            .read_hidden(args.hidden);
+            .read_git_global(args.gitignore)
+            .read_hidden(args.hidden)
+            .symlinks(symlinks)
+            .size_limits(min_size, max_size)
+            .newer_than(newer_than)
+            .max_depth(max_depth)
+            .recursive(recursive)
+            .sort(sort)
+            .exclude(exclude)?;
 
         Ok((args, skip_questions_positively, file_visibility_policy))
     }
 }
 
+/// Read the paths listed in the file at `path`, or stdin if it's `-`, one per line, or separated
+/// by NUL characters if `null` is set. Empty entries are ignored.
+fn read_files_from(path: &Path, null: bool) -> crate::Result<Vec<PathBuf>> {
+    let contents = if utils::is_stdio(path) {
+        let mut contents = vec![];
+        io::stdin().read_to_end(&mut contents)?;
+        contents
+    } else {
+        fs::read(path)?
+    };
+
+    let separator = if null { b'\0' } else { b'\n' };
+    let paths = contents
+        .split(|&byte| byte == separator)
+        // Lists written on Windows end their lines with "\r\n"
+        .map(|entry| if null { entry } else { entry.strip_suffix(b"\r").unwrap_or(entry) })
+        .filter(|entry| !entry.is_empty())
+        .map(path_from_bytes)
+        .collect();
+
+    Ok(paths)
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+    PathBuf::from(OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// Characters that make a path a glob pattern
+const GLOB_CHARS: [char; 4] = ['*', '?', '[', '{'];
+
+/// Replace the glob patterns in `files` that don't name an existing file, like `*.txt`, with the
+/// paths they match, sorted, failing if one matches nothing
+fn expand_globs(files: Vec<PathBuf>) -> crate::Result<Vec<PathBuf>> {
+    let mut expanded = vec![];
+    for file in files {
+        let is_pattern = file.to_string_lossy().contains(GLOB_CHARS);
+        if !is_pattern || utils::is_stdio(&file) || std::fs::symlink_metadata(&file).is_ok() {
+            expanded.push(file);
+            continue;
+        }
+
+        let matches = glob_matches(&file)?;
+        if matches.is_empty() {
+            return Err(Error::MissingInputs {
+                reason: FinalError::with_title(format!("No files match '{}'", EscapedPathDisplay::new(&file)))
+                    .detail("Wildcards only match within a directory, like 'logs/*.txt'")
+                    .detail("Names starting with '.' are only matched by patterns starting with '.'"),
+            });
+        }
+        expanded.extend(matches);
+    }
+    Ok(expanded)
+}
+
+/// Paths matched by `pattern`, one component at a time like shells do, so wildcards don't match
+/// across directories, nor hidden names unless the pattern starts with a dot
+fn glob_matches(pattern: &Path) -> crate::Result<Vec<PathBuf>> {
+    let mut paths = vec![PathBuf::new()];
+    for component in pattern.components() {
+        let name = component.as_os_str();
+        let text = name.to_string_lossy();
+        if !matches!(component, Component::Normal(_)) || !text.contains(GLOB_CHARS) {
+            paths.iter_mut().for_each(|path| path.push(name));
+            continue;
+        }
+
+        let matcher = GlobBuilder::new(&text)
+            .literal_separator(true)
+            // Matching follows the case sensitivity of the platform's paths
+            .case_insensitive(!cfg!(unix))
+            .build()
+            .map_err(|err| Error::InvalidUsage {
+                reason: FinalError::with_title(format!("Invalid glob pattern '{}'", EscapedPathDisplay::new(pattern)))
+                    .detail(err.kind().to_string()),
+            })?
+            .compile_matcher();
+
+        let mut matched = vec![];
+        for path in &paths {
+            let dir = if path.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                path
+            };
+            // Paths matched so far that aren't directories have nothing more to match
+            let Ok(entries) = std::fs::read_dir(dir) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let entry_name = entry.file_name();
+                let hidden = entry_name.to_string_lossy().starts_with('.') && !text.starts_with('.');
+                if !hidden && matcher.is_match(&entry_name) {
+                    matched.push(path.join(entry_name));
+                }
+            }
+        }
+        matched.sort();
+        paths = matched;
+    }
+    Ok(paths)
+}
+
 fn canonicalize_files(files: &[impl AsRef<Path>]) -> io::Result<Vec<PathBuf>> {
-    files.iter().map(fs::canonicalize).collect()
+    files
+        .iter()
+        .map(|file| {
+            let file = file.as_ref();
+            if utils::is_stdio(file) {
+                Ok(file.to_path_buf())
+            } else {
+                fs::canonicalize(file)
+            }
+        })
+        .collect()
 }
@@ -0,0 +1,208 @@
+use std::{
+    ffi::OsStr,
+    io::{self, Write},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use super::compress::{compress_files, total_input_size};
+use crate::{
+    archive::EntryBase,
+    error::{Error, FinalError},
+    extension::{parse_format, Extension},
+    utils::{
+        self,
+        logger::{warning, Verbosity},
+        Bytes, FileVisibilityPolicy,
+    },
+    QuestionPolicy,
+};
+
+/// Result of compressing the inputs with a format and a level
+struct Measurement {
+    format: String,
+    /// `None` for the default level, or for formats without levels
+    level: Option<i16>,
+    size: u64,
+    time: Duration,
+}
+
+/// Compress `files` with every combination of `formats` and `levels`, each time into a temporary
+/// file that's removed right after, and print the size of the results and how long they took.
+///
+/// Multiple inputs or directories are archived with tar first, unless the format is already an
+/// archive. An empty `levels` compresses with the default level of each format, and formats
+/// without levels are only compressed once.
+#[allow(clippy::too_many_arguments)]
+pub fn benchmark(
+    files: &[PathBuf],
+    formats: &[String],
+    levels: &[i16],
+    threads: Option<u32>,
+    json: bool,
+    question_policy: QuestionPolicy,
+    file_visibility_policy: &FileVisibilityPolicy,
+) -> crate::Result<()> {
+    if files.iter().any(|file| utils::is_stdio(file)) {
+        return Err(Error::InvalidUsage {
+            reason: FinalError::with_title("Cannot benchmark stdin")
+                .detail("The inputs are compressed once per format and level, stdin can only be read once"),
+        });
+    }
+
+    let needs_archive = files.len() > 1 || files.iter().any(|file| file.is_dir());
+    let input_size = total_input_size(files, file_visibility_policy);
+
+    let mut measurements = vec![];
+    for format in formats {
+        let mut extensions = parse_format(OsStr::new(format))?;
+        let mut format = format.clone();
+        if needs_archive && !extensions.first().is_some_and(Extension::is_archive) {
+            extensions.insert(0, parse_format(OsStr::new("tar"))?.remove(0));
+            format = format!("tar.{format}");
+        }
+
+        for level in format_levels(&format, &extensions, levels) {
+            let output = tempfile::tempfile()?;
+            let start = Instant::now();
+            let compressed = compress_files(
+                files.to_vec(),
+                extensions.clone(),
+                output.try_clone()?,
+                &PathBuf::from(format!("benchmark.{format}")),
+                Verbosity::Quiet,
+                question_policy,
+                file_visibility_policy.clone(),
+                &EntryBase::Parent,
+                level,
+                false,
+                threads,
+                None,
+                None,
+                None,
+                None,
+                false,
+                false,
+                None,
+                None,
+            )?;
+            if !compressed {
+                return Err(Error::UserDeclined);
+            }
+
+            measurements.push(Measurement {
+                format: format.clone(),
+                level,
+                size: output.metadata()?.len(),
+                time: start.elapsed(),
+            });
+        }
+    }
+
+    let out = &mut io::stdout().lock();
+    if json {
+        print_json(out, &measurements, input_size);
+    } else {
+        print_table(out, &measurements, input_size);
+    }
+    Ok(())
+}
+
+/// The levels `format` is compressed with, out of `levels`, skipping the ones it doesn't support
+fn format_levels(format: &str, extensions: &[Extension], levels: &[i16]) -> Vec<Option<i16>> {
+    let ranges: Vec<_> = extensions
+        .iter()
+        .flat_map(|extension| extension.compression_formats)
+        .filter_map(|format| format.compression_level_range())
+        .collect();
+    if levels.is_empty() || ranges.is_empty() {
+        return vec![None];
+    }
+
+    let mut supported = vec![];
+    for &level in levels {
+        if ranges.iter().all(|range| range.contains(&level)) {
+            supported.push(Some(level));
+        } else {
+            warning(format!(
+                "Skipped '{format}' at level {level}, which it doesn't support."
+            ));
+        }
+    }
+    supported
+}
+
+/// Size of the output relative to the input, as a percentage
+fn ratio(size: u64, input_size: u64) -> Option<f64> {
+    (input_size != 0).then(|| size as f64 * 100.0 / input_size as f64)
+}
+
+/// Input bytes compressed per second
+fn throughput(input_size: u64, time: Duration) -> Option<f64> {
+    let seconds = time.as_secs_f64();
+    (seconds > 0.0).then(|| input_size as f64 / seconds)
+}
+
+/// Print a row per measurement, in the order they were made, with the columns aligned
+fn print_table(out: &mut impl Write, measurements: &[Measurement], input_size: u64) {
+    let header = ["Format", "Level", "Size", "Ratio", "Time", "Throughput"].map(String::from);
+    let rows: Vec<[String; 6]> = measurements
+        .iter()
+        .map(|measurement| {
+            [
+                measurement.format.clone(),
+                measurement
+                    .level
+                    .map_or_else(|| "default".to_string(), |level| level.to_string()),
+                Bytes::new(measurement.size).to_string(),
+                ratio(measurement.size, input_size).map_or_else(|| "-".to_string(), |ratio| format!("{ratio:.1}%")),
+                format!("{:.3} s", measurement.time.as_secs_f64()),
+                throughput(input_size, measurement.time).map_or_else(
+                    || "-".to_string(),
+                    |throughput| format!("{}/s", Bytes::new(throughput as u64)),
+                ),
+            ]
+        })
+        .collect();
+
+    let mut widths = [0; 6];
+    for row in std::iter::once(&header).chain(&rows) {
+        for (width, column) in widths.iter_mut().zip(row) {
+            *width = (*width).max(column.chars().count());
+        }
+    }
+
+    let _ = writeln!(out, "Input: {}", Bytes::new(input_size));
+    for row in std::iter::once(&header).chain(&rows) {
+        // The format is aligned to the left, the numbers to the right
+        let mut line = format!("{:<width$}", row[0], width = widths[0]);
+        for (column, width) in row.iter().zip(widths).skip(1) {
+            line.push_str(&format!("  {column:>width$}"));
+        }
+        let _ = writeln!(out, "{line}");
+    }
+}
+
+/// Print the measurements as a JSON array, with sizes in bytes and times in seconds
+fn print_json(out: &mut impl Write, measurements: &[Measurement], input_size: u64) {
+    fn json_number(number: Option<impl std::fmt::Display>) -> String {
+        number.map_or_else(|| "null".to_string(), |number| number.to_string())
+    }
+
+    let _ = writeln!(out, "[");
+    for (index, measurement) in measurements.iter().enumerate() {
+        let separator = if index + 1 < measurements.len() { "," } else { "" };
+        let _ = writeln!(
+            out,
+            r#"  {{"format": "{}", "level": {}, "input_size": {}, "size": {}, "ratio": {}, "seconds": {}, "bytes_per_second": {}}}{separator}"#,
+            measurement.format,
+            json_number(measurement.level),
+            input_size,
+            measurement.size,
+            json_number(ratio(measurement.size, input_size).map(|ratio| format!("{ratio:.1}"))),
+            measurement.time.as_secs_f64(),
+            json_number(throughput(input_size, measurement.time).map(|throughput| throughput as u64)),
+        );
+    }
+    let _ = writeln!(out, "]");
+}
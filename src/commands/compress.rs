@@ -1,25 +1,63 @@
 use std::{
-    io::{self, BufWriter, Cursor, Seek, Write},
+    ffi::{OsStr, OsString},
+    io::{self, BufWriter, Cursor, Read, Seek, Write},
     path::{Path, PathBuf},
+    slice,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
 };
 
 use fs_err as fs;
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
 
-use super::warn_user_about_loading_sevenz_in_memory;
+use super::{remove_incomplete_output, remove_inputs, warn_user_about_loading_sevenz_in_memory};
 use crate::{
-    archive,
+    archive::{self, EntryBase, Reproducible, SkippedFiles},
+    check,
     commands::warn_user_about_loading_zip_in_memory,
-    extension::{split_first_compression_format, CompressionFormat::*, Extension},
-    utils::{io::lock_and_flush_output_stdio, user_wants_to_continue, FileVisibilityPolicy},
+    error::{Error, FinalError},
+    extension::{
+        split_first_compression_format,
+        CompressionFormat::{self, *},
+        Extension,
+    },
+    utils::{
+        self,
+        io::lock_and_flush_output_stdio,
+        logger::{info_accessible, Verbosity},
+        lzip::{LzipEncoder, LzipMember},
+        progress::Progress,
+        strip_cur_dir, to_utf, user_wants_to_continue, Bytes, EscapedPathDisplay, FileVisibilityPolicy,
+    },
     QuestionAction, QuestionPolicy, BUFFER_CAPACITY,
 };
 
+/// Window size used by zstd's long distance matching, 2^27 bytes = 128 MiB
+const ZSTD_LONG_WINDOW_LOG: u32 = 27;
+
+/// How much of the inputs is compressed with each format to find the best one, 1 MiB
+const BEST_FORMAT_SAMPLE_SIZE: usize = 1 << 20;
+
 /// Compress files into `output_file`.
 ///
 /// # Arguments:
-/// - `files`: is the list of paths to be compressed: ["dir/file1.txt", "dir/file2.txt"]
+/// - `files`: is the list of paths to be compressed: ["dir/file1.txt", "dir/file2.txt"], or `-` for stdin
 /// - `extensions`: is a list of compression formats for compressing, example: [Tar, Gz] (in compression order)
-/// - `output_file` is the resulting compressed file name, example: "archive.tar.gz"
+/// - `output_file` is where the result is written to, a file or stdout
+/// - `output_path` is the resulting compressed file name, example: "archive.tar.gz", or `-` for stdout
+/// - `entry_base` is what the names of archive entries are relative to
+/// - `zstd_long`, `zstd_threads` and `zstd_dict` configure the zstd encoder, ignored by other formats
+/// - `password` encrypts zip archives, ignored by other formats
+/// - `comment` is the comment of zip archives, ignored by other formats
+/// - `reproducible` normalizes the metadata of the entries of tar, zip and cpio archives
+/// - `sparse` stores the holes of sparse files in tar archives without their zeros
+/// - `xattrs` stores the extended attributes of files in tar archives
+/// - `skip_errors` leaves out the files of archives that can't be read, counting them, instead of
+///   failing
+/// - `in_memory_limit` is the input size over which the user is warned before compressing zip or
+///   7z archives chained with other formats in memory, see [`exceeds_in_memory_limit`]
 ///
 /// # Return value
 /// - Returns `Ok(true)` if compressed all files normally.
@@ -28,82 +66,171 @@ use crate::{
 pub fn compress_files(
     files: Vec<PathBuf>,
     extensions: Vec<Extension>,
-    output_file: fs::File,
+    output_file: impl Write + Send + 'static,
     output_path: &Path,
-    quiet: bool,
+    verbosity: Verbosity,
     question_policy: QuestionPolicy,
     file_visibility_policy: FileVisibilityPolicy,
+    entry_base: &EntryBase,
     level: Option<i16>,
+    zstd_long: bool,
+    zstd_threads: Option<u32>,
+    zstd_dict: Option<&[u8]>,
+    password: Option<&str>,
+    comment: Option<&str>,
+    reproducible: Option<Reproducible>,
+    sparse: bool,
+    xattrs: bool,
+    skip_errors: Option<&SkippedFiles>,
+    in_memory_limit: Option<u64>,
 ) -> crate::Result<bool> {
+    // Archives nested in a tar archive, like "archive.zip.tar", are compressed into a temporary
+    // file first, which is then stored as the only entry of the tar archive
+    if let Some(position) = extensions.iter().skip(1).rposition(Extension::is_archive) {
+        let (inner_extensions, outer_extensions) = extensions.split_at(position + 1);
+        let nested_name = nested_archive_name(output_path, inner_extensions, outer_extensions);
+
+        let mut nested_archive = tempfile::tempfile()?;
+        let compressed = compress_files(
+            files,
+            inner_extensions.to_vec(),
+            nested_archive.try_clone()?,
+            output_path,
+            verbosity,
+            question_policy,
+            file_visibility_policy,
+            entry_base,
+            level,
+            zstd_long,
+            zstd_threads,
+            zstd_dict,
+            password,
+            comment,
+            reproducible,
+            sparse,
+            xattrs,
+            skip_errors,
+            in_memory_limit,
+        )?;
+        if !compressed {
+            return Ok(false);
+        }
+        let size = nested_archive.stream_position()?;
+        nested_archive.rewind()?;
+
+        let (_, formats) = split_first_compression_format(outer_extensions);
+        let mut writer: Box<dyn Send + Write> = Box::new(BufWriter::with_capacity(BUFFER_CAPACITY, output_file));
+        let mut lzip_members = vec![];
+        for format in formats.iter().rev() {
+            writer = chain_writer_encoder(
+                format,
+                writer,
+                level,
+                zstd_long,
+                zstd_threads,
+                zstd_dict,
+                None,
+                &mut lzip_members,
+            )?;
+        }
+        let mut writer =
+            archive::tar::build_archive_from_reader(&nested_name, size, nested_archive, writer, reproducible)?;
+        writer.flush()?;
+        finish_encoders(writer, lzip_members)?;
+
+        return Ok(true);
+    }
+
     // If the input files contain a directory, then the total size will be underestimated
     let file_writer = BufWriter::with_capacity(BUFFER_CAPACITY, output_file);
 
     let mut writer: Box<dyn Send + Write> = Box::new(file_writer);
 
-    // Grab previous encoder and wrap it inside of a new one
-    let chain_writer_encoder = |format: &_, encoder| -> crate::Result<_> {
-        let encoder: Box<dyn Send + Write> = match format {
-            Gzip => Box::new(
-                // by default, ParCompress uses a default compression level of 3
-                // instead of the regular default that flate2 uses
-                gzp::par::compress::ParCompress::<gzp::deflate::Gzip>::builder()
-                    .compression_level(
-                        level.map_or_else(Default::default, |l| gzp::Compression::new((l as u32).clamp(0, 9))),
-                    )
-                    .from_writer(encoder),
-            ),
-            Bzip => Box::new(bzip2::write::BzEncoder::new(
-                encoder,
-                level.map_or_else(Default::default, |l| bzip2::Compression::new((l as u32).clamp(1, 9))),
-            )),
-            Lz4 => Box::new(lz4_flex::frame::FrameEncoder::new(encoder).auto_finish()),
-            Lzma => Box::new(xz2::write::XzEncoder::new(
-                encoder,
-                level.map_or(6, |l| (l as u32).clamp(0, 9)),
-            )),
-            Snappy => Box::new(
-                gzp::par::compress::ParCompress::<gzp::snap::Snap>::builder()
-                    .compression_level(gzp::par::compress::Compression::new(
-                        level.map_or_else(Default::default, |l| (l as u32).clamp(0, 9)),
-                    ))
-                    .from_writer(encoder),
-            ),
-            Zstd => {
-                let zstd_encoder = zstd::stream::write::Encoder::new(
-                    encoder,
-                    level.map_or(zstd::DEFAULT_COMPRESSION_LEVEL, |l| {
-                        (l as i32).clamp(zstd::zstd_safe::min_c_level(), zstd::zstd_safe::max_c_level())
-                    }),
-                );
-                // Safety:
-                //     Encoder::new() can only fail if `level` is invalid, but the level
-                //     is `clamp`ed and therefore guaranteed to be valid
-                Box::new(zstd_encoder.unwrap().auto_finish())
-            }
-            Tar | Zip | Rar | SevenZip => unreachable!(),
-        };
-        Ok(encoder)
-    };
-
     let (first_format, formats) = split_first_compression_format(&extensions);
 
+    let mut lzip_members = vec![];
     for format in formats.iter().rev() {
-        writer = chain_writer_encoder(format, writer)?;
+        writer = chain_writer_encoder(
+            format,
+            writer,
+            level,
+            zstd_long,
+            zstd_threads,
+            zstd_dict,
+            None,
+            &mut lzip_members,
+        )?;
     }
 
+    // The size of stdin isn't known upfront, so there's no progress to report
+    let input_is_stdin = files.iter().any(|file| utils::is_stdio(file));
+
+    // Walking the inputs to sum their sizes can take a while, skip it if there's nothing to report
+    let total_input_size = if verbosity.hides_progress() || input_is_stdin {
+        0
+    } else {
+        total_input_size(&files, &file_visibility_policy)
+    };
+    let progress = Progress::new(
+        format!("Compressing '{}'", EscapedPathDisplay::new(strip_cur_dir(output_path))),
+        total_input_size,
+        verbosity.hides_progress() || input_is_stdin,
+    );
+
     match first_format {
-        Gzip | Bzip | Lz4 | Lzma | Snappy | Zstd => {
-            writer = chain_writer_encoder(&first_format, writer)?;
-            let mut reader = fs::File::open(&files[0]).unwrap();
+        Gzip | Bzip | Lz4 | Xz | Lzma | Lzip | Deflate | Zlib | Snappy | Zstd => {
+            let (reader, content_size): (Box<dyn Read>, _) = if input_is_stdin {
+                (Box::new(io::stdin()), None)
+            } else {
+                let file = fs::File::open(&files[0])?;
+                let size = file.metadata()?.len();
+                (Box::new(file), Some(size))
+            };
+            writer = chain_writer_encoder(
+                &first_format,
+                writer,
+                level,
+                zstd_long,
+                zstd_threads,
+                zstd_dict,
+                content_size,
+                &mut lzip_members,
+            )?;
 
-            io::copy(&mut reader, &mut writer)?;
+            io::copy(&mut progress.wrap_read(reader), &mut writer)?;
         }
         Tar => {
-            archive::tar::build_archive_from_paths(&files, output_path, &mut writer, file_visibility_policy, quiet)?;
+            archive::tar::build_archive_from_paths(
+                &files,
+                output_path,
+                &mut writer,
+                file_visibility_policy,
+                entry_base,
+                reproducible,
+                sparse,
+                xattrs,
+                skip_errors,
+                &progress,
+                verbosity,
+            )?;
+            writer.flush()?;
+        }
+        Cpio => {
+            archive::cpio::build_archive_from_paths(
+                &files,
+                output_path,
+                &mut writer,
+                file_visibility_policy,
+                entry_base,
+                reproducible,
+                skip_errors,
+                &progress,
+                verbosity,
+            )?;
             writer.flush()?;
         }
         Zip => {
-            if !formats.is_empty() {
+            if !formats.is_empty() && exceeds_in_memory_limit(&files, &file_visibility_policy, in_memory_limit) {
                 // Locking necessary to guarantee that warning and question
                 // messages stay adjacent
                 let _locks = lock_and_flush_output_stdio();
@@ -121,7 +248,13 @@ pub fn compress_files(
                 output_path,
                 &mut vec_buffer,
                 file_visibility_policy,
-                quiet,
+                entry_base,
+                password,
+                comment,
+                reproducible,
+                skip_errors,
+                &progress,
+                verbosity,
             )?;
             vec_buffer.rewind()?;
             io::copy(&mut vec_buffer, &mut writer)?;
@@ -133,8 +266,9 @@ pub fn compress_files(
             #[cfg(not(feature = "unrar"))]
             return Err(archive::rar_stub::no_support());
         }
+        Ar => return Err(archive::ar::no_compression()),
         SevenZip => {
-            if !formats.is_empty() {
+            if !formats.is_empty() && exceeds_in_memory_limit(&files, &file_visibility_policy, in_memory_limit) {
                 // Locking necessary to guarantee that warning and question
                 // messages stay adjacent
                 let _locks = lock_and_flush_output_stdio();
@@ -146,11 +280,474 @@ pub fn compress_files(
             }
 
             let mut vec_buffer = Cursor::new(vec![]);
-            archive::sevenz::compress_sevenz(&files, output_path, &mut vec_buffer, file_visibility_policy, quiet)?;
+            archive::sevenz::compress_sevenz(
+                &files,
+                output_path,
+                &mut vec_buffer,
+                file_visibility_policy,
+                entry_base,
+                skip_errors,
+                &progress,
+                verbosity,
+            )?;
             vec_buffer.rewind()?;
             io::copy(&mut vec_buffer, &mut writer)?;
         }
     }
 
+    finish_encoders(writer, lzip_members)?;
     Ok(true)
 }
+
+/// Finish the streams of the encoders chained by [`chain_writer_encoder`] into `writer`, which
+/// write what they buffered when dropped, and wait for the threads of the lzip members among them
+pub(super) fn finish_encoders(writer: impl Write, lzip_members: Vec<LzipMember>) -> io::Result<()> {
+    drop(writer);
+    lzip_members.into_iter().try_for_each(LzipMember::finish)
+}
+
+/// Name of the archive nested in the one at `output_path`, like "archive.zip" for "archive.zip.tar.gz"
+fn nested_archive_name(output_path: &Path, inner_extensions: &[Extension], outer_extensions: &[Extension]) -> PathBuf {
+    let outer_suffix: String = outer_extensions
+        .iter()
+        .map(|extension| format!(".{extension}"))
+        .collect();
+    let name = output_path.file_name().and_then(OsStr::to_str).unwrap_or_default();
+
+    match name.strip_suffix(&outer_suffix) {
+        Some(name) if !name.is_empty() => PathBuf::from(name),
+        // Written to stdout, or the formats came from `--format`
+        _ => {
+            let inner_suffix: String = inner_extensions
+                .iter()
+                .map(|extension| format!(".{extension}"))
+                .collect();
+            PathBuf::from(format!("archive{inner_suffix}"))
+        }
+    }
+}
+
+/// Grab previous encoder and wrap it inside of a new one, `format` can't be an archive format
+///
+/// `level`, `zstd_long`, `zstd_threads` and `zstd_dict` are the same as in [`compress_files`],
+/// `content_size` is how many bytes will be written, if known, stored in the header of zstd frames
+/// so that decompressors know it upfront. The threads of lzip encoders are added to `lzip_members`,
+/// to be finished by [`finish_encoders`]
+#[allow(clippy::too_many_arguments)]
+pub(super) fn chain_writer_encoder(
+    format: &CompressionFormat,
+    encoder: Box<dyn Send + Write>,
+    level: Option<i16>,
+    zstd_long: bool,
+    zstd_threads: Option<u32>,
+    zstd_dict: Option<&[u8]>,
+    content_size: Option<u64>,
+    lzip_members: &mut Vec<LzipMember>,
+) -> crate::Result<Box<dyn Send + Write>> {
+    let encoder: Box<dyn Send + Write> = match format {
+        Gzip => Box::new(
+            // by default, ParCompress uses a default compression level of 3
+            // instead of the regular default that flate2 uses
+            gzp::par::compress::ParCompress::<gzp::deflate::Gzip>::builder()
+                .compression_level(
+                    level.map_or_else(Default::default, |l| gzp::Compression::new((l as u32).clamp(0, 9))),
+                )
+                .from_writer(encoder),
+        ),
+        Bzip => Box::new(bzip2::write::BzEncoder::new(
+            encoder,
+            level.map_or_else(Default::default, |l| bzip2::Compression::new((l as u32).clamp(1, 9))),
+        )),
+        Lz4 => Box::new(lz4_flex::frame::FrameEncoder::new(encoder).auto_finish()),
+        Xz => Box::new(xz2::write::XzEncoder::new(
+            encoder,
+            level.map_or(6, |l| (l as u32).clamp(0, 9)),
+        )),
+        Lzma => {
+            let options = xz2::stream::LzmaOptions::new_preset(level.map_or(6, |l| (l as u32).clamp(0, 9)))?;
+            Box::new(LzmaEncoder(xz2::write::XzEncoder::new_stream(
+                encoder,
+                xz2::stream::Stream::new_lzma_encoder(&options)?,
+            )))
+        }
+        Lzip => {
+            let (encoder, member) = LzipEncoder::new(encoder, level.map_or(6, |l| (l as u32).clamp(0, 9)));
+            lzip_members.push(member);
+            Box::new(encoder)
+        }
+        Deflate => Box::new(flate2::write::DeflateEncoder::new(
+            encoder,
+            level.map_or_else(Default::default, |l| flate2::Compression::new((l as u32).clamp(0, 9))),
+        )),
+        Zlib => Box::new(flate2::write::ZlibEncoder::new(
+            encoder,
+            level.map_or_else(Default::default, |l| flate2::Compression::new((l as u32).clamp(0, 9))),
+        )),
+        Snappy => Box::new(
+            gzp::par::compress::ParCompress::<gzp::snap::Snap>::builder()
+                .compression_level(gzp::par::compress::Compression::new(
+                    level.map_or_else(Default::default, |l| (l as u32).clamp(0, 9)),
+                ))
+                .from_writer(encoder),
+        ),
+        Zstd => {
+            let level = level.map_or(zstd::DEFAULT_COMPRESSION_LEVEL, |l| {
+                (l as i32).clamp(zstd::zstd_safe::min_c_level(), zstd::zstd_safe::max_c_level())
+            });
+            let mut zstd_encoder = match zstd_dict {
+                // Fails if the dictionary is invalid
+                Some(dictionary) => zstd::stream::write::Encoder::with_dictionary(encoder, level, dictionary)?,
+                // Safety:
+                //     Encoder::new() can only fail if `level` is invalid, but the level
+                //     is `clamp`ed and therefore guaranteed to be valid
+                None => zstd::stream::write::Encoder::new(encoder, level).unwrap(),
+            };
+
+            if zstd_long {
+                zstd_encoder.long_distance_matching(true)?;
+                // Same window as `zstd --long`, which decoders accept by default
+                zstd_encoder.window_log(ZSTD_LONG_WINDOW_LOG)?;
+            }
+            if let Some(threads) = zstd_threads {
+                zstd_encoder.multithread(threads)?;
+            }
+            // Writing a different number of bytes makes the encoder fail
+            zstd_encoder.set_pledged_src_size(content_size)?;
+
+            Box::new(zstd_encoder.auto_finish())
+        }
+        Tar | Zip | Rar | SevenZip | Cpio | Ar => unreachable!(),
+    };
+    Ok(encoder)
+}
+
+/// Walks `input` like the archive builders do, yielding each path that would be compressed along
+/// with its metadata.
+///
+/// Symlinks are only followed if the policy says so, broken ones are then skipped.
+fn walk_input<'a>(
+    input: &Path,
+    file_visibility_policy: &'a FileVisibilityPolicy,
+) -> impl Iterator<Item = crate::Result<(PathBuf, std::fs::Metadata)>> + 'a {
+    file_visibility_policy.build_walker(input).filter_map(|entry| {
+        let path = match entry {
+            Ok(entry) => entry.into_path(),
+            Err(err) => return Some(Err(err.into())),
+        };
+
+        let metadata = if file_visibility_policy.follows_links() {
+            path.metadata()
+        } else {
+            path.symlink_metadata()
+        };
+
+        match metadata {
+            Ok(metadata) => Some(Ok((path, metadata))),
+            // This path is for a broken symlink, we just ignore it
+            Err(err) if err.kind() == io::ErrorKind::NotFound && utils::is_symlink(&path) => None,
+            Err(err) => Some(Err(err.into())),
+        }
+    })
+}
+
+/// Sum of the sizes of all files that will be compressed, used as the total of the progress report
+/// and in the summary printed after compressing
+pub(super) fn total_input_size(files: &[PathBuf], file_visibility_policy: &FileVisibilityPolicy) -> u64 {
+    files
+        .iter()
+        .flat_map(|file| walk_input(file, file_visibility_policy))
+        .filter_map(|entry| entry.ok())
+        .filter(|(_, metadata)| metadata.is_file())
+        .map(|(_, metadata)| metadata.len())
+        .sum()
+}
+
+/// Check if `files` are larger than `limit`, or than half of the available memory if no limit
+/// was given, too large to be compressed in memory without warning the user first
+///
+/// The size of stdin isn't known, and neither is the available memory outside of Linux, these
+/// are always considered too large.
+fn exceeds_in_memory_limit(
+    files: &[PathBuf],
+    file_visibility_policy: &FileVisibilityPolicy,
+    limit: Option<u64>,
+) -> bool {
+    let Some(limit) = limit.or_else(|| available_memory().map(|available| available / 2)) else {
+        return true;
+    };
+
+    files.iter().any(|file| utils::is_stdio(file)) || total_input_size(files, file_visibility_policy) > limit
+}
+
+/// Memory available for starting new applications without swapping, in bytes
+fn available_memory() -> Option<u64> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    let line = meminfo.lines().find(|line| line.starts_with("MemAvailable:"))?;
+    // Like "MemAvailable:   12345678 kB"
+    let kibibytes: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kibibytes * 1024)
+}
+
+/// Compression format to add to a plain archive with `--auto-compress`, zstd, or with `best`, the
+/// one among gzip, bzip2, xz and zstd whose output is the smallest for the first MiB of the inputs
+pub fn auto_compression_format(
+    files: &[PathBuf],
+    file_visibility_policy: &FileVisibilityPolicy,
+    level: Option<i16>,
+    best: bool,
+) -> crate::Result<Extension> {
+    let zstd = Extension::new(&[Zstd], "zst");
+    if !best {
+        return Ok(zstd);
+    }
+
+    let mut sample = vec![];
+    'walk: for file in files {
+        for entry in walk_input(file, file_visibility_policy) {
+            let (path, metadata) = entry?;
+            if !metadata.is_file() {
+                continue;
+            }
+            let left = BEST_FORMAT_SAMPLE_SIZE - sample.len();
+            fs::File::open(&path)?.take(left as u64).read_to_end(&mut sample)?;
+            if sample.len() >= BEST_FORMAT_SAMPLE_SIZE {
+                break 'walk;
+            }
+        }
+    }
+
+    let candidates = [
+        zstd,
+        Extension::new(&[Gzip], "gz"),
+        Extension::new(&[Bzip], "bz2"),
+        Extension::new(&[Xz], "xz"),
+    ];
+    let mut best_candidate = None;
+    for candidate in candidates {
+        let size = Arc::new(AtomicU64::new(0));
+        let counter: Box<dyn Send + Write> = Box::new(CountingWriter(size.clone()));
+        let mut lzip_members = vec![];
+        let mut encoder = chain_writer_encoder(
+            &candidate.compression_formats[0],
+            counter,
+            level,
+            false,
+            None,
+            None,
+            None,
+            &mut lzip_members,
+        )?;
+        encoder.write_all(&sample)?;
+        finish_encoders(encoder, lzip_members)?;
+
+        let size = size.load(Ordering::Relaxed);
+        match &best_candidate {
+            Some((_, best_size)) if *best_size <= size => {}
+            _ => best_candidate = Some((candidate, size)),
+        }
+    }
+
+    // Unwrap safety:
+    //   there are candidates, so one of them was picked
+    Ok(best_candidate.unwrap().0)
+}
+
+/// Writer that throws the data away, only counting how many bytes were written
+struct CountingWriter(Arc<AtomicU64>);
+
+impl Write for CountingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.fetch_add(buf.len() as u64, Ordering::Relaxed);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Encoder of the legacy LZMA format, whose stream can't be flushed before it's finished
+///
+/// Flushing only flushes the wrapped writer, the compressed data is written when it's dropped.
+struct LzmaEncoder<W: Write>(xz2::write::XzEncoder<W>);
+
+impl<W: Write> Write for LzmaEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.get_mut().flush()
+    }
+}
+
+/// Print the paths that compressing `files` would add to the archive, and their total size,
+/// without creating anything.
+///
+/// Paths are named by `entry_base` like the entries of the archive, in the order of
+/// `file_visibility_policy.sort`, which the archive builders walk the inputs in too.
+pub fn print_dry_run(
+    files: &[PathBuf],
+    file_visibility_policy: &FileVisibilityPolicy,
+    entry_base: &EntryBase,
+) -> crate::Result<()> {
+    let mut file_count: usize = 0;
+    let mut total_size = 0;
+
+    for input in files {
+        for entry in walk_input(input, file_visibility_policy) {
+            let (path, metadata) = entry?;
+            let Some(entry_name) = entry_base.entry_name(input, &path)? else {
+                continue;
+            };
+
+            if metadata.is_dir() {
+                println!("{}/", EscapedPathDisplay::new(entry_name));
+            } else {
+                println!("{}", EscapedPathDisplay::new(entry_name));
+                file_count += 1;
+                total_size += metadata.len();
+            }
+        }
+    }
+
+    info_accessible(format!(
+        "Dry run, nothing was written. Would compress {file_count} files ({}).",
+        Bytes::new(total_size)
+    ));
+
+    Ok(())
+}
+
+/// Compress each file of `files` separately into `output_dir`, using a pool of `threads` threads.
+///
+/// Outputs are named after their input followed by `format_text`, example: "out/a.log.gz".
+///
+/// Failed or aborted outputs are deleted, and success messages are printed in the same
+/// order as `files`, regardless of which compression finished first. With `remove`, each input
+/// is deleted once its own output was written, unless files were left out of it by `skip_errors`.
+#[allow(clippy::too_many_arguments)]
+pub fn compress_files_into_dir(
+    files: Vec<PathBuf>,
+    extensions: Vec<Extension>,
+    format_text: &OsStr,
+    output_dir: &Path,
+    threads: usize,
+    verbosity: Verbosity,
+    question_policy: QuestionPolicy,
+    file_visibility_policy: FileVisibilityPolicy,
+    entry_base: &EntryBase,
+    level: Option<i16>,
+    zstd_long: bool,
+    zstd_threads: Option<u32>,
+    zstd_dict: Option<&[u8]>,
+    password: Option<&str>,
+    comment: Option<&str>,
+    reproducible: Option<Reproducible>,
+    sparse: bool,
+    xattrs: bool,
+    skip_errors: Option<&SkippedFiles>,
+    in_memory_limit: Option<u64>,
+    remove: bool,
+) -> crate::Result<()> {
+    let mut jobs = vec![];
+    let mut declined = false;
+
+    // Questions are asked upfront, so they don't get mixed up between threads
+    for file in files {
+        // Unwrap safety:
+        //   paths should be canonicalized by now, and the root directory rejected.
+        let mut output_name = OsString::from(file.file_name().unwrap());
+        output_name.push(".");
+        output_name.push(format_text);
+        let output_path = output_dir.join(output_name);
+
+        check::check_invalid_compression_with_non_archive_format(
+            &extensions,
+            &output_path,
+            slice::from_ref(&file),
+            Some(&format_text.to_owned()),
+        )?;
+
+        match utils::ask_to_create_file(&output_path, question_policy)? {
+            Some(output_file) => jobs.push((file, output_path, output_file)),
+            None => declined = true,
+        }
+    }
+
+    // Archive builders other than tar's change the current directory while walking their
+    // inputs, so they can't run concurrently
+    let threads = match extensions.first() {
+        Some(extension) if extension.is_archive() && extension.compression_formats[0] != Tar => 1,
+        _ => threads,
+    };
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .map_err(|err| FinalError::with_title("Could not start compression threads").detail(err.to_string()))?;
+
+    let results: Vec<_> = pool.install(|| {
+        jobs.into_par_iter()
+            .map(|(file, output_path, output_file)| {
+                // Counted for each input, to only keep those missing files with --remove
+                let skipped = SkippedFiles::default();
+                let result = compress_files(
+                    vec![file.clone()],
+                    extensions.clone(),
+                    output_file,
+                    &output_path,
+                    verbosity,
+                    question_policy,
+                    file_visibility_policy.clone(),
+                    entry_base,
+                    level,
+                    zstd_long,
+                    zstd_threads,
+                    zstd_dict,
+                    password,
+                    comment,
+                    reproducible,
+                    sparse,
+                    xattrs,
+                    skip_errors.map(|_| &skipped),
+                    in_memory_limit,
+                );
+                (file, output_path, result, skipped)
+            })
+            .collect()
+    });
+
+    let mut first_error = None;
+    for (file, output_path, result, skipped) in results {
+        let is_complete = skipped.count() == 0;
+        if let Some(skip_errors) = skip_errors {
+            skip_errors.merge(skipped);
+        }
+
+        match result {
+            Ok(true) => {
+                info_accessible(format!("Successfully compressed '{}'.", to_utf(&output_path)));
+                if remove && is_complete {
+                    if let Err(err) = remove_inputs(slice::from_ref(&file)) {
+                        first_error.get_or_insert(err);
+                    }
+                }
+            }
+            Ok(false) => {
+                remove_incomplete_output(&output_path, false);
+                declined = true;
+            }
+            Err(err) => {
+                remove_incomplete_output(&output_path, true);
+                first_error.get_or_insert(err);
+            }
+        }
+    }
+
+    match first_error {
+        Some(err) => Err(err),
+        None if declined => Err(Error::UserDeclined),
+        None => Ok(()),
+    }
+}
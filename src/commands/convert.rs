@@ -0,0 +1,182 @@
+use std::{
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use fs_err as fs;
+
+use super::{
+    chain_reader_decoder,
+    compress::{chain_writer_encoder, compress_files, finish_encoders},
+    decompress::decompress_file,
+};
+use crate::{
+    archive::{tar::Ownership, EntryBase},
+    cli::ConflictPolicy,
+    extension::{
+        flatten_compression_formats, separate_known_extensions_from_name, split_first_compression_format,
+        CompressionFormat::*, Extension,
+    },
+    utils::{
+        logger::Verbosity, progress::Progress, split::SplitReader, strip_cur_dir, EscapedPathDisplay,
+        FileVisibilityPolicy,
+    },
+    QuestionPolicy, BUFFER_CAPACITY,
+};
+
+/// Convert the file at `input_path` into other formats, writing the result to `output_file`
+///
+/// # Arguments:
+/// - `input_formats`: formats of the input, example: [Tar, Gz] (in decompression order)
+/// - `output_path` is the resulting file name, example: "archive.tar.zst"
+/// - `output_formats`: formats of the output, example: [Tar, Zstd] (in compression order)
+/// - `level` and `zstd_threads` configure the encoders, like in [`compress_files`]
+/// - `zstd_dict` is the dictionary the zstd frames of the input were compressed with, if any
+///
+/// Compressed files, and tar or cpio archives that stay tar or cpio, are streamed from the
+/// decoders into the encoders. Other archives are extracted into a temporary directory next to
+/// `output_path`, which is removed afterwards, even if the conversion fails.
+///
+/// # Return value
+/// - Returns `Ok(true)` if the file was converted normally.
+/// - Returns `Ok(false)` if user opted to abort the conversion mid-way.
+#[allow(clippy::too_many_arguments)]
+pub fn convert_file(
+    input_path: &Path,
+    input_formats: Vec<Extension>,
+    output_file: fs::File,
+    output_path: &Path,
+    output_formats: Vec<Extension>,
+    question_policy: QuestionPolicy,
+    verbosity: Verbosity,
+    level: Option<i16>,
+    zstd_threads: Option<u32>,
+    zstd_dict: Option<&[u8]>,
+) -> crate::Result<bool> {
+    let (input_first, input_rest) = split_first_compression_format(&input_formats);
+    let (output_first, output_rest) = split_first_compression_format(&output_formats);
+
+    // The formats to decode and to encode when the inner stream can be copied as is
+    let streamed_formats = match (input_first, output_first) {
+        (Tar, Tar) | (Cpio, Cpio) => Some((input_rest, output_rest)),
+        _ if !input_formats[0].is_archive() && !output_formats[0].is_archive() => Some((
+            flatten_compression_formats(&input_formats),
+            flatten_compression_formats(&output_formats),
+        )),
+        _ => None,
+    };
+
+    if let Some((decoded_formats, encoded_formats)) = streamed_formats {
+        let input_file = SplitReader::open(input_path)?;
+        let progress = Progress::new(
+            format!("Converting '{}'", EscapedPathDisplay::new(strip_cur_dir(input_path))),
+            input_file.size(),
+            verbosity.hides_progress(),
+        );
+
+        let reader = BufReader::with_capacity(BUFFER_CAPACITY, progress.wrap_read(input_file));
+        let mut reader: Box<dyn Read + Send> = Box::new(reader);
+        for format in decoded_formats.iter().rev() {
+            reader = chain_reader_decoder(format, reader, zstd_dict)?;
+        }
+
+        let mut writer: Box<dyn Send + Write> = Box::new(BufWriter::with_capacity(BUFFER_CAPACITY, output_file));
+        let mut lzip_members = vec![];
+        for format in encoded_formats.iter().rev() {
+            writer = chain_writer_encoder(
+                format,
+                writer,
+                level,
+                false,
+                zstd_threads,
+                None,
+                None,
+                &mut lzip_members,
+            )?;
+        }
+
+        io::copy(&mut reader, &mut writer)?;
+        writer.flush()?;
+        finish_encoders(writer, lzip_members)?;
+        return Ok(true);
+    }
+
+    let output_parent = output_path.parent().filter(|parent| !parent.as_os_str().is_empty());
+    let temp_dir = tempfile::tempdir_in(output_parent.unwrap_or(Path::new(".")))?;
+
+    // `decompress_file` moves the root of archives with a single one into `single_root`, and
+    // places the roots of other archives in `multiple_roots`
+    let single_root = temp_dir.path().join("single");
+    let multiple_roots = temp_dir.path().join("multiple");
+    fs::create_dir(&single_root)?;
+
+    let output_file_path = if input_formats[0].is_archive() {
+        multiple_roots.clone()
+    } else {
+        let (name, _) = separate_known_extensions_from_name(input_path);
+        single_root.join(name.file_name().unwrap_or(name.as_os_str()))
+    };
+
+    decompress_file(
+        input_path,
+        input_formats,
+        &single_root,
+        output_file_path,
+        question_policy,
+        None,
+        zstd_dict,
+        true,
+        true,
+        Ownership::Current,
+        false,
+        ConflictPolicy::Ask,
+        0,
+        false,
+        None,
+        &[],
+        false,
+        false,
+        crate::archive::DEFAULT_MAX_UNPACKED_SIZE,
+        verbosity,
+    )?;
+
+    let roots_dir = if multiple_roots.exists() {
+        multiple_roots
+    } else {
+        single_root
+    };
+    let mut roots = fs::read_dir(roots_dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    roots.sort();
+
+    // Nothing was extracted if the user chose not to continue
+    if roots.is_empty() {
+        return Ok(false);
+    }
+
+    // Everything that was extracted goes into the output, hidden files included
+    let file_visibility_policy = FileVisibilityPolicy::new().read_hidden(false);
+
+    compress_files(
+        roots,
+        output_formats,
+        output_file,
+        output_path,
+        verbosity,
+        question_policy,
+        file_visibility_policy,
+        &EntryBase::Parent,
+        level,
+        false,
+        zstd_threads,
+        None,
+        None,
+        None,
+        None,
+        false,
+        false,
+        None,
+        None,
+    )
+}
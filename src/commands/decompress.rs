@@ -1,40 +1,151 @@
 use std::{
-    io::{self, BufReader, Read},
+    io::{self, BufRead, BufReader, Read, Write},
     ops::ControlFlow,
     path::{Path, PathBuf},
 };
 
 use fs_err as fs;
+use ignore::overrides::Override;
 
 use crate::{
-    commands::{warn_user_about_loading_sevenz_in_memory, warn_user_about_loading_zip_in_memory},
+    archive::{tar::Ownership, EntrySelection, ExtractedOutput, SizeLimit},
+    cli::ConflictPolicy,
+    commands::{
+        chain_reader_decoder, list::entry_paths_at, warn_user_about_loading_sevenz_in_memory,
+        warn_user_about_loading_zip_in_memory,
+    },
+    error::{Error, FinalError},
     extension::{
-        split_first_compression_format,
+        flatten_compression_formats, split_first_compression_format,
         CompressionFormat::{self, *},
         Extension,
     },
     utils::{
-        self, io::lock_and_flush_output_stdio, logger::info_accessible, nice_directory_display, user_wants_to_continue,
+        self,
+        interrupt::{self, Writing},
+        io::lock_and_flush_output_stdio,
+        logger::{info_accessible, Verbosity},
+        nice_directory_display,
+        progress::Progress,
+        split::SplitReader,
+        strip_cur_dir, user_wants_to_continue, EscapedPathDisplay,
     },
     QuestionAction, QuestionPolicy, BUFFER_CAPACITY,
 };
 
 /// Decompress a file
 ///
-/// File at input_file_path is opened for reading, example: "archive.tar.gz"
+/// File at input_file_path is opened for reading, example: "archive.tar.gz", or stdin if it's `-`,
+/// if it's the first volume of a split archive, like "archive.tar.gz.001", all volumes are read
 /// formats contains each format necessary for decompression, example: [Gz, Tar] (in decompression order)
 /// output_dir it's where the file will be decompressed to, this function assumes that the directory exists
-/// output_file_path is only used when extracting single file formats, not archive formats like .tar or .zip
+/// output_file_path is only used when extracting single file formats, not archive formats like .tar or .zip,
+/// these are written to stdout instead if reading from stdin or with to_stdout
+/// password is used to decrypt zip archives, if not given the user is asked for it when needed
+/// zstd_dict is the dictionary zstd frames were compressed with, if any
+/// preserve_permissions restores the Unix permissions stored in tar, zip and cpio archives
+/// preserve_mtime restores the modification times stored in archives, single file formats don't store them
+/// ownership decides who owns the files extracted from tar archives
+/// xattrs restores the extended attributes stored in tar archives
+/// conflict_policy decides what happens with output files that already exist
+/// strip_components is how many leading directories are removed from the paths inside archives
+/// auto_strip removes the top-level directory of archives that only have one, like a strip_components of 1
+/// include only keeps the archive entries it whitelists, matched before stripping their paths
+/// entries only keeps the archive entries at these 1-based positions, in the order `ouch list` prints them
+/// one_dir_per_archive extracts archives into output_file_path even if they only hold one file
+/// to_stdout writes the decompressed file to stdout, for archives the only entry kept by include or entries
+/// max_decompressed_size stops decompressing once the output files add up to more than it
+///
+/// Returns how many output files were skipped because they already existed, or `None` if the user
+/// chose not to continue
+#[allow(clippy::too_many_arguments)]
 pub fn decompress_file(
     input_file_path: &Path,
     formats: Vec<Extension>,
     output_dir: &Path,
     output_file_path: PathBuf,
     question_policy: QuestionPolicy,
-    quiet: bool,
-) -> crate::Result<()> {
+    password: Option<&str>,
+    zstd_dict: Option<&[u8]>,
+    preserve_permissions: bool,
+    preserve_mtime: bool,
+    ownership: Ownership,
+    xattrs: bool,
+    conflict_policy: ConflictPolicy,
+    strip_components: usize,
+    auto_strip: bool,
+    include: Option<&Override>,
+    entries: &[usize],
+    one_dir_per_archive: bool,
+    to_stdout: bool,
+    max_decompressed_size: u64,
+    verbosity: Verbosity,
+) -> crate::Result<Option<usize>> {
     assert!(output_dir.exists());
-    let reader = fs::File::open(input_file_path)?;
+    let mut skipped = 0;
+    let input_is_stdin = utils::is_stdio(input_file_path);
+
+    // The unpackers don't number the entries, so the paths at these positions are found by listing
+    // the archive first
+    let entry_paths = if entries.is_empty() || !formats.first().is_some_and(Extension::is_archive) {
+        None
+    } else {
+        let formats = flatten_compression_formats(&formats);
+        let ControlFlow::Continue(paths) =
+            entry_paths_at(input_file_path, &formats, entries, zstd_dict, question_policy)?
+        else {
+            return Ok(None);
+        };
+        Some(
+            paths
+                .iter()
+                .filter_map(|path| utils::sanitize_entry_path(path).ok())
+                .collect(),
+        )
+    };
+    let mut selection = EntrySelection::new(include, entry_paths.as_ref());
+    if conflict_policy == ConflictPolicy::SkipExisting {
+        // Where `smart_unpack` would have moved each file, it depends on the roots of the archive
+        let destinations = if strip_components > 0 {
+            let dir = if one_dir_per_archive {
+                &output_file_path
+            } else {
+                output_dir
+            };
+            vec![(dir.to_path_buf(), strip_components)]
+        } else if one_dir_per_archive {
+            vec![(output_file_path.clone(), 0)]
+        } else {
+            let mut destinations = vec![(output_dir.to_path_buf(), 0), (output_file_path.clone(), 0)];
+            if auto_strip {
+                destinations.push((output_dir.to_path_buf(), 1));
+            }
+            destinations
+        };
+        selection = selection.skip_existing(ExtractedOutput {
+            destinations,
+            compare_mtime: preserve_mtime,
+        });
+    }
+
+    let size_limit = SizeLimit::new(input_file_path, max_decompressed_size);
+
+    // Progress is measured by how much of the (compressed) input file was read, the size of
+    // stdin isn't known, so there's no progress to report for it
+    let input_file = if input_is_stdin {
+        None
+    } else {
+        Some(SplitReader::open(input_file_path)?)
+    };
+    let progress_title = format!(
+        "Decompressing '{}'",
+        EscapedPathDisplay::new(strip_cur_dir(input_file_path))
+    );
+    let progress = Progress::new(
+        progress_title.clone(),
+        input_file.as_ref().map_or(0, SplitReader::size),
+        verbosity.hides_progress() || input_is_stdin,
+    );
 
     // Zip archives are special, because they require io::Seek, so it requires it's logic separated
     // from decoder chaining.
@@ -48,78 +159,235 @@ pub fn decompress_file(
         ..
     }] = formats.as_slice()
     {
+        let Some(input_file) = input_file else {
+            unreachable!("zip isn't allowed with stdin, it can't seek");
+        };
+        let reader = progress.wrap_read(input_file);
         let zip_archive = zip::ZipArchive::new(reader)?;
         let files_unpacked = if let ControlFlow::Continue(files) = smart_unpack(
-            |output_dir| crate::archive::zip::unpack_archive(zip_archive, output_dir, quiet),
+            |output_dir| {
+                crate::archive::zip::unpack_archive(
+                    zip_archive,
+                    input_file_path,
+                    output_dir,
+                    &selection,
+                    password,
+                    question_policy,
+                    preserve_permissions,
+                    preserve_mtime,
+                    &size_limit,
+                    verbosity,
+                )
+            },
             output_dir,
             &output_file_path,
             question_policy,
+            conflict_policy,
+            strip_components,
+            auto_strip,
+            &selection,
+            one_dir_per_archive,
+            to_stdout,
+            &mut skipped,
         )? {
             files
         } else {
-            return Ok(());
+            return Ok(None);
         };
 
         // this is only printed once, so it doesn't result in much text. On the other hand,
         // having a final status message is important especially in an accessibility context
         // as screen readers may not read a commands exit code, making it hard to reason
         // about whether the command succeeded without such a message
-        info_accessible(format!(
-            "Successfully decompressed archive in {} ({} files).",
-            nice_directory_display(output_dir),
-            files_unpacked
-        ));
+        if to_stdout {
+            info_accessible("Successfully decompressed to stdout.".to_string());
+        } else {
+            info_accessible(format!(
+                "Successfully decompressed archive in {} ({} files).",
+                nice_directory_display(output_dir),
+                files_unpacked
+            ));
+        }
 
-        return Ok(());
+        return Ok(Some(skipped));
     }
 
+    let reader: Box<dyn Read + Send> = match input_file {
+        Some(input_file) => Box::new(progress.wrap_read(input_file)),
+        None => Box::new(io::stdin()),
+    };
+
     // Will be used in decoder chaining
     let reader = BufReader::with_capacity(BUFFER_CAPACITY, reader);
-    let mut reader: Box<dyn Read> = Box::new(reader);
-
-    // Grab previous decoder and wrap it inside of a new one
-    let chain_reader_decoder = |format: &CompressionFormat, decoder: Box<dyn Read>| -> crate::Result<Box<dyn Read>> {
-        let decoder: Box<dyn Read> = match format {
-            Gzip => Box::new(flate2::read::GzDecoder::new(decoder)),
-            Bzip => Box::new(bzip2::read::BzDecoder::new(decoder)),
-            Lz4 => Box::new(lz4_flex::frame::FrameDecoder::new(decoder)),
-            Lzma => Box::new(xz2::read::XzDecoder::new(decoder)),
-            Snappy => Box::new(snap::read::FrameDecoder::new(decoder)),
-            Zstd => Box::new(zstd::stream::Decoder::new(decoder)?),
-            Tar | Zip | Rar | SevenZip => unreachable!(),
+    let mut reader: Box<dyn Read + Send> = Box::new(reader);
+
+    // Stdin has no size to measure the progress against, but zstd frames can store the size of
+    // their content, then it's measured on the decompressed data instead. Only the first decoder
+    // chained reads stdin directly
+    let mut stdin_progress_title = input_is_stdin.then_some(progress_title);
+
+    // Like `chain_reader_decoder`, with the progress of zstd frames read from stdin
+    let mut chain_decoder =
+        |format: &CompressionFormat, decoder: Box<dyn Read + Send>| -> crate::Result<Box<dyn Read + Send>> {
+            let Some(title) = stdin_progress_title.take().filter(|_| *format == Zstd) else {
+                return chain_reader_decoder(format, decoder, zstd_dict);
+            };
+
+            let mut decoder = BufReader::new(decoder);
+            let content_size = zstd::zstd_safe::get_frame_content_size(decoder.fill_buf()?)
+                .ok()
+                .flatten();
+            let zstd_decoder = chain_reader_decoder(format, Box::new(decoder), zstd_dict)?;
+            Ok(match content_size {
+                Some(content_size) => {
+                    Box::new(Progress::new(title, content_size, verbosity.hides_progress()).wrap_read(zstd_decoder))
+                }
+                None => zstd_decoder,
+            })
         };
-        Ok(decoder)
-    };
 
     let (first_extension, extensions) = split_first_compression_format(&formats);
 
-    for format in extensions.iter().rev() {
-        reader = chain_reader_decoder(format, reader)?;
+    // Every layer is checked before it's decompressed, a misnamed file fails with the layer that
+    // doesn't match, instead of a confusing error from its decoder
+    let layers: Vec<_> = extensions.iter().rev().chain([&first_extension]).copied().collect();
+    for (decoded, format) in extensions.iter().rev().enumerate() {
+        reader = chain_decoder(format, reader)?;
+        reader = check_layer_format(reader, input_file_path, &layers, decoded + 1)?;
     }
 
     let files_unpacked = match first_extension {
-        Gzip | Bzip | Lz4 | Lzma | Snappy | Zstd => {
-            reader = chain_reader_decoder(&first_extension, reader)?;
+        Gzip | Bzip | Lz4 | Xz | Lzma | Lzip | Deflate | Zlib | Snappy | Zstd => {
+            // Files compressed by the `gzip` tool keep their original name in the header
+            let output_file_path = if first_extension == Gzip && extensions.is_empty() {
+                let decoder = flate2::read::MultiGzDecoder::new(reader);
+                let original_name = gzip_original_name(&decoder);
+                reader = Box::new(decoder);
+                match original_name {
+                    Some(name) => output_file_path.with_file_name(name),
+                    None => output_file_path,
+                }
+            } else {
+                reader = chain_decoder(&first_extension, reader)?;
+                output_file_path
+            };
 
-            let mut writer = match utils::ask_to_create_file(&output_file_path, question_policy)? {
-                Some(file) => file,
-                None => return Ok(()),
+            let (mut writer, output): (Box<dyn Write>, _) = if input_is_stdin || to_stdout {
+                (Box::new(io::stdout()), None)
+            } else {
+                match utils::resolve_conflict(&output_file_path, conflict_policy, question_policy)? {
+                    Some(path) => {
+                        let writing = Writing::start(&path);
+                        (Box::new(fs::File::create(&path)?), Some((path, writing)))
+                    }
+                    None if conflict_policy == ConflictPolicy::Skip => return Ok(Some(1)),
+                    None => return Ok(None),
+                }
             };
 
-            io::copy(&mut reader, &mut writer)?;
+            let name = output_file_path.file_name().map_or(input_file_path, Path::new);
+            if let Err(err) = size_limit.copy(name, &mut reader, &mut writer) {
+                // Incomplete files aren't left behind, like those of archives, which are extracted
+                // to a temporary directory first
+                if let Some((path, _)) = &output {
+                    drop(writer);
+                    fs::remove_file(path)?;
+                }
+                return Err(err);
+            }
+            if let Some((_, writing)) = output {
+                writing.finish();
+            }
 
             1
         }
         Tar => {
             if let ControlFlow::Continue(files) = smart_unpack(
-                |output_dir| crate::archive::tar::unpack_archive(reader, output_dir, quiet),
+                |output_dir| {
+                    crate::archive::tar::unpack_archive(
+                        reader,
+                        output_dir,
+                        &selection,
+                        &size_limit,
+                        preserve_permissions,
+                        preserve_mtime,
+                        ownership,
+                        xattrs,
+                        verbosity,
+                    )
+                },
                 output_dir,
                 &output_file_path,
                 question_policy,
+                conflict_policy,
+                strip_components,
+                auto_strip,
+                &selection,
+                one_dir_per_archive,
+                to_stdout,
+                &mut skipped,
             )? {
                 files
             } else {
-                return Ok(());
+                return Ok(None);
+            }
+        }
+        Cpio => {
+            if let ControlFlow::Continue(files) = smart_unpack(
+                |output_dir| {
+                    crate::archive::cpio::unpack_archive(
+                        reader,
+                        output_dir,
+                        &selection,
+                        &size_limit,
+                        preserve_permissions,
+                        preserve_mtime,
+                        verbosity,
+                    )
+                },
+                output_dir,
+                &output_file_path,
+                question_policy,
+                conflict_policy,
+                strip_components,
+                auto_strip,
+                &selection,
+                one_dir_per_archive,
+                to_stdout,
+                &mut skipped,
+            )? {
+                files
+            } else {
+                return Ok(None);
+            }
+        }
+        Ar => {
+            if let ControlFlow::Continue(files) = smart_unpack(
+                |output_dir| {
+                    crate::archive::ar::unpack_archive(
+                        reader,
+                        output_dir,
+                        &selection,
+                        &size_limit,
+                        preserve_permissions,
+                        preserve_mtime,
+                        verbosity,
+                    )
+                },
+                output_dir,
+                &output_file_path,
+                question_policy,
+                conflict_policy,
+                strip_components,
+                auto_strip,
+                &selection,
+                one_dir_per_archive,
+                to_stdout,
+                &mut skipped,
+            )? {
+                files
+            } else {
+                return Ok(None);
             }
         }
         Zip => {
@@ -130,7 +398,7 @@ pub fn decompress_file(
 
                 warn_user_about_loading_zip_in_memory();
                 if !user_wants_to_continue(input_file_path, question_policy, QuestionAction::Decompression)? {
-                    return Ok(());
+                    return Ok(None);
                 }
             }
 
@@ -139,33 +407,73 @@ pub fn decompress_file(
             let zip_archive = zip::ZipArchive::new(io::Cursor::new(vec))?;
 
             if let ControlFlow::Continue(files) = smart_unpack(
-                |output_dir| crate::archive::zip::unpack_archive(zip_archive, output_dir, quiet),
+                |output_dir| {
+                    crate::archive::zip::unpack_archive(
+                        zip_archive,
+                        input_file_path,
+                        output_dir,
+                        &selection,
+                        password,
+                        question_policy,
+                        preserve_permissions,
+                        preserve_mtime,
+                        &size_limit,
+                        verbosity,
+                    )
+                },
                 output_dir,
                 &output_file_path,
                 question_policy,
+                conflict_policy,
+                strip_components,
+                auto_strip,
+                &selection,
+                one_dir_per_archive,
+                to_stdout,
+                &mut skipped,
             )? {
                 files
             } else {
-                return Ok(());
+                return Ok(None);
             }
         }
         #[cfg(feature = "unrar")]
         Rar => {
             type UnpackResult = crate::Result<usize>;
-            let unpack_fn: Box<dyn FnOnce(&Path) -> UnpackResult> = if formats.len() > 1 {
+            // unrar needs a path to read from, volumes of split archives have to be joined first
+            let input_is_split = utils::split::first_volume_base(input_file_path).is_some();
+            let unpack_fn: Box<dyn FnOnce(&Path) -> UnpackResult> = if formats.len() > 1
+                || input_is_stdin
+                || input_is_split
+            {
                 let mut temp_file = tempfile::NamedTempFile::new()?;
                 io::copy(&mut reader, &mut temp_file)?;
-                Box::new(move |output_dir| crate::archive::rar::unpack_archive(temp_file.path(), output_dir, quiet))
+                let (selection, size_limit) = (&selection, &size_limit);
+                Box::new(move |output_dir| {
+                    crate::archive::rar::unpack_archive(temp_file.path(), output_dir, selection, size_limit, verbosity)
+                })
             } else {
-                Box::new(|output_dir| crate::archive::rar::unpack_archive(input_file_path, output_dir, quiet))
+                Box::new(|output_dir| {
+                    crate::archive::rar::unpack_archive(input_file_path, output_dir, &selection, &size_limit, verbosity)
+                })
             };
 
-            if let ControlFlow::Continue(files) =
-                smart_unpack(unpack_fn, output_dir, &output_file_path, question_policy)?
-            {
+            if let ControlFlow::Continue(files) = smart_unpack(
+                unpack_fn,
+                output_dir,
+                &output_file_path,
+                question_policy,
+                conflict_policy,
+                strip_components,
+                auto_strip,
+                &selection,
+                one_dir_per_archive,
+                to_stdout,
+                &mut skipped,
+            )? {
                 files
             } else {
-                return Ok(());
+                return Ok(None);
             }
         }
         #[cfg(not(feature = "unrar"))]
@@ -180,7 +488,7 @@ pub fn decompress_file(
 
                 warn_user_about_loading_sevenz_in_memory();
                 if !user_wants_to_continue(input_file_path, question_policy, QuestionAction::Decompression)? {
-                    return Ok(());
+                    return Ok(None);
                 }
             }
 
@@ -188,14 +496,30 @@ pub fn decompress_file(
             io::copy(&mut reader, &mut vec)?;
 
             if let ControlFlow::Continue(files) = smart_unpack(
-                |output_dir| crate::archive::sevenz::decompress_sevenz(io::Cursor::new(vec), output_dir, quiet),
+                |output_dir| {
+                    crate::archive::sevenz::decompress_sevenz(
+                        io::Cursor::new(vec),
+                        output_dir,
+                        &selection,
+                        &size_limit,
+                        preserve_mtime,
+                        verbosity,
+                    )
+                },
                 output_dir,
                 &output_file_path,
                 question_policy,
+                conflict_policy,
+                strip_components,
+                auto_strip,
+                &selection,
+                one_dir_per_archive,
+                to_stdout,
+                &mut skipped,
             )? {
                 files
             } else {
-                return Ok(());
+                return Ok(None);
             }
         }
     };
@@ -204,25 +528,119 @@ pub fn decompress_file(
     // having a final status message is important especially in an accessibility context
     // as screen readers may not read a commands exit code, making it hard to reason
     // about whether the command succeeded without such a message
+    if to_stdout {
+        info_accessible("Successfully decompressed to stdout.".to_string());
+        return Ok(Some(skipped));
+    }
     info_accessible(format!(
         "Successfully decompressed archive in {}.",
         nice_directory_display(output_dir)
     ));
     info_accessible(format!("Files unpacked: {}", files_unpacked));
 
-    Ok(())
+    Ok(Some(skipped))
+}
+
+/// Check that the data of `reader`, left after decompressing the first `decoded` of `layers`, starts
+/// like the next layer, for the formats that have a magic number to recognize them by
+///
+/// Returns a reader with the same data, the start read to check it is put back
+fn check_layer_format(
+    mut reader: Box<dyn Read + Send>,
+    input_file_path: &Path,
+    layers: &[CompressionFormat],
+    decoded: usize,
+) -> crate::Result<Box<dyn Read + Send>> {
+    let format = layers[decoded];
+    let has_magic = matches!(
+        format,
+        Gzip | Bzip | Xz | Lzip | Lz4 | Snappy | Zstd | Zip | Rar | SevenZip
+    );
+    if !has_magic {
+        return Ok(reader);
+    }
+
+    let mut start = Vec::with_capacity(utils::INFER_LEN);
+    reader.by_ref().take(utils::INFER_LEN as u64).read_to_end(&mut start)?;
+    let detected = utils::infer_extension(&start);
+
+    // Some encoders write nothing at all for empty inputs, those are left to the decoder
+    let matches = detected
+        .as_ref()
+        .is_some_and(|extension| extension.compression_formats[0] == format);
+    if matches || start.is_empty() {
+        return Ok(Box::new(io::Cursor::new(start).chain(reader)));
+    }
+
+    let mut error = FinalError::with_title(format!(
+        "Cannot decompress '{}'",
+        EscapedPathDisplay::new(strip_cur_dir(input_file_path))
+    ))
+    .detail(format!(
+        "Layer {} of {} should be {}, going by the extensions, but it doesn't look like it",
+        decoded + 1,
+        layers.len(),
+        format.name(),
+    ))
+    .detail(format!(
+        "It's the data left after decompressing {}",
+        layers[..decoded]
+            .iter()
+            .map(CompressionFormat::name)
+            .collect::<Vec<_>>()
+            .join(", then ")
+    ));
+    if let Some(detected) = detected {
+        error = error.detail(format!(
+            "It looks like {} data instead",
+            detected.compression_formats[0].name()
+        ));
+    }
+    error = error.hint("The extensions of the file might not match its formats, use --format to give the right ones");
+
+    Err(Error::CorruptArchive { reason: error })
+}
+
+/// Name of the file compressed into a gzip stream, from the FNAME field of its header
+///
+/// Only the last component is kept, so the name can't point outside of the output directory.
+fn gzip_original_name<R>(decoder: &flate2::read::MultiGzDecoder<R>) -> Option<PathBuf> {
+    let name = decoder.header()?.filename()?;
+    let name = std::str::from_utf8(name).ok()?;
+    Path::new(name).file_name().map(PathBuf::from)
 }
 
 /// Unpacks an archive with some heuristics
 /// - If the archive contains only one file, it will be extracted to the `output_dir`
 /// - If the archive contains multiple files, it will be extracted to a subdirectory of the
 ///   output_dir named after the archive (given by `output_file_path`)
+/// - If `strip_components` is not zero, that many leading directories are removed from the
+///   extracted paths, and what's left is extracted to the `output_dir`, like `tar --strip-components`
+/// - If `auto_strip` is set and all the extracted files are inside of a single directory, it's
+///   removed as if `strip_components` was 1
+/// - If `selection` picks some entries, `unpack_fn` only extracts those
+/// - If `one_dir_per_archive` is set, everything is extracted to `output_file_path`, even a single
+///   file, and with `strip_components`
+/// - If `to_stdout` is set, the only file left after `selection` is written to stdout, and nothing
+///   is moved to `output_dir`
+///
+/// Paths that already exist are handled by `conflict_policy`, files skipped are added to `skipped`,
+/// with those `selection` found already extracted
+///
 /// Note: This functions assumes that `output_dir` exists
+#[allow(clippy::too_many_arguments)]
 fn smart_unpack(
     unpack_fn: impl FnOnce(&Path) -> crate::Result<usize>,
     output_dir: &Path,
     output_file_path: &Path,
     question_policy: QuestionPolicy,
+    conflict_policy: ConflictPolicy,
+    strip_components: usize,
+    auto_strip: bool,
+    selection: &EntrySelection,
+    one_dir_per_archive: bool,
+    to_stdout: bool,
+    skipped: &mut usize,
 ) -> crate::Result<ControlFlow<(), usize>> {
     assert!(output_dir.exists());
     let temp_dir = tempfile::tempdir_in(output_dir)?;
@@ -234,9 +652,57 @@ fn smart_unpack(
     ));
 
     let files = unpack_fn(temp_dir_path)?;
+    *skipped += selection.existing();
+
+    if selection.picks_entries() {
+        info_accessible(format!(
+            "Extracted {files} files, skipped {} that weren't picked by --include or --entry.",
+            selection.skipped()
+        ));
+    }
+    if (selection.picks_entries() || selection.existing() > 0)
+        && fs::read_dir(temp_dir_path)?.next().is_none()
+        && !to_stdout
+    {
+        return Ok(ControlFlow::Continue(files));
+    }
+
+    if to_stdout {
+        copy_only_file_to_stdout(temp_dir_path)?;
+        return Ok(ControlFlow::Continue(1));
+    }
+
+    // The files already extracted aren't in `temp_dir_path`, but they're still in the archive, so
+    // the layout of the output doesn't change when they're skipped
+    let mut roots = selection.existing_roots();
+    for entry in fs::read_dir(temp_dir_path)? {
+        roots.insert(entry?.file_name());
+    }
+
+    // Checked after `selection` skipped files, so that it's the root of what's extracted
+    let strip_components = if auto_strip && roots.len() == 1 && has_single_root_dir(temp_dir_path)? {
+        info_accessible("Removing the top-level directory all the extracted files are inside of.".into());
+        1
+    } else {
+        strip_components
+    };
+
+    let stripped_dir = tempfile::tempdir_in(output_dir)?;
+    let moves = if strip_components > 0 {
+        strip_extracted_components(temp_dir_path, strip_components, stripped_dir.path())?;
 
-    let root_contains_only_one_element = fs::read_dir(temp_dir_path)?.count() == 1;
-    if root_contains_only_one_element {
+        if one_dir_per_archive {
+            // What's left goes in the directory named after the archive
+            vec![(stripped_dir.path().to_path_buf(), output_file_path.to_path_buf())]
+        } else {
+            let mut moves = vec![];
+            for entry in fs::read_dir(stripped_dir.path())? {
+                let entry = entry?;
+                moves.push((entry.path(), output_dir.join(entry.file_name())));
+            }
+            moves
+        }
+    } else if !one_dir_per_archive && roots.len() == 1 {
         // Only one file in the root directory, so we can just move it to the output directory
         let file = fs::read_dir(temp_dir_path)?.next().expect("item exists")?;
         let file_path = file.path();
@@ -244,31 +710,148 @@ fn smart_unpack(
             .file_name()
             .expect("Should be safe because paths in archives should not end with '..'");
         let correct_path = output_dir.join(file_name);
-        // Before moving, need to check if a file with the same name already exists
-        if !utils::clear_path(&correct_path, question_policy)? {
-            return Ok(ControlFlow::Break(()));
-        }
-        fs::rename(&file_path, &correct_path)?;
-
-        info_accessible(format!(
-            "Successfully moved {} to {}.",
-            nice_directory_display(&file_path),
-            nice_directory_display(&correct_path)
-        ));
+        vec![(file_path, correct_path)]
     } else {
         // Multiple files in the root directory, so:
         // Rename the temporary directory to the archive name, which is output_file_path
-        // One case to handle tough is we need to check if a file with the same name already exists
-        if !utils::clear_path(output_file_path, question_policy)? {
-            return Ok(ControlFlow::Break(()));
+        vec![(temp_dir_path.to_path_buf(), output_file_path.to_path_buf())]
+    };
+
+    for (from, to) in &moves {
+        // Before moving, need to check if a file with the same name already exists
+        if conflict_policy == ConflictPolicy::Ask {
+            if !utils::clear_path(to, question_policy)? {
+                return Ok(ControlFlow::Break(()));
+            }
+            move_extracted(from, to)?;
+        } else {
+            *skipped += merge_extracted(from, to, conflict_policy, question_policy)?;
         }
-        fs::rename(temp_dir_path, output_file_path)?;
-        info_accessible(format!(
-            "Successfully moved {} to {}.",
-            nice_directory_display(temp_dir_path),
-            nice_directory_display(output_file_path)
-        ));
     }
+    interrupt::completed_moved(temp_dir_path, moves.into_iter().map(|(_, to)| to));
 
     Ok(ControlFlow::Continue(files))
 }
+
+/// Write the only file inside of `dir`, at any depth, to stdout, failing if there's none or more
+/// than one
+fn copy_only_file_to_stdout(dir: &Path) -> crate::Result<()> {
+    fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> crate::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if utils::is_symlink(&path) {
+                continue;
+            } else if path.is_dir() {
+                collect_files(&path, files)?;
+            } else {
+                files.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    let mut files = vec![];
+    collect_files(dir, &mut files)?;
+
+    let [file] = files.as_slice() else {
+        return Err(Error::InvalidUsage {
+            reason: FinalError::with_title("Cannot write the archive to stdout")
+                .detail(format!(
+                    "--include and --entry picked {} files, but only one can be written to stdout",
+                    files.len()
+                ))
+                .hint("Change --include or --entry so they pick a single file"),
+        });
+    };
+
+    io::copy(&mut fs::File::open(file)?, &mut io::stdout().lock())?;
+    Ok(())
+}
+
+/// Whether the only thing inside of `dir` is a directory
+fn has_single_root_dir(dir: &Path) -> crate::Result<bool> {
+    let mut entries = fs::read_dir(dir)?;
+    let (Some(root), None) = (entries.next().transpose()?, entries.next()) else {
+        return Ok(false);
+    };
+    let path = root.path();
+    Ok(path.is_dir() && !utils::is_symlink(&path))
+}
+
+/// Move what's inside of `dir`, without its first `count` components, into `to`
+///
+/// Files with `count` or fewer components are dropped, directories found under the same path
+/// after stripping are merged, and later files replace earlier ones, like `tar` does
+fn strip_extracted_components(dir: &Path, count: usize, to: &Path) -> crate::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let is_dir = path.is_dir() && !utils::is_symlink(&path);
+
+        if count > 0 {
+            if is_dir {
+                strip_extracted_components(&path, count - 1, to)?;
+            }
+            continue;
+        }
+
+        let target = to.join(entry.file_name());
+        if is_dir && target.is_dir() && !utils::is_symlink(&target) {
+            strip_extracted_components(&path, 0, &target)?;
+        } else {
+            if utils::is_symlink(&target) || target.is_file() {
+                fs::remove_file(&target)?;
+            } else if target.is_dir() {
+                fs::remove_dir_all(&target)?;
+            }
+            fs::rename(&path, &target)?;
+        }
+    }
+    Ok(())
+}
+
+fn move_extracted(from: &Path, to: &Path) -> crate::Result<()> {
+    fs::rename(from, to)?;
+    info_accessible(format!(
+        "Successfully moved {} to {}.",
+        nice_directory_display(from),
+        nice_directory_display(to)
+    ));
+    Ok(())
+}
+
+/// Move `from` to `to`, merging directories that exist in both, and resolving the conflicts of
+/// each file inside with `conflict_policy`
+///
+/// Returns how many files were skipped
+fn merge_extracted(
+    from: &Path,
+    to: &Path,
+    conflict_policy: ConflictPolicy,
+    question_policy: QuestionPolicy,
+) -> crate::Result<usize> {
+    if from.is_dir() && to.is_dir() && !utils::is_symlink(to) {
+        let mut skipped = 0;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            skipped += merge_extracted(
+                &entry.path(),
+                &to.join(entry.file_name()),
+                conflict_policy,
+                question_policy,
+            )?;
+        }
+        return Ok(skipped);
+    }
+
+    match utils::resolve_conflict(to, conflict_policy, question_policy)? {
+        Some(to) => {
+            move_extracted(from, &to)?;
+            Ok(0)
+        }
+        None => {
+            info_accessible(format!("Skipped {}, it already exists.", nice_directory_display(to)));
+            Ok(1)
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use std::io::{self, Write};
+
+use crate::extension::{supported_formats, FormatSummary};
+
+/// Print every supported format, its extensions and what `ouch` can do with it
+pub fn formats(json: bool) -> crate::Result<()> {
+    let summaries = supported_formats();
+    let out = &mut io::stdout().lock();
+    if json {
+        print_json(out, &summaries);
+    } else {
+        print_table(out, &summaries);
+    }
+    Ok(())
+}
+
+fn kind(summary: &FormatSummary) -> &'static str {
+    if summary.format.is_archive_format() {
+        "archive"
+    } else {
+        "compressor"
+    }
+}
+
+fn yes_no(value: bool) -> String {
+    if value { "yes" } else { "no" }.to_string()
+}
+
+/// Print a row per format with the columns aligned, formats only chosen with `--format` have no
+/// extensions
+fn print_table(out: &mut impl Write, summaries: &[FormatSummary]) {
+    let header = ["Format", "Type", "Compress", "Decompress", "Extensions"].map(String::from);
+    let rows: Vec<[String; 5]> = summaries
+        .iter()
+        .map(|summary| {
+            let extensions: Vec<_> = summary.extensions.iter().chain(&summary.aliases).copied().collect();
+            [
+                summary.format.name().to_string(),
+                kind(summary).to_string(),
+                yes_no(summary.format.can_compress()),
+                yes_no(summary.format.can_decompress()),
+                if extensions.is_empty() {
+                    "--format only".to_string()
+                } else {
+                    extensions.join(", ")
+                },
+            ]
+        })
+        .collect();
+
+    let mut widths = [0; 5];
+    for row in std::iter::once(&header).chain(&rows) {
+        for (width, column) in widths.iter_mut().zip(row) {
+            *width = (*width).max(column.chars().count());
+        }
+    }
+
+    for row in std::iter::once(&header).chain(&rows) {
+        let line = row
+            .iter()
+            .zip(widths)
+            .map(|(column, width)| format!("{column:<width$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+        let _ = writeln!(out, "{}", line.trim_end());
+    }
+}
+
+/// Print the formats as a JSON array
+fn print_json(out: &mut impl Write, summaries: &[FormatSummary]) {
+    fn json_strings(strings: &[&str]) -> String {
+        let strings: Vec<_> = strings.iter().map(|string| format!("\"{string}\"")).collect();
+        format!("[{}]", strings.join(", "))
+    }
+
+    let _ = writeln!(out, "[");
+    for (index, summary) in summaries.iter().enumerate() {
+        let separator = if index + 1 < summaries.len() { "," } else { "" };
+        let _ = writeln!(
+            out,
+            r#"  {{"format": "{}", "type": "{}", "compress": {}, "decompress": {}, "extensions": {}, "aliases": {}}}{separator}"#,
+            summary.format.name(),
+            kind(summary),
+            summary.format.can_compress(),
+            summary.format.can_decompress(),
+            json_strings(&summary.extensions),
+            json_strings(&summary.aliases),
+        );
+    }
+    let _ = writeln!(out, "]");
+}
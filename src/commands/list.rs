@@ -1,26 +1,116 @@
 use std::{
     io::{self, BufReader, Read},
-    path::Path,
+    ops::ControlFlow,
+    path::{Path, PathBuf},
 };
 
 use fs_err as fs;
+use ignore::overrides::Override;
 
 use crate::{
-    commands::warn_user_about_loading_zip_in_memory,
+    commands::{chain_reader_decoder, warn_user_about_loading_sevenz_in_memory, warn_user_about_loading_zip_in_memory},
+    error::{Error, FinalError},
     extension::CompressionFormat::{self, *},
-    list::{self, FileInArchive, ListOptions},
-    utils::{io::lock_and_flush_output_stdio, user_wants_to_continue},
+    list::{FileInArchive, ListPrinter},
+    utils::{io::lock_and_flush_output_stdio, is_whitelisted, user_wants_to_continue, EscapedPathDisplay},
     QuestionAction, QuestionPolicy, BUFFER_CAPACITY,
 };
 
+/// Entries of an archive, read as they're iterated, and its comment
+type ArchiveEntries = (Box<dyn Iterator<Item = crate::Result<FileInArchive>>>, Option<String>);
+
 /// File at input_file_path is opened for reading, example: "archive.tar.gz"
 /// formats contains each format necessary for decompression, example: [Gz, Tar] (in decompression order)
+/// filter, if given, only keeps the entries it whitelists, as they are read
+/// zstd_dict is the dictionary zstd frames were compressed with, if any
 pub fn list_archive_contents(
     archive_path: &Path,
     formats: Vec<CompressionFormat>,
-    list_options: ListOptions,
+    printer: &mut ListPrinter,
+    filter: Option<&Override>,
+    zstd_dict: Option<&[u8]>,
     question_policy: QuestionPolicy,
 ) -> crate::Result<()> {
+    if let (&[Zip], true, None) = (formats.as_slice(), printer.counts_only(), filter) {
+        let zip_archive = zip::ZipArchive::new(fs::File::open(archive_path)?)?;
+
+        // Reading the central directory is enough to count the files, without reading the local
+        // header of each of them, the size is unknown if some are stored with data descriptors
+        let total_size = zip_archive
+            .decompressed_size()
+            .and_then(|size| u64::try_from(size).ok());
+        if let Some(total_size) = total_size {
+            let comment = crate::archive::zip::archive_comment(&zip_archive);
+            let file_count = zip_archive.file_names().filter(|name| !name.ends_with('/')).count();
+            printer.list_count(archive_path, comment.as_deref(), file_count, total_size);
+            return Ok(());
+        }
+    }
+
+    let ControlFlow::Continue((files, comment)) = archive_entries(archive_path, &formats, zstd_dict, question_policy)?
+    else {
+        return Ok(());
+    };
+    printer.list_files(archive_path, comment.as_deref(), filter_entries(files, filter))?;
+    Ok(())
+}
+
+/// Paths of the entries of the archive at `archive_path` at the 1-based `positions`, in the order
+/// `ouch list` prints them, failing if a position is past the last entry
+///
+/// Returns `ControlFlow::Break` if the user chose not to continue.
+pub fn entry_paths_at(
+    archive_path: &Path,
+    formats: &[CompressionFormat],
+    positions: &[usize],
+    zstd_dict: Option<&[u8]>,
+    question_policy: QuestionPolicy,
+) -> crate::Result<ControlFlow<(), Vec<PathBuf>>> {
+    let ControlFlow::Continue((files, _)) = archive_entries(archive_path, formats, zstd_dict, question_policy)? else {
+        return Ok(ControlFlow::Break(()));
+    };
+
+    let mut paths = vec![];
+    let mut entry_count = 0;
+    for (position, file) in (1..).zip(files) {
+        let file = file?;
+        entry_count = position;
+        if positions.contains(&position) {
+            paths.push(file.path);
+        }
+    }
+
+    if let Some(&position) = positions
+        .iter()
+        .find(|&&position| position == 0 || position > entry_count)
+    {
+        let valid_range = match entry_count {
+            0 => "It has no entries".to_string(),
+            1 => "Its only entry is 1".to_string(),
+            _ => format!("Its entries go from 1 to {entry_count}"),
+        };
+        return Err(Error::InvalidUsage {
+            reason: FinalError::with_title(format!(
+                "No entry {position} in '{}'",
+                EscapedPathDisplay::new(archive_path)
+            ))
+            .detail(valid_range)
+            .hint("Entries are numbered in the order 'ouch list' prints them, starting at 1"),
+        });
+    }
+
+    Ok(ControlFlow::Continue(paths))
+}
+
+/// Read the entries of the archive at `archive_path`, decompressing zstd frames with `zstd_dict`
+///
+/// Returns `ControlFlow::Break` if the user chose not to continue.
+fn archive_entries(
+    archive_path: &Path,
+    formats: &[CompressionFormat],
+    zstd_dict: Option<&[u8]>,
+    question_policy: QuestionPolicy,
+) -> crate::Result<ControlFlow<(), ArchiveEntries>> {
     let reader = fs::File::open(archive_path)?;
 
     // Zip archives are special, because they require io::Seek, so it requires it's logic separated
@@ -30,39 +120,36 @@ pub fn list_archive_contents(
     // in-memory decompression/copying first.
     //
     // Any other Zip decompression done can take up the whole RAM and freeze ouch.
-    if let &[Zip] = formats.as_slice() {
+    if let &[Zip] = formats {
         let zip_archive = zip::ZipArchive::new(reader)?;
-        let files = crate::archive::zip::list_archive(zip_archive);
-        list::list_files(archive_path, files, list_options)?;
+        let comment = crate::archive::zip::archive_comment(&zip_archive);
+        let files = Box::new(crate::archive::zip::list_archive(zip_archive));
 
-        return Ok(());
+        return Ok(ControlFlow::Continue((files, comment)));
+    }
+
+    // 7z archives also require io::Seek, but unlike zip, only their headers are read
+    if let &[SevenZip] = formats {
+        let files = Box::new(crate::archive::sevenz::list_archive(reader)?);
+
+        return Ok(ControlFlow::Continue((files, None)));
     }
 
     // Will be used in decoder chaining
     let reader = BufReader::with_capacity(BUFFER_CAPACITY, reader);
     let mut reader: Box<dyn Read + Send> = Box::new(reader);
 
-    // Grab previous decoder and wrap it inside of a new one
-    let chain_reader_decoder =
-        |format: &CompressionFormat, decoder: Box<dyn Read + Send>| -> crate::Result<Box<dyn Read + Send>> {
-            let decoder: Box<dyn Read + Send> = match format {
-                Gzip => Box::new(flate2::read::GzDecoder::new(decoder)),
-                Bzip => Box::new(bzip2::read::BzDecoder::new(decoder)),
-                Lz4 => Box::new(lz4_flex::frame::FrameDecoder::new(decoder)),
-                Lzma => Box::new(xz2::read::XzDecoder::new(decoder)),
-                Snappy => Box::new(snap::read::FrameDecoder::new(decoder)),
-                Zstd => Box::new(zstd::stream::Decoder::new(decoder)?),
-                Tar | Zip | Rar | SevenZip => unreachable!(),
-            };
-            Ok(decoder)
-        };
-
     for format in formats.iter().skip(1).rev() {
-        reader = chain_reader_decoder(format, reader)?;
+        reader = chain_reader_decoder(format, reader, zstd_dict)?;
     }
 
+    // Only zip archives have a comment
+    let mut comment = None;
+
     let files: Box<dyn Iterator<Item = crate::Result<FileInArchive>>> = match formats[0] {
         Tar => Box::new(crate::archive::tar::list_archive(tar::Archive::new(reader))),
+        Cpio => Box::new(crate::archive::cpio::list_archive(reader)),
+        Ar => Box::new(crate::archive::ar::list_archive(reader)),
         Zip => {
             if formats.len() > 1 {
                 // Locking necessary to guarantee that warning and question
@@ -71,13 +158,14 @@ pub fn list_archive_contents(
 
                 warn_user_about_loading_zip_in_memory();
                 if !user_wants_to_continue(archive_path, question_policy, QuestionAction::Decompression)? {
-                    return Ok(());
+                    return Ok(ControlFlow::Break(()));
                 }
             }
 
             let mut vec = vec![];
             io::copy(&mut reader, &mut vec)?;
             let zip_archive = zip::ZipArchive::new(io::Cursor::new(vec))?;
+            comment = crate::archive::zip::archive_comment(&zip_archive);
 
             Box::new(crate::archive::zip::list_archive(zip_archive))
         }
@@ -101,27 +189,31 @@ pub fn list_archive_contents(
                 // messages stay adjacent
                 let _locks = lock_and_flush_output_stdio();
 
-                warn_user_about_loading_zip_in_memory();
+                warn_user_about_loading_sevenz_in_memory();
                 if !user_wants_to_continue(archive_path, question_policy, QuestionAction::Decompression)? {
-                    return Ok(());
+                    return Ok(ControlFlow::Break(()));
                 }
             }
 
-            let mut files = Vec::new();
+            let mut vec = vec![];
+            io::copy(&mut reader, &mut vec)?;
 
-            sevenz_rust::decompress_file_with_extract_fn(archive_path, ".", |entry, _, _| {
-                files.push(Ok(FileInArchive {
-                    path: entry.name().into(),
-                    is_dir: entry.is_directory(),
-                }));
-                Ok(true)
-            })?;
-            Box::new(files.into_iter())
+            Box::new(crate::archive::sevenz::list_archive(io::Cursor::new(vec))?)
         }
-        Gzip | Bzip | Lz4 | Lzma | Snappy | Zstd => {
+        Gzip | Bzip | Lz4 | Xz | Lzma | Lzip | Deflate | Zlib | Snappy | Zstd => {
             panic!("Not an archive! This should never happen, if it does, something is wrong with `CompressionFormat::is_archive()`. Please report this error!");
         }
     };
-    list::list_files(archive_path, files, list_options)?;
-    Ok(())
+    Ok(ControlFlow::Continue((files, comment)))
+}
+
+/// Entries of `files` that `filter` whitelists, or all of them if there's no filter, errors are kept
+fn filter_entries<'a>(
+    files: impl IntoIterator<Item = crate::Result<FileInArchive>> + 'a,
+    filter: Option<&'a Override>,
+) -> impl Iterator<Item = crate::Result<FileInArchive>> + 'a {
+    files.into_iter().filter(move |file| match (file, filter) {
+        (Ok(file), Some(filter)) => is_whitelisted(filter, &file.path, file.is_dir),
+        _ => true,
+    })
 }
@@ -1,25 +1,103 @@
 //! Receive command from the cli and call the respective function for that command.
 
+mod benchmark;
 mod compress;
+mod convert;
 mod decompress;
+mod formats;
 mod list;
+mod test;
+mod train_dict;
 
-use std::{ops::ControlFlow, path::PathBuf};
+use std::{
+    ffi::OsString,
+    io::{self, Read, Write},
+    num::NonZeroUsize,
+    ops::ControlFlow,
+    path::{Path, PathBuf},
+    slice, thread,
+};
 
+use clap::CommandFactory;
+use fs_err as fs;
 use rayon::prelude::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
 use utils::colors;
 
 use crate::{
+    archive::{self, tar::Ownership, SkippedFiles},
     check,
-    cli::Subcommand,
-    commands::{compress::compress_files, decompress::decompress_file, list::list_archive_contents},
+    cli::{ConflictPolicy, Subcommand},
+    commands::{
+        benchmark::benchmark,
+        compress::{auto_compression_format, compress_files, compress_files_into_dir, print_dry_run, total_input_size},
+        convert::convert_file,
+        decompress::decompress_file,
+        formats::formats,
+        list::list_archive_contents,
+        test::test_archive,
+        train_dict::train_dict,
+    },
     error::{Error, FinalError},
-    extension::{self, parse_format},
-    list::ListOptions,
-    utils::{self, colors::*, logger::info_accessible, to_utf, EscapedPathDisplay, FileVisibilityPolicy},
+    extension::{self, parse_format, CompressionFormat, Extension},
+    list::{ListFormat, ListOptions, ListPrinter},
+    utils::{
+        self, checksum,
+        colors::*,
+        logger::{self, info, info_accessible, warning, Verbosity},
+        progress,
+        split::{self, SplitWriter},
+        strip_cur_dir, to_utf, EscapedPathDisplay, FileVisibilityPolicy, SymlinkPolicy,
+    },
     CliArgs, QuestionPolicy,
 };
 
+/// Name of the directory that archives read from stdin are unpacked to, if they have multiple
+/// entries at their root
+const STDIN_OUTPUT_NAME: &str = "stdin";
+
+/// What the names of the entries of archives made from `files` are relative to, failing if an
+/// input isn't inside of `base_dir`, or `change_dir`, which is used the same way
+fn archive_entry_base(
+    files: &[PathBuf],
+    base_dir: Option<PathBuf>,
+    change_dir: Option<PathBuf>,
+    absolute_paths: bool,
+) -> crate::Result<archive::EntryBase> {
+    let (base_dir, option) = match (base_dir, change_dir) {
+        (Some(base_dir), _) => (base_dir, "--base-dir"),
+        (None, Some(change_dir)) => (change_dir, "--change-dir"),
+        (None, None) => {
+            return Ok(match absolute_paths {
+                true => archive::EntryBase::Root,
+                false => archive::EntryBase::Parent,
+            })
+        }
+    };
+
+    // Compared with the inputs, which are canonicalized
+    let base_dir = fs::canonicalize(&base_dir).map_err(|err| {
+        FinalError::with_title(format!(
+            "Cannot use '{}' as {option}",
+            EscapedPathDisplay::new(&base_dir)
+        ))
+        .detail(format!("Error: {err}."))
+    })?;
+    for file in files.iter().filter(|file| !utils::is_stdio(file)) {
+        if !file.starts_with(&base_dir) {
+            return Err(Error::InvalidUsage {
+                reason: FinalError::with_title(format!(
+                    "Cannot name '{}' relative to {option}",
+                    EscapedPathDisplay::new(file)
+                ))
+                .detail(format!("It isn't inside of '{}'", EscapedPathDisplay::new(&base_dir)))
+                .hint(format!("Give a {option} that has every input in it")),
+            });
+        }
+    }
+
+    Ok(archive::EntryBase::Dir(base_dir))
+}
+
 /// Warn the user that (de)compressing this .zip archive might freeze their system.
 fn warn_user_about_loading_zip_in_memory() {
     const ZIP_IN_MEMORY_LIMITATION_WARNING: &str = "\n  \
@@ -27,7 +105,9 @@ fn warn_user_about_loading_zip_in_memory() {
         When chaining '.zip' with other formats, all (de)compression needs to be done in-memory\n  \
         Careful, you might run out of RAM if the archive is too large!";
 
-    eprintln!("{}[WARNING]{}: {ZIP_IN_MEMORY_LIMITATION_WARNING}", *ORANGE, *RESET);
+    if !logger::is_quiet() {
+        progress::suspend(|| eprintln!("{}[WARNING]{}: {ZIP_IN_MEMORY_LIMITATION_WARNING}", *ORANGE, *RESET));
+    }
 }
 
 /// Warn the user that (de)compressing this .7z archive might freeze their system.
@@ -37,7 +117,294 @@ fn warn_user_about_loading_sevenz_in_memory() {
         When chaining '.7z' with other formats, all (de)compression needs to be done in-memory\n  \
         Careful, you might run out of RAM if the archive is too large!";
 
-    eprintln!("{}[WARNING]{}: {SEVENZ_IN_MEMORY_LIMITATION_WARNING}", *ORANGE, *RESET);
+    if !logger::is_quiet() {
+        progress::suspend(|| eprintln!("{}[WARNING]{}: {SEVENZ_IN_MEMORY_LIMITATION_WARNING}", *ORANGE, *RESET));
+    }
+}
+
+/// Grab previous decoder and wrap it inside of a new one, for every command reading compressed
+/// data, `zstd_dict` is the dictionary zstd frames were compressed with, if any
+fn chain_reader_decoder(
+    format: &CompressionFormat,
+    decoder: Box<dyn Read + Send>,
+    zstd_dict: Option<&[u8]>,
+) -> crate::Result<Box<dyn Read + Send>> {
+    use CompressionFormat::*;
+
+    let decoder: Box<dyn Read + Send> = match format {
+        Gzip => Box::new(flate2::read::MultiGzDecoder::new(decoder)),
+        Bzip => Box::new(bzip2::read::MultiBzDecoder::new(decoder)),
+        Lz4 => Box::new(lz4_flex::frame::FrameDecoder::new(decoder)),
+        Xz => Box::new(xz2::read::XzDecoder::new_multi_decoder(decoder)),
+        Lzma => Box::new(xz2::read::XzDecoder::new_stream(
+            decoder,
+            xz2::stream::Stream::new_lzma_decoder(u64::MAX)?,
+        )),
+        Lzip => Box::new(utils::lzip::LzipDecoder::new(decoder)),
+        Deflate => Box::new(flate2::read::DeflateDecoder::new(decoder)),
+        Zlib => Box::new(flate2::read::ZlibDecoder::new(decoder)),
+        Snappy => Box::new(snap::read::FrameDecoder::new(decoder)),
+        Zstd => match zstd_dict {
+            Some(dictionary) => Box::new(zstd::stream::Decoder::with_dictionary(
+                io::BufReader::new(decoder),
+                dictionary,
+            )?),
+            None => Box::new(zstd::stream::Decoder::new(decoder)?),
+        },
+        Tar | Zip | Rar | SevenZip | Cpio | Ar => unreachable!(),
+    };
+    Ok(decoder)
+}
+
+/// Delete the incomplete file left at `output_path` by an aborted or failed compression.
+///
+/// If deleting fails, print an extra alert message pointing out that we left a
+/// possibly CORRUPTED file at `output_path`.
+fn remove_incomplete_output(output_path: &Path, compression_failed: bool) {
+    if utils::remove_file_or_dir(output_path).is_err() {
+        eprintln!("{red}FATAL ERROR:\n", red = *colors::RED);
+        eprintln!(
+            "  Ouch failed to delete the file '{}'.",
+            EscapedPathDisplay::new(output_path)
+        );
+        eprintln!("  Please delete it manually.");
+        eprintln!("  This file is corrupted if compression didn't finished.");
+
+        if compression_failed {
+            eprintln!("  Compression failed for reasons below.");
+        }
+    }
+}
+
+/// Delete the inputs of a successful compression, stdin is left alone
+fn remove_inputs(files: &[PathBuf]) -> crate::Result<()> {
+    for file in files.iter().filter(|file| !utils::is_stdio(file)) {
+        utils::remove_file_or_dir(file)?;
+        info_accessible(format!("Removed '{}'.", to_utf(file)));
+    }
+    Ok(())
+}
+
+/// Fail with the paths listed by `--files-from` that couldn't be read, once the others are
+/// compressed, so the exit code tells that the output is missing some of them
+fn report_unreadable_listed(unreadable: &[(PathBuf, String)]) -> crate::Result<()> {
+    if unreadable.is_empty() {
+        return Ok(());
+    }
+
+    Err(Error::MissingInputs {
+        reason: unreadable_listed_error(unreadable).hint("They were left out, every other path was compressed"),
+    })
+}
+
+/// Error saying how many files `--skip-errors` left out of the archives, if any, and that the
+/// inputs they're in weren't deleted if `remove` is set
+fn report_skipped_files(skipped: &SkippedFiles, remove: bool) -> crate::Result<()> {
+    let title = match skipped.count() {
+        0 => return Ok(()),
+        1 => "A file couldn't be read".to_string(),
+        count => format!("{count} files couldn't be read"),
+    };
+
+    let mut error = FinalError::with_title(title).detail("They were skipped by --skip-errors, as warned above");
+    if remove {
+        error = error.detail("The inputs they're in weren't removed, as they aren't fully archived");
+    }
+    Err(Error::MissingInputs {
+        reason: error.hint("They were left out, every other file was compressed"),
+    })
+}
+
+/// Error listing the paths given by `--files-from` that couldn't be read, with the reason why
+fn unreadable_listed_error(unreadable: &[(PathBuf, String)]) -> FinalError {
+    let title = if unreadable.len() == 1 {
+        "A listed path couldn't be read".to_string()
+    } else {
+        format!("{} listed paths couldn't be read", unreadable.len())
+    };
+    let mut error = FinalError::with_title(title);
+    for (path, err) in unreadable {
+        error = error.detail(format!("'{}': {err}", EscapedPathDisplay::new(path)));
+    }
+    error
+}
+
+/// Delete an archive that was decompressed successfully, with every volume if it's split
+fn remove_archive(path: &Path) -> crate::Result<()> {
+    let volumes = match split::first_volume_base(path) {
+        Some(base_path) => split::volume_paths(&base_path),
+        None => vec![path.to_path_buf()],
+    };
+    remove_inputs(&volumes)
+}
+
+/// Check if walking `files` yields anything other than directories
+fn any_file_left(files: &[PathBuf], file_visibility_policy: &FileVisibilityPolicy) -> bool {
+    files.iter().any(|file| {
+        utils::is_stdio(file)
+            || file_visibility_policy
+                .build_walker(file)
+                .flatten()
+                .any(|entry| entry.file_type().is_some_and(|file_type| !file_type.is_dir()))
+    })
+}
+
+/// Formats that are left to apply when compressing `files` into `formats`
+///
+/// A single input file already in the first formats of the output, like "file.tar.gz" into
+/// "file.tar.gz.xz", is only compressed with the formats that follow them, instead of being
+/// compressed twice. Formats are compared extension by extension, an input whose formats aren't
+/// the first ones of the output, like "file.gz" into "file.tar.gz", is compressed as is.
+fn skip_input_formats(files: &[PathBuf], formats: Vec<Extension>, output_path: &Path) -> crate::Result<Vec<Extension>> {
+    let [file] = files else {
+        return Ok(formats);
+    };
+    let input_formats = extension::extensions_from_path(file);
+    if !file.is_file() || input_formats.is_empty() || !formats.starts_with(&input_formats) {
+        return Ok(formats);
+    }
+
+    let join = |formats: &[Extension]| formats.iter().map(Extension::to_string).collect::<Vec<_>>().join(".");
+    let (already_applied, left) = formats.split_at(input_formats.len());
+
+    if left.is_empty() {
+        return Err(Error::InvalidUsage {
+            reason: FinalError::with_title(format!(
+                "Cannot compress '{}' into '{}'",
+                EscapedPathDisplay::new(strip_cur_dir(file)),
+                EscapedPathDisplay::new(strip_cur_dir(output_path)),
+            ))
+            .detail(format!(
+                "The input is already in the '{}' format of the output",
+                join(already_applied)
+            ))
+            .hint("Add the formats to compress it with to the output, like 'file.gz.xz'")
+            .hint("Or use 'ouch convert' to change its formats"),
+        });
+    }
+
+    info_accessible(format!(
+        "'{}' is already a '{}' file, only compressing it with '{}'.",
+        EscapedPathDisplay::new(strip_cur_dir(file)),
+        join(already_applied),
+        join(left)
+    ));
+    Ok(left.to_vec())
+}
+
+/// Like "Compressed 1.50 MiB into 512.00 kiB, 33.3% of the original size."
+fn compression_summary(input_size: u64, output_size: u64) -> String {
+    let sizes = format!(
+        "Compressed {} into {}",
+        utils::Bytes::new(input_size),
+        utils::Bytes::new(output_size)
+    );
+    if input_size == 0 {
+        return format!("{sizes}.");
+    }
+    let ratio = output_size as f64 * 100.0 / input_size as f64;
+    format!("{sizes}, {ratio:.1}% of the original size.")
+}
+
+/// Report the files skipped by `--min-size` and `--max-size`
+fn report_size_excluded(file_visibility_policy: &FileVisibilityPolicy) {
+    let (count, size) = file_visibility_policy.size_excluded_summary();
+    if count > 0 {
+        info_accessible(format!(
+            "Skipped {count} files because of their size ({}).",
+            utils::Bytes::new(size)
+        ));
+    }
+}
+
+/// The formats of each input file, in the same order as the files
+type FormatsOfFiles = Vec<Vec<Extension>>;
+
+/// Formats of each of the `files` to be decompressed, from `--format` or their extensions, along
+/// with the file names they decompress to when they aren't archives.
+///
+/// Returns `ControlFlow::Break` if the user chose not to continue.
+fn decompression_formats(
+    files: &[PathBuf],
+    format_flag: Option<OsString>,
+    question_policy: QuestionPolicy,
+) -> crate::Result<ControlFlow<(), (Vec<PathBuf>, FormatsOfFiles)>> {
+    let mut output_paths = vec![];
+    let mut formats = vec![];
+
+    if let Some(format) = format_flag {
+        let format = parse_format(&format)?;
+        for path in files.iter() {
+            let file_name = if utils::is_stdio(path) {
+                PathBuf::from(STDIN_OUTPUT_NAME)
+            } else {
+                // The volumes of split archives are named after it, like "archive.001"
+                let path = split::first_volume_base(path).unwrap_or_else(|| path.clone());
+                path.file_name()
+                    .ok_or_else(|| Error::NotFound {
+                        error_title: format!("{} does not have a file name", EscapedPathDisplay::new(&path)),
+                    })?
+                    .into()
+            };
+            output_paths.push(file_name);
+            formats.push(extension::outermost_archive_formats(format.clone()));
+        }
+    } else {
+        for path in files.iter() {
+            // The extensions of split archives come before the volume number, like "archive.tar.gz.001"
+            let name_path = split::first_volume_base(path).unwrap_or_else(|| path.clone());
+            let (pathbase, mut file_formats) = extension::separate_known_extensions_from_name(&name_path);
+            let pathbase = pathbase.to_path_buf();
+
+            // Stdin can't be sniffed without consuming it, `--format` is required instead
+            if !utils::is_stdio(path) {
+                if let ControlFlow::Break(_) = check::check_mime_type(path, &mut file_formats, question_policy)? {
+                    return Ok(ControlFlow::Break(()));
+                }
+            }
+
+            output_paths.push(pathbase);
+            formats.push(extension::outermost_archive_formats(file_formats));
+        }
+    }
+
+    Ok(ControlFlow::Continue((output_paths, formats)))
+}
+
+/// For `--raw`, drop the archive formats of `formats`, so only the compression around them is
+/// undone, and name the outputs after the archives, like "archive.tar" for "archive.tar.gz"
+fn raw_archive_formats(
+    files: &[PathBuf],
+    mut output_paths: Vec<PathBuf>,
+    mut formats: FormatsOfFiles,
+) -> crate::Result<(Vec<PathBuf>, FormatsOfFiles)> {
+    for ((path, file_name), formats) in files.iter().zip(&mut output_paths).zip(&mut formats) {
+        // Not an archive, decompressed as usual
+        let Some(archive) = extension::strip_archive_format(formats) else {
+            continue;
+        };
+        if formats.is_empty() {
+            return Err(Error::InvalidUsage {
+                reason: FinalError::with_title(format!(
+                    "Cannot decompress '{}' with --raw",
+                    EscapedPathDisplay::new(path)
+                ))
+                .detail(format!(
+                    "It's a {} archive without compression, there's nothing to undo",
+                    archive.name()
+                ))
+                .hint("Extract its files without --raw, or use it as it is"),
+            });
+        }
+
+        let extension = match archive {
+            CompressionFormat::Ar => "a",
+            archive => archive.name(),
+        };
+        file_name.as_mut_os_string().push(format!(".{extension}"));
+    }
+
+    Ok((output_paths, formats))
 }
 
 /// This function checks what command needs to be run and performs A LOT of ahead-of-time checks
@@ -49,27 +416,321 @@ pub fn run(
     question_policy: QuestionPolicy,
     file_visibility_policy: FileVisibilityPolicy,
 ) -> crate::Result<()> {
+    let verbosity = Verbosity::new(args.quiet, args.verbose);
+    // Values of environment variables and the config file are defaults, a level out of range is
+    // clamped by each format and threads are left unused by the formats that don't need them
+    let given = |id: &str| !args.defaults.iter().any(|default| default == id);
+    let threads_given = args.threads.is_some() && given("threads");
     match args.cmd {
         Subcommand::Compress {
             files,
             output: output_path,
+            output_dir,
+            each,
+            // Already appended to `files`
+            files_from: _,
+            null: _,
+            files_from_stdin0: _,
+            unreadable_listed,
             level,
             fast,
             slow,
+            zstd_long,
+            zstd_dict,
+            password,
+            password_stdin,
+            password_file,
+            comment,
+            exclude,
+            reproducible,
+            mtime,
+            base_dir,
+            change_dir,
+            absolute_paths,
+            sparse,
+            xattrs,
+            // Already part of `file_visibility_policy`
+            dereference: _,
+            no_dereference: _,
+            skip_symlinks: _,
+            max_size: _,
+            min_size: _,
+            newer_than: _,
+            max_depth: _,
+            no_recursive: _,
+            sort: _,
+            split_size,
+            dry_run,
+            checksum,
+            remove,
+            append,
+            in_memory_limit,
+            auto_compress,
+            best,
+            skip_errors,
+            // Run by `main` once the command is done
+            on_success: _,
+            on_failure: _,
         } => {
+            // Inputs aren't filtered when walking them, only what's inside of them, so the size
+            // limits and --newer-than are applied to the input files here
+            let files: Vec<_> = files
+                .into_iter()
+                .filter(|file| match fs::metadata(file) {
+                    Ok(metadata) if metadata.is_file() => {
+                        !file_visibility_policy.is_excluded_by_metadata(file, &metadata)
+                    }
+                    _ => true,
+                })
+                .collect();
+
             // After cleaning, if there are no input files left, exit
-            if files.is_empty() {
+            if files.is_empty() && !file_visibility_policy.filters_by_metadata() {
+                if !unreadable_listed.is_empty() {
+                    return Err(Error::MissingInputs {
+                        reason: unreadable_listed_error(&unreadable_listed).hint("No other path was left to compress"),
+                    });
+                }
                 return Err(FinalError::with_title("No files to compress").into());
             }
 
-            // Formats from path extension, like "file.tar.gz.xz" -> vec![Tar, Gzip, Lzma]
+            // Directories would still be archived, without any file in them
+            if file_visibility_policy.filters_by_metadata() && !any_file_left(&files, &file_visibility_policy) {
+                return Err(FinalError::with_title("No files to compress")
+                    .detail("Every file was skipped by --min-size, --max-size or --newer-than")
+                    .into());
+            }
+
+            let mut output_path = match output_dir {
+                Some(output_dir) => {
+                    if output_path.is_absolute() || utils::is_stdio(&output_path) {
+                        return Err(Error::InvalidUsage {
+                            reason: FinalError::with_title("Cannot use --output-dir with this output")
+                                .detail(format!(
+                                    "'{}' already says where to write the output",
+                                    EscapedPathDisplay::new(&output_path)
+                                ))
+                                .hint("Give only the output file name, like 'archive.tar.gz', or drop --output-dir"),
+                        });
+                    }
+                    // Nothing is written in a dry run
+                    if !dry_run {
+                        utils::create_dir_if_non_existent(&output_dir)?;
+                    }
+                    // With --each, the output is the directory the archives are written to
+                    match each {
+                        true => output_dir,
+                        false => output_dir.join(output_path),
+                    }
+                }
+                None => output_path,
+            };
+
+            let output_is_stdout = utils::is_stdio(&output_path);
+            // The output directory of --each doesn't exist yet in a dry run
+            let output_is_dir = each || output_path.is_dir();
+
+            let zstd_dict = zstd_dict.map(fs::read).transpose()?;
+            let skipped_files = SkippedFiles::default();
+            let skip_errors = skip_errors.then_some(&skipped_files);
+
+            let password = if password_stdin {
+                Some(utils::read_password_from_stdin()?)
+            } else if let Some(password_file) = password_file {
+                Some(utils::read_password_from_file(&password_file)?)
+            } else {
+                password
+            };
+
+            // These are clamped by each format to their lowest and highest levels
+            let clamped_level = if fast {
+                Some(1) // Lowest level of compression
+            } else if slow {
+                Some(i16::MAX) // Highest level of compression
+            } else {
+                level
+            };
+
+            let reproducible = reproducible.then(|| archive::Reproducible {
+                mtime: mtime.unwrap_or(0),
+            });
+            let entry_base = archive_entry_base(&files, base_dir, change_dir, absolute_paths)?;
+
+            if split_size.is_some() && (output_is_stdout || output_is_dir) {
+                return Err(Error::InvalidUsage {
+                    reason: FinalError::with_title("Cannot split the output into volumes")
+                        .detail("--split-size only works when compressing into a single output file")
+                        .hint("Give the path of the output file instead, like 'archive.tar.gz'"),
+                });
+            }
+
+            if checksum.is_some() && (output_is_stdout || output_is_dir || split_size.is_some()) {
+                return Err(Error::InvalidUsage {
+                    reason: FinalError::with_title("Cannot write a checksum file for this output")
+                        .detail(
+                            "--checksum only works when compressing into a single output file, without --split-size",
+                        )
+                        .hint("Give the path of the output file instead, like 'archive.tar.gz'"),
+                });
+            }
+
+            if remove {
+                // Outputs are written in the output path when it's a directory, or next to it
+                let output_dir = if output_is_stdout {
+                    None
+                } else if output_is_dir {
+                    Some(output_path.as_path())
+                } else {
+                    output_path.parent()
+                };
+                // `exclude` is also part of `file_visibility_policy`
+                let files_are_filtered = args.hidden
+                    || args.gitignore
+                    || !exclude.is_empty()
+                    || file_visibility_policy.min_size.is_some()
+                    || file_visibility_policy.max_size.is_some()
+                    || file_visibility_policy.newer_than.is_some()
+                    || file_visibility_policy.max_depth.is_some()
+                    || !file_visibility_policy.recursive
+                    || file_visibility_policy.symlinks == SymlinkPolicy::Skip;
+                check::check_remove_inputs(&files, output_dir, files_are_filtered)?;
+            }
+
+            if output_is_dir && !output_is_stdout {
+                if files.iter().any(|file| utils::is_stdio(file)) {
+                    return Err(Error::InvalidUsage {
+                        reason: FinalError::with_title("Cannot compress stdin into a directory")
+                            .detail(
+                                "Files compressed into a directory are named after the inputs, but stdin has no name",
+                            )
+                            .hint("Give the path of the output file instead"),
+                    });
+                }
+
+                let Some(formats_from_flag) = args.format else {
+                    return Err(Error::InvalidUsage {
+                        reason: FinalError::with_title(format!(
+                            "Cannot compress into directory '{}'.",
+                            EscapedPathDisplay::new(&output_path)
+                        ))
+                        .detail("Each file is compressed separately when the output is a directory.")
+                        .detail("The compression formats can't be inferred from a directory.")
+                        .hint("Use --format to choose them, for example:")
+                        .hint(format!(
+                            "  ouch compress <FILES>... {} --format gz",
+                            EscapedPathDisplay::new(&output_path)
+                        )),
+                    });
+                };
+                let formats = parse_format(&formats_from_flag)?;
+
+                if append {
+                    check::check_append(&formats, &output_path)?;
+                }
+                if let Some(level) = level.filter(|_| given("level")) {
+                    check::check_compression_level(&formats, level)?;
+                }
+                // The inputs are compressed in parallel, whatever the format
+                check::check_zstd_options(&formats, zstd_long, zstd_dict.is_some(), false);
+                check::check_password_options(&formats, password.is_some());
+                check::check_comment_options(&formats, comment.is_some());
+                check::check_reproducible_options(&formats, reproducible.is_some());
+                check::check_sparse_options(&formats, sparse);
+                check::check_xattrs_options(&formats, xattrs);
+                check::check_entry_base_options(&formats, &entry_base);
+
+                if dry_run {
+                    print_dry_run(&files, &file_visibility_policy, &entry_base)?;
+                    report_size_excluded(&file_visibility_policy);
+                    return Ok(());
+                }
+
+                let threads = args.threads.map_or_else(
+                    || thread::available_parallelism().map_or(1, NonZeroUsize::get),
+                    |n| n as usize,
+                );
+
+                let result = compress_files_into_dir(
+                    files,
+                    formats,
+                    &formats_from_flag,
+                    &output_path,
+                    threads,
+                    verbosity,
+                    question_policy,
+                    file_visibility_policy.clone(),
+                    &entry_base,
+                    clamped_level,
+                    zstd_long,
+                    args.threads,
+                    zstd_dict.as_deref(),
+                    password.as_deref(),
+                    comment.as_deref(),
+                    reproducible,
+                    sparse,
+                    xattrs,
+                    skip_errors,
+                    in_memory_limit,
+                    remove,
+                );
+                report_size_excluded(&file_visibility_policy);
+                return result
+                    .and_then(|()| report_unreadable_listed(&unreadable_listed))
+                    .and_then(|()| report_skipped_files(&skipped_files, remove));
+            }
+
+            // Formats from path extension, like "file.tar.gz.xz" -> vec![Tar, Gzip, Xz]
             let (formats_from_flag, formats) = match args.format {
                 Some(formats) => {
                     let parsed_formats = parse_format(&formats)?;
                     (Some(formats), parsed_formats)
                 }
+                None if output_is_stdout => {
+                    return Err(Error::InvalidUsage {
+                        reason: FinalError::with_title("Cannot infer the compression formats of stdout")
+                            .detail("The formats are inferred from the extensions of the output file, stdout has none")
+                            .hint("Use --format to choose them, for example:")
+                            .hint("  ouch compress <FILES>... - --format tar.gz"),
+                    });
+                }
                 None => (None, extension::extensions_from_path(&output_path)),
             };
+            let mut formats = skip_input_formats(&files, formats, &output_path)?;
+
+            // Plain archives, the only outputs that aren't compressed at all
+            let is_plain_archive = matches!(
+                formats.as_slice(),
+                [Extension {
+                    compression_formats: [CompressionFormat::Tar | CompressionFormat::Cpio],
+                    ..
+                }]
+            );
+            if is_plain_archive && formats_from_flag.is_none() && !append {
+                if auto_compress {
+                    let format = auto_compression_format(&files, &file_visibility_policy, clamped_level, best)?;
+                    let mut name = output_path.into_os_string();
+                    name.push(format!(".{format}"));
+                    output_path = PathBuf::from(name);
+                    info_accessible(format!(
+                        "Also compressing with '{format}', into '{}'.",
+                        EscapedPathDisplay::new(&output_path)
+                    ));
+                    formats.push(format);
+                } else if !args.quiet {
+                    info(format!(
+                        "'{}' won't be compressed, add a format like '.zst' to its name, or use --auto-compress.",
+                        EscapedPathDisplay::new(&output_path)
+                    ));
+                }
+            }
+            if append {
+                check::check_append(&formats, &output_path)?;
+            }
+
+            check::check_stdin_input(&files, &formats)?;
+            if output_is_stdout {
+                check::check_streamed_formats(&formats, "stdout")?;
+            }
 
             check::check_invalid_compression_with_non_archive_format(
                 &formats,
@@ -78,87 +739,229 @@ pub fn run(
                 formats_from_flag.as_ref(),
             )?;
             check::check_archive_formats_position(&formats, &output_path)?;
+            if let Some(level) = level.filter(|_| given("level")) {
+                check::check_compression_level(&formats, level)?;
+            }
+            check::check_zstd_options(&formats, zstd_long, zstd_dict.is_some(), threads_given);
+            check::check_password_options(&formats, password.is_some());
+            check::check_comment_options(&formats, comment.is_some());
+            check::check_reproducible_options(&formats, reproducible.is_some());
+            check::check_sparse_options(&formats, sparse);
+            check::check_xattrs_options(&formats, xattrs);
+            check::check_entry_base_options(&formats, &entry_base);
 
-            let output_file = match utils::ask_to_create_file(&output_path, question_policy)? {
-                Some(writer) => writer,
-                None => return Ok(()),
-            };
+            // Return before the output file is created
+            if dry_run {
+                print_dry_run(&files, &file_visibility_policy, &entry_base)?;
+                report_size_excluded(&file_visibility_policy);
+                return Ok(());
+            }
 
-            let level = if fast {
-                Some(1) // Lowest level of compression
-            } else if slow {
-                Some(i16::MAX) // Highest level of compression
+            // Where the appended entries start, the archive is cut back there if compression fails
+            let mut append_start = None;
+
+            let output_file: Box<dyn Write + Send> = if output_is_stdout {
+                Box::new(io::stdout())
+            } else if append && output_path.exists() {
+                let (file, end) = archive::tar::open_for_append(&output_path)?;
+                append_start = Some(end);
+                Box::new(file)
+            } else if let Some(volume_size) = split_size {
+                let Some(first_volume) =
+                    utils::ask_to_create_file(&split::volume_path(&output_path, 1), question_policy)?
+                else {
+                    return Err(Error::UserDeclined);
+                };
+                // Volumes left by a previous split of the same archive would be read as part of this one
+                for old_volume in split::volume_paths(&output_path).into_iter().skip(1) {
+                    utils::remove_file_or_dir(&old_volume)?;
+                }
+                Box::new(SplitWriter::new(output_path.clone(), volume_size, first_volume))
             } else {
-                level
+                match utils::ask_to_create_file(&output_path, question_policy)? {
+                    Some(writer) => Box::new(writer),
+                    None => return Err(Error::UserDeclined),
+                }
             };
 
+            // Only deleted if compression succeeds
+            let files_to_remove = if remove { files.clone() } else { vec![] };
+
+            // For the summary printed after compressing, the size of stdin isn't known
+            let input_is_stdin = files.iter().any(|file| utils::is_stdio(file));
+            // The output also holds the entries appended to, which would make the ratio meaningless
+            let input_size = (!args.quiet && !input_is_stdin && !output_is_stdout && append_start.is_none())
+                .then(|| total_input_size(&files, &file_visibility_policy));
+
             let compress_result = compress_files(
                 files,
                 formats,
                 output_file,
                 &output_path,
-                args.quiet,
+                verbosity,
                 question_policy,
-                file_visibility_policy,
-                level,
+                file_visibility_policy.clone(),
+                &entry_base,
+                clamped_level,
+                zstd_long,
+                args.threads,
+                zstd_dict.as_deref(),
+                password.as_deref(),
+                comment.as_deref(),
+                reproducible,
+                sparse,
+                xattrs,
+                skip_errors,
+                in_memory_limit,
             );
 
+            let compress_result = match (compress_result, checksum) {
+                (Ok(true), Some(algorithm)) => {
+                    checksum::write_checksum_file(&output_path, algorithm).map(|checksum_path| {
+                        info_accessible(format!("Checksum written to '{}'.", to_utf(&checksum_path)));
+                        true
+                    })
+                }
+                (result, _) => result,
+            };
+
             if let Ok(true) = compress_result {
                 // this is only printed once, so it doesn't result in much text. On the other hand,
                 // having a final status message is important especially in an accessibility context
                 // as screen readers may not read a commands exit code, making it hard to reason
                 // about whether the command succeeded without such a message
-                info_accessible(format!("Successfully compressed '{}'.", to_utf(&output_path)));
-            } else {
-                // If Ok(false) or Err() occurred, delete incomplete file at `output_path`
-                //
-                // if deleting fails, print an extra alert message pointing
-                // out that we left a possibly CORRUPTED file at `output_path`
-                if utils::remove_file_or_dir(&output_path).is_err() {
-                    eprintln!("{red}FATAL ERROR:\n", red = *colors::RED);
-                    eprintln!(
-                        "  Ouch failed to delete the file '{}'.",
-                        EscapedPathDisplay::new(&output_path)
-                    );
-                    eprintln!("  Please delete it manually.");
-                    eprintln!("  This file is corrupted if compression didn't finished.");
+                if output_is_stdout {
+                    info_accessible("Successfully compressed to stdout.".to_string());
+                } else if split_size.is_some() {
+                    info_accessible(format!(
+                        "Successfully compressed '{}' into {} volumes.",
+                        to_utf(&output_path),
+                        split::volume_paths(&output_path).len()
+                    ));
+                } else {
+                    info_accessible(format!("Successfully compressed '{}'.", to_utf(&output_path)));
+                }
 
-                    if compress_result.is_err() {
-                        eprintln!("  Compression failed for reasons below.");
-                    }
+                if let Some(input_size) = input_size {
+                    let output_size = if split_size.is_some() {
+                        let volumes = split::volume_paths(&output_path);
+                        volumes
+                            .iter()
+                            .map(|volume| Ok(fs::metadata(volume)?.len()))
+                            .sum::<io::Result<u64>>()?
+                    } else {
+                        fs::metadata(&output_path)?.len()
+                    };
+                    info_accessible(compression_summary(input_size, output_size));
+                }
+                report_size_excluded(&file_visibility_policy);
+                // Files left out by --skip-errors would be lost
+                if skipped_files.count() == 0 {
+                    remove_inputs(&files_to_remove)?;
                 }
+            } else if let Some(append_start) = append_start {
+                // If Ok(false) or Err() occurred, keep the archive as it was before
+                archive::tar::truncate_archive(&output_path, append_start)?;
+            } else if split_size.is_some() {
+                // If Ok(false) or Err() occurred, delete every volume written so far
+                for volume in split::volume_paths(&output_path) {
+                    remove_incomplete_output(&volume, compress_result.is_err());
+                }
+            } else if !output_is_stdout {
+                // If Ok(false) or Err() occurred, delete incomplete file at `output_path`
+                remove_incomplete_output(&output_path, compress_result.is_err());
             }
 
-            compress_result.map(|_| ())
+            match compress_result? {
+                true => report_unreadable_listed(&unreadable_listed)
+                    .and_then(|()| report_skipped_files(&skipped_files, remove)),
+                false => Err(Error::UserDeclined),
+            }
         }
-        Subcommand::Decompress { files, output_dir } => {
-            let mut output_paths = vec![];
-            let mut formats = vec![];
+        Subcommand::Decompress {
+            files,
+            output_dir,
+            password,
+            password_file,
+            zstd_dict,
+            no_preserve_permissions,
+            no_preserve_mtime,
+            preserve_owner,
+            numeric_owner,
+            xattrs,
+            on_conflict,
+            skip_existing,
+            strip_components,
+            auto_strip,
+            include,
+            entry,
+            verify_checksum,
+            remove,
+            one_dir_per_archive,
+            to_stdout,
+            max_decompressed_size,
+            raw,
+            // Run by `main` once the command is done
+            on_success: _,
+            on_failure: _,
+        } => {
+            let ControlFlow::Continue((output_paths, formats)) =
+                decompression_formats(&files, args.format, question_policy)?
+            else {
+                return Err(Error::UserDeclined);
+            };
 
-            if let Some(format) = args.format {
-                let format = parse_format(&format)?;
-                for path in files.iter() {
-                    let file_name = path.file_name().ok_or_else(|| Error::NotFound {
-                        error_title: format!("{} does not have a file name", EscapedPathDisplay::new(path)),
-                    })?;
-                    output_paths.push(file_name.as_ref());
-                    formats.push(format.clone());
+            check::check_missing_formats_when_decompressing(&files, &formats)?;
+            let (output_paths, formats) = if raw {
+                raw_archive_formats(&files, output_paths, formats)?
+            } else {
+                (output_paths, formats)
+            };
+            for (path, formats) in files.iter().zip(&formats) {
+                if utils::is_stdio(path) {
+                    check::check_streamed_formats(formats, "stdin")?;
                 }
+            }
+            let password = match password_file {
+                Some(password_file) => Some(utils::read_password_from_file(&password_file)?),
+                None => password,
+            };
+            check::check_password_options(&formats.concat(), password.is_some());
+            check::check_zstd_options(&formats.concat(), false, zstd_dict.is_some(), false);
+            check::check_include_options(&formats.concat(), !include.is_empty());
+            check::check_entry_options(&files, &formats.concat(), !entry.is_empty())?;
+            if to_stdout {
+                check::check_to_stdout(&files, &formats, !include.is_empty() || !entry.is_empty())?;
+            }
+            let ownership = if numeric_owner {
+                Ownership::Numeric
+            } else if preserve_owner {
+                Ownership::ByName
             } else {
-                for path in files.iter() {
-                    let (pathbase, mut file_formats) = extension::separate_known_extensions_from_name(path);
-
-                    if let ControlFlow::Break(_) = check::check_mime_type(path, &mut file_formats, question_policy)? {
-                        return Ok(());
-                    }
+                Ownership::Current
+            };
+            let ownership = check::check_owner_options(&formats.concat(), ownership);
+            check::check_xattrs_options(&formats.concat(), xattrs);
+            let zstd_dict = zstd_dict.map(fs::read).transpose()?;
+            let include = if include.is_empty() {
+                None
+            } else {
+                Some(utils::build_glob_whitelist(&include, "include")?)
+            };
 
-                    output_paths.push(pathbase);
-                    formats.push(file_formats);
+            // Verify every file before extracting any of them
+            if verify_checksum {
+                if files.iter().any(|file| utils::is_stdio(file)) {
+                    return Err(Error::InvalidUsage {
+                        reason: FinalError::with_title("Cannot verify the checksum of stdin")
+                            .detail("Checksum files are looked up next to the input file, stdin has no name"),
+                    });
+                }
+                for file in &files {
+                    checksum::verify_checksum_file(file)?;
                 }
             }
 
-            check::check_missing_formats_when_decompressing(&files, &formats)?;
-
             // The directory that will contain the output files
             // We default to the current directory if the user didn't specify an output directory with --dir
             let output_dir = if let Some(dir) = output_dir {
@@ -168,27 +971,110 @@ pub fn run(
                 PathBuf::from(".")
             };
 
-            files
-                .par_iter()
-                .zip(formats)
-                .zip(output_paths)
-                .try_for_each(|((input_path, formats), file_name)| {
-                    let output_file_path = output_dir.join(file_name); // Path used by single file format archives
-                    decompress_file(
-                        input_path,
-                        formats,
-                        &output_dir,
-                        output_file_path,
-                        question_policy,
-                        args.quiet,
-                    )
-                })
+            let conflict_policy = match skip_existing {
+                true => ConflictPolicy::SkipExisting,
+                false => on_conflict.unwrap_or(ConflictPolicy::Ask),
+            };
+
+            // Archives are independent, each one is decompressed by a thread of the pool
+            let threads = args.threads.map_or_else(
+                || thread::available_parallelism().map_or(1, NonZeroUsize::get),
+                |n| n as usize,
+            );
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .map_err(|err| {
+                    FinalError::with_title("Could not start decompression threads").detail(err.to_string())
+                })?;
+
+            let results: Vec<_> = pool.install(|| {
+                files
+                    .par_iter()
+                    .zip(formats)
+                    .zip(output_paths)
+                    .map(|((input_path, formats), file_name)| -> crate::Result<Option<usize>> {
+                        let output_file_path = output_dir.join(file_name); // Path used by single file format archives
+                        let skipped = decompress_file(
+                            input_path,
+                            formats,
+                            &output_dir,
+                            output_file_path,
+                            question_policy,
+                            password.as_deref(),
+                            zstd_dict.as_deref(),
+                            !no_preserve_permissions,
+                            !no_preserve_mtime,
+                            ownership,
+                            xattrs,
+                            conflict_policy,
+                            strip_components,
+                            auto_strip,
+                            include.as_ref(),
+                            &entry,
+                            one_dir_per_archive,
+                            to_stdout,
+                            max_decompressed_size,
+                            verbosity,
+                        )?;
+
+                        // Archives are only deleted when everything in them was extracted
+                        match skipped {
+                            Some(0) if remove && !utils::is_stdio(input_path) => remove_archive(input_path)?,
+                            Some(skipped) if remove && skipped > 0 => warning(format!(
+                                "Kept '{}', some of its files were skipped.",
+                                to_utf(input_path)
+                            )),
+                            _ => {}
+                        }
+
+                        Ok(skipped)
+                    })
+                    .collect()
+            });
+
+            // A failed archive doesn't stop the others, their errors are reported together at the end
+            let mut skipped = 0;
+            let mut declined = false;
+            let mut errors = vec![];
+            for (input_path, result) in files.iter().zip(results) {
+                match result {
+                    Ok(Some(skipped_files)) => skipped += skipped_files,
+                    Ok(None) => declined = true,
+                    Err(err) => errors.push((input_path, err)),
+                }
+            }
+
+            if skipped > 0 {
+                info_accessible(format!("Skipped {skipped} files that already existed."));
+            }
+
+            match errors.len() {
+                0 if declined => Err(Error::UserDeclined),
+                0 => Ok(()),
+                1 => Err(errors.remove(0).1),
+                failed => Err(Error::from_errors(
+                    format!("Failed to decompress {failed} of {} archives", files.len()),
+                    errors.iter().map(|(input_path, err)| {
+                        (format!("'{}'", EscapedPathDisplay::new(strip_cur_dir(input_path))), err)
+                    }),
+                )),
+            }
         }
-        Subcommand::List { archives: files, tree } => {
+        Subcommand::List {
+            archives: files,
+            tree,
+            json,
+            human,
+            filter,
+            count,
+            long,
+            zstd_dict,
+        } => {
             let mut formats = vec![];
 
             if let Some(format) = args.format {
-                let format = parse_format(&format)?;
+                let format = extension::outermost_archive_formats(parse_format(&format)?);
                 for _ in 0..files.len() {
                     formats.push(format.clone());
                 }
@@ -197,26 +1083,240 @@ pub fn run(
                     let mut file_formats = extension::extensions_from_path(path);
 
                     if let ControlFlow::Break(_) = check::check_mime_type(path, &mut file_formats, question_policy)? {
-                        return Ok(());
+                        return Err(Error::UserDeclined);
                     }
 
-                    formats.push(file_formats);
+                    formats.push(extension::outermost_archive_formats(file_formats));
                 }
             }
 
             // Ensure we were not told to list the content of a non-archive compressed file
             check::check_for_non_archive_formats(&files, &formats)?;
+            check::check_zstd_options(&formats.concat(), false, zstd_dict.is_some(), false);
+            let zstd_dict = zstd_dict.map(fs::read).transpose()?;
 
-            let list_options = ListOptions { tree };
+            let format = if json { ListFormat::Json } else { ListFormat::Text };
+            let filtered = !filter.is_empty();
+            let filter = if filtered {
+                Some(utils::build_glob_whitelist(&filter, "filter")?)
+            } else {
+                None
+            };
+            let mut printer = ListPrinter::new(ListOptions {
+                tree,
+                format,
+                human,
+                filtered,
+                count,
+                long,
+            });
 
-            for (i, (archive_path, formats)) in files.iter().zip(formats).enumerate() {
-                if i > 0 {
-                    println!();
-                }
+            for (archive_path, formats) in files.iter().zip(formats) {
                 let formats = extension::flatten_compression_formats(&formats);
-                list_archive_contents(archive_path, formats, list_options, question_policy)?;
+                list_archive_contents(
+                    archive_path,
+                    formats,
+                    &mut printer,
+                    filter.as_ref(),
+                    zstd_dict.as_deref(),
+                    question_policy,
+                )?;
+            }
+            printer.finish();
+
+            Ok(())
+        }
+        Subcommand::Test {
+            archives: files,
+            password,
+            password_file,
+            zstd_dict,
+        } => {
+            if files.iter().any(|file| utils::is_stdio(file)) {
+                return Err(Error::InvalidUsage {
+                    reason: FinalError::with_title("Cannot test stdin")
+                        .detail("Only archives given by their paths can be tested"),
+                });
+            }
+
+            let ControlFlow::Continue((_, formats)) = decompression_formats(&files, args.format, question_policy)?
+            else {
+                return Err(Error::UserDeclined);
+            };
+
+            check::check_missing_formats_when_decompressing(&files, &formats)?;
+            let password = match password_file {
+                Some(password_file) => Some(utils::read_password_from_file(&password_file)?),
+                None => password,
+            };
+            check::check_password_options(&formats.concat(), password.is_some());
+            check::check_zstd_options(&formats.concat(), false, zstd_dict.is_some(), false);
+            let zstd_dict = zstd_dict.map(fs::read).transpose()?;
+
+            let mut errors = vec![];
+            for (archive_path, formats) in files.iter().zip(formats) {
+                let path = EscapedPathDisplay::new(strip_cur_dir(archive_path));
+                match test_archive(
+                    archive_path,
+                    formats,
+                    question_policy,
+                    password.as_deref(),
+                    zstd_dict.as_deref(),
+                    args.quiet,
+                ) {
+                    Ok(Some(files_tested)) => println!("{path}: {}OK{} ({files_tested} files)", *GREEN, *RESET),
+                    Ok(None) => println!("{path}: SKIPPED"),
+                    Err(err) => {
+                        println!("{path}: {}FAILED{}", *RED, *RESET);
+                        eprintln!("{err}");
+                        errors.push(err);
+                    }
+                }
+            }
+
+            if !errors.is_empty() {
+                // The errors were already printed, only their exit code is kept
+                let reason =
+                    FinalError::with_title(format!("{} of {} archives failed the test", errors.len(), files.len()));
+                return Err(Error::summarize(reason, &errors));
+            }
+
+            Ok(())
+        }
+        Subcommand::Convert {
+            input,
+            output: output_path,
+            level,
+            zstd_dict,
+        } => {
+            if utils::is_stdio(&input) || utils::is_stdio(&output_path) {
+                return Err(Error::InvalidUsage {
+                    reason: FinalError::with_title("Cannot convert from stdin or to stdout")
+                        .detail("Give the paths of the input and output files instead"),
+                });
             }
 
+            let (_, input_formats) = extension::separate_known_extensions_from_name(&input);
+            let output_formats = extension::extensions_from_path(&output_path);
+
+            check::check_missing_formats_when_decompressing(slice::from_ref(&input), slice::from_ref(&input_formats))?;
+            if output_formats.is_empty() {
+                return Err(Error::InvalidUsage {
+                    reason: FinalError::with_title(format!(
+                        "Cannot convert into '{}'",
+                        EscapedPathDisplay::new(&output_path)
+                    ))
+                    .detail("The new formats are inferred from the extensions of the output file, it has none")
+                    .hint(format!(
+                        "Add the extensions of the new formats, like '{}.tar.zst'",
+                        EscapedPathDisplay::new(&output_path)
+                    )),
+                });
+            }
+            if input_formats[0].is_archive() && !output_formats[0].is_archive() {
+                return Err(Error::InvalidUsage {
+                    reason: FinalError::with_title("Cannot convert an archive into a single compressed file")
+                        .detail(format!(
+                            "'{}' is an archive, but '{}' doesn't start with an archive format",
+                            EscapedPathDisplay::new(strip_cur_dir(&input)),
+                            EscapedPathDisplay::new(&output_path)
+                        ))
+                        .hint("Use an archive format first, like 'tar.zst' or 'zip'"),
+                });
+            }
+            check::check_archive_formats_position(&output_formats, &output_path)?;
+            if let Some(level) = level.filter(|_| given("level")) {
+                check::check_compression_level(&output_formats, level)?;
+            }
+            check::check_zstd_options(&input_formats, false, zstd_dict.is_some(), false);
+            check::check_zstd_options(&output_formats, false, false, threads_given);
+            let zstd_dict = zstd_dict.map(fs::read).transpose()?;
+
+            if output_path.exists() && same_file::is_same_file(&input, &output_path)? {
+                return Err(Error::InvalidUsage {
+                    reason: FinalError::with_title("Cannot convert a file into itself")
+                        .detail("The output would be overwritten while the input is read"),
+                });
+            }
+
+            let Some(output_file) = utils::ask_to_create_file(&output_path, question_policy)? else {
+                return Err(Error::UserDeclined);
+            };
+
+            let convert_result = convert_file(
+                &input,
+                input_formats,
+                output_file,
+                &output_path,
+                output_formats,
+                question_policy,
+                verbosity,
+                level,
+                args.threads,
+                zstd_dict.as_deref(),
+            );
+
+            if let Ok(true) = convert_result {
+                info_accessible(format!(
+                    "Successfully converted '{}' into '{}'.",
+                    to_utf(strip_cur_dir(&input)),
+                    to_utf(&output_path)
+                ));
+            } else {
+                // If Ok(false) or Err() occurred, delete incomplete file at `output_path`
+                remove_incomplete_output(&output_path, convert_result.is_err());
+            }
+
+            match convert_result? {
+                true => Ok(()),
+                false => Err(Error::UserDeclined),
+            }
+        }
+        Subcommand::TrainDict {
+            files,
+            output: output_path,
+            max_size,
+        } => {
+            let dictionary = train_dict(
+                &files,
+                usize::try_from(max_size).unwrap_or(usize::MAX),
+                &file_visibility_policy,
+            )?;
+
+            let Some(mut output_file) = utils::ask_to_create_file(&output_path, question_policy)? else {
+                return Err(Error::UserDeclined);
+            };
+            output_file.write_all(&dictionary)?;
+
+            info_accessible(format!(
+                "Dictionary of {} written to '{}'.",
+                utils::Bytes::new(dictionary.len() as u64),
+                to_utf(&output_path)
+            ));
+
+            Ok(())
+        }
+        Subcommand::Benchmark {
+            files,
+            formats,
+            levels,
+            json,
+        } => benchmark(
+            &files,
+            &formats,
+            &levels,
+            args.threads,
+            json,
+            question_policy,
+            &file_visibility_policy,
+        ),
+        Subcommand::Formats { json } => formats(json),
+        Subcommand::Completions { shell } => {
+            clap_complete::generate(shell, &mut CliArgs::command(), "ouch", &mut io::stdout());
+            Ok(())
+        }
+        Subcommand::Man => {
+            clap_mangen::Man::new(CliArgs::command()).render(&mut io::stdout())?;
             Ok(())
         }
     }
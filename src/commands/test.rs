@@ -0,0 +1,128 @@
+use std::{
+    io::{self, BufReader, Read},
+    path::Path,
+};
+
+use crate::{
+    commands::{chain_reader_decoder, warn_user_about_loading_sevenz_in_memory, warn_user_about_loading_zip_in_memory},
+    extension::{split_first_compression_format, CompressionFormat::*, Extension},
+    utils::{
+        io::lock_and_flush_output_stdio, progress::Progress, split::SplitReader, strip_cur_dir, user_wants_to_continue,
+        EscapedPathDisplay,
+    },
+    QuestionAction, QuestionPolicy, BUFFER_CAPACITY,
+};
+
+/// Test the integrity of an archive, or compressed file, by decompressing all of it without
+/// writing the results anywhere
+///
+/// File at archive_path is opened for reading, example: "archive.tar.gz", if it's the first
+/// volume of a split archive, like "archive.tar.gz.001", all volumes are read
+/// formats contains each format necessary for decompression, example: [Gz, Tar] (in decompression order)
+/// password is used to decrypt zip archives, if not given the user is asked for it when needed
+/// zstd_dict is the dictionary zstd frames were compressed with, if any
+///
+/// Returns the number of files tested, or `None` if the user chose not to continue
+pub fn test_archive(
+    archive_path: &Path,
+    formats: Vec<Extension>,
+    question_policy: QuestionPolicy,
+    password: Option<&str>,
+    zstd_dict: Option<&[u8]>,
+    quiet: bool,
+) -> crate::Result<Option<usize>> {
+    let input_file = SplitReader::open(archive_path)?;
+    let progress = Progress::new(
+        format!("Testing '{}'", EscapedPathDisplay::new(strip_cur_dir(archive_path))),
+        input_file.size(),
+        quiet,
+    );
+
+    // Zip archives are special, because they require io::Seek, so it requires it's logic separated
+    // from decoder chaining.
+    //
+    // This is the only case where we can read it directly, without having to do
+    // in-memory decompression/copying first.
+    if let [Extension {
+        compression_formats: [Zip],
+        ..
+    }] = formats.as_slice()
+    {
+        let zip_archive = zip::ZipArchive::new(progress.wrap_read(input_file))?;
+        let files_tested = crate::archive::zip::test_archive(zip_archive, archive_path, password, question_policy)?;
+        return Ok(Some(files_tested));
+    }
+
+    // Will be used in decoder chaining
+    let reader = BufReader::with_capacity(BUFFER_CAPACITY, progress.wrap_read(input_file));
+    let mut reader: Box<dyn Read + Send> = Box::new(reader);
+
+    let (first_extension, extensions) = split_first_compression_format(&formats);
+
+    for format in extensions.iter().rev() {
+        reader = chain_reader_decoder(format, reader, zstd_dict)?;
+    }
+
+    let files_tested = match first_extension {
+        Gzip | Bzip | Lz4 | Xz | Lzma | Lzip | Deflate | Zlib | Snappy | Zstd => {
+            reader = chain_reader_decoder(&first_extension, reader, zstd_dict)?;
+            io::copy(&mut reader, &mut io::sink())?;
+            1
+        }
+        Tar => crate::archive::tar::test_archive(reader)?,
+        Cpio => crate::archive::cpio::test_archive(reader)?,
+        Ar => crate::archive::ar::test_archive(reader)?,
+        Zip => {
+            if formats.len() > 1 {
+                // Locking necessary to guarantee that warning and question
+                // messages stay adjacent
+                let _locks = lock_and_flush_output_stdio();
+
+                warn_user_about_loading_zip_in_memory();
+                if !user_wants_to_continue(archive_path, question_policy, QuestionAction::Decompression)? {
+                    return Ok(None);
+                }
+            }
+
+            let mut vec = vec![];
+            io::copy(&mut reader, &mut vec)?;
+            let zip_archive = zip::ZipArchive::new(io::Cursor::new(vec))?;
+
+            crate::archive::zip::test_archive(zip_archive, archive_path, password, question_policy)?
+        }
+        #[cfg(feature = "unrar")]
+        Rar => {
+            // unrar needs a path to read from, volumes of split archives have to be joined first
+            if formats.len() > 1 || crate::utils::split::first_volume_base(archive_path).is_some() {
+                let mut temp_file = tempfile::NamedTempFile::new()?;
+                io::copy(&mut reader, &mut temp_file)?;
+                crate::archive::rar::test_archive(temp_file.path())?
+            } else {
+                crate::archive::rar::test_archive(archive_path)?
+            }
+        }
+        #[cfg(not(feature = "unrar"))]
+        Rar => {
+            return Err(crate::archive::rar_stub::no_support());
+        }
+        SevenZip => {
+            if formats.len() > 1 {
+                // Locking necessary to guarantee that warning and question
+                // messages stay adjacent
+                let _locks = lock_and_flush_output_stdio();
+
+                warn_user_about_loading_sevenz_in_memory();
+                if !user_wants_to_continue(archive_path, question_policy, QuestionAction::Decompression)? {
+                    return Ok(None);
+                }
+            }
+
+            let mut vec = vec![];
+            io::copy(&mut reader, &mut vec)?;
+
+            crate::archive::sevenz::test_archive(io::Cursor::new(vec))?
+        }
+    };
+
+    Ok(Some(files_tested))
+}
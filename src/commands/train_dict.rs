@@ -0,0 +1,37 @@
+use std::path::PathBuf;
+
+use crate::{error::FinalError, utils::FileVisibilityPolicy};
+
+/// Train a zstd dictionary of at most `max_size` bytes on the files in `inputs`
+///
+/// Directories are walked like when compressing, with `file_visibility_policy`, every file found
+/// is a sample.
+pub fn train_dict(
+    inputs: &[PathBuf],
+    max_size: usize,
+    file_visibility_policy: &FileVisibilityPolicy,
+) -> crate::Result<Vec<u8>> {
+    let mut samples = vec![];
+    for input in inputs {
+        for entry in file_visibility_policy.build_walker(input) {
+            let path = entry?.into_path();
+            if path.is_file() {
+                samples.push(path);
+            }
+        }
+    }
+
+    if samples.is_empty() {
+        return Err(FinalError::with_title("No samples to train the dictionary with")
+            .detail("The inputs don't contain any file")
+            .into());
+    }
+
+    zstd::dict::from_files(samples.iter().map(PathBuf::as_path), max_size).map_err(|err| {
+        FinalError::with_title("Could not train the zstd dictionary")
+            .detail(err.to_string())
+            .detail(format!("Trained on {} samples", samples.len()))
+            .hint("Dictionaries need many samples, like hundreds of small files similar to each other")
+            .into()
+    })
+}
@@ -7,7 +7,12 @@ use std::{
     fmt::{self, Display},
 };
 
-use crate::{accessible::is_running_in_accessible_mode, utils::colors::*};
+use crate::{
+    accessible::is_running_in_accessible_mode,
+    extension::{PRETTY_SUPPORTED_ALIASES, PRETTY_SUPPORTED_EXTENSIONS},
+    utils::colors::*,
+    EXIT_CORRUPT, EXIT_DECLINED, EXIT_FAILURE, EXIT_NOT_FOUND, EXIT_USAGE,
+};
 
 /// All errors that can be generated by `ouch`
 #[derive(Debug)]
@@ -39,6 +44,72 @@ pub enum Error {
     /// Recognised but unsupported format
     // currently only RAR when built without the `unrar` feature
     UnsupportedFormat { reason: String },
+    /// Arguments that can't be used together, or with the given inputs and formats
+    InvalidUsage { reason: FinalError },
+    /// Inputs that don't exist
+    MissingInputs { reason: FinalError },
+    /// Detected from io::Error if .kind() is io::ErrorKind::InvalidData or UnexpectedEof, the
+    /// input can't be decoded
+    CorruptArchive { reason: FinalError },
+    /// The user chose not to continue, or not to overwrite a file
+    UserDeclined,
+}
+
+impl Error {
+    /// Exit code of `ouch` when it stops because of this error
+    pub fn exit_code(&self) -> i32 {
+        use sevenz_rust::Error as SevenzError;
+
+        match self {
+            Error::InvalidUsage { .. } | Error::InvalidFormat { .. } => EXIT_USAGE,
+            Error::NotFound { .. } | Error::MissingInputs { .. } => EXIT_NOT_FOUND,
+            Error::UserDeclined => EXIT_DECLINED,
+            Error::CorruptArchive { .. } | Error::InvalidZipArchive(_) => EXIT_CORRUPT,
+            Error::SevenzipError(err) => match err {
+                SevenzError::BadSignature(_)
+                | SevenzError::ChecksumVerificationFailed
+                | SevenzError::NextHeaderCrcMismatch
+                | SevenzError::BadTerminatedStreamsInfo(_)
+                | SevenzError::BadTerminatedUnpackInfo
+                | SevenzError::BadTerminatedPackInfo(_)
+                | SevenzError::BadTerminatedSubStreamsInfo
+                | SevenzError::BadTerminatedheader(_) => EXIT_CORRUPT,
+                _ => EXIT_FAILURE,
+            },
+            Error::IoError { .. }
+            | Error::Lz4Error { .. }
+            | Error::AlreadyExists { .. }
+            | Error::PermissionDenied { .. }
+            | Error::UnsupportedZipArchive(_)
+            | Error::CompressingRootFolder
+            | Error::WalkdirError { .. }
+            | Error::Custom { .. }
+            | Error::UnsupportedFormat { .. } => EXIT_FAILURE,
+        }
+    }
+
+    /// Summarize the errors of several inputs like [`FinalError::from_errors`], keeping the exit
+    /// code they share
+    pub fn from_errors<'a>(title: impl Into<CowStr>, errors: impl IntoIterator<Item = (String, &'a Error)>) -> Self {
+        let errors: Vec<_> = errors.into_iter().collect();
+        let reason = FinalError::from_errors(title, errors.iter().map(|(input, err)| (input.clone(), *err)));
+        Self::summarize(reason, errors.iter().map(|(_, err)| *err))
+    }
+
+    /// An error described by `reason`, with the exit code shared by `errors`, or the generic one if
+    /// they don't share it
+    pub fn summarize<'a>(reason: FinalError, errors: impl IntoIterator<Item = &'a Error>) -> Self {
+        let mut exit_codes = errors.into_iter().map(Error::exit_code);
+        let first_exit_code = exit_codes.next();
+        let shared_exit_code = first_exit_code.filter(|&code| exit_codes.all(|other| other == code));
+
+        match shared_exit_code {
+            Some(EXIT_USAGE) => Error::InvalidUsage { reason },
+            Some(EXIT_NOT_FOUND) => Error::MissingInputs { reason },
+            Some(EXIT_CORRUPT) => Error::CorruptArchive { reason },
+            _ => Error::Custom { reason },
+        }
+    }
 }
 
 /// Alias to std's Result with ouch's Error
@@ -120,11 +191,31 @@ impl FinalError {
         self.hints.push(hint.into());
         self
     }
+
+    /// Summarize the errors of several inputs, each detail names an input and what went wrong with it
+    #[must_use]
+    pub fn from_errors<'a>(title: impl Into<CowStr>, errors: impl IntoIterator<Item = (String, &'a Error)>) -> Self {
+        let mut summary = Self::with_title(title);
+        for (input, err) in errors {
+            let err = FinalError::from(err);
+            let mut detail = format!("{input}: {}", err.title);
+            for err_detail in &err.details {
+                detail += &format!(", {err_detail}");
+            }
+            summary.details.push(detail.into());
+            for hint in err.hints {
+                if !summary.hints.contains(&hint) {
+                    summary.hints.push(hint);
+                }
+            }
+        }
+        summary
+    }
 }
 
-impl fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let err = match self {
+impl From<&Error> for FinalError {
+    fn from(err: &Error) -> Self {
+        match err {
             Error::WalkdirError { reason } => FinalError::with_title(reason.to_string()),
             Error::NotFound { error_title } => FinalError::with_title(error_title.to_string()).detail("File not found"),
             Error::CompressingRootFolder => {
@@ -142,15 +233,27 @@ impl fmt::Display for Error {
                 FinalError::with_title(error_title.to_string()).detail("Permission denied")
             }
             Error::UnsupportedZipArchive(reason) => FinalError::with_title("Unsupported zip archive").detail(*reason),
-            Error::InvalidFormat { reason } => FinalError::with_title("Invalid archive format").detail(reason.clone()),
+            Error::InvalidFormat { reason } => FinalError::with_title("Invalid archive format")
+                .detail(reason.clone())
+                .hint(format!("Supported extensions are: {PRETTY_SUPPORTED_EXTENSIONS}"))
+                .hint(format!("Supported aliases are: {PRETTY_SUPPORTED_ALIASES}"))
+                .hint("Formats are chained with dots, like 'tar.gz'"),
             Error::Custom { reason } => reason.clone(),
             Error::SevenzipError(reason) => FinalError::with_title("7z error").detail(reason.to_string()),
             Error::UnsupportedFormat { reason } => {
                 FinalError::with_title("Recognised but unsupported format").detail(reason.clone())
             }
-        };
+            Error::InvalidUsage { reason } | Error::MissingInputs { reason } | Error::CorruptArchive { reason } => {
+                reason.clone()
+            }
+            Error::UserDeclined => FinalError::with_title("Stopped, as chosen by the user"),
+        }
+    }
+}
 
-        write!(f, "{err}")
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", FinalError::from(self))
     }
 }
 
@@ -166,6 +269,9 @@ impl From<std::io::Error> for Error {
             std::io::ErrorKind::AlreadyExists => Self::AlreadyExists {
                 error_title: err.to_string(),
             },
+            std::io::ErrorKind::InvalidData | std::io::ErrorKind::UnexpectedEof => Self::CorruptArchive {
+                reason: FinalError::with_title(err.to_string()),
+            },
             _other => Self::IoError {
                 reason: err.to_string(),
             },
@@ -183,6 +289,14 @@ impl From<zip::result::ZipError> for Error {
                 reason: FinalError::with_title("Unexpected error in zip archive").detail("File not found"),
             },
             ZipError::UnsupportedArchive(filename) => Self::UnsupportedZipArchive(filename),
+            ZipError::InvalidPassword => Self::Custom {
+                reason: FinalError::with_title("Failed to decrypt zip archive")
+                    .detail("The password is incorrect")
+                    .hint("Run the command again with the correct password."),
+            },
+            other => Self::Custom {
+                reason: FinalError::with_title("Unexpected error in zip archive").detail(other.to_string()),
+            },
         }
     }
 }
@@ -210,6 +324,12 @@ impl From<ignore::Error> for Error {
     }
 }
 
+impl From<xz2::stream::Error> for Error {
+    fn from(err: xz2::stream::Error) -> Self {
+        Self::from(std::io::Error::from(err))
+    }
+}
+
 impl From<FinalError> for Error {
     fn from(err: FinalError) -> Self {
         Self::Custom { reason: err }
@@ -1,6 +1,6 @@
 //! Our representation of all the supported compression formats.
 
-use std::{ffi::OsStr, fmt, path::Path};
+use std::{ffi::OsStr, fmt, ops::RangeInclusive, path::Path};
 
 use bstr::ByteSlice;
 
@@ -10,27 +10,39 @@ use crate::{error::Error, utils::logger::warning};
 pub const SUPPORTED_EXTENSIONS: &[&str] = &[
     "tar",
     "zip",
+    "cpio",
     "bz",
     "bz2",
+    "bzip2",
     "gz",
+    "gzip",
     "lz4",
     "xz",
     "lzma",
+    "lz",
     "sz",
     "zst",
+    "zstd",
     #[cfg(feature = "unrar")]
     "rar",
     "7z",
+    "a",
+    "deb",
 ];
 
-pub const SUPPORTED_ALIASES: &[&str] = &["tgz", "tbz", "tlz4", "txz", "tzlma", "tsz", "tzst"];
+pub const SUPPORTED_ALIASES: &[&str] = &["tgz", "tbz", "tbz2", "tlz4", "txz", "tlzma", "tlz", "tsz", "tzst"];
 
 #[cfg(not(feature = "unrar"))]
-pub const PRETTY_SUPPORTED_EXTENSIONS: &str = "tar, zip, bz, bz2, gz, lz4, xz, lzma, sz, zst, 7z";
+pub const PRETTY_SUPPORTED_EXTENSIONS: &str =
+    "tar, zip, cpio, bz, bz2, bzip2, gz, gzip, lz4, xz, lzma, lz, sz, zst, zstd, 7z, a, deb";
 #[cfg(feature = "unrar")]
-pub const PRETTY_SUPPORTED_EXTENSIONS: &str = "tar, zip, bz, bz2, gz, lz4, xz, lzma, sz, zst, rar, 7z";
+pub const PRETTY_SUPPORTED_EXTENSIONS: &str =
+    "tar, zip, cpio, bz, bz2, bzip2, gz, gzip, lz4, xz, lzma, lz, sz, zst, zstd, rar, 7z, a, deb";
 
-pub const PRETTY_SUPPORTED_ALIASES: &str = "tgz, tbz, tlz4, txz, tzlma, tsz, tzst";
+pub const PRETTY_SUPPORTED_ALIASES: &str = "tgz, tbz, tbz2, tlz4, txz, tlzma, tlz, tsz, tzst";
+
+/// Formats without an extension, only accepted by `--format`
+pub const FLAG_ONLY_FORMATS: &[&str] = &["deflate", "zlib"];
 
 /// A wrapper around `CompressionFormat` that allows combinations like `tgz`
 #[derive(Debug, Clone, Eq)]
@@ -82,11 +94,19 @@ pub enum CompressionFormat {
     Bzip,
     /// .lz4
     Lz4,
-    /// .xz .lzma
+    /// .xz
+    Xz,
+    /// .lzma, the legacy LZMA format that predates .xz
     Lzma,
+    /// .lz
+    Lzip,
+    /// Raw deflate stream, only chosen with `--format deflate`
+    Deflate,
+    /// Deflate stream in a zlib wrapper, only chosen with `--format zlib`
+    Zlib,
     /// .sz
     Snappy,
-    /// tar, tgz, tbz, tbz2, txz, tlz4, tlzma, tsz, tzst
+    /// tar, tgz, tbz, tbz2, txz, tlz4, tlzma, tlz, tsz, tzst
     Tar,
     /// .zst
     Zstd,
@@ -97,22 +117,73 @@ pub enum CompressionFormat {
     Rar,
     /// .7z
     SevenZip,
+    /// .cpio
+    Cpio,
+    /// .a .deb, only listed and extracted
+    Ar,
 }
 
 impl CompressionFormat {
-    /// Currently supported archive formats are .tar (and aliases to it) and .zip
-    fn is_archive_format(&self) -> bool {
+    /// Currently supported archive formats are .tar (and aliases to it), .zip, .rar, .7z, .cpio and .a
+    pub fn is_archive_format(&self) -> bool {
         // Keep this match like that without a wildcard `_` so we don't forget to update it
         match self {
-            Tar | Zip | Rar | SevenZip => true,
+            Tar | Zip | Rar | SevenZip | Cpio | Ar => true,
             Gzip => false,
             Bzip => false,
             Lz4 => false,
+            Xz => false,
             Lzma => false,
+            Lzip => false,
+            Deflate => false,
+            Zlib => false,
             Snappy => false,
             Zstd => false,
         }
     }
+
+    /// Name of the format, like "gzip" or "7z"
+    pub fn name(&self) -> &'static str {
+        match self {
+            Gzip => "gzip",
+            Bzip => "bzip2",
+            Lz4 => "lz4",
+            Xz => "xz",
+            Lzma => "lzma",
+            Lzip => "lzip",
+            Deflate => "deflate",
+            Zlib => "zlib",
+            Snappy => "snappy",
+            Tar => "tar",
+            Zstd => "zstd",
+            Zip => "zip",
+            Rar => "rar",
+            SevenZip => "7z",
+            Cpio => "cpio",
+            Ar => "ar",
+        }
+    }
+
+    /// Whether `ouch` can write this format, RAR and ar are only read
+    pub fn can_compress(&self) -> bool {
+        !matches!(self, Rar | Ar)
+    }
+
+    /// Whether `ouch` can read this format, RAR needs the `unrar` feature
+    pub fn can_decompress(&self) -> bool {
+        cfg!(feature = "unrar") || *self != Rar
+    }
+
+    /// The range of levels accepted by `--level`, `None` if the format has no compression level
+    pub fn compression_level_range(&self) -> Option<RangeInclusive<i16>> {
+        match self {
+            Gzip | Deflate | Zlib => Some(0..=9),
+            Bzip => Some(1..=9),
+            Xz | Lzma | Lzip => Some(0..=9),
+            Zstd => Some(1..=zstd::zstd_safe::max_c_level() as i16),
+            Tar | Zip | Rar | SevenZip | Cpio | Ar | Lz4 | Snappy => None,
+        }
+    }
 }
 
 fn to_extension(ext: &[u8]) -> Option<Extension> {
@@ -122,18 +193,98 @@ fn to_extension(ext: &[u8]) -> Option<Extension> {
             b"tgz" => &[Tar, Gzip],
             b"tbz" | b"tbz2" => &[Tar, Bzip],
             b"tlz4" => &[Tar, Lz4],
-            b"txz" | b"tlzma" => &[Tar, Lzma],
+            b"txz" => &[Tar, Xz],
+            b"tlzma" => &[Tar, Lzma],
+            b"tlz" => &[Tar, Lzip],
             b"tsz" => &[Tar, Snappy],
             b"tzst" => &[Tar, Zstd],
             b"zip" => &[Zip],
-            b"bz" | b"bz2" => &[Bzip],
-            b"gz" => &[Gzip],
+            // The names of the formats are accepted as well, like "file.gzip", shown as they were written
+            b"bz" | b"bz2" | b"bzip2" => &[Bzip],
+            b"gz" | b"gzip" => &[Gzip],
             b"lz4" => &[Lz4],
-            b"xz" | b"lzma" => &[Lzma],
+            b"xz" => &[Xz],
+            b"lzma" => &[Lzma],
+            b"lz" => &[Lzip],
             b"sz" => &[Snappy],
-            b"zst" => &[Zstd],
+            b"zst" | b"zstd" => &[Zstd],
             b"rar" => &[Rar],
             b"7z" => &[SevenZip],
+            b"cpio" => &[Cpio],
+            b"a" | b"deb" => &[Ar],
+            _ => return None,
+        },
+        ext.to_str_lossy(),
+    ))
+}
+
+/// A supported format and the extensions that select it, as listed by `ouch formats`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatSummary {
+    pub format: CompressionFormat,
+    /// Extensions of this format alone, like "bz" and "bz2", empty if it's only chosen with `--format`
+    pub extensions: Vec<&'static str>,
+    /// Shorthands of tar with this format, like "tbz" and "tbz2"
+    pub aliases: Vec<&'static str>,
+}
+
+/// Every supported format, in the order of [`SUPPORTED_EXTENSIONS`], found by parsing the
+/// extensions like paths and `--format` are, so the list can't differ from what is accepted
+pub fn supported_formats() -> Vec<FormatSummary> {
+    // RAR is recognized even without the `unrar` feature, to say it can't be decompressed
+    let rar = (!SUPPORTED_EXTENSIONS.contains(&"rar")).then_some("rar");
+    let extensions = SUPPORTED_EXTENSIONS
+        .iter()
+        .copied()
+        .chain(rar)
+        .chain(FLAG_ONLY_FORMATS.iter().copied())
+        .chain(SUPPORTED_ALIASES.iter().copied());
+
+    let mut summaries: Vec<FormatSummary> = vec![];
+    for text in extensions {
+        // Unwrap safety:
+        //   every supported extension is parsed by `to_extension` or `to_flag_only_extension`,
+        //   which is checked by the tests.
+        let extension = to_extension(text.as_bytes())
+            .or_else(|| to_flag_only_extension(text.as_bytes()))
+            .unwrap();
+        // Aliases are listed with the format that compresses the tar archive
+        let (&format, is_alias) = match extension.compression_formats {
+            [format] => (format, false),
+            [.., format] => (format, true),
+            [] => unreachable!(),
+        };
+
+        let index = match summaries.iter().position(|summary| summary.format == format) {
+            Some(index) => index,
+            None => {
+                summaries.push(FormatSummary {
+                    format,
+                    extensions: vec![],
+                    aliases: vec![],
+                });
+                summaries.len() - 1
+            }
+        };
+        let summary = &mut summaries[index];
+        if is_alias {
+            summary.aliases.push(text);
+        } else if !FLAG_ONLY_FORMATS.contains(&text) {
+            summary.extensions.push(text);
+        }
+    }
+
+    summaries
+}
+
+/// Formats without an agreed upon extension, only accepted by `--format` and never inferred
+/// from a path
+fn to_flag_only_extension(ext: &[u8]) -> Option<Extension> {
+    Some(Extension::new(
+        match ext {
+            // Keep in sync with `FLAG_ONLY_FORMATS`
+            b"deflate" => &[Deflate],
+            b"zlib" => &[Zlib],
             _ => return None,
         },
         ext.to_str_lossy(),
@@ -157,9 +308,11 @@ pub fn parse_format(fmt: &OsStr) -> crate::Result<Vec<Extension>> {
 
     let mut extensions = Vec::new();
     for extension in fmt.split_str(b".") {
-        let extension = to_extension(extension).ok_or_else(|| Error::InvalidFormat {
-            reason: format!("Unsupported extension: {}", extension.to_str_lossy()),
-        })?;
+        let extension = to_extension(extension)
+            .or_else(|| to_flag_only_extension(extension))
+            .ok_or_else(|| Error::InvalidFormat {
+                reason: format!("Unsupported extension: {}", extension.to_str_lossy()),
+            })?;
         extensions.push(extension);
     }
 
@@ -199,6 +352,35 @@ pub fn extensions_from_path(path: &Path) -> Vec<Extension> {
     extensions
 }
 
+/// Drop the formats of an archive nested in another one, like the zip of "archive.zip.tar",
+/// which is read as a tar archive with "archive.zip" in it
+pub fn outermost_archive_formats(mut formats: Vec<Extension>) -> Vec<Extension> {
+    if let Some(position) = formats.iter().skip(1).rposition(Extension::is_archive) {
+        formats.drain(..=position);
+    }
+    formats
+}
+
+/// Drop the format of the archive `formats` start with, keeping the compression around it, like the
+/// gzip of "archive.tar.gz" or "archive.tgz". Returns the format of the archive, `None` if
+/// `formats` don't start with one.
+pub fn strip_archive_format(formats: &mut Vec<Extension>) -> Option<CompressionFormat> {
+    let first = formats.first().filter(|first| first.is_archive())?;
+    let (&archive, compression) = first.compression_formats.split_first()?;
+
+    if compression.is_empty() {
+        formats.remove(0);
+    } else {
+        let text = compression
+            .iter()
+            .map(CompressionFormat::name)
+            .collect::<Vec<_>>()
+            .join(".");
+        formats[0] = Extension::new(compression, text);
+    }
+    Some(archive)
+}
+
 /// Panics if formats has an empty list of compression formats
 pub fn split_first_compression_format(formats: &[Extension]) -> (CompressionFormat, Vec<CompressionFormat>) {
     let mut extensions: Vec<CompressionFormat> = flatten_compression_formats(formats);
@@ -262,6 +444,145 @@ mod tests {
         assert_eq!(formats, vec![Tar, Gzip]);
     }
 
+    #[test]
+    fn test_xz_lzma_and_lzip_are_distinct() {
+        let formats = |path| flatten_compression_formats(&extensions_from_path(Path::new(path)));
+
+        assert_eq!(formats("file.xz"), vec![Xz]);
+        assert_eq!(formats("file.lzma"), vec![Lzma]);
+        assert_eq!(formats("archive.txz"), vec![Tar, Xz]);
+        assert_eq!(formats("archive.tlzma"), vec![Tar, Lzma]);
+        assert_eq!(formats("file.lz"), vec![Lzip]);
+        assert_eq!(formats("archive.tar.lz"), vec![Tar, Lzip]);
+    }
+
+    #[test]
+    fn test_format_names_as_extensions() {
+        let names = [
+            ("gzip", vec![Gzip]),
+            ("bzip2", vec![Bzip]),
+            ("zstd", vec![Zstd]),
+            // The legacy container of xz, which is its own format
+            ("lzma", vec![Lzma]),
+        ];
+
+        for (name, expected) in names {
+            // As the extension of an output to compress or an archive to decompress
+            let extensions = extensions_from_path(Path::new(&format!("archive.tar.{name}")));
+            assert_eq!(
+                flatten_compression_formats(&extensions),
+                [&[Tar][..], &expected].concat(),
+                "{name}"
+            );
+            // Errors and hints show the name that was written
+            assert_eq!(extensions[1].to_string(), name);
+
+            // As `--format`
+            let extensions = parse_format(OsStr::new(name)).unwrap();
+            assert_eq!(flatten_compression_formats(&extensions), expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn test_tar_aliases() {
+        let aliases = [
+            ("tgz", vec![Tar, Gzip]),
+            ("tbz", vec![Tar, Bzip]),
+            ("tbz2", vec![Tar, Bzip]),
+            ("tlz4", vec![Tar, Lz4]),
+            ("txz", vec![Tar, Xz]),
+            ("tlzma", vec![Tar, Lzma]),
+            ("tlz", vec![Tar, Lzip]),
+            ("tsz", vec![Tar, Snappy]),
+            ("tzst", vec![Tar, Zstd]),
+        ];
+        assert_eq!(aliases.len(), SUPPORTED_ALIASES.len());
+
+        for (alias, expected) in aliases {
+            assert!(SUPPORTED_ALIASES.contains(&alias), "{alias}");
+
+            // As the extension of an output to compress or an archive to decompress
+            let extensions = extensions_from_path(Path::new(&format!("archive.{alias}")));
+            assert_eq!(flatten_compression_formats(&extensions), expected, "{alias}");
+            // Errors and hints show the alias that was written
+            assert_eq!(extensions.len(), 1);
+            assert_eq!(extensions[0].to_string(), alias);
+
+            // As `--format`
+            let extensions = parse_format(OsStr::new(alias)).unwrap();
+            assert_eq!(flatten_compression_formats(&extensions), expected, "{alias}");
+        }
+    }
+
+    #[test]
+    fn test_supported_formats() {
+        let summaries = supported_formats();
+        let formats: Vec<_> = summaries.iter().map(|summary| summary.format).collect();
+        assert_eq!(
+            formats,
+            [Tar, Zip, Cpio, Bzip, Gzip, Lz4, Xz, Lzma, Lzip, Snappy, Zstd, Rar, SevenZip, Ar, Deflate, Zlib]
+        );
+
+        let gzip = summaries.iter().find(|summary| summary.format == Gzip).unwrap();
+        assert_eq!(
+            (gzip.extensions.as_slice(), gzip.aliases.as_slice()),
+            (&["gz", "gzip"][..], &["tgz"][..])
+        );
+        let bzip = summaries.iter().find(|summary| summary.format == Bzip).unwrap();
+        assert_eq!(bzip.extensions, ["bz", "bz2", "bzip2"]);
+        assert_eq!(bzip.aliases, ["tbz", "tbz2"]);
+        let deflate = summaries.iter().find(|summary| summary.format == Deflate).unwrap();
+        assert!(deflate.extensions.is_empty() && deflate.aliases.is_empty());
+
+        // Every listed extension selects its format
+        for summary in &summaries {
+            for extension in &summary.extensions {
+                let formats = flatten_compression_formats(&parse_format(OsStr::new(extension)).unwrap());
+                assert_eq!(formats, [summary.format], "{extension}");
+            }
+            for alias in &summary.aliases {
+                let formats = flatten_compression_formats(&parse_format(OsStr::new(alias)).unwrap());
+                assert_eq!(formats, [Tar, summary.format], "{alias}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_deflate_and_zlib_only_from_format_flag() {
+        let formats = |format| flatten_compression_formats(&parse_format(OsStr::new(format)).unwrap());
+
+        assert_eq!(formats("deflate"), vec![Deflate]);
+        assert_eq!(formats("tar.zlib"), vec![Tar, Zlib]);
+        assert!(extensions_from_path(Path::new("file.deflate")).is_empty());
+        assert!(extensions_from_path(Path::new("file.zlib")).is_empty());
+    }
+
+    #[test]
+    fn test_outermost_archive_formats() {
+        let outermost =
+            |path| flatten_compression_formats(&outermost_archive_formats(extensions_from_path(Path::new(path))));
+
+        assert_eq!(outermost("archive.zip.tar"), vec![Tar]);
+        assert_eq!(outermost("archive.tar.gz.tar.xz"), vec![Tar, Xz]);
+        assert_eq!(outermost("archive.tar.gz"), vec![Tar, Gzip]);
+        assert_eq!(outermost("file.gz.xz"), vec![Gzip, Xz]);
+    }
+
+    #[test]
+    fn test_strip_archive_format() {
+        let stripped = |path| {
+            let mut formats = extensions_from_path(Path::new(path));
+            let archive = strip_archive_format(&mut formats);
+            (archive, flatten_compression_formats(&formats))
+        };
+
+        assert_eq!(stripped("archive.tar.gz"), (Some(Tar), vec![Gzip]));
+        assert_eq!(stripped("archive.tzst"), (Some(Tar), vec![Zstd]));
+        assert_eq!(stripped("archive.zip.xz"), (Some(Zip), vec![Xz]));
+        assert_eq!(stripped("archive.tar"), (Some(Tar), vec![]));
+        assert_eq!(stripped("file.gz"), (None, vec![Gzip]));
+    }
+
     #[test]
     fn builds_suggestion_correctly() {
         assert_eq!(build_archive_file_suggestion(Path::new("linux.png"), ".tar"), None);
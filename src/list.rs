@@ -5,14 +5,43 @@ use std::{
     path::{Path, PathBuf},
 };
 
+use time::OffsetDateTime;
+
 use self::tree::Tree;
-use crate::{accessible::is_running_in_accessible_mode, utils::EscapedPathDisplay};
+use crate::{
+    accessible::is_running_in_accessible_mode,
+    utils::{
+        colors::{ALL_RESET, BLUE, GREEN, STYLE_BOLD},
+        split::SplitReader,
+        Bytes, EscapedPathDisplay,
+    },
+};
 
 /// Options controlling how archive contents should be listed
 #[derive(Debug, Clone, Copy)]
 pub struct ListOptions {
-    /// Whether to show a tree view
+    /// Whether to show a tree view, ignored by [`ListFormat::Json`]
     pub tree: bool,
+    /// How the entries are printed
+    pub format: ListFormat,
+    /// Whether sizes are shown in KiB, MiB, etc, instead of bytes, ignored by [`ListFormat::Json`]
+    pub human: bool,
+    /// Whether entries are left out by `--filter`, the total then doesn't compare their size to
+    /// the size of the archive
+    pub filtered: bool,
+    /// Whether only the total is printed, without the entries, ignored by [`ListFormat::Json`]
+    pub count: bool,
+    /// Whether the permissions and modification time of the entries are shown, like `ls -l`
+    pub long: bool,
+}
+
+/// Output format of the list command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListFormat {
+    /// Human readable, one entry per line or as a tree
+    Text,
+    /// A flat JSON array with an object per entry, for scripts
+    Json,
 }
 
 /// Represents a single file in an archive, used in `list::list_files()`
@@ -23,50 +52,432 @@ pub struct FileInArchive {
 
     /// Whether this file is a directory
     pub is_dir: bool,
+
+    /// Uncompressed size in bytes, if known
+    pub size: Option<u64>,
+
+    /// Size in bytes inside of the archive, only known for formats that compress each entry
+    /// separately, like zip
+    pub compressed_size: Option<u64>,
+
+    /// Last modification time as a Unix timestamp in seconds, if stored
+    pub modified: Option<i64>,
+
+    /// Unix permission bits, like 0o755, if stored
+    pub mode: Option<u32>,
 }
 
-/// Actually print the files
-/// Returns an Error, if one of the files can't be read
-pub fn list_files(
-    archive: &Path,
-    files: impl IntoIterator<Item = crate::Result<FileInArchive>>,
-    list_options: ListOptions,
-) -> crate::Result<()> {
-    let out = &mut stdout().lock();
-    let _ = writeln!(out, "Archive: {}", EscapedPathDisplay::new(archive));
+/// Prints the entries of the listed archives.
+///
+/// The output of all archives is kept together, so JSON stays a single valid array.
+pub struct ListPrinter {
+    options: ListOptions,
+    archives_listed: usize,
+    json_entries_printed: usize,
+}
+
+impl ListPrinter {
+    pub fn new(options: ListOptions) -> Self {
+        Self {
+            options,
+            archives_listed: 0,
+            json_entries_printed: 0,
+        }
+    }
+
+    /// Whether only the totals are printed, so entries only need to be counted
+    pub fn counts_only(&self) -> bool {
+        self.options.count && self.options.format == ListFormat::Text
+    }
+
+    /// Actually print the files, after the `comment` of the archive if it has one
+    /// Returns an Error, if one of the files can't be read
+    pub fn list_files(
+        &mut self,
+        archive: &Path,
+        comment: Option<&str>,
+        files: impl IntoIterator<Item = crate::Result<FileInArchive>>,
+    ) -> crate::Result<()> {
+        let out = &mut stdout().lock();
+        self.archives_listed += 1;
+
+        if self.options.format == ListFormat::Json {
+            for file in files {
+                let file = file?;
+                let separator = if self.json_entries_printed == 0 { "[" } else { "," };
+                let _ = writeln!(out, "{separator}");
+                let _ = write!(out, "  ");
+                print_json_entry(out, archive, comment, &file);
+                self.json_entries_printed += 1;
+            }
+            return Ok(());
+        }
+
+        if self.archives_listed > 1 {
+            let _ = writeln!(out);
+        }
+        let _ = writeln!(out, "Archive: {}", EscapedPathDisplay::new(archive));
+        if let Some(comment) = comment {
+            let _ = writeln!(out, "Comment: {}", escape_comment(comment));
+        }
+
+        // Entries are printed as they're read, except for the tree, which needs all of them, and
+        // a batch of entries is kept so that they can be aligned
+        let mut total = Total::default();
+        if self.options.tree && !self.options.count {
+            let files = files.into_iter().collect::<crate::Result<Vec<_>>>()?;
+            files.iter().for_each(|file| total.add(file));
+            let tree: Tree = files.into_iter().collect();
+            tree.print(out);
+        } else {
+            let mut rows = Vec::new();
+            for file in files {
+                let file = file?;
+                total.add(&file);
+                if self.options.count {
+                    continue;
+                }
+                rows.push(self.row(&file));
+                if rows.len() == ALIGNED_BATCH_SIZE {
+                    print_rows(out, &rows);
+                    rows.clear();
+                }
+            }
+            print_rows(out, &rows);
+        }
+
+        self.print_total(out, archive, total);
+        Ok(())
+    }
+
+    /// Print only the total of an archive, whose files were counted without being listed
+    pub fn list_count(&mut self, archive: &Path, comment: Option<&str>, file_count: usize, total_size: u64) {
+        let out = &mut stdout().lock();
+        self.archives_listed += 1;
+
+        if self.archives_listed > 1 {
+            let _ = writeln!(out);
+        }
+        let _ = writeln!(out, "Archive: {}", EscapedPathDisplay::new(archive));
+        if let Some(comment) = comment {
+            let _ = writeln!(out, "Comment: {}", escape_comment(comment));
+        }
+        let total = Total {
+            file_count,
+            size: Some(total_size),
+        };
+        self.print_total(out, archive, total);
+    }
+
+    /// Print the number of files and their total size, if it's known
+    fn print_total(&self, out: &mut impl Write, archive: &Path, total: Total) {
+        let file_count = total.file_count;
 
-    if list_options.tree {
-        let tree = files.into_iter().collect::<crate::Result<Tree>>()?;
-        tree.print(out);
-    } else {
-        for file in files {
-            let FileInArchive { path, is_dir } = file?;
-            print_entry(out, EscapedPathDisplay::new(&path), is_dir);
+        // Formats like tar compress the whole archive at once, so the ratio is only known for all
+        // entries together, from the size of the archive file (or of all its volumes, if split)
+        match (total.size, SplitReader::open(archive)) {
+            (Some(total_size), Ok(archive_file)) => {
+                let sizes = if self.options.filtered {
+                    self.format_size(total_size)
+                } else {
+                    self.compressed_sizes(total_size, archive_file.size())
+                };
+                let _ = writeln!(out, "Total: {file_count} files, {sizes}");
+            }
+            _ if self.options.count => {
+                let _ = writeln!(out, "Total: {file_count} files");
+            }
+            _ => {}
+        }
+    }
+
+    /// Format `bytes` as a plain byte count, or with units if `--human` was given
+    fn format_size(&self, bytes: u64) -> String {
+        if self.options.human {
+            Bytes::new(bytes).to_string()
+        } else {
+            format!("{bytes} B")
+        }
+    }
+
+    /// Like "1024 B -> 512 B (50.0%)", the ratio is left out for empty files
+    fn compressed_sizes(&self, size: u64, compressed_size: u64) -> String {
+        let sizes = format!("{} -> {}", self.format_size(size), self.format_size(compressed_size));
+        if size == 0 {
+            return sizes;
+        }
+        let ratio = compressed_size as f64 * 100.0 / size as f64;
+        format!("{sizes} ({ratio:.1}%)")
+    }
+
+    /// The columns of `file` in the flat listing
+    fn row(&self, file: &FileInArchive) -> Row {
+        let kind = EntryKind::of(file);
+        let mut row = Row {
+            name: EscapedPathDisplay::new(&file.path).to_string(),
+            kind,
+            ..Row::default()
+        };
+
+        if self.options.long {
+            row.permissions = format_permissions(kind, file.mode);
+            row.modified = file.modified.map(format_timestamp).unwrap_or_default();
+        }
+
+        // Directories have no size of their own
+        if let Some(size) = file.size.filter(|_| !file.is_dir) {
+            row.size = self.format_size(size);
+            if let Some(compressed_size) = file.compressed_size {
+                row.compressed_size = self.format_size(compressed_size);
+                if size != 0 {
+                    row.ratio = format!("({:.1}%)", compressed_size as f64 * 100.0 / size as f64);
+                }
+            }
+        }
+        row
+    }
+
+    /// Close the JSON array, it has to be called after all archives were listed
+    pub fn finish(self) {
+        if self.options.format == ListFormat::Json {
+            let out = &mut stdout().lock();
+            if self.json_entries_printed == 0 {
+                let _ = writeln!(out, "[]");
+            } else {
+                let _ = writeln!(out, "\n]");
+            }
         }
     }
-    Ok(())
 }
 
-/// Print an entry and highlight directories, either by coloring them
-/// if that's supported or by adding a trailing /
-fn print_entry(out: &mut impl Write, name: impl std::fmt::Display, is_dir: bool) {
-    use crate::utils::colors::*;
+/// Number of entries of the flat listing that are aligned together, so that huge archives are
+/// still printed as they're read
+const ALIGNED_BATCH_SIZE: usize = 1000;
 
-    if is_dir {
+/// What an entry is, to highlight it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum EntryKind {
+    #[default]
+    File,
+    Executable,
+    Directory,
+}
+
+impl EntryKind {
+    fn of(file: &FileInArchive) -> Self {
+        if file.is_dir {
+            Self::Directory
+        } else if file.mode.is_some_and(|mode| mode & 0o111 != 0) {
+            Self::Executable
+        } else {
+            Self::File
+        }
+    }
+}
+
+/// The columns of an entry in the flat listing, empty when they're unknown or not shown
+#[derive(Debug, Default)]
+struct Row {
+    permissions: String,
+    modified: String,
+    name: String,
+    kind: EntryKind,
+    size: String,
+    compressed_size: String,
+    ratio: String,
+}
+
+/// Print `rows` with their columns aligned, sizes to the right, and the name padded to the
+/// longest one when sizes follow it.
+///
+/// Screen readers would read the padding out loud, so the columns are only separated by two
+/// spaces in accessible mode.
+fn print_rows(out: &mut impl Write, rows: &[Row]) {
+    let accessible = is_running_in_accessible_mode();
+    let width = |column: fn(&Row) -> &str| {
+        if accessible {
+            0
+        } else {
+            rows.iter().map(|row| column(row).chars().count()).max().unwrap_or(0)
+        }
+    };
+    let modified_width = width(|row| &row.modified);
+    let name_width = rows
+        .iter()
+        .filter(|row| !row.size.is_empty())
+        .map(displayed_name_len)
+        .max()
+        .filter(|_| !accessible)
+        .unwrap_or(0);
+    let size_width = width(|row| &row.size);
+    let compressed_size_width = width(|row| &row.compressed_size);
+    let ratio_width = width(|row| &row.ratio);
+
+    for row in rows {
+        let mut line = String::new();
+        if !row.permissions.is_empty() {
+            line.push_str(&format!("{}  {:<modified_width$}  ", row.permissions, row.modified));
+        }
+        line.push_str(&highlighted_name(&row.name, row.kind));
+
+        if !row.size.is_empty() {
+            let padding = name_width.saturating_sub(displayed_name_len(row));
+            line.push_str(&format!("{:padding$}  {:>size_width$}", "", row.size));
+            if !row.compressed_size.is_empty() {
+                line.push_str(&format!(" -> {:>compressed_size_width$}", row.compressed_size));
+            }
+            if !row.ratio.is_empty() {
+                line.push_str(&format!(" {:>ratio_width$}", row.ratio));
+            }
+        }
+        let _ = writeln!(out, "{}", line.trim_end());
+    }
+}
+
+/// Length of the name of `row` as printed, without colors
+fn displayed_name_len(row: &Row) -> usize {
+    let slash = row.kind == EntryKind::Directory && (BLUE.is_empty() || is_running_in_accessible_mode());
+    row.name.chars().count() + usize::from(slash)
+}
+
+/// Permissions of an entry like `ls -l`, such as "-rwxr-xr-x", with question marks if they're unknown
+fn format_permissions(kind: EntryKind, mode: Option<u32>) -> String {
+    let file_type = if kind == EntryKind::Directory { 'd' } else { '-' };
+    let Some(mode) = mode else {
+        return format!("{file_type}?????????");
+    };
+
+    // The setuid, setgid and sticky bits replace the execute bit they go with
+    let special = [(0o4000, 's'), (0o2000, 's'), (0o1000, 't')];
+    let mut permissions = String::from(file_type);
+    for (shift, (special_bit, special_char)) in [6, 3, 0].into_iter().zip(special) {
+        let bits = (mode >> shift) & 0o7;
+        permissions.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        permissions.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        permissions.push(match (bits & 0o1 != 0, mode & special_bit != 0) {
+            (true, true) => special_char,
+            (false, true) => special_char.to_ascii_uppercase(),
+            (true, false) => 'x',
+            (false, false) => '-',
+        });
+    }
+    permissions
+}
+
+/// A Unix timestamp as "2024-01-31 12:00", in UTC
+fn format_timestamp(timestamp: i64) -> String {
+    match OffsetDateTime::from_unix_timestamp(timestamp) {
+        Ok(time) => format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}",
+            time.year(),
+            u8::from(time.month()),
+            time.day(),
+            time.hour(),
+            time.minute(),
+        ),
+        Err(_) => String::new(),
+    }
+}
+
+/// Number of files of an archive, directories aside, and their total size
+#[derive(Debug, Clone, Copy)]
+struct Total {
+    file_count: usize,
+    /// `None` if the size of one of the files isn't stored
+    size: Option<u64>,
+}
+
+impl Default for Total {
+    fn default() -> Self {
+        Self {
+            file_count: 0,
+            size: Some(0),
+        }
+    }
+}
+
+impl Total {
+    fn add(&mut self, file: &FileInArchive) {
+        if file.is_dir {
+            return;
+        }
+        self.file_count += 1;
+        self.size = self.size.zip(file.size).map(|(total, size)| total + size);
+    }
+}
+
+/// Print an entry as a JSON object, with the `comment` of its archive, without a trailing newline
+fn print_json_entry(out: &mut impl Write, archive: &Path, comment: Option<&str>, file: &FileInArchive) {
+    fn json_number(number: Option<impl std::fmt::Display>) -> String {
+        number.map_or_else(|| "null".to_string(), |number| number.to_string())
+    }
+
+    let _ = write!(
+        out,
+        r#"{{"archive": {}, "comment": {}, "path": {}, "size": {}, "compressed_size": {}, "is_dir": {}, "modified": {}}}"#,
+        json_string(&archive.to_string_lossy()),
+        comment.map_or_else(|| "null".to_string(), json_string),
+        json_string(&file.path.to_string_lossy()),
+        json_number(file.size),
+        json_number(file.compressed_size),
+        file.is_dir,
+        json_number(file.modified),
+    );
+}
+
+/// Quote and escape `text` as a JSON string, invalid UTF-8 must already have been replaced
+fn json_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for char in text.chars() {
+        match char {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            char if char.is_control() => quoted.push_str(&format!("\\u{:04x}", char as u32)),
+            char => quoted.push(char),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Escape the control characters of an archive comment, other than line breaks and tabs, so that
+/// printing it can't move the cursor or change the colors of the terminal
+fn escape_comment(comment: &str) -> String {
+    let mut escaped = String::with_capacity(comment.len());
+    for char in comment.chars() {
+        match char {
+            '\n' | '\t' => escaped.push(char),
+            char if char.is_control() => escaped.extend(char.escape_default()),
+            char => escaped.push(char),
+        }
+    }
+    escaped
+}
+
+/// Print an entry, highlighted with [`highlighted_name`]
+fn print_entry(out: &mut impl Write, name: impl std::fmt::Display, kind: EntryKind) {
+    let _ = writeln!(out, "{}", highlighted_name(name, kind));
+}
+
+/// Highlight directories, either by coloring them if that's supported or by adding a trailing /,
+/// and color executables
+fn highlighted_name(name: impl std::fmt::Display, kind: EntryKind) -> String {
+    match kind {
         // if colors are deactivated, print final / to mark directories
-        if BLUE.is_empty() {
-            let _ = writeln!(out, "{name}/");
+        EntryKind::Directory if BLUE.is_empty() => format!("{name}/"),
         // if in ACCESSIBLE mode, use colors but print final / in case colors
         // aren't read out aloud with a screen reader or aren't printed on a
         // braille reader
-        } else if is_running_in_accessible_mode() {
-            let _ = writeln!(out, "{}{}{}/{}", *BLUE, *STYLE_BOLD, name, *ALL_RESET);
-        } else {
-            let _ = writeln!(out, "{}{}{}{}", *BLUE, *STYLE_BOLD, name, *ALL_RESET);
+        EntryKind::Directory if is_running_in_accessible_mode() => {
+            format!("{}{}{}/{}", *BLUE, *STYLE_BOLD, name, *ALL_RESET)
         }
-    } else {
-        // not a dir -> just print the file name
-        let _ = writeln!(out, "{name}");
+        EntryKind::Directory => format!("{}{}{}{}", *BLUE, *STYLE_BOLD, name, *ALL_RESET),
+        EntryKind::Executable => format!("{}{}{}{}", *GREEN, *STYLE_BOLD, name, *ALL_RESET),
+        EntryKind::File => name.to_string(),
     }
 }
 
@@ -84,7 +495,7 @@ mod tree {
     use bstr::{ByteSlice, ByteVec};
     use linked_hash_map::LinkedHashMap;
 
-    use super::FileInArchive;
+    use super::{EntryKind, FileInArchive};
     use crate::utils::{logger::warning, EscapedPathDisplay};
 
     /// Directory tree
@@ -144,11 +555,11 @@ mod tree {
             };
 
             print!("{prefix}{final_part}");
-            let is_dir = match self.file {
-                Some(FileInArchive { is_dir, .. }) => is_dir,
-                None => true,
+            let kind = match &self.file {
+                Some(file) => EntryKind::of(file),
+                None => EntryKind::Directory,
             };
-            super::print_entry(out, <Vec<u8> as ByteVec>::from_os_str_lossy(name).as_bstr(), is_dir);
+            super::print_entry(out, <Vec<u8> as ByteVec>::from_os_str_lossy(name).as_bstr(), kind);
 
             // Construct prefix for children, adding either a line if this isn't
             // the last entry in the parent dir or empty space if it is.
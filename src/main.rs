@@ -15,7 +15,7 @@ use error::{Error, Result};
 use once_cell::sync::Lazy;
 use utils::{QuestionAction, QuestionPolicy};
 
-use crate::utils::logger::spawn_logger_thread;
+use crate::utils::{hooks::Hooks, logger::spawn_logger_thread};
 
 // Used in BufReader and BufWriter to perform less syscalls
 const BUFFER_CAPACITY: usize = 1024 * 32;
@@ -23,21 +23,54 @@ const BUFFER_CAPACITY: usize = 1024 * 32;
 /// Current directory or empty directory
 static CURRENT_DIRECTORY: Lazy<PathBuf> = Lazy::new(|| env::current_dir().unwrap_or_default());
 
-/// The status code returned from `ouch` on error
+/// The status code returned from `ouch` on errors without a code of their own
 pub const EXIT_FAILURE: i32 = libc::EXIT_FAILURE;
+/// The status code of invalid arguments, the same as clap's
+pub const EXIT_USAGE: i32 = 2;
+/// The status code when an input doesn't exist
+pub const EXIT_NOT_FOUND: i32 = 3;
+/// The status code when the user chose not to continue, or not to overwrite a file
+pub const EXIT_DECLINED: i32 = 4;
+/// The status code when an input is corrupted or truncated
+pub const EXIT_CORRUPT: i32 = 5;
 
 fn main() {
+    utils::interrupt::install_handler();
     let handler = spawn_logger_thread();
-    let result = run();
+    let (result, hooks) = run();
     handler.shutdown_and_wait();
 
-    if let Err(err) = result {
+    if let Err(err) = &result {
+        // The user already knows why ouch stopped, they chose it
+        if !matches!(err, Error::UserDeclined) {
+            eprintln!("{err}");
+        }
+    }
+
+    // Last, so the output of the hooks comes after the messages of ouch
+    let hook_result = hooks.map_or(Ok(()), |hooks| hooks.run(&result));
+    if let Err(err) = &hook_result {
         eprintln!("{err}");
-        std::process::exit(EXIT_FAILURE);
+    }
+
+    // A failing --on-failure hook doesn't change why ouch failed
+    if let Err(err) = result.and(hook_result) {
+        std::process::exit(err.exit_code());
     }
 }
 
-fn run() -> Result<()> {
-    let (args, skip_questions_positively, file_visibility_policy) = CliArgs::parse_and_validate_args()?;
-    commands::run(args, skip_questions_positively, file_visibility_policy)
+/// Run the subcommand, returning the hooks to run after it, which also run when the arguments
+/// are valid but the inputs aren't, like a missing file
+fn run() -> (Result<()>, Option<Hooks>) {
+    let args = match CliArgs::parse_with_config() {
+        Ok(args) => args,
+        Err(err) => return (Err(err), None),
+    };
+    let hooks = Hooks::of(&args.cmd);
+    let result = args
+        .validate()
+        .and_then(|(args, skip_questions_positively, file_visibility_policy)| {
+            commands::run(args, skip_questions_positively, file_visibility_policy)
+        });
+    (result, hooks)
 }
@@ -0,0 +1,112 @@
+//! Checksum files written next to archives, in the `<hash>  <filename>` format of `sha256sum` and friends.
+
+use std::{
+    ffi::OsString,
+    io::{self, BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+use fs_err as fs;
+use sha2::Digest;
+
+use crate::{
+    cli::ChecksumAlgorithm,
+    error::FinalError,
+    utils::{strip_cur_dir, EscapedPathDisplay},
+    BUFFER_CAPACITY,
+};
+
+const ALGORITHMS: [ChecksumAlgorithm; 3] = [
+    ChecksumAlgorithm::Sha256,
+    ChecksumAlgorithm::Sha512,
+    ChecksumAlgorithm::Md5,
+];
+
+impl ChecksumAlgorithm {
+    /// Extension of the checksum files, also the name used by the `*sum` tools
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Sha256 => "sha256",
+            Self::Sha512 => "sha512",
+            Self::Md5 => "md5",
+        }
+    }
+
+    /// Lowercase hex digest of everything read from `reader`
+    pub fn hex_digest(self, reader: impl Read) -> io::Result<String> {
+        match self {
+            Self::Sha256 => hex_digest::<sha2::Sha256>(reader),
+            Self::Sha512 => hex_digest::<sha2::Sha512>(reader),
+            Self::Md5 => hex_digest::<md5::Md5>(reader),
+        }
+    }
+}
+
+fn hex_digest<D: Digest + io::Write>(reader: impl Read) -> io::Result<String> {
+    let mut hasher = D::new();
+    io::copy(&mut BufReader::with_capacity(BUFFER_CAPACITY, reader), &mut hasher)?;
+    Ok(hasher.finalize().iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+/// Path of the checksum file of `path`, like "archive.tar.gz.sha256"
+pub fn checksum_path(path: &Path, algorithm: ChecksumAlgorithm) -> PathBuf {
+    let mut checksum_path = OsString::from(path);
+    checksum_path.push(".");
+    checksum_path.push(algorithm.extension());
+    checksum_path.into()
+}
+
+/// Hash the file at `path` and write its checksum file, returning the path of the checksum file
+pub fn write_checksum_file(path: &Path, algorithm: ChecksumAlgorithm) -> crate::Result<PathBuf> {
+    let digest = algorithm.hex_digest(fs::File::open(path)?)?;
+    // Only the file name, so the pair can be moved around and still checked with `sha256sum -c`
+    let file_name = path.file_name().unwrap_or(path.as_os_str()).to_string_lossy();
+
+    let checksum_path = checksum_path(path, algorithm);
+    fs::write(&checksum_path, format!("{digest}  {file_name}\n"))?;
+    Ok(checksum_path)
+}
+
+/// Check the file at `path` against the first checksum file found next to it
+pub fn verify_checksum_file(path: &Path) -> crate::Result<()> {
+    let display_path = EscapedPathDisplay::new(strip_cur_dir(path));
+
+    let Some((algorithm, checksum_path)) = ALGORITHMS
+        .into_iter()
+        .map(|algorithm| (algorithm, checksum_path(path, algorithm)))
+        .find(|(_, checksum_path)| checksum_path.is_file())
+    else {
+        let extensions: Vec<_> = ALGORITHMS.iter().map(|algorithm| algorithm.extension()).collect();
+        return Err(
+            FinalError::with_title(format!("Cannot verify the checksum of '{display_path}'"))
+                .detail("No checksum file was found next to it")
+                .hint(format!(
+                    "Expected a file named like '{}' with one of the extensions: {}",
+                    EscapedPathDisplay::new(&checksum_path(strip_cur_dir(path), ALGORITHMS[0])),
+                    extensions.join(", "),
+                ))
+                .into(),
+        );
+    };
+
+    // Formatted like "<hash>  <filename>", or "<hash> *<filename>" for binary mode
+    let contents = fs::read_to_string(&checksum_path)?;
+    let expected = contents
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let actual = algorithm.hex_digest(fs::File::open(path)?)?;
+
+    if expected != actual {
+        return Err(
+            FinalError::with_title(format!("Checksum mismatch for '{display_path}'"))
+                .detail(format!("Expected {} {expected}", algorithm.extension()))
+                .detail(format!("Found    {} {actual}", algorithm.extension()))
+                .hint("The file might be corrupted or incomplete, it wasn't extracted")
+                .into(),
+        );
+    }
+
+    Ok(())
+}
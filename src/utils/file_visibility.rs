@@ -1,6 +1,32 @@
-use std::path::Path;
+use std::{
+    collections::BTreeMap,
+    fs::Metadata,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use ignore::overrides::{Override, OverrideBuilder};
+
+use crate::{
+    cli::SortOrder,
+    error::{Error, FinalError},
+};
+
+/// What happens to the symlinks found while walking directories
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+    /// Walk through the targets of symlinks, compressing them instead of the links
+    Follow,
+    /// Store the links themselves, in the formats that support it
+    #[default]
+    Store,
+    /// Leave symlinks out
+    Skip,
+}
 
 /// Determines which files should be read or ignored during directory walking
+#[derive(Clone)]
 pub struct FileVisibilityPolicy {
     /// Enables reading .ignore files.
     ///
@@ -12,13 +38,63 @@ pub struct FileVisibilityPolicy {
     /// Disabled by default
     pub read_hidden: bool,
 
-    /// Enables reading .gitignore files.
+    /// Enables reading .gitignore files, even outside of git repositories.
     ///
-    /// This is enabled by default.
+    /// Disabled by default.
     pub read_git_ignore: bool,
 
     /// Enables reading `.git/info/exclude` files.
     pub read_git_exclude: bool,
+
+    /// Enables reading the global gitignore file, set by git's `core.excludesFile`.
+    ///
+    /// Disabled by default.
+    pub read_git_global: bool,
+
+    /// Glob patterns of the paths to skip, matched relative to each walked root.
+    ///
+    /// Empty by default.
+    pub exclude: Vec<String>,
+
+    /// Whether symlinks are followed, yielded as links, or skipped.
+    ///
+    /// [`SymlinkPolicy::Store`] by default.
+    pub symlinks: SymlinkPolicy,
+
+    /// Skips files smaller than this many bytes.
+    ///
+    /// `None` by default.
+    pub min_size: Option<u64>,
+
+    /// Skips files larger than this many bytes.
+    ///
+    /// `None` by default.
+    pub max_size: Option<u64>,
+
+    /// Skips files last modified at or before this time.
+    ///
+    /// `None` by default.
+    pub newer_than: Option<SystemTime>,
+
+    /// How many levels deep directories are walked, 1 being what's right inside of them.
+    ///
+    /// `None` by default, no limit.
+    pub max_depth: Option<u32>,
+
+    /// Whether the subdirectories of directories are walked too, otherwise only the files right
+    /// inside of them are, without the subdirectories.
+    ///
+    /// `true` by default.
+    pub recursive: bool,
+
+    /// Order of the entries of each directory.
+    ///
+    /// [`SortOrder::None`] by default, the order of the filesystem.
+    pub sort: SortOrder,
+
+    /// Files skipped because of their size, and their sizes, shared by clones of the policy,
+    /// so they can be reported once after walking
+    size_excluded: Arc<Mutex<BTreeMap<PathBuf, u64>>>,
 }
 
 impl Default for FileVisibilityPolicy {
@@ -28,6 +104,16 @@ impl Default for FileVisibilityPolicy {
             read_hidden: true,
             read_git_ignore: false,
             read_git_exclude: false,
+            read_git_global: false,
+            exclude: Vec::new(),
+            symlinks: SymlinkPolicy::default(),
+            min_size: None,
+            max_size: None,
+            newer_than: None,
+            max_depth: None,
+            recursive: true,
+            sort: SortOrder::default(),
+            size_excluded: Arc::default(),
         }
     }
 }
@@ -62,18 +148,227 @@ impl FileVisibilityPolicy {
     }
 
     #[must_use]
-    /// Enables reading `.git/info/exclude` files.
+    /// Enables reading the global gitignore file.
+    pub fn read_git_global(self, read_git_global: bool) -> Self {
+        Self {
+            read_git_global,
+            ..self
+        }
+    }
+
+    #[must_use]
+    /// Enables ignoring hidden files.
     pub fn read_hidden(self, read_hidden: bool) -> Self {
         Self { read_hidden, ..self }
     }
 
+    #[must_use]
+    /// Sets what happens to symlinks.
+    pub fn symlinks(self, symlinks: SymlinkPolicy) -> Self {
+        Self { symlinks, ..self }
+    }
+
+    /// Check if symlinks are walked through, instead of yielded as links
+    pub fn follows_links(&self) -> bool {
+        self.symlinks == SymlinkPolicy::Follow
+    }
+
+    #[must_use]
+    /// Skips files smaller than `min_size` or larger than `max_size`, in bytes.
+    pub fn size_limits(self, min_size: Option<u64>, max_size: Option<u64>) -> Self {
+        Self {
+            min_size,
+            max_size,
+            ..self
+        }
+    }
+
+    #[must_use]
+    /// Skips files that weren't modified after `newer_than`.
+    pub fn newer_than(self, newer_than: Option<SystemTime>) -> Self {
+        Self { newer_than, ..self }
+    }
+
+    #[must_use]
+    /// Only walks `max_depth` levels deep into directories.
+    pub fn max_depth(self, max_depth: Option<u32>) -> Self {
+        Self { max_depth, ..self }
+    }
+
+    #[must_use]
+    /// Only walks the files right inside of directories, skipping their subdirectories, unless
+    /// `recursive` is set.
+    pub fn recursive(self, recursive: bool) -> Self {
+        Self { recursive, ..self }
+    }
+
+    #[must_use]
+    /// Sets the order of the entries of each directory.
+    pub fn sort(self, sort: SortOrder) -> Self {
+        Self { sort, ..self }
+    }
+
+    /// Check if files are skipped based on their metadata, by the size limits or `newer_than`
+    pub fn filters_by_metadata(&self) -> bool {
+        self.min_size.is_some() || self.max_size.is_some() || self.newer_than.is_some()
+    }
+
+    /// Check if the file at `path` is skipped by the size limits or `newer_than`
+    pub fn is_excluded_by_metadata(&self, path: &Path, metadata: &Metadata) -> bool {
+        // Files whose modification time can't be read are kept
+        let is_old = |newer_than| metadata.modified().is_ok_and(|modified| modified <= newer_than);
+        self.newer_than.is_some_and(is_old) || self.is_excluded_by_size(path, metadata.len())
+    }
+
+    /// Check if a file of `size` bytes is skipped by the size limits, remembering it if so
+    pub fn is_excluded_by_size(&self, path: &Path, size: u64) -> bool {
+        let excluded = self.min_size.is_some_and(|min_size| size < min_size)
+            || self.max_size.is_some_and(|max_size| size > max_size);
+
+        if excluded {
+            // Poisoning only happens if another thread panicked, which already aborts ouch
+            let mut size_excluded = self.size_excluded.lock().unwrap();
+            size_excluded.insert(path.to_path_buf(), size);
+        }
+
+        excluded
+    }
+
+    /// Number and total size of the files skipped by the size limits so far
+    pub fn size_excluded_summary(&self) -> (usize, u64) {
+        let size_excluded = self.size_excluded.lock().unwrap();
+        (size_excluded.len(), size_excluded.values().sum())
+    }
+
+    /// Skips paths matched by any of the glob `patterns`, which use the `.gitignore` syntax.
+    ///
+    /// Fails if one of the patterns is not a valid glob.
+    pub fn exclude(self, patterns: Vec<String>) -> crate::Result<Self> {
+        let policy = Self {
+            exclude: patterns,
+            ..self
+        };
+
+        // Check the patterns upfront, so `build_walker` can't fail
+        policy
+            .build_overrides(Path::new(""))
+            .map_err(|err| Error::InvalidUsage {
+                reason: FinalError::with_title("Invalid --exclude pattern")
+                    .detail(err.to_string())
+                    .hint("Patterns use the same syntax as .gitignore files, like '*.log' or 'target/**'."),
+            })?;
+
+        Ok(policy)
+    }
+
+    /// Overrides that ignore the paths matched by `self.exclude`, relative to `root`
+    fn build_overrides(&self, root: &Path) -> Result<Override, ignore::Error> {
+        let mut builder = OverrideBuilder::new(root);
+        // Matching follows the case sensitivity of the platform's paths
+        builder.case_insensitive(!cfg!(unix))?;
+
+        for pattern in &self.exclude {
+            // Globs are whitelists for overrides, a leading '!' turns them into ignores
+            builder.add(&format!("!{pattern}"))?;
+        }
+
+        builder.build()
+    }
+
     /// Walks through a directory using [`ignore::Walk`]
+    ///
+    /// Directories matched by an exclude pattern are skipped without being traversed.
     pub fn build_walker(&self, path: impl AsRef<Path>) -> ignore::Walk {
-        ignore::WalkBuilder::new(path)
+        let path = path.as_ref();
+        // Safety:
+        //     the patterns were already checked in `Self::exclude`
+        let overrides = self.build_overrides(path).expect("exclude patterns should be valid");
+
+        let mut builder = ignore::WalkBuilder::new(path);
+        if self.filters_by_metadata() || self.symlinks == SymlinkPolicy::Skip || !self.recursive {
+            let policy = self.clone();
+            // Not applied to `path` itself, the root of the walk
+            builder.filter_entry(move |entry| {
+                if policy.symlinks == SymlinkPolicy::Skip && entry.path_is_symlink() {
+                    return false;
+                }
+                let is_dir = entry.file_type().is_some_and(|file_type| file_type.is_dir());
+                if !policy.recursive && is_dir {
+                    return false;
+                }
+                let is_file = entry.file_type().is_some_and(|file_type| file_type.is_file());
+                match entry.metadata() {
+                    Ok(metadata) if is_file => !policy.is_excluded_by_metadata(entry.path(), &metadata),
+                    _ => true,
+                }
+            });
+        }
+
+        match self.sort {
+            SortOrder::None => {}
+            SortOrder::Name => {
+                builder.sort_by_file_name(|a, b| a.cmp(b));
+            }
+            SortOrder::Size => {
+                // Symlinks are sorted by their own size, unless they are followed
+                let follow_links = self.follows_links();
+                let size = move |path: &Path| {
+                    let metadata = if follow_links {
+                        path.metadata()
+                    } else {
+                        path.symlink_metadata()
+                    };
+                    metadata.map_or(0, |metadata| metadata.len())
+                };
+                builder.sort_by_file_path(move |a, b| size(a).cmp(&size(b)).then_with(|| a.cmp(b)));
+            }
+        }
+
+        builder
             .git_exclude(self.read_git_exclude)
             .git_ignore(self.read_git_ignore)
+            .git_global(self.read_git_global)
+            // Projects that aren't git repositories (yet) can still have a .gitignore
+            .require_git(false)
             .ignore(self.read_ignore)
             .hidden(self.read_hidden)
+            .follow_links(self.follows_links())
+            // The walked path itself is at depth 0
+            .max_depth(match self.recursive {
+                true => self.max_depth.map(|depth| depth as usize),
+                false => Some(1),
+            })
+            .overrides(overrides)
             .build()
     }
 }
+
+/// Whitelist of the archive entries matched by any of the glob `patterns` given to `--{flag}`,
+/// which use the `.gitignore` syntax, like `--exclude` when compressing
+pub fn build_glob_whitelist(patterns: &[String], flag: &str) -> crate::Result<Override> {
+    let build = || {
+        let mut builder = OverrideBuilder::new("");
+        // Matching follows the case sensitivity of the platform's paths
+        builder.case_insensitive(!cfg!(unix))?;
+        for pattern in patterns {
+            builder.add(pattern)?;
+        }
+        builder.build()
+    };
+
+    build().map_err(|err: ignore::Error| Error::InvalidUsage {
+        reason: FinalError::with_title(format!("Invalid --{flag} pattern"))
+            .detail(err.to_string())
+            .hint("Patterns use the same syntax as .gitignore files, like '*.md' or 'docs/**'."),
+    })
+}
+
+/// Check if `whitelist` matches the archive entry at `path`, or one of the directories it's in
+pub fn is_whitelisted(whitelist: &Override, path: &Path, is_dir: bool) -> bool {
+    whitelist.matched(path, is_dir).is_whitelist()
+        || path
+            .ancestors()
+            .skip(1)
+            .filter(|ancestor| !ancestor.as_os_str().is_empty())
+            .any(|ancestor| whitelist.matched(ancestor, true).is_whitelist())
+}
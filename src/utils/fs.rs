@@ -3,13 +3,16 @@
 use std::{
     env,
     io::Read,
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf},
 };
 
 use fs_err as fs;
 
 use super::user_wants_to_overwrite;
 use crate::{
+    archive::EntryBase,
+    cli::ConflictPolicy,
+    error::FinalError,
     extension::Extension,
     utils::{logger::info_accessible, EscapedPathDisplay},
     QuestionPolicy,
@@ -30,6 +33,114 @@ pub fn clear_path(path: &Path, question_policy: QuestionPolicy) -> crate::Result
     Ok(true)
 }
 
+/// Decide, with `conflict_policy`, where to write `path` if something is already there.
+///
+/// * `Ok(Some(_))` is the path to write to, a new sibling of `path` when renaming,
+///   or `path` itself, which is removed if it has to be overwritten
+/// * `Ok(None)` means `path` should be skipped
+/// * `Err(_)` is an error
+pub fn resolve_conflict(
+    path: &Path,
+    conflict_policy: ConflictPolicy,
+    question_policy: QuestionPolicy,
+) -> crate::Result<Option<PathBuf>> {
+    if !path.exists() {
+        return Ok(Some(path.to_path_buf()));
+    }
+
+    match conflict_policy {
+        ConflictPolicy::Ask => Ok(clear_path(path, question_policy)?.then(|| path.to_path_buf())),
+        // The files that were already extracted are kept before getting here
+        ConflictPolicy::Overwrite | ConflictPolicy::SkipExisting => {
+            remove_file_or_dir(path)?;
+            Ok(Some(path.to_path_buf()))
+        }
+        ConflictPolicy::Skip => Ok(None),
+        ConflictPolicy::Rename => Ok(Some(unused_sibling_path(path))),
+    }
+}
+
+/// First of "name (1).ext", "name (2).ext"... that doesn't exist yet, directories keep
+/// their whole name, like "name.d (1)".
+fn unused_sibling_path(path: &Path) -> PathBuf {
+    let (stem, extension) = match (path.is_dir(), path.file_stem(), path.extension()) {
+        (false, Some(stem), Some(extension)) => (stem, Some(extension)),
+        _ => (path.file_name().unwrap_or(path.as_os_str()), None),
+    };
+
+    (1..)
+        .map(|number| {
+            let mut name = stem.to_owned();
+            name.push(format!(" ({number})"));
+            if let Some(extension) = extension {
+                name.push(".");
+                name.push(extension);
+            }
+            path.with_file_name(name)
+        })
+        .find(|path| !path.exists())
+        .expect("there's always an unused number")
+}
+
+/// Make the path of an archive entry relative to the output directory, see [`safe_join`] to get
+/// the path to write it to
+///
+/// Leading `/` and Windows prefixes are removed, like `tar` does, paths with `..` are rejected as
+/// they could write outside of the output directory. The returned path is empty for root entries
+/// like "." or "/".
+pub fn sanitize_entry_path(name: &Path) -> crate::Result<PathBuf> {
+    let mut path = PathBuf::new();
+
+    for component in name.components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => {}
+            Component::ParentDir => {
+                return Err(unsafe_entry_path_error(
+                    name,
+                    "Entries with '..' could be written outside of the output directory",
+                ));
+            }
+        }
+    }
+
+    Ok(path)
+}
+
+/// Join the path of an archive entry to `output_folder`, after making it relative with
+/// [`sanitize_entry_path`], and create its missing parent directories, every unpacker must use it
+/// to get the path it writes an entry to
+///
+/// Fails if any part of the path is a symlink: `output_folder` starts empty, so it was extracted
+/// from an earlier entry, and writing through it could write outside of `output_folder`.
+/// The returned path is `output_folder` itself for root entries.
+pub fn safe_join(output_folder: &Path, name: &Path) -> crate::Result<PathBuf> {
+    let mut path = output_folder.to_path_buf();
+
+    for component in sanitize_entry_path(name)?.components() {
+        path.push(component);
+        if fs::symlink_metadata(&path).is_ok_and(|metadata| metadata.file_type().is_symlink()) {
+            return Err(unsafe_entry_path_error(
+                name,
+                "The entry would be written through a symlink extracted from the archive",
+            ));
+        }
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    Ok(path)
+}
+
+fn unsafe_entry_path_error(name: &Path, detail: &'static str) -> crate::Error {
+    FinalError::with_title(format!("Unsafe path in archive: '{}'", EscapedPathDisplay::new(name)))
+        .detail(detail)
+        .hint("The archive might be malicious, nothing was written outside of the output directory")
+        .into()
+}
+
 pub fn remove_file_or_dir(path: &Path) -> crate::Result<()> {
     if path.is_dir() {
         fs::remove_dir_all(path)?;
@@ -39,6 +150,11 @@ pub fn remove_file_or_dir(path: &Path) -> crate::Result<()> {
     Ok(())
 }
 
+/// Returns true if `path` is `-`, which stands for stdin when reading and for stdout when writing.
+pub fn is_stdio(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
 /// Creates a directory at the path, if there is nothing there.
 pub fn create_dir_if_non_existent(path: &Path) -> crate::Result<()> {
     if !path.exists() {
@@ -51,19 +167,84 @@ pub fn create_dir_if_non_existent(path: &Path) -> crate::Result<()> {
 }
 
 /// Returns current directory, but before change the process' directory to the
-/// one that contains the file pointed to by `filename`.
-pub fn cd_into_same_dir_as(filename: &Path) -> crate::Result<PathBuf> {
+/// one that the entry names of `filename` are relative to, its parent by default.
+pub fn cd_into_entry_base(filename: &Path, entry_base: &EntryBase) -> crate::Result<PathBuf> {
     let previous_location = env::current_dir()?;
 
-    let parent = filename.parent().ok_or(crate::Error::CompressingRootFolder)?;
-    env::set_current_dir(parent)?;
+    env::set_current_dir(entry_base.dir_of(filename)?)?;
 
     Ok(previous_location)
 }
 
+/// Unix mode of an entry extracted from an archive.
+///
+/// The `archive_mode` stored in the archive is kept if `preserve_permissions` is set, otherwise
+/// files get `644` and directories `755`, regardless of what the archive says.
+#[cfg(unix)]
+pub fn extracted_mode(archive_mode: u32, is_dir: bool, preserve_permissions: bool) -> u32 {
+    match (preserve_permissions, is_dir) {
+        (true, _) => archive_mode,
+        (false, true) => 0o755,
+        (false, false) => 0o644,
+    }
+}
+
 /// Try to detect the file extension by looking for known magic strings
 /// Source: <https://en.wikipedia.org/wiki/List_of_file_signatures>
 pub fn try_infer_extension(path: &Path) -> Option<Extension> {
+    let mut buf = [0; INFER_LEN];
+
+    // Error cause will be ignored, so use std::fs instead of fs_err
+    let result = std::fs::File::open(path).map(|mut file| file.read(&mut buf));
+
+    // In case of file open or read failure, could not infer a extension
+    if result.is_err() {
+        return None;
+    }
+
+    infer_extension(&buf)
+}
+
+/// What a file holds when [`try_infer_extension`] found no format in it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnknownContent {
+    /// UTF-8 without control characters other than whitespace
+    Text,
+    Binary,
+}
+
+/// Guess what the file at `path` holds from its first KiB, `None` if it can't be read or is empty,
+/// which says nothing about its format
+pub fn sniff_unknown_content(path: &Path) -> Option<UnknownContent> {
+    let mut buf = [0; 1024];
+    // Error cause will be ignored, so use std::fs instead of fs_err
+    let len = std::fs::File::open(path)
+        .and_then(|mut file| file.read(&mut buf))
+        .ok()?;
+    if len == 0 {
+        return None;
+    }
+    let start = &buf[..len];
+
+    // The last character can be cut in the middle
+    let is_utf8 = match std::str::from_utf8(start) {
+        Ok(_) => true,
+        Err(err) => err.error_len().is_none(),
+    };
+    let is_control = |byte: &u8| byte.is_ascii_control() && !byte.is_ascii_whitespace();
+
+    Some(if is_utf8 && !start.iter().any(is_control) {
+        UnknownContent::Text
+    } else {
+        UnknownContent::Binary
+    })
+}
+
+/// How many bytes from the start of a file are needed to infer its extension
+pub const INFER_LEN: usize = 270;
+
+/// Try to detect the extension of data starting with `start`, like [`try_infer_extension`]
+pub fn infer_extension(start: &[u8]) -> Option<Extension> {
     fn is_zip(buf: &[u8]) -> bool {
         buf.len() >= 3
             && buf[..=1] == [0x50, 0x4B]
@@ -81,6 +262,15 @@ pub fn try_infer_extension(path: &Path) -> Option<Extension> {
     fn is_xz(buf: &[u8]) -> bool {
         buf.starts_with(&[0xFD, 0x37, 0x7A, 0x58, 0x5A, 0x00])
     }
+    fn is_lzma(buf: &[u8]) -> bool {
+        // The legacy format has no magic number, but its header almost always starts with the
+        // default properties byte and a little-endian dictionary size that is a multiple of
+        // 64 KiB, which can't start any other format in this list
+        buf.starts_with(&[0x5D, 0x00, 0x00])
+    }
+    fn is_lzip(buf: &[u8]) -> bool {
+        buf.starts_with(crate::utils::lzip::MAGIC)
+    }
     fn is_lz4(buf: &[u8]) -> bool {
         buf.starts_with(&[0x04, 0x22, 0x4D, 0x18])
     }
@@ -98,19 +288,22 @@ pub fn try_infer_extension(path: &Path) -> Option<Extension> {
     fn is_sevenz(buf: &[u8]) -> bool {
         buf.starts_with(&[0x37, 0x7A, 0xBC, 0xAF, 0x27, 0x1C])
     }
+    fn is_ar(buf: &[u8]) -> bool {
+        buf.starts_with(crate::archive::ar::MAGIC)
+    }
+    fn is_deb(buf: &[u8]) -> bool {
+        // The first member of Debian packages
+        is_ar(buf) && buf[8..].starts_with(b"debian-binary")
+    }
+    fn is_cpio(buf: &[u8]) -> bool {
+        // "070701" (newc) and "070702" (newc with CRC)
+        buf.starts_with(b"070701") || buf.starts_with(b"070702")
+    }
 
-    let buf = {
-        let mut buf = [0; 270];
-
-        // Error cause will be ignored, so use std::fs instead of fs_err
-        let result = std::fs::File::open(path).map(|mut file| file.read(&mut buf));
-
-        // In case of file open or read failure, could not infer a extension
-        if result.is_err() {
-            return None;
-        }
-        buf
-    };
+    // Shorter data is padded, so the checks don't have to bother with its length
+    let mut buf = [0; INFER_LEN];
+    let len = start.len().min(INFER_LEN);
+    buf[..len].copy_from_slice(&start[..len]);
 
     use crate::extension::CompressionFormat::*;
     if is_zip(&buf) {
@@ -122,7 +315,9 @@ pub fn try_infer_extension(path: &Path) -> Option<Extension> {
     } else if is_bz2(&buf) {
         Some(Extension::new(&[Bzip], "bz2"))
     } else if is_xz(&buf) {
-        Some(Extension::new(&[Lzma], "xz"))
+        Some(Extension::new(&[Xz], "xz"))
+    } else if is_lzip(&buf) {
+        Some(Extension::new(&[Lzip], "lz"))
     } else if is_lz4(&buf) {
         Some(Extension::new(&[Lz4], "lz4"))
     } else if is_sz(&buf) {
@@ -133,6 +328,14 @@ pub fn try_infer_extension(path: &Path) -> Option<Extension> {
         Some(Extension::new(&[Rar], "rar"))
     } else if is_sevenz(&buf) {
         Some(Extension::new(&[SevenZip], "7z"))
+    } else if is_cpio(&buf) {
+        Some(Extension::new(&[Cpio], "cpio"))
+    } else if is_deb(&buf) {
+        Some(Extension::new(&[Ar], "deb"))
+    } else if is_ar(&buf) {
+        Some(Extension::new(&[Ar], "a"))
+    } else if is_lzma(&buf) {
+        Some(Extension::new(&[Lzma], "lzma"))
     } else {
         None
     }
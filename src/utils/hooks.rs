@@ -0,0 +1,95 @@
+//! Shell commands run after compressing or decompressing, given by `--on-success` and `--on-failure`
+
+use std::{path::PathBuf, process::Command};
+
+use crate::{cli::Subcommand, error::FinalError, utils::EscapedPathDisplay};
+
+/// The hooks of a subcommand, and the path of its output given to them in `OUCH_OUTPUT`
+pub struct Hooks {
+    on_success: Option<String>,
+    on_failure: Option<String>,
+    output: PathBuf,
+}
+
+impl Hooks {
+    /// Hooks given to `cmd`, `None` if there aren't any, or for a dry run, which writes nothing
+    pub fn of(cmd: &Subcommand) -> Option<Self> {
+        let hooks = match cmd {
+            // With --each, the output is the directory the archives are written to
+            Subcommand::Compress {
+                output,
+                output_dir,
+                each,
+                dry_run: false,
+                on_success,
+                on_failure,
+                ..
+            } => Self {
+                on_success: on_success.clone(),
+                on_failure: on_failure.clone(),
+                output: match (each, output_dir) {
+                    (true, Some(output_dir)) => output_dir.clone(),
+                    (true, None) => PathBuf::from("."),
+                    (false, Some(output_dir)) => output_dir.join(output),
+                    (false, None) => output.clone(),
+                },
+            },
+            // Archives are unpacked into the output directory, under names that depend on their contents
+            Subcommand::Decompress {
+                output_dir,
+                to_stdout,
+                on_success,
+                on_failure,
+                ..
+            } => Self {
+                on_success: on_success.clone(),
+                on_failure: on_failure.clone(),
+                output: match (to_stdout, output_dir) {
+                    (true, _) => PathBuf::from("-"),
+                    (false, Some(output_dir)) => output_dir.clone(),
+                    (false, None) => PathBuf::from("."),
+                },
+            },
+            _ => return None,
+        };
+
+        (hooks.on_success.is_some() || hooks.on_failure.is_some()).then_some(hooks)
+    }
+
+    /// Run `--on-success` if `result` is `Ok`, or `--on-failure` with the exit code of its error in
+    /// `OUCH_EXIT_CODE`, failing if the hook can't be run or exits with an error
+    pub fn run(&self, result: &crate::Result<()>) -> crate::Result<()> {
+        let (flag, command, exit_code) = match result {
+            Ok(()) => ("--on-success", &self.on_success, None),
+            Err(err) => ("--on-failure", &self.on_failure, Some(err.exit_code())),
+        };
+        let Some(command) = command else {
+            return Ok(());
+        };
+
+        let mut shell = shell(command);
+        shell.env("OUCH_OUTPUT", &self.output);
+        if let Some(exit_code) = exit_code {
+            shell.env("OUCH_EXIT_CODE", exit_code.to_string());
+        }
+
+        let failure = match shell.status() {
+            Ok(status) if status.success() => return Ok(()),
+            Ok(status) => format!("It exited with {status}"),
+            Err(err) => format!("It couldn't be run: {err}"),
+        };
+        Err(FinalError::with_title(format!("The {flag} command failed"))
+            .detail(failure)
+            .detail(format!("Command: {command}"))
+            .detail(format!("Output: '{}'", EscapedPathDisplay::new(&self.output)))
+            .into())
+    }
+}
+
+/// `command` run by the shell, `sh` or `cmd` on Windows
+fn shell(command: &str) -> Command {
+    let (shell, flag) = if cfg!(windows) { ("cmd", "/C") } else { ("sh", "-c") };
+    let mut shell = Command::new(shell);
+    shell.arg(flag).arg(command);
+    shell
+}
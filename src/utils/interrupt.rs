@@ -0,0 +1,183 @@
+//! Cleanup of decompressed files when ouch is interrupted with Ctrl-C
+//!
+//! Files are registered while they're written, so that an interrupt removes the ones left
+//! incomplete, and lists the ones completed so far, which are kept.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{Mutex, PoisonError},
+};
+
+use super::{
+    colors::{ORANGE, RESET, YELLOW},
+    progress, EscapedPathDisplay,
+};
+
+/// Files being written and files completed, by every thread
+static OUTPUTS: Mutex<Outputs> = Mutex::new(Outputs {
+    writing: Vec::new(),
+    completed: Vec::new(),
+});
+
+struct Outputs {
+    writing: Vec<PathBuf>,
+    completed: Vec<PathBuf>,
+}
+
+fn outputs() -> std::sync::MutexGuard<'static, Outputs> {
+    OUTPUTS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// A file being written, removed if ouch is interrupted before [`Writing::finish`] is called
+#[must_use]
+pub struct Writing {
+    path: PathBuf,
+}
+
+impl Writing {
+    pub fn start(path: &Path) -> Self {
+        outputs().writing.push(path.to_path_buf());
+        Self {
+            path: path.to_path_buf(),
+        }
+    }
+
+    /// The file is complete, it's kept and listed if ouch is interrupted afterwards
+    pub fn finish(self) {
+        outputs().completed.push(self.path.clone());
+    }
+}
+
+impl Drop for Writing {
+    fn drop(&mut self) {
+        let mut outputs = outputs();
+        if let Some(index) = outputs.writing.iter().rposition(|path| *path == self.path) {
+            outputs.writing.swap_remove(index);
+        }
+    }
+}
+
+/// The completed files inside of `dir` were moved to `destinations`, which are listed instead
+pub fn completed_moved(dir: &Path, destinations: impl IntoIterator<Item = PathBuf>) {
+    let mut outputs = outputs();
+    outputs.completed.retain(|path| !path.starts_with(dir));
+    outputs.completed.extend(destinations);
+}
+
+/// Remove the files being written and list the completed ones, called when interrupted
+fn clean_up() {
+    let outputs = outputs();
+    if outputs.writing.is_empty() && outputs.completed.is_empty() {
+        return;
+    }
+
+    // Printed directly, the logger thread might not get to it before ouch exits
+    progress::suspend(|| {
+        eprintln!();
+        for path in &outputs.writing {
+            match fs::remove_file(path) {
+                Ok(()) => eprintln!(
+                    "{}[INFO]{} Interrupted, removed the incomplete file '{}'.",
+                    *YELLOW,
+                    *RESET,
+                    EscapedPathDisplay::new(path)
+                ),
+                Err(_) => eprintln!(
+                    "{}[WARNING]{} Interrupted, failed to remove the incomplete file '{}', please delete it manually.",
+                    *ORANGE,
+                    *RESET,
+                    EscapedPathDisplay::new(path)
+                ),
+            }
+        }
+
+        // Files since removed, like the ones of an extraction that was canceled, aren't listed
+        let completed: Vec<_> = outputs
+            .completed
+            .iter()
+            .filter(|path| path.symlink_metadata().is_ok())
+            .collect();
+        if !completed.is_empty() {
+            eprintln!("{}[INFO]{} Files completed before the interrupt:", *YELLOW, *RESET);
+            for path in completed {
+                eprintln!("  {}", EscapedPathDisplay::new(path));
+            }
+        }
+    });
+}
+
+/// Clean up the decompressed files when ouch is interrupted, it must be called before any thread
+/// is spawned
+///
+/// SIGINT is blocked in every thread and waited for by a new one, so the cleanup doesn't run in a
+/// signal handler. Then ouch is killed by SIGINT, like it would be without the handler.
+#[cfg(unix)]
+pub fn install_handler() {
+    use std::{mem, ptr, thread};
+
+    // Safety: the set is initialized by `sigemptyset` before it's used
+    let set = unsafe {
+        let mut set: libc::sigset_t = mem::zeroed();
+        libc::sigemptyset(&mut set);
+        libc::sigaddset(&mut set, libc::SIGINT);
+        set
+    };
+
+    // The mask is inherited by the threads spawned afterwards
+    // Safety: `set` is initialized, and a null pointer is allowed for the previous mask
+    if unsafe { libc::pthread_sigmask(libc::SIG_BLOCK, &set, ptr::null_mut()) } != 0 {
+        return;
+    }
+
+    thread::spawn(move || {
+        let mut signal = 0;
+        // Safety: `set` is initialized and `signal` is valid for the whole call
+        if unsafe { libc::sigwait(&set, &mut signal) } != 0 {
+            return;
+        }
+
+        clean_up();
+
+        // Safety: SIG_DFL is a valid disposition for SIGINT, `set` is initialized and a null pointer
+        // is allowed for the previous mask
+        unsafe {
+            libc::signal(libc::SIGINT, libc::SIG_DFL);
+            libc::pthread_sigmask(libc::SIG_UNBLOCK, &set, ptr::null_mut());
+            libc::raise(libc::SIGINT);
+        }
+    });
+}
+
+/// Clean up the decompressed files when ouch is interrupted
+///
+/// The handler runs in its own thread, then the default handler terminates ouch.
+#[cfg(windows)]
+pub fn install_handler() {
+    type HandlerRoutine = unsafe extern "system" fn(u32) -> i32;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn SetConsoleCtrlHandler(handler: Option<HandlerRoutine>, add: i32) -> i32;
+    }
+
+    const CTRL_C_EVENT: u32 = 0;
+    const CTRL_BREAK_EVENT: u32 = 1;
+
+    unsafe extern "system" fn handler(event: u32) -> i32 {
+        if matches!(event, CTRL_C_EVENT | CTRL_BREAK_EVENT) {
+            clean_up();
+        }
+        // Not handled, so the next handler runs
+        0
+    }
+
+    // Safety: `handler` is a function with the signature of a handler routine, valid for as long
+    // as ouch runs
+    unsafe {
+        SetConsoleCtrlHandler(Some(handler), 1);
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn install_handler() {}
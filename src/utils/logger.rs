@@ -5,6 +5,19 @@ pub use logger_thread::spawn_logger_thread;
 use super::colors::{ORANGE, RESET, YELLOW};
 use crate::accessible::is_running_in_accessible_mode;
 
+/// Global flag for `--quiet`, dropping every `[INFO]` and `[WARNING]` log, errors are still printed
+static QUIET: OnceLock<bool> = OnceLock::new();
+
+/// Set the value of the global [`QUIET`] flag.
+pub fn set_quiet(value: bool) {
+    let _ = QUIET.set(value);
+}
+
+/// Check if `Ouch` is running with `--quiet`.
+pub fn is_quiet() -> bool {
+    QUIET.get().copied().unwrap_or(false)
+}
+
 /// An `[INFO]` log to be displayed if we're not running accessibility mode.
 ///
 /// Same as `.info_accessible()`, but only displayed if accessibility mode
@@ -37,6 +50,50 @@ fn info_with_accessibility(contents: String, accessible: bool) {
     });
 }
 
+/// How much is printed about the files being processed, set by `--quiet` and `--verbose`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Nothing, with `--quiet`
+    Quiet,
+    /// Each file, except in accessibility mode
+    #[default]
+    Normal,
+    /// Each file, even in accessibility mode, and no progress bar getting in the way, with `-v`
+    Verbose,
+    /// Each file with its size, with `-vv`
+    VeryVerbose,
+}
+
+impl Verbosity {
+    pub fn new(quiet: bool, verbose: u8) -> Self {
+        match (quiet, verbose) {
+            (true, _) => Self::Quiet,
+            (false, 0) => Self::Normal,
+            (false, 1) => Self::Verbose,
+            (false, _) => Self::VeryVerbose,
+        }
+    }
+
+    pub fn is_quiet(self) -> bool {
+        self == Self::Quiet
+    }
+
+    /// Progress isn't reported when quiet, or when every file is listed
+    pub fn hides_progress(self) -> bool {
+        self != Self::Normal
+    }
+}
+
+/// An `[INFO]` log about a single file being processed, only built if it's displayed at `verbosity`
+#[track_caller]
+pub fn file_info(verbosity: Verbosity, contents: impl FnOnce() -> String) {
+    match verbosity {
+        Verbosity::Quiet => {}
+        Verbosity::Normal => info(contents()),
+        Verbosity::Verbose | Verbosity::VeryVerbose => info_accessible(contents()),
+    }
+}
+
 pub fn warning(contents: String) {
     logger_thread::send_log_message(PrintMessage {
         contents,
@@ -63,6 +120,10 @@ struct PrintMessage {
 
 impl PrintMessage {
     fn to_processed_message(&self) -> Option<String> {
+        if is_quiet() {
+            return None;
+        }
+
         match self.level {
             MessageLevel::Info => {
                 if self.accessible {
@@ -156,7 +217,9 @@ mod logger_thread {
             shutdown_barrier: shutdown_barrier.clone(),
         };
 
-        rayon::spawn(move || run_logger(log_receiver, shutdown_barrier));
+        // Not a rayon thread, it would take one of the workers used to (de)compress in parallel,
+        // leaving none when there's a single CPU
+        std::thread::spawn(move || run_logger(log_receiver, shutdown_barrier));
 
         handle
     }
@@ -200,7 +263,8 @@ mod logger_thread {
     fn flush_logs_to_stderr(buffer: &mut Vec<String>) {
         if !buffer.is_empty() {
             let text = buffer.join("\n");
-            eprintln!("{text}");
+            // Progress bars would be drawn over the logs
+            crate::utils::progress::suspend(|| eprintln!("{text}"));
             buffer.clear();
         }
     }
@@ -0,0 +1,303 @@
+//! The lzip format, `.lz`, a header and a trailer around a raw LZMA stream.
+//!
+//! Each member starts with the magic `LZIP`, the version and the coded dictionary size, followed
+//! by an LZMA stream with an end marker, and ends with the CRC32 and the size of the uncompressed
+//! data and the size of the whole member. A file can have multiple members, decompressed one after
+//! the other.
+
+use std::{
+    io::{self, BufReader, Read, Write},
+    sync::{
+        mpsc::{self, Receiver, SyncSender},
+        Arc, Mutex, PoisonError,
+    },
+    thread::{self, JoinHandle},
+};
+
+use lzma_rust::{CountingWriter, LZMA2Options, LZMAReader, LZMAWriter};
+
+pub const MAGIC: &[u8; 4] = b"LZIP";
+const VERSION: u8 = 1;
+const HEADER_SIZE: u64 = 6;
+const TRAILER_SIZE: u64 = 20;
+
+// The only LZMA properties lzip allows, literal context bits, literal position bits and position bits
+const LC: u32 = 3;
+const LP: u32 = 0;
+const PB: u32 = 2;
+
+const MIN_DICT_SIZE_LOG: u8 = 12;
+const MAX_DICT_SIZE_LOG: u8 = 29;
+
+/// Maximum number of chunks waiting to be compressed, so that the input isn't read ahead forever
+const PENDING_CHUNKS: usize = 16;
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("lzip: {message}"))
+}
+
+/// Dictionary size from its coded byte in the header, a power of 2 minus some sixteenths of it
+fn decode_dict_size(coded: u8) -> io::Result<u32> {
+    let log = coded & 0x1F;
+    if !(MIN_DICT_SIZE_LOG..=MAX_DICT_SIZE_LOG).contains(&log) {
+        return Err(invalid_data("invalid dictionary size"));
+    }
+    let size = 1u32 << log;
+    Ok(size - (size / 16) * u32::from(coded >> 5))
+}
+
+/// Coded byte of the smallest dictionary size that's at least `size`
+fn encode_dict_size(size: u32) -> u8 {
+    let log = (MIN_DICT_SIZE_LOG..=MAX_DICT_SIZE_LOG)
+        .find(|log| 1u32 << log >= size)
+        .unwrap_or(MAX_DICT_SIZE_LOG);
+
+    let base = 1u32 << log;
+    let fraction = (0..8u8)
+        .rev()
+        .find(|&fraction| base - (base / 16) * u32::from(fraction) >= size)
+        .unwrap_or(0);
+
+    (fraction << 5) | log
+}
+
+/// Writer that compresses to the lzip format on a separate thread, the member is finished once
+/// it's dropped, and [`LzipMember::finish`] waits for it to be written
+pub struct LzipEncoder {
+    sender: SyncSender<Vec<u8>>,
+    member: LzipMember,
+}
+
+/// The thread compressing the member of an [`LzipEncoder`]
+pub struct LzipMember(Arc<Mutex<Option<JoinHandle<io::Result<()>>>>>);
+
+impl LzipEncoder {
+    /// `level` is the LZMA preset, from 0 to 9
+    pub fn new(output: impl Write + Send + 'static, level: u32) -> (Self, LzipMember) {
+        let mut options = LZMA2Options::with_preset(level.min(9));
+        options.lc = LC;
+        options.lp = LP;
+        options.pb = PB;
+
+        let (sender, receiver) = mpsc::sync_channel(PENDING_CHUNKS);
+        let thread = thread::spawn(move || encode_member(receiver, output, &options));
+        let member = Arc::new(Mutex::new(Some(thread)));
+
+        let encoder = Self {
+            sender,
+            member: LzipMember(Arc::clone(&member)),
+        };
+        (encoder, LzipMember(member))
+    }
+}
+
+impl LzipMember {
+    /// Wait for the compression thread to write the whole member, returning its error, if any
+    ///
+    /// The thread only stops once the [`LzipEncoder`] is dropped, or because of an error.
+    pub fn finish(self) -> io::Result<()> {
+        let thread = self.0.lock().unwrap_or_else(PoisonError::into_inner).take();
+        match thread.map(JoinHandle::join) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => Err(io::Error::other("lzip: compression thread panicked")),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Write for LzipEncoder {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        if self.sender.send(buf.to_vec()).is_err() {
+            // The thread stopped early because of an error
+            LzipMember(Arc::clone(&self.member.0)).finish()?;
+            return Err(io::Error::other("lzip: the member was already finished"));
+        }
+        Ok(buf.len())
+    }
+
+    /// The compressed data can only be written when the member is finished
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Compress the chunks from `receiver` into a single member written to `output`
+fn encode_member(receiver: Receiver<Vec<u8>>, mut output: impl Write, options: &LZMA2Options) -> io::Result<()> {
+    output.write_all(MAGIC)?;
+    output.write_all(&[VERSION, encode_dict_size(options.dict_size)])?;
+
+    let compressed = CountingWriter::new(&mut output);
+    let compressed_size = compressed.counting();
+    let mut encoder = LZMAWriter::new_no_header(compressed, options, true)?;
+
+    let mut crc = crc32fast::Hasher::new();
+    let mut data_size: u64 = 0;
+    for chunk in receiver {
+        crc.update(&chunk);
+        data_size += chunk.len() as u64;
+        encoder.write_all(&chunk)?;
+    }
+    encoder.finish()?;
+    drop(encoder);
+
+    let member_size = HEADER_SIZE + compressed_size.get() as u64 + TRAILER_SIZE;
+    output.write_all(&crc.finalize().to_le_bytes())?;
+    output.write_all(&data_size.to_le_bytes())?;
+    output.write_all(&member_size.to_le_bytes())?;
+    output.flush()
+}
+
+/// Input of the decoder, shared with the LZMA reader of the current member so that the trailer
+/// can be read after it
+struct SharedInput<R>(Arc<Mutex<(BufReader<R>, u64)>>);
+
+impl<R> Clone for SharedInput<R> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+impl<R: Read> SharedInput<R> {
+    /// How many bytes were read from the input
+    fn position(&self) -> u64 {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner).1
+    }
+
+    /// Fill `buf` unless the input ends first, returns how many bytes were read
+    fn read_up_to(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            match self.read(&mut buf[filled..]) {
+                Ok(0) => break,
+                Ok(read) => filled += read,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(filled)
+    }
+}
+
+impl<R: Read> Read for SharedInput<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut input = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        let read = input.0.read(buf)?;
+        input.1 += read as u64;
+        Ok(read)
+    }
+}
+
+/// The member being decompressed
+struct Member<R> {
+    reader: LZMAReader<SharedInput<R>>,
+    start: u64,
+    crc: crc32fast::Hasher,
+    data_size: u64,
+}
+
+/// Reader that decompresses all the members of an lzip file
+pub struct LzipDecoder<R> {
+    input: SharedInput<R>,
+    member: Option<Member<R>>,
+    /// Whether at least a member was read, an empty input isn't a valid lzip file
+    started: bool,
+}
+
+impl<R: Read> LzipDecoder<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            input: SharedInput(Arc::new(Mutex::new((BufReader::new(reader), 0)))),
+            member: None,
+            started: false,
+        }
+    }
+
+    /// Read the header of the next member, `None` if the input ended
+    fn next_member(&mut self) -> io::Result<Option<Member<R>>> {
+        let start = self.input.position();
+        let mut header = [0; HEADER_SIZE as usize];
+        match self.input.read_up_to(&mut header)? {
+            0 if self.started => return Ok(None),
+            read if read < header.len() => return Err(invalid_data("unexpected end of file")),
+            _ => {}
+        }
+
+        if &header[..4] != MAGIC {
+            let message = if self.started {
+                "trailing data after the last member"
+            } else {
+                "bad magic number"
+            };
+            return Err(invalid_data(message));
+        }
+        if header[4] != VERSION {
+            return Err(invalid_data("unsupported version"));
+        }
+        let dict_size = decode_dict_size(header[5])?;
+        self.started = true;
+
+        let reader = LZMAReader::new(self.input.clone(), u64::MAX, LC, LP, PB, dict_size, None)?;
+        Ok(Some(Member {
+            reader,
+            start,
+            crc: crc32fast::Hasher::new(),
+            data_size: 0,
+        }))
+    }
+
+    /// Check the trailer of `member`, which was fully decompressed
+    fn check_trailer(&mut self, member: Member<R>) -> io::Result<()> {
+        drop(member.reader);
+
+        let mut trailer = [0; TRAILER_SIZE as usize];
+        if self.input.read_up_to(&mut trailer)? < trailer.len() {
+            return Err(invalid_data("unexpected end of file"));
+        }
+
+        // Unwrap safety:
+        //   the slices have the size of the integers
+        let crc = u32::from_le_bytes(trailer[..4].try_into().unwrap());
+        let data_size = u64::from_le_bytes(trailer[4..12].try_into().unwrap());
+        let member_size = u64::from_le_bytes(trailer[12..].try_into().unwrap());
+        if crc != member.crc.finalize() {
+            return Err(invalid_data("CRC mismatch"));
+        }
+        if data_size != member.data_size || member_size != self.input.position() - member.start {
+            return Err(invalid_data("size mismatch"));
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for LzipDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        loop {
+            let mut member = match self.member.take() {
+                Some(member) => member,
+                None => match self.next_member()? {
+                    Some(member) => member,
+                    None => return Ok(0),
+                },
+            };
+
+            let read = member.reader.read(buf)?;
+            if read == 0 {
+                self.check_trailer(member)?;
+                continue;
+            }
+
+            member.crc.update(&buf[..read]);
+            member.data_size += read as u64;
+            self.member = Some(member);
+            return Ok(read);
+        }
+    }
+}
@@ -3,23 +3,34 @@
 //! In here we have the logic for custom formatting, some file and directory utils, and user
 //! stdin interaction helpers.
 
+pub mod checksum;
 pub mod colors;
 mod file_visibility;
 mod formatting;
 mod fs;
+pub mod hooks;
+pub mod interrupt;
 pub mod io;
 pub mod logger;
+pub mod lzip;
+pub mod progress;
 mod question;
+pub mod split;
 
-pub use file_visibility::FileVisibilityPolicy;
+pub use file_visibility::{build_glob_whitelist, is_whitelisted, FileVisibilityPolicy, SymlinkPolicy};
 pub use formatting::{
     nice_directory_display, pretty_format_list_of_paths, strip_cur_dir, to_utf, Bytes, EscapedPathDisplay,
 };
+#[cfg(unix)]
+pub use fs::extracted_mode;
 pub use fs::{
-    cd_into_same_dir_as, clear_path, create_dir_if_non_existent, is_symlink, remove_file_or_dir, try_infer_extension,
+    cd_into_entry_base, clear_path, create_dir_if_non_existent, infer_extension, is_stdio, is_symlink,
+    remove_file_or_dir, resolve_conflict, safe_join, sanitize_entry_path, sniff_unknown_content, try_infer_extension,
+    UnknownContent, INFER_LEN,
 };
 pub use question::{
-    ask_to_create_file, user_wants_to_continue, user_wants_to_overwrite, QuestionAction, QuestionPolicy,
+    ask_for_password, ask_to_create_file, read_password_from_file, read_password_from_stdin, user_wants_to_continue,
+    user_wants_to_overwrite, AnswerPolicy, OverwritePolicy, QuestionAction, QuestionPolicy,
 };
 pub use utf8::{get_invalid_utf8_paths, is_invalid_utf8};
 
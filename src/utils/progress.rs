@@ -0,0 +1,142 @@
+//! Progress reporting for (de)compression, shown as a progress bar or as periodic log lines.
+
+use std::{
+    io::{self, Read, Seek, SeekFrom},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use indicatif::{MultiProgress, ProgressBar, ProgressFinish, ProgressStyle};
+use once_cell::sync::Lazy;
+
+use super::{logger::info_accessible, Bytes};
+use crate::accessible::is_running_in_accessible_mode;
+
+/// Holds every progress bar, so they can be drawn together and hidden while other text is printed
+static MULTI_PROGRESS: Lazy<MultiProgress> = Lazy::new(MultiProgress::new);
+
+/// How often a progress line is printed when the progress bar can't be shown
+const LINES_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Run `f` with progress bars hidden, anything printing to the terminal should be wrapped by this
+pub fn suspend<T>(f: impl FnOnce() -> T) -> T {
+    MULTI_PROGRESS.suspend(f)
+}
+
+/// Reports how many of the `total` bytes were processed.
+#[derive(Clone)]
+pub enum Progress {
+    /// Nothing is reported, used by `--quiet`
+    Hidden,
+    /// A progress bar with throughput and ETA, used when stdout is a terminal
+    Bar(ProgressBar),
+    /// Periodic `[INFO]` lines, used in accessibility mode or when stdout isn't a terminal
+    Lines(Arc<ProgressLines>),
+}
+
+impl Progress {
+    /// Create the progress report that fits the current output, `title` describes the operation.
+    pub fn new(title: String, total: u64, quiet: bool) -> Self {
+        if quiet {
+            return Self::Hidden;
+        }
+
+        if is_running_in_accessible_mode() || atty::isnt(atty::Stream::Stdout) {
+            return Self::Lines(Arc::new(ProgressLines {
+                title,
+                total,
+                position: AtomicU64::new(0),
+                last_report: Mutex::new(Instant::now()),
+            }));
+        }
+
+        // Safety:
+        //     the template is constant and was checked to be valid
+        let style = ProgressStyle::with_template(
+            "{prefix} [{elapsed_precise}] [{wide_bar}] {bytes}/{total_bytes} ({bytes_per_sec}, ETA {eta})",
+        )
+        .unwrap()
+        .progress_chars("=> ");
+
+        let bar = ProgressBar::new(total)
+            .with_style(style)
+            .with_prefix(title)
+            .with_finish(ProgressFinish::AndClear);
+
+        Self::Bar(MULTI_PROGRESS.add(bar))
+    }
+
+    /// Mark `delta` more bytes as processed.
+    pub fn inc(&self, delta: u64) {
+        match self {
+            Self::Hidden => {}
+            Self::Bar(bar) => bar.inc(delta),
+            Self::Lines(lines) => lines.inc(delta),
+        }
+    }
+
+    /// Wrap `reader`, marking every byte read from it as processed.
+    pub fn wrap_read<R>(&self, reader: R) -> ProgressReader<R> {
+        ProgressReader {
+            inner: reader,
+            progress: self.clone(),
+        }
+    }
+}
+
+/// State of [`Progress::Lines`].
+pub struct ProgressLines {
+    title: String,
+    total: u64,
+    position: AtomicU64,
+    last_report: Mutex<Instant>,
+}
+
+impl ProgressLines {
+    fn inc(&self, delta: u64) {
+        let position = self.position.fetch_add(delta, Ordering::Relaxed) + delta;
+
+        // Another thread is already reporting, no need to wait for it
+        let Ok(mut last_report) = self.last_report.try_lock() else {
+            return;
+        };
+
+        if last_report.elapsed() < LINES_INTERVAL {
+            return;
+        }
+        *last_report = Instant::now();
+
+        let position = position.min(self.total);
+        let percentage = (position * 100).checked_div(self.total).unwrap_or(100);
+
+        info_accessible(format!(
+            "{}: {percentage}% ({} of {}).",
+            self.title,
+            Bytes::new(position),
+            Bytes::new(self.total)
+        ));
+    }
+}
+
+/// Reader that reports the bytes that go through it, see [`Progress::wrap_read`].
+pub struct ProgressReader<R> {
+    inner: R,
+    progress: Progress,
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes_read = self.inner.read(buf)?;
+        self.progress.inc(bytes_read as u64);
+        Ok(bytes_read)
+    }
+}
+
+impl<R: Seek> Seek for ProgressReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
@@ -5,7 +5,7 @@
 
 use std::{
     borrow::Cow,
-    io::{stdin, BufRead},
+    io::{stdin, BufRead, BufReader},
     path::Path,
 };
 
@@ -15,20 +15,40 @@ use super::{strip_cur_dir, to_utf};
 use crate::{
     accessible::is_running_in_accessible_mode,
     error::{Error, FinalError, Result},
-    utils::{self, colors, io::lock_and_flush_output_stdio},
+    utils::{self, colors, io::lock_and_flush_output_stdio, progress},
 };
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
-/// Determines if overwrite questions should be skipped or asked to the user
-pub enum QuestionPolicy {
+/// Determines if questions should be skipped or asked to the user
+pub struct QuestionPolicy {
+    /// How all questions are answered
+    pub answers: AnswerPolicy,
+    /// How overwrite questions are answered, wins over `answers`
+    pub overwrite: OverwritePolicy,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+/// Determines if questions should be skipped or asked to the user
+pub enum AnswerPolicy {
     /// Ask the user every time
     Ask,
-    /// Set by `--yes`, will say 'Y' to all overwrite questions
+    /// Set by `--yes`, will say 'Y' to all questions
     AlwaysYes,
-    /// Set by `--no`, will say 'N' to all overwrite questions
+    /// Set by `--no`, will say 'N' to all questions
     AlwaysNo,
 }
 
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+/// Determines if files that already exist are overwritten, independently of `--yes` and `--no`
+pub enum OverwritePolicy {
+    /// Follow the [`AnswerPolicy`], asking the user if it does
+    Ask,
+    /// Set by `--overwrite`, will always overwrite them
+    Always,
+    /// Set by `--no-clobber`, will never overwrite them
+    Never,
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 /// Determines which action is being questioned
 pub enum QuestionAction {
@@ -40,10 +60,16 @@ pub enum QuestionAction {
 
 /// Check if QuestionPolicy flags were set, otherwise, ask user if they want to overwrite.
 pub fn user_wants_to_overwrite(path: &Path, question_policy: QuestionPolicy) -> crate::Result<bool> {
-    match question_policy {
-        QuestionPolicy::AlwaysYes => Ok(true),
-        QuestionPolicy::AlwaysNo => Ok(false),
-        QuestionPolicy::Ask => {
+    match question_policy.overwrite {
+        OverwritePolicy::Always => return Ok(true),
+        OverwritePolicy::Never => return Ok(false),
+        OverwritePolicy::Ask => {}
+    }
+
+    match question_policy.answers {
+        AnswerPolicy::AlwaysYes => Ok(true),
+        AnswerPolicy::AlwaysNo => Ok(false),
+        AnswerPolicy::Ask => {
             let path = to_utf(strip_cur_dir(path));
             let path = Some(&*path);
             let placeholder = Some("FILE");
@@ -75,10 +101,10 @@ pub fn user_wants_to_continue(
     question_policy: QuestionPolicy,
     question_action: QuestionAction,
 ) -> crate::Result<bool> {
-    match question_policy {
-        QuestionPolicy::AlwaysYes => Ok(true),
-        QuestionPolicy::AlwaysNo => Ok(false),
-        QuestionPolicy::Ask => {
+    match question_policy.answers {
+        AnswerPolicy::AlwaysYes => Ok(true),
+        AnswerPolicy::AlwaysNo => Ok(false),
+        AnswerPolicy::Ask => {
             let action = match question_action {
                 QuestionAction::Compression => "compress",
                 QuestionAction::Decompression => "decompress",
@@ -91,6 +117,74 @@ pub fn user_wants_to_continue(
     }
 }
 
+/// Check if QuestionPolicy flags were set, otherwise, ask the user for the password of an encrypted archive.
+///
+/// There's no sensible answer to skip this question with, so `--yes` and `--no` make it fail.
+pub fn ask_for_password(path: &Path, question_policy: QuestionPolicy) -> crate::Result<String> {
+    let path = to_utf(strip_cur_dir(path));
+
+    if question_policy.answers != AnswerPolicy::Ask {
+        let error = FinalError::with_title(format!("Archive '{path}' is encrypted"))
+            .detail("A password is required to decrypt it")
+            .hint("Use `--password` or `--password-file` to provide it.");
+
+        return Err(error.into());
+    }
+
+    // Progress bars would be drawn over the question
+    progress::suspend(|| {
+        let _locks = lock_and_flush_output_stdio()?;
+
+        // The password isn't echoed when typed in a terminal, piped passwords are read as is
+        if atty::is(atty::Stream::Stdin) {
+            return Ok(rpassword::prompt_password(format!(
+                "Archive '{path}' is encrypted, enter its password: "
+            ))?);
+        }
+
+        eprintln!("Archive '{path}' is encrypted, enter its password:");
+        read_password_from_stdin()
+    })
+}
+
+/// Read a password from the first line of stdin, without the line ending.
+pub fn read_password_from_stdin() -> crate::Result<String> {
+    let mut password = String::new();
+    let bytes_read = stdin().lock().read_line(&mut password)?;
+
+    if bytes_read == 0 {
+        let error = FinalError::with_title("Unexpected EOF when reading password.")
+            .detail("Expected a password in the first line of stdin, but found EOF instead.");
+
+        return Err(error.into());
+    }
+
+    let password_len = password.trim_end_matches(['\r', '\n']).len();
+    password.truncate(password_len);
+
+    Ok(password)
+}
+
+/// Read a password from the first line of the file at `path`, without the line ending.
+pub fn read_password_from_file(path: &Path) -> crate::Result<String> {
+    let mut password = String::new();
+    let bytes_read = BufReader::new(fs::File::open(path)?).read_line(&mut password)?;
+
+    if bytes_read == 0 {
+        let error = FinalError::with_title("Empty password file.").detail(format!(
+            "Expected a password in the first line of '{}', but the file is empty.",
+            to_utf(strip_cur_dir(path))
+        ));
+
+        return Err(error.into());
+    }
+
+    let password_len = password.trim_end_matches(['\r', '\n']).len();
+    password.truncate(password_len);
+
+    Ok(password)
+}
+
 /// Confirmation dialog for end user with [Y/n] question.
 ///
 /// If the placeholder is found in the prompt text, it will be replaced to form the final message.
@@ -121,6 +215,12 @@ impl<'a> Confirmation<'a> {
             (Some(placeholder), Some(subs)) => Cow::Owned(self.prompt.replace(placeholder, subs)),
         };
 
+        // Progress bars would be drawn over the question
+        progress::suspend(|| Self::read_answer(&message))
+    }
+
+    /// Display the question `message` and read answers until a valid one is given
+    fn read_answer(message: &str) -> crate::Result<bool> {
         let _locks = lock_and_flush_output_stdio()?;
         let mut stdin_lock = stdin().lock();
 
@@ -0,0 +1,176 @@
+//! Split archives, written as numbered volumes like `archive.tar.gz.001`, `archive.tar.gz.002`...
+
+use std::{
+    ffi::OsStr,
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use fs_err as fs;
+
+/// Path of the volume number `number` (starting at 1) of the split archive `base_path`.
+///
+/// E.g.: `volume_path("archive.tar.gz", 2)` results in `"archive.tar.gz.002"`
+pub fn volume_path(base_path: &Path, number: usize) -> PathBuf {
+    let mut path = base_path.as_os_str().to_owned();
+    path.push(format!(".{number:03}"));
+    path.into()
+}
+
+/// If `path` is the first volume of a split archive, like `archive.tar.gz.001`, returns the path
+/// of the archive without the volume suffix.
+pub fn first_volume_base(path: &Path) -> Option<PathBuf> {
+    (path.extension() == Some(OsStr::new("001"))).then(|| path.with_extension(""))
+}
+
+/// Paths of the existing volumes of the split archive `base_path`, in order.
+///
+/// Stops at the first missing volume.
+pub fn volume_paths(base_path: &Path) -> Vec<PathBuf> {
+    (1..)
+        .map(|number| volume_path(base_path, number))
+        .take_while(|path| path.is_file())
+        .collect()
+}
+
+/// Writer that rolls over to the next volume of a split archive when the current one is full.
+///
+/// Volumes are only created when there's something to write to them, so there's no empty volume
+/// at the end.
+pub struct SplitWriter {
+    base_path: PathBuf,
+    volume_size: u64,
+    volume: fs::File,
+    volume_number: usize,
+    written_to_volume: u64,
+}
+
+impl SplitWriter {
+    /// `first_volume` is the already created file for `volume_path(base_path, 1)`, the next
+    /// volumes are created, or overwritten, as needed.
+    pub fn new(base_path: PathBuf, volume_size: u64, first_volume: fs::File) -> Self {
+        assert!(volume_size > 0);
+        Self {
+            base_path,
+            volume_size,
+            volume: first_volume,
+            volume_number: 1,
+            written_to_volume: 0,
+        }
+    }
+}
+
+impl Write for SplitWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        if self.written_to_volume == self.volume_size {
+            self.volume.flush()?;
+            self.volume_number += 1;
+            self.volume = fs::File::create(volume_path(&self.base_path, self.volume_number))?;
+            self.written_to_volume = 0;
+        }
+
+        let space_left = self.volume_size - self.written_to_volume;
+        let len = buf.len().min(usize::try_from(space_left).unwrap_or(usize::MAX));
+        let written = self.volume.write(&buf[..len])?;
+        self.written_to_volume += written as u64;
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.volume.flush()
+    }
+}
+
+/// Reader over the volumes of a split archive, as if they were a single file.
+///
+/// Paths that aren't the first volume of a split archive are read as a single volume.
+pub struct SplitReader {
+    /// Each volume and its size
+    volumes: Vec<(fs::File, u64)>,
+    /// Index of the volume being read
+    current: usize,
+}
+
+impl SplitReader {
+    pub fn open(path: &Path) -> crate::Result<Self> {
+        let mut paths = first_volume_base(path).map_or_else(Vec::new, |base_path| volume_paths(&base_path));
+        if paths.is_empty() {
+            paths.push(path.to_path_buf());
+        }
+
+        let volumes = paths
+            .iter()
+            .map(|path| {
+                let file = fs::File::open(path)?;
+                let size = file.metadata()?.len();
+                Ok((file, size))
+            })
+            .collect::<io::Result<_>>()?;
+
+        Ok(Self { volumes, current: 0 })
+    }
+
+    /// Sum of the sizes of all volumes.
+    pub fn size(&self) -> u64 {
+        self.volumes.iter().map(|(_, size)| size).sum()
+    }
+
+    /// Offset of the start of the volume at `index`.
+    fn volume_offset(&self, index: usize) -> u64 {
+        self.volumes[..index].iter().map(|(_, size)| size).sum()
+    }
+}
+
+impl Read for SplitReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let (volume, _) = &mut self.volumes[self.current];
+            let bytes_read = volume.read(buf)?;
+
+            if bytes_read > 0 || buf.is_empty() || self.current + 1 == self.volumes.len() {
+                return Ok(bytes_read);
+            }
+
+            self.current += 1;
+            self.volumes[self.current].0.rewind()?;
+        }
+    }
+}
+
+impl Seek for SplitReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => self.size().checked_add_signed(delta),
+            SeekFrom::Current(delta) => {
+                let position = self.volume_offset(self.current) + self.volumes[self.current].0.stream_position()?;
+                position.checked_add_signed(delta)
+            }
+        };
+        let Some(target) = target else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            ));
+        };
+
+        // Seeking past the end places the cursor in the last volume, like with a single file
+        let last_index = self.volumes.len() - 1;
+        let mut offset = 0;
+        for (index, (volume, size)) in self.volumes.iter_mut().enumerate() {
+            if target < offset + *size || index == last_index {
+                volume.seek(SeekFrom::Start(target - offset))?;
+                self.current = index;
+                break;
+            }
+            offset += *size;
+        }
+
+        Ok(target)
+    }
+}
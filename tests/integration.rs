@@ -1,8 +1,13 @@
 #[macro_use]
 mod utils;
 
-use std::{iter::once, path::PathBuf};
+use std::{
+    io::{Read, Write},
+    iter::once,
+    path::PathBuf,
+};
 
+use filetime_creation::{set_file_mtime, FileTime};
 use fs_err as fs;
 use parse_display::Display;
 use proptest::sample::size_range;
@@ -12,16 +17,18 @@ use test_strategy::{proptest, Arbitrary};
 
 use crate::utils::{assert_same_directory, write_random_content};
 
-/// tar and zip extensions
+/// Archive extensions
 #[derive(Arbitrary, Debug, Display)]
 #[display(style = "lowercase")]
 enum DirectoryExtension {
     #[display("7z")]
     SevenZ,
+    Cpio,
     Tar,
     Tbz,
     Tbz2,
     Tgz,
+    Tlz,
     Tlz4,
     Tlzma,
     Tsz,
@@ -37,6 +44,7 @@ enum FileExtension {
     Bz,
     Bz2,
     Gz,
+    Lz,
     Lz4,
     Lzma,
     Sz,
@@ -77,7 +85,7 @@ fn create_random_files(dir: impl Into<PathBuf>, depth: u8, rng: &mut SmallRng) {
 
     // create more random files in 0 to 2 new directories
     for _ in 0..rng.gen_range(0..=2u32) {
-        create_random_files(&tempfile::tempdir_in(dir).unwrap().into_path(), depth - 1, rng);
+        create_random_files(tempfile::tempdir_in(dir).unwrap().into_path(), depth - 1, rng);
     }
 }
 
@@ -105,10 +113,11 @@ fn single_empty_file(ext: Extension, #[any(size_range(0..8).lift())] exts: Vec<F
 fn single_file(
     ext: Extension,
     #[any(size_range(0..8).lift())] exts: Vec<FileExtension>,
-    #[cfg_attr(not(target_arch = "arm"), strategy(proptest::option::of(0i16..12)))]
+    // Levels from 1 to 9 are valid for every format that has a compression level
+    #[cfg_attr(not(target_arch = "arm"), strategy(proptest::option::of(1i16..10)))]
     // Decrease the value of --level flag for `arm` systems, because our GitHub
     // Actions CI runs QEMU which makes the memory consumption higher.
-    #[cfg_attr(target_arch = "arm", strategy(proptest::option::of(0i16..8)))]
+    #[cfg_attr(target_arch = "arm", strategy(proptest::option::of(1i16..8)))]
     level: Option<i16>,
 ) {
     let dir = tempdir().unwrap();
@@ -173,3 +182,3985 @@ fn unpack_rar() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+/// RAR archives are listed, also when their format is only known from their signature, but
+/// can't be created
+#[cfg(feature = "unrar")]
+#[test]
+fn list_rar() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let datadir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data");
+
+    let without_extension = &dir.join("testfile");
+    fs::copy(datadir.join("testfile.rar5.rar"), without_extension).unwrap();
+
+    for archive in [
+        &datadir.join("testfile.rar5.rar"),
+        &datadir.join("testfile.rar3.rar.gz"),
+        without_extension,
+    ] {
+        let output = ouch!("-A", "l", archive);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.contains("testfile.txt  12 B\n"), "unexpected listing: {stdout}");
+    }
+
+    let input = &dir.join("input.txt");
+    fs::write(input, "Testing 123\n").unwrap();
+    let output = crate::utils::cargo_bin()
+        .args(["-A", "c"])
+        .arg(input)
+        .arg(dir.join("output.rar"))
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Creating RAR archives is not allowed"), "{stderr}");
+    assert!(!dir.join("output.rar").exists());
+}
+
+/// List the contents of a 7z archive, both standalone and chained with a stream format
+#[test]
+fn list_sevenz_archive() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    fs::create_dir_all(before.join("subdir")).unwrap();
+    fs::write(before.join("subdir/file.txt"), "Testing 123\n").unwrap();
+
+    for extension in ["7z", "7z.gz"] {
+        let archive = &dir.join(format!("archive.{extension}"));
+        ouch!("-A", "c", before, archive);
+
+        let output = ouch!("-A", "l", archive);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(
+            stdout.contains("before/subdir/file.txt"),
+            "unexpected listing: {stdout}"
+        );
+    }
+}
+
+/// Compress and decompress a directory through `.tar.lz4`, lz4 is a stream format
+/// so no in-memory warning should be displayed
+#[test]
+fn tar_lz4_roundtrip() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    let before_dir = &before.join("dir");
+    fs::create_dir_all(before_dir).unwrap();
+    let archive = &dir.join("archive.tar.lz4");
+    let after = &dir.join("after");
+    create_random_files(before_dir, 3, &mut SmallRng::from_entropy());
+
+    let output = ouch!("-A", "c", before_dir, archive);
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("in-memory"));
+
+    ouch!("-A", "d", archive, "-d", after);
+    assert_same_directory(before, after, true);
+}
+
+/// Several inputs can go into `.tar.lz4` and its `.tlz4` shorthand, which are listed and
+/// decompressed back, but not into a bare `.lz4`, which holds a single file
+#[test]
+fn tar_lz4_multiple_inputs() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    fs::create_dir_all(before.join("sub")).unwrap();
+    fs::write(before.join("sub/a.txt"), "a").unwrap();
+    fs::write(before.join("b.txt"), "b").unwrap();
+    let inputs = [before.join("sub"), before.join("b.txt")];
+
+    for extension in ["tar.lz4", "tlz4"] {
+        let archive = &dir.join(format!("archive.{extension}"));
+        ouch!("-A", "c", &inputs[0], &inputs[1], archive);
+
+        let output = ouch!("-A", "l", archive);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        for entry in ["sub/a.txt", "b.txt"] {
+            assert!(stdout.contains(entry), "{extension}: unexpected listing: {stdout}");
+        }
+
+        // With more than one entry at the root, they're unpacked into a directory named after it
+        let after = &dir.join(format!("after_{extension}"));
+        ouch!("-A", "d", archive, "-d", after);
+        assert_same_directory(before, after.join("archive"), false);
+    }
+
+    let output = crate::utils::cargo_bin()
+        .args(["-A", "c"])
+        .args(&inputs)
+        .arg(dir.join("archive.lz4"))
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("tar.lz4"), "{stderr}");
+}
+
+/// Decompress a snappy file without extension, relying on its magic bytes to detect the format
+#[test]
+fn decompress_sz_without_extension() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    fs::create_dir(before).unwrap();
+    let before_file = &before.join("file");
+    let archive = &dir.join("file.sz");
+    let archive_without_extension = &dir.join("file");
+    let after = &dir.join("after");
+    write_random_content(
+        &mut fs::File::create(before_file).unwrap(),
+        &mut SmallRng::from_entropy(),
+    );
+
+    ouch!("-A", "c", before_file, archive);
+    fs::rename(archive, archive_without_extension).unwrap();
+    ouch!("-A", "d", archive_without_extension, "-d", after);
+    assert_same_directory(before, after, false);
+}
+
+/// Extracting a cpio archive restores the permissions stored in its headers
+#[cfg(unix)]
+#[test]
+fn cpio_preserves_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    fs::create_dir(before).unwrap();
+    let script = &before.join("script.sh");
+    fs::write(script, "#!/bin/sh\n").unwrap();
+    fs::set_permissions(script, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let archive = &dir.join("archive.cpio.gz");
+    let after = &dir.join("after");
+
+    ouch!("-A", "c", before, archive);
+    ouch!("-A", "d", archive, "-d", after);
+
+    let mode = fs::metadata(after.join("before/script.sh"))
+        .unwrap()
+        .permissions()
+        .mode();
+    assert_eq!(mode & 0o777, 0o755);
+}
+
+/// Extracting a tar archive restores the permissions stored in its headers,
+/// unless `--no-preserve-permissions` is passed
+#[cfg(unix)]
+#[test]
+fn tar_preserves_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    fs::create_dir(before).unwrap();
+    let script = &before.join("script.sh");
+    fs::write(script, "#!/bin/sh\n").unwrap();
+    fs::set_permissions(script, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let archive = &dir.join("archive.tar.gz");
+    let preserved = &dir.join("preserved");
+    let not_preserved = &dir.join("not_preserved");
+
+    ouch!("-A", "c", before, archive);
+    ouch!("-A", "d", archive, "-d", preserved);
+    ouch!("-A", "d", archive, "-d", not_preserved, "--no-preserve-permissions");
+
+    let mode_of = |path: &std::path::Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+    assert_eq!(mode_of(&preserved.join("before/script.sh")), 0o755);
+    assert_eq!(mode_of(&not_preserved.join("before/script.sh")), 0o644);
+}
+
+/// Symlinks are stored as links in tar and zip archives, or followed with `--dereference`
+#[cfg(unix)]
+#[test]
+fn compress_preserves_symlinks() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    fs::create_dir(before).unwrap();
+    fs::write(before.join("file.txt"), "content\n").unwrap();
+    std::os::unix::fs::symlink("file.txt", before.join("link")).unwrap();
+
+    for format in ["tar", "zip"] {
+        let archive = &dir.join(format!("archive.{format}"));
+        let after = &dir.join(format!("after_{format}"));
+        ouch!("-A", "c", before, archive);
+        ouch!("-A", "d", archive, "-d", after);
+
+        let link = after.join("before/link");
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_link(&link).unwrap(), std::path::Path::new("file.txt"));
+
+        let archive = &dir.join(format!("dereferenced.{format}"));
+        let after = &dir.join(format!("after_dereferenced_{format}"));
+        ouch!("-A", "c", before, archive, "--dereference");
+        ouch!("-A", "d", archive, "-d", after);
+
+        let link = after.join("before/link");
+        assert!(fs::symlink_metadata(&link).unwrap().is_file());
+        assert_eq!(fs::read_to_string(&link).unwrap(), "content\n");
+    }
+}
+
+/// Symlinks to directories are stored as links by default, walked through with `--dereference`,
+/// and left out with `--skip-symlinks`
+#[cfg(unix)]
+#[test]
+fn compress_symlink_to_directory() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    fs::create_dir_all(before.join("real")).unwrap();
+    fs::write(before.join("real/file.txt"), "content\n").unwrap();
+    std::os::unix::fs::symlink("real", before.join("link")).unwrap();
+
+    let stored = &dir.join("stored.tar");
+    ouch!("-A", "c", before, stored, "--no-dereference");
+    ouch!("-A", "d", stored, "-d", dir.join("stored"));
+    let link = dir.join("stored/before/link");
+    assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+    assert_eq!(fs::read_link(&link).unwrap(), std::path::Path::new("real"));
+
+    let followed = &dir.join("followed.tar");
+    ouch!("-A", "c", before, followed, "--dereference");
+    ouch!("-A", "d", followed, "-d", dir.join("followed"));
+    let link = dir.join("followed/before/link");
+    assert!(fs::symlink_metadata(&link).unwrap().is_dir());
+    assert_eq!(fs::read_to_string(link.join("file.txt")).unwrap(), "content\n");
+
+    let skipped = &dir.join("skipped.tar");
+    ouch!("-A", "c", before, skipped, "--skip-symlinks");
+    ouch!("-A", "d", skipped, "-d", dir.join("skipped"));
+    assert!(fs::symlink_metadata(dir.join("skipped/before/link")).is_err());
+    assert!(dir.join("skipped/before/real/file.txt").is_file());
+}
+
+/// An out of range `--level` is rejected before the output file is created
+#[test]
+fn compress_with_invalid_level() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let input = &dir.join("input");
+    fs::write(input, "Testing 123\n").unwrap();
+
+    for (extension, level) in [("gz", "10"), ("tar.bz2", "0"), ("zst", "23")] {
+        let archive = &dir.join(format!("input.{extension}"));
+
+        crate::utils::cargo_bin()
+            .args(["-A", "c", "--level", level])
+            .arg(input)
+            .arg(archive)
+            .assert()
+            .failure();
+        assert!(!archive.exists());
+    }
+
+    ouch!("-A", "c", "--slow", input, dir.join("input.tar.bz2.zst"));
+}
+
+/// Files compressed with `--zstd-long` and `--threads` can be decompressed with the default decoder
+#[test]
+fn zstd_long_roundtrip() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    fs::create_dir(before).unwrap();
+    let before_file = &before.join("file");
+    let archive = &dir.join("file.zst");
+    let after = &dir.join("after");
+    write_random_content(
+        &mut fs::File::create(before_file).unwrap(),
+        &mut SmallRng::from_entropy(),
+    );
+
+    ouch!("-A", "c", "--zstd-long", "--threads", "2", before_file, archive);
+    ouch!("-A", "d", archive, "-d", after);
+    assert_same_directory(before, after, false);
+}
+
+/// Compressing into a directory compresses each input separately, in parallel
+#[test]
+fn compress_multiple_files_into_dir() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let out = &dir.join("out");
+    fs::create_dir(out).unwrap();
+    let names = ["a.log", "b.log", "c.log"];
+    for name in names {
+        fs::write(dir.join(name), format!("contents of {name}\n")).unwrap();
+    }
+
+    // `--threads` sizes the pool here, it isn't ignored without zstd
+    let output = crate::utils::cargo_bin()
+        .args(["-A", "--threads", "2", "c"])
+        .args(names.map(|name| dir.join(name)))
+        .arg(out)
+        .args(["--format", "gz"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("--threads"));
+
+    for name in names {
+        let archive = out.join(format!("{name}.gz"));
+        let after = &dir.join(format!("after-{name}"));
+        ouch!("-A", "d", archive, "-d", after);
+        assert_eq!(
+            fs::read_to_string(after.join(name)).unwrap(),
+            format!("contents of {name}\n")
+        );
+    }
+}
+
+/// `--each` compresses every input into its own archive, named after it with the extensions of
+/// `--format`, in the current directory or `--output-dir`
+#[test]
+fn compress_each() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let sites = &dir.join("sites");
+    for site in ["site1", "site2"] {
+        fs::create_dir_all(sites.join(site)).unwrap();
+        fs::write(sites.join(site).join("index.html"), site).unwrap();
+    }
+
+    // The last path is an input too, not the output
+    crate::utils::cargo_bin()
+        .current_dir(dir)
+        .args(["-A", "c", "--each", "sites/site1", "sites/site2", "--format", "tar.gz"])
+        .assert()
+        .success();
+    for site in ["site1", "site2"] {
+        let after = &dir.join(format!("after-{site}"));
+        ouch!("-A", "d", dir.join(format!("{site}.tar.gz")), "-d", after);
+        assert_eq!(fs::read_to_string(after.join(site).join("index.html")).unwrap(), site);
+    }
+
+    let out = &dir.join("out");
+    ouch!(
+        "-A",
+        "c",
+        "--each",
+        sites.join("site1"),
+        "--format",
+        "zip",
+        "--output-dir",
+        out
+    );
+    assert!(out.join("site1.zip").exists());
+
+    // The names of the archives can't be known without it
+    crate::utils::cargo_bin()
+        .current_dir(dir)
+        .args(["-A", "c", "--each", "sites/site1", "sites/site2"])
+        .assert()
+        .code(2);
+}
+
+/// Tar archives are built concurrently without changing the current directory, inputs in
+/// different directories keep their own contents and names
+#[test]
+fn compress_tar_archives_concurrently() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let out = &dir.join("out");
+    fs::create_dir(out).unwrap();
+    let inputs: Vec<_> = ["first", "second", "third", "fourth"]
+        .iter()
+        .map(|name| {
+            let input = dir.join(format!("parent-{name}")).join(name);
+            fs::create_dir_all(input.join("nested")).unwrap();
+            fs::write(input.join("nested/file.txt"), format!("contents of {name}\n")).unwrap();
+            input
+        })
+        .collect();
+
+    crate::utils::cargo_bin()
+        .args(["-A", "--threads", "4", "c"])
+        .args(&inputs)
+        .arg(out)
+        .args(["--format", "tar"])
+        .assert()
+        .success();
+
+    for input in &inputs {
+        let name = input.file_name().unwrap().to_str().unwrap();
+        let after = &dir.join(format!("after-{name}"));
+        ouch!("-A", "d", out.join(format!("{name}.tar")), "-d", after);
+        assert_same_directory(input, after.join(name), false);
+    }
+}
+
+/// Archives can be nested in a tar archive, which then holds them as a single entry
+#[test]
+fn nested_archive_in_tar() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    fs::create_dir(before).unwrap();
+    fs::write(before.join("file.txt"), "Testing 123\n").unwrap();
+    let archive = &dir.join("foo.zip.tar");
+
+    ouch!("-A", "c", before, archive);
+    let output = ouch!("-A", "l", archive);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let entries: Vec<_> = stdout.lines().filter(|line| line.starts_with("foo.zip")).collect();
+    assert_eq!(entries.len(), 1, "unexpected listing: {stdout}");
+    assert!(stdout.contains("Total: 1 files"), "unexpected listing: {stdout}");
+
+    // Decompressing the tar gives back the zip, which holds the original files
+    let after = &dir.join("after");
+    ouch!("-A", "d", archive, "-d", after);
+    ouch!("-A", "d", after.join("foo.zip"), "-d", after);
+    assert_same_directory(before, after.join("before"), false);
+}
+
+/// `--on-conflict` decides, file by file, what happens with the extracted files that already exist
+#[test]
+fn decompress_on_conflict() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    fs::create_dir(before).unwrap();
+    fs::write(before.join("a.txt"), "original a\n").unwrap();
+    fs::write(before.join("b.txt"), "original b\n").unwrap();
+    let archive = &dir.join("archive.tar.gz");
+    ouch!("-A", "c", before, archive);
+
+    let after = &dir.join("after");
+    let extracted = &after.join("before");
+    fs::create_dir_all(extracted).unwrap();
+    fs::write(extracted.join("a.txt"), "modified a\n").unwrap();
+
+    // Only the missing file is extracted
+    let output = ouch!("-A", "d", archive, "-d", after, "--on-conflict", "skip");
+    let messages = String::from_utf8(output.stderr).unwrap() + &String::from_utf8(output.stdout).unwrap();
+    assert!(messages.contains("Skipped 1 files that already existed."), "{messages}");
+    assert_eq!(fs::read_to_string(extracted.join("a.txt")).unwrap(), "modified a\n");
+    assert_eq!(fs::read_to_string(extracted.join("b.txt")).unwrap(), "original b\n");
+
+    // New files are placed next to the existing ones
+    ouch!("-A", "d", archive, "-d", after, "--on-conflict", "rename");
+    assert_eq!(fs::read_to_string(extracted.join("a.txt")).unwrap(), "modified a\n");
+    assert_eq!(fs::read_to_string(extracted.join("a (1).txt")).unwrap(), "original a\n");
+    assert_eq!(fs::read_to_string(extracted.join("b (1).txt")).unwrap(), "original b\n");
+
+    // Existing files are replaced, the others are kept
+    ouch!("-A", "d", archive, "-d", after, "--on-conflict", "overwrite");
+    assert_eq!(fs::read_to_string(extracted.join("a.txt")).unwrap(), "original a\n");
+    assert!(extracted.join("a (1).txt").exists());
+}
+
+/// `--skip-existing` resumes an extraction, keeping the files already extracted with the same size
+/// and modification time, and replacing the ones that differ
+#[test]
+fn decompress_skip_existing() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    fs::create_dir(before).unwrap();
+    for name in ["a.txt", "b.txt", "c.txt"] {
+        fs::write(before.join(name), format!("original {name}\n")).unwrap();
+    }
+    for format in ["tar.gz", "zip", "7z"] {
+        let archive = &dir.join(format!("archive.{format}"));
+        ouch!("-A", "c", before, archive);
+
+        let after = &dir.join(format!("after-{format}"));
+        let extracted = &after.join("before");
+        ouch!("-A", "d", archive, "-d", after);
+
+        // Like an extraction that was interrupted before writing a.txt
+        fs::remove_file(extracted.join("a.txt")).unwrap();
+        // The same size and modification time, the contents aren't compared
+        let mtime = FileTime::from_last_modification_time(&fs::metadata(extracted.join("b.txt")).unwrap());
+        fs::write(extracted.join("b.txt"), "modified b.txt\n").unwrap();
+        set_file_mtime(extracted.join("b.txt"), mtime).unwrap();
+        fs::write(extracted.join("c.txt"), "cut").unwrap();
+
+        let output = ouch!("-A", "d", archive, "-d", after, "--skip-existing");
+        let messages = String::from_utf8(output.stderr).unwrap() + &String::from_utf8(output.stdout).unwrap();
+        assert!(
+            messages.contains("Skipped 1 files that already existed."),
+            "{format}: {messages}"
+        );
+        assert_eq!(fs::read_to_string(extracted.join("a.txt")).unwrap(), "original a.txt\n");
+        assert_eq!(fs::read_to_string(extracted.join("b.txt")).unwrap(), "modified b.txt\n");
+        assert_eq!(fs::read_to_string(extracted.join("c.txt")).unwrap(), "original c.txt\n");
+
+        // Nothing left to extract, the files still go in the same place
+        let output = ouch!("-A", "d", archive, "-d", after, "--skip-existing");
+        let messages = String::from_utf8(output.stderr).unwrap() + &String::from_utf8(output.stdout).unwrap();
+        assert!(
+            messages.contains("Skipped 3 files that already existed."),
+            "{format}: {messages}"
+        );
+        assert_eq!(fs::read_dir(after).unwrap().count(), 1);
+        assert_eq!(fs::read_to_string(extracted.join("b.txt")).unwrap(), "modified b.txt\n");
+    }
+}
+
+/// Leading directories are removed from the extracted paths, files that would be left without a
+/// name are skipped
+#[test]
+fn decompress_strip_components() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let wrapper = &dir.join("project-1.0");
+    fs::create_dir_all(wrapper.join("src")).unwrap();
+    fs::write(wrapper.join("README"), "readme\n").unwrap();
+    fs::write(wrapper.join("src/main.rs"), "fn main() {}\n").unwrap();
+
+    for format in ["tar.gz", "zip", "7z"] {
+        let archive = &dir.join(format!("archive.{format}"));
+        ouch!("-A", "c", wrapper, archive);
+
+        let after = &dir.join(format!("after-{format}"));
+        ouch!("-A", "d", archive, "-d", after, "--strip-components", "1");
+        assert!(!after.join("project-1.0").exists());
+        assert_same_directory(wrapper, after, false);
+
+        // Only files nested deep enough are kept
+        let deeper = &dir.join(format!("deeper-{format}"));
+        ouch!("-A", "d", archive, "-d", deeper, "--strip-components", "2");
+        assert_eq!(fs::read_to_string(deeper.join("main.rs")).unwrap(), "fn main() {}\n");
+        assert!(!deeper.join("README").exists());
+    }
+}
+
+/// Archives with entries that would be written outside of the output directory are rejected
+#[test]
+fn decompress_rejects_path_traversal() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let archive = &dir.join("archive.tar");
+
+    // `tar::Builder` refuses to write ".." paths, so the name is set in the raw header
+    let mut builder = tar::Builder::new(fs::File::create(archive).unwrap());
+    for name in ["safe.txt", "../escape"] {
+        let mut header = tar::Header::new_gnu();
+        header.as_gnu_mut().unwrap().name[..name.len()].copy_from_slice(name.as_bytes());
+        header.set_size(6);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append(&header, &b"owned\n"[..]).unwrap();
+    }
+    builder.finish().unwrap();
+    drop(builder);
+
+    let out = &dir.join("out");
+    let output = crate::utils::cargo_bin()
+        .args(["-A", "--yes", "d"])
+        .arg(archive)
+        .arg("-d")
+        .arg(out)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Unsafe path in archive: '../escape'"), "{stderr}");
+
+    assert!(!dir.join("escape").exists());
+    // The temporary directory is removed, nothing is left behind
+    assert_eq!(fs::read_dir(out).unwrap().count(), 0);
+}
+
+/// Entries can't be written through symlinks extracted from the same archive
+#[cfg(unix)]
+#[test]
+fn decompress_rejects_writing_through_symlinks() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let victim = &dir.join("victim");
+    fs::create_dir(victim).unwrap();
+
+    let archive = &dir.join("archive.zip");
+    let mut writer = zip::ZipWriter::new(fs::File::create(archive).unwrap());
+    let options = zip::write::SimpleFileOptions::default();
+    writer.add_symlink("link", victim.to_str().unwrap(), options).unwrap();
+    writer.start_file("link/pwned.txt", options).unwrap();
+    writer.write_all(b"owned\n").unwrap();
+    writer.finish().unwrap();
+
+    let out = &dir.join("out");
+    let output = crate::utils::cargo_bin()
+        .args(["-A", "--yes", "d"])
+        .arg(archive)
+        .arg("-d")
+        .arg(out)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Unsafe path in archive: 'link/pwned.txt'"), "{stderr}");
+
+    assert!(!victim.join("pwned.txt").exists());
+    assert_eq!(fs::read_dir(out).unwrap().count(), 0);
+
+    // Even if they point inside of the output directory, the target could be replaced later
+    let archive = &dir.join("archive.tar");
+    let mut builder = tar::Builder::new(fs::File::create(archive).unwrap());
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Symlink);
+    header.set_size(0);
+    builder.append_link(&mut header, "link", "dir").unwrap();
+    let mut header = tar::Header::new_gnu();
+    header.set_size(6);
+    header.set_mode(0o644);
+    builder
+        .append_data(&mut header, "link/pwned.txt", &b"owned\n"[..])
+        .unwrap();
+    builder.finish().unwrap();
+    drop(builder);
+
+    let output = crate::utils::cargo_bin()
+        .args(["-A", "--yes", "d"])
+        .arg(archive)
+        .arg("-d")
+        .arg(out)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Unsafe path in archive: 'link/pwned.txt'"), "{stderr}");
+    assert_eq!(fs::read_dir(out).unwrap().count(), 0);
+}
+
+/// Inputs are only deleted with `--remove`, after they were compressed or decompressed successfully
+#[test]
+fn remove_inputs() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    fs::create_dir(before).unwrap();
+    fs::write(before.join("file.txt"), "Testing 123\n").unwrap();
+    let backup = &dir.join("backup");
+    fs::create_dir(backup).unwrap();
+    fs::copy(before.join("file.txt"), backup.join("file.txt")).unwrap();
+
+    // Removing the input would also remove the output inside of it
+    crate::utils::cargo_bin()
+        .args(["-A", "c", "--remove"])
+        .arg(before)
+        .arg(before.join("archive.tar.gz"))
+        .assert()
+        .failure();
+    // Hidden files would be deleted without being archived
+    crate::utils::cargo_bin()
+        .args(["-A", "c", "--remove", "--hidden"])
+        .arg(before)
+        .arg(dir.join("archive.tar.gz"))
+        .assert()
+        .failure();
+    assert!(before.join("file.txt").exists());
+
+    let archive = &dir.join("archive.tar.gz");
+    ouch!("-A", "c", before, archive, "--remove");
+    assert!(!before.exists());
+
+    let after = &dir.join("after");
+    ouch!("-A", "d", archive, "-d", after, "--remove");
+    assert!(!archive.exists());
+    assert_same_directory(backup, after.join("before"), false);
+
+    // Kept when some files weren't extracted
+    ouch!("-A", "c", after.join("before"), archive);
+    ouch!("-A", "d", archive, "-d", after, "--remove", "--on-conflict", "skip");
+    assert!(archive.exists());
+}
+
+/// Paths listed with `--files-from` are compressed along with the positional inputs
+#[test]
+fn compress_files_from() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    fs::create_dir(before).unwrap();
+    for name in ["a.txt", "b.txt", "c\nd.txt"] {
+        fs::write(before.join(name), name).unwrap();
+    }
+
+    let list = &dir.join("list");
+    fs::write(
+        list,
+        format!(
+            "{}\n\n{}\n",
+            before.join("a.txt").display(),
+            before.join("b.txt").display()
+        ),
+    )
+    .unwrap();
+    let archive = &dir.join("archive.tar");
+    ouch!("-A", "c", "--files-from", list, archive);
+    let after = &dir.join("after");
+    ouch!("-A", "d", archive, "-d", after);
+    assert_eq!(fs::read_to_string(after.join("archive/a.txt")).unwrap(), "a.txt");
+    assert_eq!(fs::read_to_string(after.join("archive/b.txt")).unwrap(), "b.txt");
+
+    // NUL separated paths, read from stdin, may contain newlines
+    let archive = &dir.join("archive.zip");
+    crate::utils::cargo_bin()
+        .args(["-A", "c", "--files-from", "-", "--null"])
+        .arg(before.join("a.txt"))
+        .arg(archive)
+        .write_stdin(format!("{}\0", before.join("c\nd.txt").display()))
+        .assert()
+        .success();
+    let after = &dir.join("after-null");
+    ouch!("-A", "d", archive, "-d", after);
+    assert!(after.join("archive/a.txt").exists());
+    assert_eq!(fs::read_to_string(after.join("archive/c\nd.txt")).unwrap(), "c\nd.txt");
+
+    // Every missing path is reported
+    fs::write(list, "missing-1\nmissing-2\n").unwrap();
+    let output = crate::utils::cargo_bin()
+        .args(["-A", "c", "--files-from"])
+        .arg(list)
+        .arg(dir.join("missing.tar"))
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("'missing-1'") && stderr.contains("'missing-2'"),
+        "{stderr}"
+    );
+
+    // With `--files-from-stdin0`, the paths that can't be read are reported after compressing the others
+    let archive = &dir.join("partial.tar");
+    let output = crate::utils::cargo_bin()
+        .args(["-A", "c", "--files-from-stdin0"])
+        .arg(archive)
+        .write_stdin(format!(
+            "{}\0{}\0{}\0",
+            before.join("a.txt").display(),
+            dir.join("vanished.txt").display(),
+            before.join("c\nd.txt").display()
+        ))
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(3));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("vanished.txt"), "{stderr}");
+    let mut names: Vec<_> = tar::Archive::new(fs::File::open(archive).unwrap())
+        .entries()
+        .unwrap()
+        .map(|entry| entry.unwrap().path().unwrap().to_str().unwrap().to_string())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["a.txt", "c\nd.txt"]);
+}
+
+/// Archives are decompressed in parallel, a corrupted one doesn't stop the others
+#[test]
+fn decompress_multiple_with_errors() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let mut archives = vec![];
+    for i in 0..4 {
+        let input = dir.join(format!("file-{i}.txt"));
+        fs::write(&input, format!("content {i}\n")).unwrap();
+        let archive = dir.join(format!("file-{i}.txt.gz"));
+        ouch!("-A", "c", &input, &archive);
+        archives.push(archive);
+    }
+    fs::write(dir.join("bad-1.txt.gz"), "not gzip").unwrap();
+    fs::write(dir.join("bad-2.txt.gz"), "not gzip either").unwrap();
+
+    let out = &dir.join("out");
+    let output = crate::utils::cargo_bin()
+        .args(["-A", "-j", "2", "d"])
+        .args(&archives)
+        .arg(dir.join("bad-1.txt.gz"))
+        .arg(dir.join("bad-2.txt.gz"))
+        .arg("-d")
+        .arg(out)
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Failed to decompress 2 of 6 archives"), "{stderr}");
+    assert!(
+        stderr.contains("bad-1.txt.gz") && stderr.contains("bad-2.txt.gz"),
+        "{stderr}"
+    );
+    for i in 0..4 {
+        let contents = fs::read_to_string(out.join(format!("file-{i}.txt"))).unwrap();
+        assert_eq!(contents, format!("content {i}\n"));
+    }
+}
+
+/// Files compressed with a zstd dictionary can only be decompressed with the same dictionary
+#[test]
+fn zstd_dictionary_roundtrip() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let samples = &dir.join("samples");
+    fs::create_dir(samples).unwrap();
+    for i in 0..300 {
+        let json = format!(r#"{{"id": {i}, "name": "user{i}", "email": "user{i}@example.com", "active": true}}"#);
+        fs::write(samples.join(format!("{i}.json")), json).unwrap();
+    }
+
+    let dict = &dir.join("dict");
+    ouch!("-A", "train-dict", samples, "-o", dict);
+    assert!(fs::metadata(dict).unwrap().len() > 0);
+
+    let archive = &dir.join("archive.tar.zst");
+    ouch!("-A", "c", samples, archive, "--zstd-dict", dict);
+
+    crate::utils::cargo_bin()
+        .args(["-A", "d"])
+        .arg(archive)
+        .arg("-d")
+        .arg(dir.join("no-dict"))
+        .assert()
+        .failure();
+
+    let after = &dir.join("after");
+    ouch!("-A", "d", archive, "-d", after, "--zstd-dict", dict);
+    assert_same_directory(samples, after.join("samples"), false);
+
+    // Listing, testing and converting read the archive with the same dictionary
+    crate::utils::cargo_bin()
+        .args(["-A", "l"])
+        .arg(archive)
+        .assert()
+        .failure();
+    let output = crate::utils::cargo_bin()
+        .args(["-A", "l", "--zstd-dict"])
+        .arg(dict)
+        .arg(archive)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout).unwrap().contains("samples/299.json"));
+
+    crate::utils::cargo_bin()
+        .args(["-A", "t"])
+        .arg(archive)
+        .assert()
+        .failure();
+    ouch!("-A", "t", archive, "--zstd-dict", dict);
+
+    let converted = &dir.join("converted.tar.gz");
+    ouch!("-A", "convert", archive, converted, "--zstd-dict", dict);
+    let after = &dir.join("after-convert");
+    ouch!("-A", "d", converted, "-d", after);
+    assert_same_directory(samples, after.join("samples"), false);
+}
+
+/// Files outside of --min-size and --max-size are left out of the archive
+#[test]
+fn compress_max_size() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    fs::create_dir_all(before.join("nested")).unwrap();
+    fs::write(before.join("small.txt"), "a".repeat(100)).unwrap();
+    fs::write(before.join("nested/small.txt"), "b".repeat(100)).unwrap();
+    fs::write(before.join("large.txt"), "c".repeat(4096)).unwrap();
+    fs::write(before.join("nested/large.txt"), "d".repeat(4096)).unwrap();
+
+    let archive = &dir.join("small.tar.gz");
+    ouch!("-A", "c", before, archive, "--max-size", "1KiB");
+    let after = &dir.join("small");
+    ouch!("-A", "d", archive, "-d", after);
+    let after = &after.join("before");
+    assert!(after.join("small.txt").exists());
+    assert!(after.join("nested/small.txt").exists());
+    assert!(!after.join("large.txt").exists());
+    assert!(!after.join("nested/large.txt").exists());
+
+    let archive = &dir.join("large.zip");
+    ouch!("-A", "c", before, archive, "--min-size", "1KiB");
+    let after = &dir.join("large");
+    ouch!("-A", "d", archive, "-d", after);
+    let after = &after.join("before");
+    assert!(!after.join("small.txt").exists());
+    assert!(after.join("large.txt").exists());
+    assert!(after.join("nested/large.txt").exists());
+
+    // Nothing left to compress
+    crate::utils::cargo_bin()
+        .args(["-A", "c", "--max-size", "1KiB"])
+        .arg(before.join("large.txt"))
+        .arg(dir.join("none.tar.gz"))
+        .assert()
+        .failure();
+}
+
+/// Only files modified after --newer-than are archived
+#[test]
+fn compress_newer_than() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    fs::create_dir(before).unwrap();
+    fs::write(before.join("old.txt"), "old").unwrap();
+    fs::write(before.join("new.txt"), "new").unwrap();
+    // 2001-09-09
+    set_file_mtime(before.join("old.txt"), FileTime::from_unix_time(1_000_000_000, 0)).unwrap();
+
+    let archive = &dir.join("archive.tar.zst");
+    ouch!("-A", "c", before, archive, "--newer-than", "2024-01-01");
+    let after = &dir.join("after");
+    ouch!("-A", "d", archive, "-d", after);
+    assert!(after.join("before/new.txt").exists());
+    assert!(!after.join("before/old.txt").exists());
+
+    // Everything is older than that, no empty archive is written
+    let output = crate::utils::cargo_bin()
+        .args(["-A", "c", "--newer-than", "2999-01-01T00:00:00Z"])
+        .arg(before)
+        .arg(dir.join("empty.tar.zst"))
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No files to compress"));
+    assert!(!dir.join("empty.tar.zst").exists());
+}
+
+/// `ouch completions <shell>` prints the completion script to stdout
+#[test]
+fn print_completions() {
+    for shell in ["bash", "zsh", "fish", "powershell", "elvish"] {
+        let output = crate::utils::cargo_bin().args(["completions", shell]).output().unwrap();
+        assert!(output.status.success());
+        let script = String::from_utf8(output.stdout).unwrap();
+        assert!(script.contains("ouch"));
+        assert!(script.contains("decompress"));
+    }
+
+    crate::utils::cargo_bin()
+        .args(["completions", "tcsh"])
+        .assert()
+        .failure();
+}
+
+/// `ouch man` prints the man page to stdout
+#[test]
+fn print_man_page() {
+    let output = crate::utils::cargo_bin().arg("man").output().unwrap();
+    assert!(output.status.success());
+    let man_page = String::from_utf8(output.stdout).unwrap();
+    assert!(man_page.contains(".TH ouch"));
+    assert!(man_page.contains("decompress"));
+}
+
+/// Flags missing from the command line are taken from the config file, unknown keys are ignored
+#[test]
+fn config_file_defaults() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let file = &dir.join("file.txt");
+    fs::write(file, "Testing 123\n").unwrap();
+    let config = &dir.join("config.toml");
+    fs::write(config, "format = \"zip\"\nunknown_key = 1\n").unwrap();
+
+    let output = crate::utils::cargo_bin()
+        .env("OUCH_CONFIG", config)
+        .args(["-A", "c"])
+        .arg(file)
+        .arg(dir.join("from-config"))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Unknown key 'unknown_key'"));
+    assert!(fs::read(dir.join("from-config")).unwrap().starts_with(b"PK"));
+
+    // The command line wins over the config file
+    crate::utils::cargo_bin()
+        .env("OUCH_CONFIG", config)
+        .args(["-A", "c", "--format", "gz"])
+        .arg(file)
+        .arg(dir.join("from-cli"))
+        .assert()
+        .success();
+    assert!(fs::read(dir.join("from-cli")).unwrap().starts_with(&[0x1f, 0x8b]));
+
+    // Keys conflicting with the command line are ignored, the others are given to the subcommand
+    // even when a value before it looks like its name
+    fs::write(config, "level = 1\nfast = true\n").unwrap();
+    crate::utils::cargo_bin()
+        .env("OUCH_CONFIG", config)
+        .args(["-A", "c", "--slow"])
+        .arg(file)
+        .arg(dir.join("slow.gz"))
+        .assert()
+        .success();
+    let output = crate::utils::cargo_bin()
+        .env("OUCH_CONFIG", config)
+        .args(["-A", "--format", "c", "c"])
+        .arg(file)
+        .arg(dir.join("format-c"))
+        .output()
+        .unwrap();
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Unsupported extension: c"));
+
+    // Values are checked like the flags'
+    fs::write(config, "threads = 0\n").unwrap();
+    let output = crate::utils::cargo_bin()
+        .env("OUCH_CONFIG", config)
+        .args(["-A", "c"])
+        .arg(file)
+        .arg(dir.join("invalid.gz"))
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Invalid config file"));
+
+    // Defaults like the environment variables, the level is clamped and threads aren't warned about
+    fs::write(config, "threads = 8\nlevel = 19\n").unwrap();
+    let output = crate::utils::cargo_bin()
+        .env("OUCH_CONFIG", config)
+        .args(["-A", "c"])
+        .arg(file)
+        .arg(dir.join("clamped.gz"))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("--threads"));
+
+    // An explicit config file must exist
+    crate::utils::cargo_bin()
+        .env("OUCH_CONFIG", dir.join("missing.toml"))
+        .args(["-A", "c"])
+        .arg(file)
+        .arg(dir.join("archive.zip"))
+        .assert()
+        .failure();
+}
+
+/// OUCH_THREADS, OUCH_LEVEL and OUCH_YES are used when their flags aren't given
+#[test]
+fn env_var_defaults() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let file = &dir.join("file.txt");
+    fs::write(file, "Testing 123\n").unwrap();
+    let archive = &dir.join("archive.tar.zst");
+
+    crate::utils::cargo_bin()
+        .env("OUCH_THREADS", "4")
+        .args(["-A", "c"])
+        .arg(file)
+        .arg(archive)
+        .assert()
+        .success();
+
+    // Picked up, 0 threads is invalid
+    let output = crate::utils::cargo_bin()
+        .env("OUCH_THREADS", "0")
+        .args(["-A", "c", "-y"])
+        .arg(file)
+        .arg(archive)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--threads"));
+
+    // The flag wins over the environment variable
+    crate::utils::cargo_bin()
+        .env("OUCH_THREADS", "0")
+        .args(["-A", "c", "-y", "--threads", "2"])
+        .arg(file)
+        .arg(archive)
+        .assert()
+        .success();
+
+    // Overwrites without asking, `--level` wins over OUCH_LEVEL
+    crate::utils::cargo_bin()
+        .env("OUCH_YES", "1")
+        .env("OUCH_LEVEL", "99")
+        .args(["-A", "c", "--level", "3"])
+        .arg(file)
+        .arg(archive)
+        .assert()
+        .success();
+    // Clamped to the range of each format, unlike `--level`
+    crate::utils::cargo_bin()
+        .env("OUCH_LEVEL", "99")
+        .args(["-A", "c", "-y"])
+        .arg(file)
+        .arg(archive)
+        .assert()
+        .success();
+    crate::utils::cargo_bin()
+        .env("OUCH_LEVEL", "19")
+        .args(["-A", "c", "-y"])
+        .arg(file)
+        .arg(dir.join("file.txt.gz"))
+        .assert()
+        .success();
+    crate::utils::cargo_bin()
+        .args(["-A", "c", "-y", "--level", "19"])
+        .arg(file)
+        .arg(dir.join("file.txt.gz"))
+        .assert()
+        .code(2);
+
+    // Only `--threads` on the command line is warned about when no format uses it
+    let output = crate::utils::cargo_bin()
+        .env("OUCH_THREADS", "4")
+        .args(["-A", "c", "-y"])
+        .arg(file)
+        .arg(dir.join("file.txt.gz"))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("--threads"));
+    let output = crate::utils::cargo_bin()
+        .args(["-A", "c", "-y", "--threads", "4"])
+        .arg(file)
+        .arg(dir.join("file.txt.gz"))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--threads"));
+
+    // Ignored when conflicting with the command line, the existing archive is kept
+    crate::utils::cargo_bin()
+        .env("OUCH_YES", "1")
+        .env("OUCH_LEVEL", "3")
+        .args(["-A", "c", "--no", "--fast"])
+        .arg(file)
+        .arg(archive)
+        .assert()
+        .code(4);
+}
+
+/// The sizes before and after compressing are printed, unless --quiet is given
+#[test]
+fn compress_size_summary() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let file = &dir.join("file.txt");
+    fs::write(file, "Testing 123\n".repeat(1000)).unwrap();
+
+    let output = crate::utils::cargo_bin()
+        .args(["-A", "c"])
+        .arg(file)
+        .arg(dir.join("archive.tar.gz"))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Compressed 12.00 kiB into"), "{stderr}");
+    assert!(stderr.contains("% of the original size."), "{stderr}");
+
+    let output = crate::utils::cargo_bin()
+        .args(["-A", "c", "-q"])
+        .arg(file)
+        .arg(dir.join("quiet.tar.gz"))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("original size"));
+}
+
+/// A single file already in the first formats of the output is only compressed with the formats
+/// that follow them
+#[test]
+fn compress_skips_input_formats() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    fs::create_dir(before).unwrap();
+    fs::write(before.join("file.txt"), "Testing 123\n").unwrap();
+
+    // Without skipping, the extracted files would be the input archives instead of their contents
+    for (input, output) in [
+        ("archive.tar.gz", "archive.tar.gz.xz"),
+        ("archive.zip", "archive.zip.gz"),
+    ] {
+        let input = &dir.join(input);
+        let output = &dir.join(output);
+        ouch!("-A", "c", before, input);
+        ouch!("-A", "c", input, output);
+
+        let after = &dir.join(format!("after-{}", output.file_name().unwrap().to_str().unwrap()));
+        ouch!("-A", "-y", "d", output, "-d", after);
+        assert_same_directory(before, after.join("before"), false);
+    }
+
+    // Nothing left to compress it with
+    let input = &dir.join("file.gz");
+    ouch!("-A", "c", before.join("file.txt"), input);
+    crate::utils::cargo_bin()
+        .args(["-A", "c"])
+        .arg(input)
+        .arg(dir.join("other.gz"))
+        .assert()
+        .failure();
+    assert!(!dir.join("other.gz").exists());
+}
+
+/// --append adds entries to an existing tar archive, keeping the ones already in it
+#[test]
+fn compress_append_to_tar() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let old = &dir.join("old.txt");
+    let new = &dir.join("new.txt");
+    fs::write(old, "old").unwrap();
+    fs::write(new, "new").unwrap();
+
+    let archive = &dir.join("archive.tar");
+    ouch!("-A", "c", old, archive);
+    ouch!("-A", "c", new, archive, "--append");
+
+    let output = crate::utils::cargo_bin()
+        .args(["-A", "l"])
+        .arg(archive)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let listing = String::from_utf8(output.stdout).unwrap();
+    assert!(listing.contains("old.txt"), "{listing}");
+    assert!(listing.contains("new.txt"), "{listing}");
+
+    let after = &dir.join("after");
+    ouch!("-A", "d", archive, "-d", after);
+    assert_eq!(fs::read(after.join("archive/old.txt")).unwrap(), b"old");
+    assert_eq!(fs::read(after.join("archive/new.txt")).unwrap(), b"new");
+
+    // Compressed streams can't be appended to
+    let compressed = &dir.join("archive.tar.gz");
+    ouch!("-A", "c", old, compressed);
+    let before = fs::read(compressed).unwrap();
+    crate::utils::cargo_bin()
+        .args(["-A", "c", "--append"])
+        .arg(new)
+        .arg(compressed)
+        .assert()
+        .failure();
+    assert_eq!(fs::read(compressed).unwrap(), before);
+}
+
+/// The comment given with `--comment` is stored in zip archives and shown when listing them
+#[test]
+fn compress_zip_comment() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let file = &dir.join("file.txt");
+    fs::write(file, "contents").unwrap();
+
+    let archive = &dir.join("archive.zip");
+    ouch!("-A", "c", file, archive, "--comment", "made by ouch");
+
+    let output = crate::utils::cargo_bin()
+        .args(["-A", "l"])
+        .arg(archive)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let listing = String::from_utf8(output.stdout).unwrap();
+    assert!(listing.contains("Comment: made by ouch"), "{listing}");
+
+    // Control characters that would be run by the terminal are escaped, it's part of each JSON entry
+    let archive = &dir.join("escaped.zip");
+    ouch!("-A", "c", file, archive, "--comment", "\x1b[2Jmade by ouch");
+    let listing = String::from_utf8(ouch!("-A", "l", archive).stdout).unwrap();
+    assert!(listing.contains("Comment: \\u{1b}[2Jmade by ouch"), "{listing}");
+    let listing = String::from_utf8(ouch!("-A", "l", archive, "--json").stdout).unwrap();
+    assert!(
+        listing.contains(r#""comment": "\u001b[2Jmade by ouch", "path": "file.txt""#),
+        "{listing}"
+    );
+
+    // Other formats have nowhere to store it
+    let output = crate::utils::cargo_bin()
+        .args(["-A", "c", "--comment", "made by ouch"])
+        .arg(file)
+        .arg(dir.join("archive.tar"))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("'--comment' flag only applies to zip"), "{stderr}");
+}
+
+/// Gzip files with their original name in the header are decompressed to that name
+#[test]
+fn decompress_gzip_original_name() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+
+    let with_name = &dir.join("download.gz");
+    let mut encoder = flate2::GzBuilder::new()
+        .filename("report.csv")
+        .write(fs::File::create(with_name).unwrap(), flate2::Compression::default());
+    encoder.write_all(b"a,b,c").unwrap();
+    encoder.finish().unwrap();
+
+    // Only the file name is used, even if the header holds a path
+    let with_path = &dir.join("other.gz");
+    let mut encoder = flate2::GzBuilder::new()
+        .filename("../escaped.csv")
+        .write(fs::File::create(with_path).unwrap(), flate2::Compression::default());
+    encoder.write_all(b"d,e,f").unwrap();
+    encoder.finish().unwrap();
+
+    // Without a name in the header, the name comes from the input path
+    let file = &dir.join("plain.txt");
+    fs::write(file, "plain").unwrap();
+    let without_name = &dir.join("plain.txt.gz");
+    ouch!("-A", "c", file, without_name);
+
+    let out = &dir.join("out");
+    ouch!("-A", "d", with_name, with_path, without_name, "-d", out);
+    assert_eq!(fs::read(out.join("report.csv")).unwrap(), b"a,b,c");
+    assert_eq!(fs::read(out.join("escaped.csv")).unwrap(), b"d,e,f");
+    assert_eq!(fs::read(out.join("plain.txt")).unwrap(), b"plain");
+    assert!(!out.join("download").exists());
+}
+
+/// Gzip files made of several members, like the ones written by `bgzip`, are decompressed whole
+#[test]
+fn decompress_multi_member_gzip() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+
+    let archive = &dir.join("joined.txt.gz");
+    let mut contents = vec![];
+    for member in ["first member\n", "second member\n"] {
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(member.as_bytes()).unwrap();
+        contents.extend(encoder.finish().unwrap());
+    }
+    fs::write(archive, contents).unwrap();
+
+    let out = &dir.join("out");
+    ouch!("-A", "d", archive, "-d", out);
+    assert_eq!(
+        fs::read_to_string(out.join("joined.txt")).unwrap(),
+        "first member\nsecond member\n"
+    );
+}
+
+/// Only the entries matching `--include` are extracted, before `--strip-components` is applied
+#[test]
+fn decompress_include() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    fs::create_dir_all(before.join("sub")).unwrap();
+    fs::write(before.join("a.txt"), "a").unwrap();
+    fs::write(before.join("b.md"), "b").unwrap();
+    fs::write(before.join("sub/c.txt"), "c").unwrap();
+    fs::write(before.join("sub/d.rs"), "d").unwrap();
+
+    for format in ["tar.gz", "zip", "7z", "cpio"] {
+        let archive = &dir.join(format!("archive.{format}"));
+        ouch!("-A", "c", before, archive);
+
+        let out = &dir.join(format!("out_{format}"));
+        let output = crate::utils::cargo_bin()
+            .args(["-A", "d", "--include", "*.txt", "-d"])
+            .arg(out)
+            .arg(archive)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains("Extracted 2 files, skipped 2"), "{stderr}");
+        assert_eq!(fs::read(out.join("before/a.txt")).unwrap(), b"a");
+        assert_eq!(fs::read(out.join("before/sub/c.txt")).unwrap(), b"c");
+        assert!(!out.join("before/b.md").exists());
+        assert!(!out.join("before/sub/d.rs").exists());
+
+        let stripped = &dir.join(format!("stripped_{format}"));
+        ouch!(
+            "-A",
+            "d",
+            archive,
+            "-d",
+            stripped,
+            "--include",
+            "before/sub/**",
+            "--strip-components",
+            "1"
+        );
+        assert_eq!(fs::read(stripped.join("sub/c.txt")).unwrap(), b"c");
+        assert_eq!(fs::read(stripped.join("sub/d.rs")).unwrap(), b"d");
+        assert!(!stripped.join("a.txt").exists());
+    }
+
+    // Entries are skipped before they're read, the password of the others isn't needed
+    let archive = &dir.join("encrypted.zip");
+    let mut writer = zip::ZipWriter::new(fs::File::create(archive).unwrap());
+    let options = zip::write::SimpleFileOptions::default();
+    writer.start_file("plain.txt", options).unwrap();
+    writer.write_all(b"plain").unwrap();
+    writer
+        .start_file(
+            "secret.txt",
+            options.with_aes_encryption(zip::AesMode::Aes256, "hunter2"),
+        )
+        .unwrap();
+    writer.write_all(b"secret").unwrap();
+    writer.finish().unwrap();
+
+    let out = &dir.join("out_encrypted");
+    ouch!("-A", "d", archive, "-d", out, "--include", "plain.txt");
+    assert_eq!(fs::read(out.join("plain.txt")).unwrap(), b"plain");
+    assert!(!out.join("secret.txt").exists());
+}
+
+/// `list --filter` only prints the matching entries, along with their directories in tree mode
+#[test]
+fn list_filter() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let project = &dir.join("project");
+    fs::create_dir_all(project.join("src")).unwrap();
+    fs::write(project.join("src/main.rs"), "fn main() {}").unwrap();
+    fs::write(project.join("src/notes.txt"), "notes").unwrap();
+    fs::write(project.join("README.md"), "readme").unwrap();
+
+    let archive = &dir.join("project.tar.gz");
+    ouch!("-A", "c", project, archive);
+
+    for tree in [false, true] {
+        let output = crate::utils::cargo_bin()
+            .args(["-A", "l", "--filter", "*.rs"])
+            .args(tree.then_some("--tree"))
+            .arg(archive)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let listing = String::from_utf8(output.stdout).unwrap();
+        assert!(listing.contains("main.rs"), "{listing}");
+        assert!(!listing.contains("notes.txt"), "{listing}");
+        assert!(!listing.contains("README.md"), "{listing}");
+        if tree {
+            assert!(listing.contains("src"), "{listing}");
+        }
+    }
+}
+
+/// `list --count` only prints the number of files and their total size, from the central
+/// directory of zip archives, and while reading the entries of other formats
+#[test]
+fn list_count() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+
+    let archive = &dir.join("many.zip");
+    let mut writer = zip::ZipWriter::new(fs::File::create(archive).unwrap());
+    let options = zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    writer.add_directory("dir/", options).unwrap();
+    for number in 0..2_000 {
+        writer.start_file(format!("dir/file_{number}.txt"), options).unwrap();
+        writer.write_all(b"four").unwrap();
+    }
+    writer.finish().unwrap();
+
+    let count = |args: &[&str], archive: &PathBuf| {
+        let output = crate::utils::cargo_bin()
+            .args(["-A", "l", "--count"])
+            .args(args)
+            .arg(archive)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    let listing = count(&[], archive);
+    assert_eq!(listing.lines().count(), 2, "{listing}");
+    assert!(listing.starts_with("Archive: "), "{listing}");
+    assert!(listing.contains("Total: 2000 files, 8000 B -> "), "{listing}");
+
+    // Counted while reading the entries, with the filter applied
+    let listing = count(&["--filter", "dir/file_1*"], archive);
+    assert!(listing.ends_with("Total: 1111 files, 4444 B\n"), "{listing}");
+
+    let project = &dir.join("project");
+    fs::create_dir_all(project.join("src")).unwrap();
+    fs::write(project.join("src/main.rs"), "fn main() {}").unwrap();
+    fs::write(project.join("README.md"), "readme").unwrap();
+    let archive = &dir.join("project.tar.gz");
+    ouch!("-A", "c", project, archive);
+
+    let listing = count(&[], archive);
+    assert_eq!(listing.lines().count(), 2, "{listing}");
+    assert!(listing.contains("Total: 2 files, 18 B -> "), "{listing}");
+}
+
+/// `list` aligns the sizes of the entries, and `--long` adds their permissions and modification time
+#[test]
+fn list_aligned_columns() {
+    let dir = tempdir().unwrap();
+    let archive = &dir.path().join("archive.tar");
+
+    let mut builder = tar::Builder::new(fs::File::create(archive).unwrap());
+    for (path, mode, data) in [
+        ("run.sh", 0o755, &b"#!/bin/sh\n"[..]),
+        ("data/long_file_name.bin", 0o644, &[0; 1234][..]),
+    ] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(mode);
+        header.set_mtime(1_700_000_000);
+        builder.append_data(&mut header, path, data).unwrap();
+    }
+    builder.finish().unwrap();
+    drop(builder);
+
+    let list = |args: &[&str]| {
+        let output = crate::utils::cargo_bin()
+            .args(["l"])
+            .args(args)
+            .arg(archive)
+            .env("NO_COLOR", "1")
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    let listing = list(&[]);
+    let entries: Vec<_> = listing.lines().skip(1).take(2).collect();
+    assert_eq!(
+        entries,
+        ["run.sh                     10 B", "data/long_file_name.bin  1234 B"],
+        "{listing}"
+    );
+
+    let listing = list(&["--long"]);
+    let entries: Vec<_> = listing.lines().skip(1).take(2).collect();
+    assert_eq!(
+        entries,
+        [
+            "-rwxr-xr-x  2023-11-14 22:13  run.sh                     10 B",
+            "-rw-r--r--  2023-11-14 22:13  data/long_file_name.bin  1234 B",
+        ],
+        "{listing}"
+    );
+
+    // The padding would be read out loud by screen readers
+    let listing = list(&["-A"]);
+    assert!(listing.contains("\nrun.sh  10 B\n"), "{listing}");
+}
+
+/// `--one-dir-per-archive` extracts each archive into a directory named after it, so files with
+/// the same name don't collide, even with `--strip-components`
+#[test]
+fn decompress_one_dir_per_archive() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+
+    for (name, content) in [("first", "one"), ("second", "two")] {
+        let input = &dir.join(name).join("notes.txt");
+        fs::create_dir(dir.join(name)).unwrap();
+        fs::write(input, content).unwrap();
+        ouch!("-A", "c", input, dir.join(format!("{name}.tar.gz")));
+    }
+    let first = &dir.join("first.tar.gz");
+    let second = &dir.join("second.tar.gz");
+
+    let out = &dir.join("out");
+    ouch!("-A", "d", first, second, "-d", out, "--one-dir-per-archive");
+    assert_eq!(fs::read_to_string(out.join("first/notes.txt")).unwrap(), "one");
+    assert_eq!(fs::read_to_string(out.join("second/notes.txt")).unwrap(), "two");
+
+    // Without the flag, the single file of an archive is extracted right into the output directory
+    let flat = &dir.join("flat");
+    ouch!("-A", "d", first, "-d", flat);
+    assert_eq!(fs::read_to_string(flat.join("notes.txt")).unwrap(), "one");
+
+    let project = &dir.join("project");
+    fs::create_dir_all(project.join("src")).unwrap();
+    fs::write(project.join("src/main.rs"), "fn main() {}").unwrap();
+    let archive = &dir.join("project.zip");
+    ouch!("-A", "c", project, archive);
+
+    let stripped = &dir.join("stripped");
+    ouch!(
+        "-A",
+        "d",
+        archive,
+        "-d",
+        stripped,
+        "--strip-components",
+        "1",
+        "--one-dir-per-archive"
+    );
+    assert!(stripped.join("project/src/main.rs").is_file());
+}
+
+/// `--auto-strip` removes the top-level directory only when every extracted file is inside of it,
+/// which is checked after `--include` left some of them out
+#[test]
+fn decompress_auto_strip() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+
+    let project = &dir.join("project-1.0");
+    fs::create_dir_all(project.join("src")).unwrap();
+    fs::write(project.join("src/main.rs"), "fn main() {}").unwrap();
+    fs::write(project.join("README.md"), "readme").unwrap();
+    let single_root = &dir.join("single.tar.gz");
+    ouch!("-A", "c", project, single_root);
+
+    let out = &dir.join("single");
+    ouch!("-A", "d", single_root, "-d", out, "--auto-strip");
+    assert!(out.join("src/main.rs").is_file());
+    assert!(out.join("README.md").is_file());
+    assert!(!out.join("project-1.0").exists());
+
+    let docs = &dir.join("docs");
+    fs::create_dir(docs).unwrap();
+    fs::write(docs.join("guide.md"), "guide").unwrap();
+    let notes = &dir.join("notes.txt");
+    fs::write(notes, "notes").unwrap();
+    let multi_root = &dir.join("multi.tar.gz");
+    ouch!("-A", "c", docs, notes, multi_root);
+
+    // Multiple roots are kept, in a directory named after the archive as usual
+    let out = &dir.join("multi");
+    ouch!("-A", "d", multi_root, "-d", out, "--auto-strip");
+    assert!(out.join("multi/docs/guide.md").is_file());
+    assert!(out.join("multi/notes.txt").is_file());
+
+    // Only 'docs' is left after the filter, so it's the single root that's removed
+    let out = &dir.join("included");
+    ouch!("-A", "d", multi_root, "-d", out, "--auto-strip", "--include", "docs/**");
+    assert!(out.join("guide.md").is_file());
+    assert!(!out.join("notes.txt").exists());
+}
+
+/// `--overwrite` and `--no-clobber` decide what happens to existing output files on their own,
+/// whatever `--yes` and `--no` answer to the other questions
+#[test]
+fn overwrite_and_no_clobber() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let input = &dir.join("file.txt");
+    fs::write(input, "new").unwrap();
+    let archive = &dir.join("archive.zip");
+
+    let run = |args: &[&str]| {
+        fs::write(archive, "old").unwrap();
+        crate::utils::cargo_bin()
+            .args(args)
+            .args(["-A", "c"])
+            .arg(input)
+            .arg(archive)
+            .assert()
+    };
+
+    run(&["--no", "--overwrite"]).success();
+    assert_ne!(fs::read(archive).unwrap(), b"old");
+
+    run(&["--yes", "--no-clobber"]).code(4);
+    assert_eq!(fs::read(archive).unwrap(), b"old");
+
+    run(&["--overwrite", "--no-clobber"]).failure();
+    assert_eq!(fs::read(archive).unwrap(), b"old");
+
+    // The same when decompressing over an existing file
+    let compressed = &dir.join("file.txt.gz");
+    ouch!("-A", "c", input, compressed);
+    let out = &dir.join("out");
+    fs::create_dir(out).unwrap();
+    for (flag, expected, code) in [("--overwrite", "new", 0), ("--no-clobber", "old", 4)] {
+        fs::write(out.join("file.txt"), "old").unwrap();
+        crate::utils::cargo_bin()
+            .args(["-A", "d", flag])
+            .arg(compressed)
+            .arg("-d")
+            .arg(out)
+            .assert()
+            .code(code);
+        assert_eq!(fs::read_to_string(out.join("file.txt")).unwrap(), expected);
+    }
+}
+
+/// Files compressed to zstd store their size in the frame header, also with multiple threads,
+/// so that decompressors know it upfront
+#[test]
+fn zstd_content_size() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let input = &dir.join("file.txt");
+    fs::write(input, "Testing 123\n".repeat(10_000)).unwrap();
+
+    for threads in ["1", "4"] {
+        let output = &dir.join(format!("file-{threads}.txt.zst"));
+        ouch!("-A", "c", input, output, "--threads", threads);
+
+        let compressed = fs::read(output).unwrap();
+        let content_size = zstd::zstd_safe::get_frame_content_size(&compressed).unwrap();
+        assert_eq!(content_size, Some(120_000));
+    }
+}
+
+/// `benchmark` measures every format and supported level, without writing anything
+#[test]
+fn benchmark_formats_and_levels() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let input = &dir.join("input");
+    fs::create_dir(input).unwrap();
+    fs::write(input.join("a.txt"), "ouch ".repeat(10_000)).unwrap();
+    fs::write(input.join("b.txt"), "benchmark ".repeat(10_000)).unwrap();
+
+    let output = crate::utils::cargo_bin()
+        .current_dir(dir)
+        .args(["benchmark", "input", "--formats", "gz,zst,lz4", "--levels", "1,20"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let formats: Vec<_> = stdout
+        .lines()
+        .skip(2)
+        .map(|line| line.split_whitespace().take(2).collect::<Vec<_>>().join(" "))
+        .collect();
+    assert_eq!(formats, ["tar.gz 1", "tar.zst 1", "tar.zst 20", "tar.lz4 default"]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Skipped 'tar.gz' at level 20"), "{stderr}");
+
+    let output = crate::utils::cargo_bin()
+        .current_dir(dir)
+        .args(["benchmark", "input/a.txt", "--formats", "xz", "--json"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.starts_with("[\n  {\"format\": \"xz\", \"level\": null, \"input_size\": 50000,"),
+        "{stdout}"
+    );
+
+    let mut entries: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    entries.sort();
+    assert_eq!(entries, ["input"]);
+}
+
+/// `--sort name` adds the files in the same order however the filesystem lists them, so the
+/// same tree gives the same archive
+#[test]
+fn compress_sort_name_is_reproducible() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let mtime = FileTime::from_unix_time(1_000_000_000, 0);
+
+    // The same tree, with its files created in opposite orders
+    let names = ["b.txt", "a.txt", "nested/d.txt", "nested/c.txt", "e.txt"];
+    for (root, names) in [
+        ("first", names.to_vec()),
+        ("second", names.iter().rev().copied().collect()),
+    ] {
+        let tree = dir.join(root).join("tree");
+        fs::create_dir_all(tree.join("nested")).unwrap();
+        for name in names {
+            fs::write(tree.join(name), name.repeat(100)).unwrap();
+            set_file_mtime(tree.join(name), mtime).unwrap();
+        }
+        set_file_mtime(tree.join("nested"), mtime).unwrap();
+        set_file_mtime(&tree, mtime).unwrap();
+    }
+
+    let first = &dir.join("first.tar");
+    let second = &dir.join("second.tar");
+    ouch!("-A", "c", dir.join("first/tree"), first, "--sort", "name");
+    ouch!("-A", "c", dir.join("second/tree"), second, "--sort", "name");
+    assert_eq!(fs::read(first).unwrap(), fs::read(second).unwrap());
+
+    let output = crate::utils::cargo_bin()
+        .args(["-A", "list"])
+        .arg(first)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let entries: Vec<_> = stdout
+        .lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .filter(|name| name.ends_with(".txt"))
+        .collect();
+    assert_eq!(
+        entries,
+        [
+            "tree/a.txt",
+            "tree/b.txt",
+            "tree/e.txt",
+            "tree/nested/c.txt",
+            "tree/nested/d.txt"
+        ],
+    );
+
+    // Sorted by size, the smallest first
+    let sized = &dir.join("sized");
+    fs::create_dir(sized).unwrap();
+    for (name, size) in [("large", 300), ("small", 100), ("medium", 200)] {
+        fs::write(sized.join(name), "x".repeat(size)).unwrap();
+    }
+    let archive = &dir.join("sized.tar");
+    ouch!("-A", "c", sized, archive, "--sort", "size");
+    let output = crate::utils::cargo_bin()
+        .args(["-A", "list"])
+        .arg(archive)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let entries: Vec<_> = stdout
+        .lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .filter(|name| name.starts_with("sized/") && *name != "sized/")
+        .collect();
+    assert_eq!(entries, ["sized/small", "sized/medium", "sized/large"]);
+}
+
+/// `--reproducible` gives the same archive for the same files, whatever their modification
+/// times, permissions and the order they were created in
+#[test]
+fn compress_reproducible() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let tree = &dir.join("tree");
+
+    let create_tree = |names: &[&str], mtime| {
+        if tree.exists() {
+            fs::remove_dir_all(tree).unwrap();
+        }
+        fs::create_dir_all(tree.join("nested")).unwrap();
+        for name in names {
+            fs::write(tree.join(name), name.repeat(100)).unwrap();
+            set_file_mtime(tree.join(name), FileTime::from_unix_time(mtime, 0)).unwrap();
+        }
+    };
+
+    let names = ["b.txt", "a.txt", "nested/d.txt", "nested/c.txt"];
+    for format in ["tar.gz", "zip", "cpio"] {
+        create_tree(&names, 1_000_000_000);
+        let first = &dir.join(format!("first.{format}"));
+        ouch!("-A", "c", tree, first, "--reproducible");
+
+        let reversed: Vec<_> = names.iter().rev().copied().collect();
+        create_tree(&reversed, 1_500_000_000);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(tree.join("a.txt"), std::fs::Permissions::from_mode(0o600)).unwrap();
+        }
+        let second = &dir.join(format!("second.{format}"));
+        ouch!("-A", "c", tree, second, "--reproducible");
+
+        assert_eq!(fs::read(first).unwrap(), fs::read(second).unwrap(), "{format}");
+    }
+
+    // Entries have the fixed modification time and no owner
+    let archive = &dir.join("fixed.tar");
+    ouch!("-A", "c", tree, archive, "--reproducible", "--mtime", "2024-01-01");
+    let mut archive = tar::Archive::new(fs::File::open(archive).unwrap());
+    for entry in archive.entries().unwrap() {
+        let entry = entry.unwrap();
+        let header = entry.header();
+        assert_eq!(header.mtime().unwrap(), 1_704_067_200);
+        assert_eq!((header.uid().unwrap(), header.gid().unwrap()), (0, 0));
+        let expected_mode = if header.entry_type().is_dir() { 0o755 } else { 0o644 };
+        assert_eq!(header.mode().unwrap(), expected_mode);
+    }
+}
+
+/// `--base-dir` names the entries relative to a directory with every input in it, and
+/// `--absolute-paths` relative to the root
+#[test]
+fn compress_base_dir() {
+    let dir = tempdir().unwrap();
+    let dir = &dir.path().canonicalize().unwrap();
+    let root = &dir.join("root");
+    fs::create_dir_all(root.join("first/docs")).unwrap();
+    fs::create_dir_all(root.join("second/src")).unwrap();
+    fs::write(root.join("first/docs/readme.txt"), "readme").unwrap();
+    fs::write(root.join("second/src/main.rs"), "fn main() {}").unwrap();
+
+    let listed = |archive: &std::path::Path| -> Vec<String> {
+        let mut paths: Vec<_> = match archive.extension().unwrap().to_str().unwrap() {
+            "zip" => {
+                let mut archive = zip::ZipArchive::new(fs::File::open(archive).unwrap()).unwrap();
+                (0..archive.len())
+                    .map(|i| archive.by_index(i).unwrap().name().trim_end_matches('/').to_string())
+                    .collect()
+            }
+            _ => tar::Archive::new(fs::File::open(archive).unwrap())
+                .entries()
+                .unwrap()
+                .map(|entry| entry.unwrap().path().unwrap().to_str().unwrap().to_string())
+                .map(|path| path.trim_end_matches('/').to_string())
+                .collect(),
+        };
+        paths.sort();
+        paths
+    };
+
+    for format in ["tar", "zip"] {
+        let archive = &dir.join(format!("archive.{format}"));
+        ouch!(
+            "-A",
+            "c",
+            root.join("first/docs"),
+            root.join("second/src"),
+            archive,
+            "--base-dir",
+            root
+        );
+        assert_eq!(
+            listed(archive),
+            [
+                "first/docs",
+                "first/docs/readme.txt",
+                "second/src",
+                "second/src/main.rs"
+            ],
+            "{format}"
+        );
+
+        // Several entries at the root are unpacked into a directory named after the archive
+        let after = &dir.join(format!("after-{format}")).join("archive");
+        ouch!("-A", "d", archive, "-d", after.parent().unwrap());
+        assert_eq!(
+            fs::read_to_string(after.join("first/docs/readme.txt")).unwrap(),
+            "readme"
+        );
+        assert_eq!(
+            fs::read_to_string(after.join("second/src/main.rs")).unwrap(),
+            "fn main() {}"
+        );
+    }
+
+    // The base directory itself can be an input, without an entry of its own
+    let archive = &dir.join("whole.tar");
+    ouch!("-A", "c", root.join("first"), archive, "--base-dir", root.join("first"));
+    assert_eq!(listed(archive), ["docs", "docs/readme.txt"]);
+
+    // Inputs outside of the base directory are rejected
+    crate::utils::cargo_bin()
+        .args(["-A", "c"])
+        .arg(root.join("first/docs"))
+        .arg(dir.join("outside.tar"))
+        .arg("--base-dir")
+        .arg(root.join("second"))
+        .assert()
+        .failure();
+    assert!(!dir.join("outside.tar").exists());
+
+    // Ignored by formats without entry names
+    let output = crate::utils::cargo_bin()
+        .args(["-A", "c"])
+        .arg(root.join("first/docs/readme.txt"))
+        .arg(dir.join("readme.txt.gz"))
+        .arg("--base-dir")
+        .arg(root)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("flags only apply to archives"), "{stderr}");
+
+    #[cfg(unix)]
+    {
+        let archive = &dir.join("absolute.tar");
+        ouch!("-A", "c", root.join("first/docs"), archive, "--absolute-paths");
+        let docs = root.join("first/docs");
+        let docs = docs.strip_prefix("/").unwrap().to_str().unwrap();
+        assert_eq!(listed(archive), [docs.to_string(), format!("{docs}/readme.txt")]);
+    }
+}
+
+/// `formats` lists every format with its extensions, and whether it can be written and read
+#[test]
+fn formats_lists_supported_formats() {
+    let output = crate::utils::cargo_bin().arg("formats").output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let rows: Vec<Vec<_>> = stdout.lines().map(|line| line.split_whitespace().collect()).collect();
+    assert_eq!(rows[0], ["Format", "Type", "Compress", "Decompress", "Extensions"]);
+    assert!(
+        rows.contains(&vec!["gzip", "compressor", "yes", "yes", "gz,", "gzip,", "tgz"]),
+        "{stdout}"
+    );
+    assert!(
+        rows.contains(&vec!["ar", "archive", "no", "yes", "a,", "deb"]),
+        "{stdout}"
+    );
+    let rar = rows.iter().find(|row| row[0] == "rar").unwrap();
+    assert_eq!(rar[..3], ["rar", "archive", "no"]);
+
+    let output = crate::utils::cargo_bin().args(["formats", "--json"]).output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains(
+            r#"{"format": "bzip2", "type": "compressor", "compress": true, "decompress": true, "extensions": ["bz", "bz2", "bzip2"], "aliases": ["tbz", "tbz2"]}"#
+        ),
+        "{stdout}"
+    );
+}
+
+/// Interrupting a decompression removes the file being written, but keeps the completed ones
+#[cfg(unix)]
+#[test]
+fn decompress_interrupted_removes_incomplete_file() {
+    use std::{
+        os::unix::process::ExitStatusExt,
+        process::{Command, Stdio},
+        thread,
+        time::{Duration, Instant},
+    };
+
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+
+    let mut archive = tar::Builder::new(vec![]);
+    for (name, size) in [("complete", 1000), ("incomplete", 1_000_000)] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(size as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        archive.append_data(&mut header, name, &vec![b'o'; size][..]).unwrap();
+    }
+    let archive = archive.into_inner().unwrap();
+
+    let mut child = Command::new(assert_cmd::cargo::cargo_bin("ouch"))
+        .current_dir(dir)
+        .args(["decompress", "-", "--format", "tar", "--dir", "out"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // Stops in the middle of the second file
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all(&archive[..archive.len() / 2]).unwrap();
+
+    // Archives are extracted into a temporary directory first
+    let find_extracted = |name: &str| {
+        fs::read_dir(dir.join("out"))
+            .ok()?
+            .map(|entry| entry.unwrap().path().join(name))
+            .find(|path| path.exists())
+    };
+    let start = Instant::now();
+    let incomplete = loop {
+        if let Some(path) = find_extracted("incomplete") {
+            break path;
+        }
+        assert!(start.elapsed() < Duration::from_secs(30), "nothing was extracted");
+        thread::sleep(Duration::from_millis(10));
+    };
+
+    unsafe { libc::kill(child.id() as i32, libc::SIGINT) };
+    let output = child.wait_with_output().unwrap();
+    drop(stdin);
+    assert_eq!(output.status.signal(), Some(libc::SIGINT));
+
+    let complete = incomplete.with_file_name("complete");
+    assert_eq!(fs::read(&complete).unwrap(), vec![b'o'; 1000]);
+    assert!(!incomplete.exists());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let removed = stderr.lines().find(|line| line.contains("removed the incomplete file"));
+    assert!(removed.is_some_and(|line| line.ends_with("incomplete'.")), "{stderr}");
+    let (_, completed) = stderr.split_once("Files completed before the interrupt:\n").unwrap();
+    assert!(completed.trim().ends_with("complete"), "{stderr}");
+}
+
+/// `--verbose` lists each file even in accessibility mode, `-vv` also prints the sizes of the
+/// files compressed
+#[test]
+fn verbose_lists_each_file() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    fs::create_dir(dir.join("input")).unwrap();
+    fs::write(dir.join("input/file.txt"), "ouch").unwrap();
+
+    let stderr_of = |args: &[&str]| {
+        let output = crate::utils::cargo_bin()
+            .current_dir(dir)
+            .args(args)
+            .arg("--accessible")
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stderr).unwrap()
+    };
+
+    let stderr = stderr_of(&["compress", "input", "quiet.tar"]);
+    assert!(!stderr.contains("Compressing 'input/file.txt'"), "{stderr}");
+
+    let stderr = stderr_of(&["compress", "input", "verbose.tar", "-v"]);
+    assert!(stderr.contains("Compressing 'input/file.txt'."), "{stderr}");
+
+    let stderr = stderr_of(&["compress", "input", "sizes.tar", "-vv"]);
+    assert!(stderr.contains("Compressing 'input/file.txt' (4.00 B)."), "{stderr}");
+
+    let stderr = stderr_of(&["decompress", "verbose.tar", "--dir", "out", "-v"]);
+    assert!(stderr.contains("file.txt\" extracted. (4.00 B)"), "{stderr}");
+}
+
+/// `--quiet` prints nothing when everything goes well, but errors are still reported
+#[test]
+fn quiet_prints_nothing_on_success() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    fs::create_dir(dir.join("input")).unwrap();
+    fs::write(dir.join("input/file.txt"), "ouch").unwrap();
+
+    for args in [
+        &["compress", "input", "archive.tar.gz"][..],
+        &["decompress", "archive.tar.gz", "--dir", "out"],
+        // Warned about, since the input isn't an archive
+        &["compress", "input/file.txt", "file.gz"],
+        &["decompress", "file.gz", "--dir", "gz", "--include", "*.md"],
+    ] {
+        let output = crate::utils::cargo_bin()
+            .current_dir(dir)
+            .args(args)
+            .arg("--quiet")
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{args:?}");
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), "", "{args:?}");
+        assert_eq!(String::from_utf8(output.stderr).unwrap(), "", "{args:?}");
+    }
+    assert_eq!(fs::read(dir.join("out/input/file.txt")).unwrap(), b"ouch");
+
+    let output = crate::utils::cargo_bin()
+        .current_dir(dir)
+        .args(["decompress", "missing.tar.gz", "--quiet"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr).unwrap().contains("[ERROR]"));
+}
+
+/// The exit code tells apart why ouch failed
+#[test]
+fn exit_codes_by_error_category() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    fs::write(dir.join("file.txt"), "ouch").unwrap();
+    ouch!("-A", "c", dir.join("file.txt"), dir.join("file.txt.gz"));
+    let compressed = fs::read(dir.join("file.txt.gz")).unwrap();
+    fs::write(dir.join("truncated.gz"), &compressed[..compressed.len() / 2]).unwrap();
+
+    for (args, code) in [
+        // The formats of the output are missing
+        (&["compress", "file.txt", "output"][..], 2),
+        (&["decompress", "missing.tar.gz"], 3),
+        (&["compress", "missing.txt", "output.gz"], 3),
+        // Not overwriting the existing output
+        (&["compress", "file.txt", "file.txt.gz", "--no"], 4),
+        (&["decompress", "truncated.gz", "--dir", "out"], 5),
+    ] {
+        crate::utils::cargo_bin()
+            .current_dir(dir)
+            .arg("-A")
+            .args(args)
+            .assert()
+            .code(code);
+    }
+}
+
+/// `--to-stdout` writes the decompressed file to stdout without creating any file, archives need
+/// `--include` to pick a single entry
+#[test]
+fn decompress_to_stdout() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    fs::create_dir_all(dir.join("input/docs")).unwrap();
+    fs::write(dir.join("input/file.txt"), "ouch").unwrap();
+    fs::write(dir.join("input/docs/README.md"), "read me").unwrap();
+    ouch!("-A", "c", dir.join("input/file.txt"), dir.join("file.txt.xz.gz"));
+    ouch!("-A", "c", dir.join("input"), dir.join("archive.tar.zst"));
+    let files_before = fs::read_dir(dir).unwrap().count();
+
+    for (args, expected) in [
+        (&["file.txt.xz.gz"][..], "ouch"),
+        (&["archive.tar.zst", "--include", "*.md"], "read me"),
+    ] {
+        let output = crate::utils::cargo_bin()
+            .current_dir(dir)
+            .args(["-A", "d", "--to-stdout"])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{args:?}");
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), expected, "{args:?}");
+    }
+    assert_eq!(fs::read_dir(dir).unwrap().count(), files_before);
+
+    // Archives need --include to match a single file
+    for args in [&["archive.tar.zst"][..], &["archive.tar.zst", "--include", "input/**"]] {
+        let output = crate::utils::cargo_bin()
+            .current_dir(dir)
+            .args(["-A", "d", "-c"])
+            .args(args)
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(2), "{args:?}");
+        assert!(output.stdout.is_empty(), "{args:?}");
+    }
+    assert_eq!(fs::read_dir(dir).unwrap().count(), files_before);
+}
+
+/// A file with more extensions than layers fails on the first layer that doesn't match, instead of
+/// an error from its decoder
+#[test]
+fn decompress_mislabeled_layer() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    fs::create_dir(dir.join("input")).unwrap();
+    fs::write(dir.join("input/file.txt"), "ouch").unwrap();
+    ouch!("-A", "c", dir.join("input/file.txt"), dir.join("file.gz"));
+    ouch!("-A", "c", dir.join("input"), dir.join("archive.tar.gz"));
+    fs::rename(dir.join("file.gz"), dir.join("file.gz.gz")).unwrap();
+    fs::rename(dir.join("archive.tar.gz"), dir.join("archive.zst.gz")).unwrap();
+
+    for (file, looks_like) in [("file.gz.gz", None), ("archive.zst.gz", Some("tar"))] {
+        let output = crate::utils::cargo_bin()
+            .current_dir(dir)
+            .args(["-A", "d", file, "--dir", "out"])
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(5), "{file}");
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains("Layer 2 of 2"), "{stderr}");
+        assert!(stderr.contains("after decompressing gzip"), "{stderr}");
+        if let Some(looks_like) = looks_like {
+            assert!(stderr.contains(&format!("looks like {looks_like} data")), "{stderr}");
+        }
+        assert_eq!(fs::read_dir(dir.join("out")).unwrap().count(), 0, "{file}");
+    }
+}
+
+/// `--entry` extracts the entries at the positions `ouch list` prints them at, and fails on positions
+/// past the last one
+#[test]
+fn decompress_entries_by_position() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let archive = &dir.join("archive.tar");
+    let mut builder = tar::Builder::new(fs::File::create(archive).unwrap());
+    for (name, content) in [("a.txt", "1"), ("weird *name?", "2"), ("c.txt", "3")] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, name, content.as_bytes()).unwrap();
+    }
+    builder.finish().unwrap();
+
+    let out = &dir.join("out");
+    ouch!("-A", "d", archive, "--entry", "2", "--entry", "3", "-d", out);
+    let mut extracted: Vec<_> = fs::read_dir(out.join("archive"))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    extracted.sort();
+    assert_eq!(extracted, ["c.txt", "weird *name?"]);
+
+    let output = crate::utils::cargo_bin()
+        .args(["-A", "d", "--to-stdout", "--entry", "2"])
+        .arg(archive)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"2");
+
+    let output = crate::utils::cargo_bin()
+        .args(["-A", "d", "--entry", "4", "-d"])
+        .arg(dir.join("out_of_range"))
+        .arg(archive)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("Its entries go from 1 to 3"));
+}
+
+/// `--change-dir` finds the inputs in a directory and names the entries relative to it, like
+/// `tar -C`, while the output stays relative to the current directory
+#[test]
+fn compress_change_dir() {
+    let dir = tempdir().unwrap();
+    let dir = &dir.path().canonicalize().unwrap();
+    let root = &dir.join("root");
+    fs::create_dir_all(root.join("docs")).unwrap();
+    fs::write(root.join("docs/readme.txt"), "readme").unwrap();
+    fs::write(root.join("main.rs"), "fn main() {}").unwrap();
+
+    let listed = |archive: &std::path::Path| -> Vec<String> {
+        let mut paths: Vec<_> = tar::Archive::new(fs::File::open(archive).unwrap())
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                entry
+                    .unwrap()
+                    .path()
+                    .unwrap()
+                    .to_str()
+                    .unwrap()
+                    .trim_end_matches('/')
+                    .to_string()
+            })
+            .collect();
+        paths.sort();
+        paths
+    };
+
+    crate::utils::cargo_bin()
+        .current_dir(dir)
+        .args(["-A", "c", "-C", "root", ".", "whole.tar"])
+        .assert()
+        .success();
+    assert_eq!(listed(&dir.join("whole.tar")), ["docs", "docs/readme.txt", "main.rs"]);
+
+    crate::utils::cargo_bin()
+        .current_dir(dir)
+        .args([
+            "-A",
+            "c",
+            "--change-dir",
+            "root",
+            "docs/readme.txt",
+            "main.rs",
+            "some.tar",
+        ])
+        .assert()
+        .success();
+    assert_eq!(listed(&dir.join("some.tar")), ["docs/readme.txt", "main.rs"]);
+
+    // Inputs that aren't in the directory are rejected
+    crate::utils::cargo_bin()
+        .current_dir(dir)
+        .args(["-A", "c", "-C", "root/docs", "../main.rs", "outside.tar"])
+        .assert()
+        .failure()
+        .code(2);
+    assert!(!dir.join("outside.tar").exists());
+
+    // It's an alias of --dir when decompressing
+    ouch!("-A", "d", dir.join("whole.tar"), "-C", dir.join("out"));
+    assert_eq!(
+        fs::read_to_string(dir.join("out/whole/docs/readme.txt")).unwrap(),
+        "readme"
+    );
+}
+
+/// `--sparse` stores the holes of sparse files without their zeros, and unpacking recreates them
+#[cfg(target_os = "linux")]
+#[test]
+fn compress_sparse_file() {
+    use std::os::unix::fs::{FileExt, MetadataExt};
+
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let path = dir.join("disk.img");
+    let file = fs::File::create(&path).unwrap();
+    file.set_len(64 << 20).unwrap();
+    file.file().write_all_at(b"boot", 0).unwrap();
+    file.file().write_all_at(b"data", 32 << 20).unwrap();
+    drop(file);
+    // Holes depend on the filesystem
+    if fs::metadata(&path).unwrap().blocks() * 512 >= 64 << 20 {
+        return;
+    }
+
+    let archive = dir.join("disk.tar");
+    ouch!("-A", "c", &path, &archive, "--sparse");
+    assert!(fs::metadata(&archive).unwrap().len() < 1 << 20);
+
+    ouch!("-A", "d", &archive, "-d", dir.join("out"));
+    let unpacked = dir.join("out/disk.img");
+    let metadata = fs::metadata(&unpacked).unwrap();
+    assert_eq!(metadata.len(), 64 << 20);
+    assert!(metadata.blocks() * 512 < 1 << 20);
+    assert!(fs::read(&unpacked).unwrap() == fs::read(&path).unwrap());
+
+    // Without the flag, the holes are stored as zeros
+    let archive = dir.join("whole.tar");
+    ouch!("-A", "c", &path, &archive);
+    assert!(fs::metadata(&archive).unwrap().len() >= 64 << 20);
+}
+
+/// `--xattrs` stores the extended attributes of files and directories in tar archives, and restores
+/// them when decompressing with it, even on read-only files
+#[cfg(target_os = "linux")]
+#[test]
+fn compress_xattrs() {
+    use std::{fs::Permissions, os::unix::fs::PermissionsExt};
+
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let docs = &dir.join("docs");
+    fs::create_dir(docs).unwrap();
+    fs::write(docs.join("notes.txt"), "tagged").unwrap();
+    // User attributes depend on the filesystem
+    if xattr::set(docs.join("notes.txt"), "user.color", b"blue").is_err() {
+        return;
+    }
+    xattr::set(docs, "user.kind", b"folder").unwrap();
+    fs::set_permissions(docs.join("notes.txt"), Permissions::from_mode(0o444)).unwrap();
+
+    let archive = &dir.join("docs.tar.gz");
+    ouch!("-A", "c", docs, archive, "--xattrs");
+
+    ouch!("-A", "d", archive, "-d", dir.join("out"), "--xattrs");
+    let notes = dir.join("out/docs/notes.txt");
+    assert_eq!(xattr::get(&notes, "user.color").unwrap(), Some(b"blue".to_vec()));
+    assert_eq!(
+        xattr::get(dir.join("out/docs"), "user.kind").unwrap(),
+        Some(b"folder".to_vec())
+    );
+    assert_eq!(fs::metadata(&notes).unwrap().permissions().mode() & 0o777, 0o444);
+
+    // Only restored when asked for
+    ouch!("-A", "d", archive, "-d", dir.join("plain"));
+    assert_eq!(
+        xattr::get(dir.join("plain/docs/notes.txt"), "user.color").unwrap(),
+        None
+    );
+}
+
+/// Empty directories get entries of their own, so they're recreated when unpacking
+#[test]
+fn empty_directories_are_archived() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let project = &dir.join("project");
+    fs::create_dir_all(project.join("assets")).unwrap();
+    fs::create_dir_all(project.join("src/generated")).unwrap();
+    fs::write(project.join("src/main.rs"), "fn main() {}").unwrap();
+
+    for format in ["tar.gz", "zip", "7z", "cpio"] {
+        let archive = &dir.join(format!("project.{format}"));
+        ouch!("-A", "c", project, archive);
+
+        let after = &dir.join(format!("after-{format}"));
+        ouch!("-A", "d", archive, "-d", after);
+        for empty in ["project/assets", "project/src/generated"] {
+            let path = after.join(empty);
+            assert!(path.is_dir(), "{format}: {empty} is missing");
+            assert_eq!(fs::read_dir(&path).unwrap().count(), 0, "{format}: {empty}");
+        }
+        assert_eq!(
+            fs::read_to_string(after.join("project/src/main.rs")).unwrap(),
+            "fn main() {}"
+        );
+    }
+
+    // Also when the empty directory is an input itself
+    let archive = &dir.join("assets.zip");
+    ouch!("-A", "c", project.join("assets"), project.join("src/main.rs"), archive);
+    ouch!("-A", "d", archive, "-d", dir.join("after-inputs"));
+    assert!(dir.join("after-inputs/assets/assets").is_dir());
+}
+
+/// `--max-depth` stops descending into the input directories after the given number of levels,
+/// along with `--exclude`
+#[test]
+fn compress_max_depth() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let tree = &dir.join("tree");
+    fs::create_dir_all(tree.join("one/two/three")).unwrap();
+    fs::write(tree.join("top.txt"), "1").unwrap();
+    fs::write(tree.join("top.log"), "1").unwrap();
+    fs::write(tree.join("one/middle.txt"), "2").unwrap();
+    fs::write(tree.join("one/two/deep.txt"), "3").unwrap();
+    fs::write(tree.join("one/two/three/deepest.txt"), "4").unwrap();
+
+    let listed = |archive: &std::path::Path| -> Vec<String> {
+        let mut paths: Vec<_> = tar::Archive::new(fs::File::open(archive).unwrap())
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                entry
+                    .unwrap()
+                    .path()
+                    .unwrap()
+                    .to_str()
+                    .unwrap()
+                    .trim_end_matches('/')
+                    .to_string()
+            })
+            .collect();
+        paths.sort();
+        paths
+    };
+
+    let archive = &dir.join("two.tar");
+    ouch!("-A", "c", tree, archive, "--max-depth", "2", "--exclude", "*.log");
+    assert_eq!(
+        listed(archive),
+        [
+            "tree",
+            "tree/one",
+            "tree/one/middle.txt",
+            "tree/one/two",
+            "tree/top.txt"
+        ]
+    );
+
+    let archive = &dir.join("one.tar");
+    ouch!("-A", "c", tree, archive, "--max-depth", "1");
+    assert_eq!(listed(archive), ["tree", "tree/one", "tree/top.log", "tree/top.txt"]);
+
+    // Files given as inputs are compressed whatever the depth
+    let archive = &dir.join("file.tar");
+    ouch!("-A", "c", tree.join("one/two/deep.txt"), archive, "--max-depth", "1");
+    assert_eq!(listed(archive), ["deep.txt"]);
+
+    crate::utils::cargo_bin()
+        .args(["-A", "c"])
+        .arg(tree)
+        .arg(dir.join("zero.tar"))
+        .args(["--max-depth", "0"])
+        .assert()
+        .failure()
+        .code(2);
+}
+
+/// `--no-recursive` only compresses the files right inside of the input directories, without their
+/// subdirectories or what's in them
+#[test]
+fn compress_no_recursive() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let tree = &dir.join("tree");
+    fs::create_dir_all(tree.join("one/two")).unwrap();
+    fs::create_dir(tree.join("empty")).unwrap();
+    fs::write(tree.join("top.txt"), "1").unwrap();
+    fs::write(tree.join("one/middle.txt"), "2").unwrap();
+    fs::write(tree.join("one/two/deep.txt"), "3").unwrap();
+
+    let archive = &dir.join("flat.tar");
+    ouch!("-A", "c", tree, archive, "--no-recursive", "--sort", "name");
+    assert_eq!(tar_entry_names(archive), ["tree", "tree/top.txt"].map(PathBuf::from));
+
+    // Directories given as inputs are still walked one level down
+    let archive = &dir.join("one.tar");
+    ouch!(
+        "-A",
+        "c",
+        tree.join("one"),
+        tree.join("top.txt"),
+        archive,
+        "--no-recursive",
+        "--sort",
+        "name"
+    );
+    assert_eq!(
+        tar_entry_names(archive),
+        ["one", "one/middle.txt", "top.txt"].map(PathBuf::from)
+    );
+}
+
+/// With `--skip-errors`, files that can't be read are left out and counted instead of failing
+/// the whole archive, which is kept with every other file
+#[cfg(unix)]
+#[test]
+fn compress_skip_errors() {
+    use std::{fs::Permissions, os::unix::fs::PermissionsExt};
+
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let tree = &dir.join("tree");
+    fs::create_dir(tree).unwrap();
+    fs::write(tree.join("a.txt"), "a").unwrap();
+    let secret = &tree.join("secret.txt");
+    fs::write(secret, "s").unwrap();
+    fs::set_permissions(secret, Permissions::from_mode(0o000)).unwrap();
+    // Followed by --dereference, it can't be opened, not even by root
+    std::os::unix::fs::symlink("loop", tree.join("loop")).unwrap();
+    // Root can still read the file without permissions
+    let secret_is_readable = std::fs::File::open(secret).is_ok();
+
+    let archive = &dir.join("archive.tar");
+    crate::utils::cargo_bin()
+        .args(["-A", "c", "--dereference"])
+        .arg(tree)
+        .arg(archive)
+        .assert()
+        .code(1);
+    assert!(!archive.exists());
+
+    let output = crate::utils::cargo_bin()
+        .args(["-A", "c", "--dereference", "--skip-errors"])
+        .arg(tree)
+        .arg(archive)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(3));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let skipped = if secret_is_readable { 1 } else { 2 };
+    assert_eq!(
+        stderr.matches("Skipped a file that couldn't be read").count(),
+        skipped,
+        "{stderr}"
+    );
+
+    let mut names = tar_entry_names(archive);
+    names.sort();
+    let mut expected = vec!["tree", "tree/a.txt"];
+    if secret_is_readable {
+        expected.push("tree/secret.txt");
+    }
+    assert_eq!(names, expected.into_iter().map(PathBuf::from).collect::<Vec<_>>());
+
+    // With --remove, inputs missing files in their archive are kept
+    let clean = &dir.join("clean");
+    fs::create_dir(clean).unwrap();
+    fs::write(clean.join("b.txt"), "b").unwrap();
+    let output = crate::utils::cargo_bin()
+        .args(["-A", "c", "--dereference", "--skip-errors", "--remove"])
+        .arg(tree)
+        .arg(dir.join("removed.tar"))
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(3));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("weren't removed"), "{stderr}");
+    assert!(tree.join("a.txt").exists());
+
+    crate::utils::cargo_bin()
+        .current_dir(dir)
+        .args([
+            "-A",
+            "c",
+            "--dereference",
+            "--skip-errors",
+            "--remove",
+            "--each",
+            "--format",
+            "tar",
+        ])
+        .args(["tree", "clean"])
+        .assert()
+        .code(3);
+    assert!(dir.join("tree.tar").exists());
+    assert!(dir.join("clean.tar").exists());
+    assert!(tree.join("a.txt").exists());
+    assert!(!clean.exists());
+}
+
+/// `--on-success` runs after compressing or decompressing, `--on-failure` after an error or a
+/// declined question, with the output and the exit code in environment variables
+#[cfg(unix)]
+#[test]
+fn on_success_and_on_failure_hooks() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    fs::write(dir.join("file.txt"), "hooked").unwrap();
+    let log = &dir.join("log");
+    let hooks = [
+        "--on-success",
+        "echo \"success $OUCH_OUTPUT\" >> log",
+        "--on-failure",
+        "echo \"failure $OUCH_OUTPUT $OUCH_EXIT_CODE\" >> log",
+    ];
+    let read_log = || fs::read_to_string(log).unwrap_or_default();
+
+    crate::utils::cargo_bin()
+        .current_dir(dir)
+        .args(["-A", "c", "file.txt", "file.tar.gz"])
+        .args(hooks)
+        .assert()
+        .success();
+    assert_eq!(read_log(), "success file.tar.gz\n");
+
+    // Declined, the success hook doesn't run
+    crate::utils::cargo_bin()
+        .current_dir(dir)
+        .args(["-A", "c", "file.txt", "file.tar.gz", "--no-clobber"])
+        .args(hooks)
+        .assert()
+        .code(4);
+    assert_eq!(read_log(), "success file.tar.gz\nfailure file.tar.gz 4\n");
+
+    // Nothing is written in a dry run, so no hook runs
+    crate::utils::cargo_bin()
+        .current_dir(dir)
+        .args(["-A", "c", "file.txt", "dry.tar.gz", "--dry-run"])
+        .args(hooks)
+        .assert()
+        .success();
+    assert_eq!(read_log().lines().count(), 2);
+
+    fs::remove_file(log).unwrap();
+    crate::utils::cargo_bin()
+        .current_dir(dir)
+        .args(["-A", "d", "file.tar.gz", "-d", "out"])
+        .args(hooks)
+        .assert()
+        .success();
+    assert_eq!(read_log(), "success out\n");
+    assert_eq!(fs::read_to_string(dir.join("out/file.txt")).unwrap(), "hooked");
+
+    crate::utils::cargo_bin()
+        .current_dir(dir)
+        .args(["-A", "d", "missing.tar.gz", "-d", "out"])
+        .args(hooks)
+        .assert()
+        .code(3);
+    assert_eq!(read_log(), "success out\nfailure out 3\n");
+
+    // A failing success hook fails ouch, after the output was written
+    let output = crate::utils::cargo_bin()
+        .current_dir(dir)
+        .args(["-A", "c", "file.txt", "again.tar.gz", "--on-success", "exit 7"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("The --on-success command failed"), "{stderr}");
+    assert!(dir.join("again.tar.gz").exists());
+}
+
+/// `--max-decompressed-size` stops decompressing once a file, or all of them together, go over
+/// it, naming the file and leaving nothing behind, whatever the format
+#[test]
+fn max_decompressed_size() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    fs::write(dir.join("bomb"), vec![0; 1 << 20]).unwrap();
+    fs::write(dir.join("small"), vec![0; 600 << 10]).unwrap();
+    fs::write(dir.join("small2"), vec![0; 600 << 10]).unwrap();
+
+    let decompress = |archive: &str, max_size: &str| {
+        crate::utils::cargo_bin()
+            .current_dir(dir)
+            // Past the question about their compression ratio
+            .args(["-A", "-y", "d", archive, "-d", "out", "--max-decompressed-size", max_size])
+            .output()
+            .unwrap()
+    };
+
+    for format in ["zip", "tar.gz", "7z", "cpio.zst"] {
+        let bomb = format!("bomb.{format}");
+        let pair = format!("pair.{format}");
+        crate::utils::cargo_bin()
+            .current_dir(dir)
+            .args(["-A", "c", "bomb", &bomb])
+            .assert()
+            .success();
+        crate::utils::cargo_bin()
+            .current_dir(dir)
+            .args(["-A", "c", "small", "small2", &pair])
+            .assert()
+            .success();
+
+        let output = decompress(&bomb, "512KiB");
+        assert_eq!(output.status.code(), Some(1));
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains("'bomb' decompresses into more than"), "{stderr}");
+        assert_eq!(fs::read_dir(dir.join("out")).unwrap().count(), 0);
+
+        // Each file fits, both together don't
+        let output = decompress(&pair, "1MiB");
+        assert_eq!(output.status.code(), Some(1));
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(
+            stderr.contains("in total, the limit was reached while extracting"),
+            "{stderr}"
+        );
+        assert_eq!(fs::read_dir(dir.join("out")).unwrap().count(), 0);
+
+        assert!(decompress(&bomb, "1MiB").status.success());
+        assert_eq!(fs::read(dir.join("out/bomb")).unwrap().len(), 1 << 20);
+        fs::remove_dir_all(dir.join("out")).unwrap();
+    }
+
+    // Compressed files stop being written once they go over it
+    fs::create_dir(dir.join("out")).unwrap();
+    crate::utils::cargo_bin()
+        .current_dir(dir)
+        .args(["-A", "c", "bomb", "bomb.gz"])
+        .assert()
+        .success();
+    let output = decompress("bomb.gz", "512KiB");
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("'bomb' decompresses into more than"), "{stderr}");
+    assert_eq!(fs::read_dir(dir.join("out")).unwrap().count(), 0);
+    assert!(decompress("bomb.gz", "1MiB").status.success());
+    assert_eq!(fs::read(dir.join("out/bomb")).unwrap().len(), 1 << 20);
+}
+
+/// Zip entries that decompress into far more than their compressed size are warned about, and only
+/// extracted if the user goes on
+#[test]
+fn zip_high_compression_ratio() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    fs::write(dir.join("zeros"), vec![0; 4 << 20]).unwrap();
+    crate::utils::cargo_bin()
+        .current_dir(dir)
+        .args(["-A", "c", "zeros", "zeros.zip"])
+        .assert()
+        .success();
+
+    let output = crate::utils::cargo_bin()
+        .current_dir(dir)
+        .args(["-A", "d", "zeros.zip", "-d", "out"])
+        .write_stdin("n\n")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(4));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("The entry 'zeros' of") && stderr.contains("like a zip bomb would"),
+        "{stderr}"
+    );
+    assert!(!dir.join("out/zeros").exists());
+
+    crate::utils::cargo_bin()
+        .current_dir(dir)
+        .args(["-A", "-y", "d", "zeros.zip", "-d", "out"])
+        .assert()
+        .success();
+    assert_eq!(fs::read(dir.join("out/zeros")).unwrap(), vec![0; 4 << 20]);
+
+    // Files that don't compress that well are extracted without asking
+    let text: String = (0..200_000).map(|n| format!("{n}\n")).collect();
+    fs::write(dir.join("text"), text).unwrap();
+    crate::utils::cargo_bin()
+        .current_dir(dir)
+        .args(["-A", "c", "text", "text.zip"])
+        .assert()
+        .success();
+    crate::utils::cargo_bin()
+        .current_dir(dir)
+        .args(["-A", "d", "text.zip", "-d", "out"])
+        .assert()
+        .success();
+}
+
+/// `--raw` only undoes the compression around an archive, writing the archive itself to a file
+/// named after it, or to stdout with `--to-stdout`
+#[test]
+fn decompress_raw_archive() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    fs::create_dir(dir.join("docs")).unwrap();
+    fs::write(dir.join("docs/notes.txt"), "raw").unwrap();
+    ouch!("-A", "c", dir.join("docs"), dir.join("docs.tgz"));
+
+    let output = crate::utils::cargo_bin()
+        .current_dir(dir)
+        .args(["-A", "d", "docs.tgz", "--raw", "--to-stdout"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let mut archive = tar::Archive::new(output.stdout.as_slice());
+    let names: Vec<_> = archive
+        .entries()
+        .unwrap()
+        .map(|entry| entry.unwrap().path().unwrap().into_owned())
+        .collect();
+    assert_eq!(names, [PathBuf::from("docs"), PathBuf::from("docs/notes.txt")]);
+
+    ouch!("-A", "d", dir.join("docs.tgz"), "--raw", "-d", dir.join("out"));
+    assert_eq!(fs::read(dir.join("out/docs.tar")).unwrap(), output.stdout);
+    assert!(!dir.join("out/docs").exists());
+
+    // Nothing to undo for an archive without compression
+    ouch!("-A", "c", dir.join("docs"), dir.join("docs.tar"));
+    crate::utils::cargo_bin()
+        .args(["-A", "d"])
+        .arg(dir.join("docs.tar"))
+        .arg("--raw")
+        .assert()
+        .code(2);
+}
+
+/// Names of the entries of the tar archive at `path`
+fn tar_entry_names(path: &std::path::Path) -> Vec<PathBuf> {
+    let mut archive = tar::Archive::new(fs::File::open(path).unwrap());
+    archive
+        .entries()
+        .unwrap()
+        .map(|entry| entry.unwrap().path().unwrap().into_owned())
+        .collect()
+}
+
+/// With `--glob`, input patterns like `*.txt` are expanded into the files they match, and
+/// patterns that match nothing fail
+#[test]
+fn compress_glob_inputs() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    fs::create_dir(dir.join("notes")).unwrap();
+    for file in ["a.txt", "b.txt", ".hidden.txt", "c.md", "notes/d.txt"] {
+        fs::write(dir.join(file), file).unwrap();
+    }
+
+    crate::utils::cargo_bin()
+        .current_dir(dir)
+        .args(["-A", "c", "*.txt", "notes/*.txt", "text.tar", "--glob"])
+        .assert()
+        .success();
+    assert_eq!(
+        tar_entry_names(&dir.join("text.tar")),
+        ["a.txt", "b.txt", "d.txt"].map(PathBuf::from)
+    );
+
+    crate::utils::cargo_bin()
+        .current_dir(dir)
+        .args(["-A", "c", "*.rs", "code.tar", "--glob"])
+        .assert()
+        .code(3);
+}
+
+/// The shell doesn't expand globs on Windows, so `ouch` always does
+#[cfg(windows)]
+#[test]
+fn compress_glob_inputs_on_windows() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    for file in ["a.txt", "b.txt", "c.md"] {
+        fs::write(dir.join(file), file).unwrap();
+    }
+
+    crate::utils::cargo_bin()
+        .current_dir(dir)
+        .args(["-A", "c", "*.txt", "text.tar"])
+        .assert()
+        .success();
+    assert_eq!(
+        tar_entry_names(&dir.join("text.tar")),
+        ["a.txt", "b.txt"].map(PathBuf::from)
+    );
+}
+
+/// Decompressing a file that isn't compressed says what it seems to hold instead
+#[test]
+fn decompress_plain_text_file() {
+    let temp_dir = tempdir().unwrap();
+    let dir = temp_dir.path();
+    fs::write(dir.join("readme.txt"), "Read me first\n").unwrap();
+
+    let output = crate::utils::cargo_bin()
+        .current_dir(dir)
+        .args(["-A", "decompress", "readme.txt"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("This doesn't look like a compressed file"), "{stderr}");
+    assert!(stderr.contains("'readme.txt' is plain text"), "{stderr}");
+    assert!(!stderr.contains("Supported extensions are"), "{stderr}");
+}
+
+/// Paths and symlink targets longer than the 100 bytes of the basic tar header round-trip intact
+#[test]
+fn tar_long_paths() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    let deep: PathBuf = (0..6)
+        .map(|depth| format!("directory_with_a_long_name_{depth}"))
+        .collect();
+    let file = before.join(&deep).join("file_with_a_rather_long_name_as_well.txt");
+    assert!(file.strip_prefix(dir).unwrap().as_os_str().len() > 100);
+    fs::create_dir_all(file.parent().unwrap()).unwrap();
+    fs::write(&file, "deep").unwrap();
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(file.strip_prefix(before).unwrap(), before.join("link")).unwrap();
+
+    let archive = &dir.join("archive.tar");
+    ouch!("-A", "c", before, archive);
+    let after = &dir.join("after");
+    ouch!("-A", "d", archive, "-d", after);
+
+    let relative = file.strip_prefix(dir).unwrap();
+    assert_eq!(fs::read_to_string(after.join(relative)).unwrap(), "deep");
+    #[cfg(unix)]
+    assert_eq!(
+        fs::read_link(after.join("before/link")).unwrap(),
+        file.strip_prefix(before).unwrap()
+    );
+}
+
+/// Hard linked files are stored once in tar archives, and linked again when extracted
+#[cfg(unix)]
+#[test]
+fn tar_hard_links() {
+    use std::os::unix::fs::MetadataExt;
+
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    fs::create_dir(before).unwrap();
+    fs::write(before.join("original.txt"), "x".repeat(100_000)).unwrap();
+    fs::hard_link(before.join("original.txt"), before.join("link.txt")).unwrap();
+
+    let archive = &dir.join("archive.tar");
+    ouch!("-A", "c", before, archive);
+    assert!(fs::metadata(archive).unwrap().len() < 150_000);
+
+    let after = &dir.join("after");
+    ouch!("-A", "d", archive, "-d", after);
+    let original = fs::metadata(after.join("before/original.txt")).unwrap();
+    let link = fs::metadata(after.join("before/link.txt")).unwrap();
+    assert_eq!(original.ino(), link.ino());
+    assert_eq!(fs::read(after.join("before/link.txt")).unwrap().len(), 100_000);
+}
+
+/// Bzip2 files made of several streams, like the ones written by `pbzip2`, are decompressed whole
+#[test]
+fn decompress_multi_stream_bzip2() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+
+    let archive = &dir.join("joined.txt.bz2");
+    let mut contents = vec![];
+    for stream in ["first stream\n", "second stream\n"] {
+        let mut encoder = bzip2::write::BzEncoder::new(vec![], bzip2::Compression::default());
+        encoder.write_all(stream.as_bytes()).unwrap();
+        contents.extend(encoder.finish().unwrap());
+    }
+    fs::write(archive, contents).unwrap();
+
+    let out = &dir.join("out");
+    ouch!("-A", "d", archive, "-d", out);
+    assert_eq!(
+        fs::read_to_string(out.join("joined.txt")).unwrap(),
+        "first stream\nsecond stream\n"
+    );
+}
+
+/// Xz files made of several streams, or of several blocks like the ones written by `xz -T0`, are
+/// decompressed whole
+#[test]
+fn decompress_multi_stream_xz() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+
+    let first: String = (0..20_000).map(|line| format!("line {line}\n")).collect();
+    let multi_block = xz2::stream::MtStreamBuilder::new()
+        .threads(2)
+        .block_size(16 * 1024)
+        .encoder()
+        .unwrap();
+    let mut encoder = xz2::write::XzEncoder::new_stream(vec![], multi_block);
+    encoder.write_all(first.as_bytes()).unwrap();
+    let mut contents = encoder.finish().unwrap();
+
+    let mut encoder = xz2::write::XzEncoder::new(vec![], 6);
+    encoder.write_all(b"second stream\n").unwrap();
+    contents.extend(encoder.finish().unwrap());
+
+    let archive = &dir.join("joined.txt.xz");
+    fs::write(archive, contents).unwrap();
+
+    let out = &dir.join("out");
+    ouch!("-A", "d", archive, "-d", out);
+    assert_eq!(
+        fs::read_to_string(out.join("joined.txt")).unwrap(),
+        first + "second stream\n"
+    );
+}
+
+/// `.xz` and `.lzma` are different formats, each is written with its own header and read back
+#[test]
+fn xz_and_lzma_round_trip() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let contents: String = (0..1_000).map(|line| format!("line {line}\n")).collect();
+    fs::write(dir.join("file.txt"), &contents).unwrap();
+
+    for (extension, magic) in [
+        ("xz", &[0xFD, 0x37, 0x7A, 0x58, 0x5A, 0x00][..]),
+        ("lzma", &[0x5D, 0x00, 0x00]),
+    ] {
+        let archive = &dir.join(format!("file.txt.{extension}"));
+        ouch!("-A", "c", dir.join("file.txt"), archive);
+        assert!(fs::read(archive).unwrap().starts_with(magic), "{extension}");
+
+        let out = &dir.join(format!("out_{extension}"));
+        ouch!("-A", "d", archive, "-d", out);
+        assert_eq!(fs::read_to_string(out.join("file.txt")).unwrap(), contents);
+    }
+
+    // Written by another encoder, with the uncompressed size in the header
+    let options = xz2::stream::LzmaOptions::new_preset(6).unwrap();
+    let mut encoder =
+        xz2::write::XzEncoder::new_stream(vec![], xz2::stream::Stream::new_lzma_encoder(&options).unwrap());
+    encoder.write_all(contents.as_bytes()).unwrap();
+    fs::write(dir.join("other.txt.lzma"), encoder.finish().unwrap()).unwrap();
+
+    let out = &dir.join("out_other");
+    ouch!("-A", "d", dir.join("other.txt.lzma"), "-d", out);
+    assert_eq!(fs::read_to_string(out.join("other.txt")).unwrap(), contents);
+}
+
+/// `.tar.lzma` and `.tar.xz` archives round-trip, with their `tlzma` and `txz` aliases
+#[test]
+fn tar_xz_and_tar_lzma_round_trip() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    fs::create_dir_all(before.join("sub")).unwrap();
+    fs::write(before.join("a.txt"), "a").unwrap();
+    fs::write(before.join("sub/b.txt"), "b").unwrap();
+
+    for extension in ["tar.xz", "txz", "tar.lzma", "tlzma"] {
+        let archive = &dir.join(format!("archive.{extension}"));
+        ouch!("-A", "c", before, archive);
+
+        let out = &dir.join(format!("out_{extension}"));
+        ouch!("-A", "d", archive, "-d", out);
+        assert_same_directory(before, out.join("before"), false);
+    }
+}
+
+/// Every shorthand of tar with a compression format, like `tgz`, compresses into a tar archive
+/// and decompresses back
+#[test]
+fn tar_aliases_round_trip() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    fs::create_dir_all(before.join("sub")).unwrap();
+    fs::write(before.join("a.txt"), "a").unwrap();
+    fs::write(before.join("sub/b.txt"), "b").unwrap();
+
+    for (alias, full) in [
+        ("tgz", "tar.gz"),
+        ("tbz", "tar.bz"),
+        ("tbz2", "tar.bz2"),
+        ("tlz4", "tar.lz4"),
+        ("txz", "tar.xz"),
+        ("tlzma", "tar.lzma"),
+        ("tlz", "tar.lz"),
+        ("tsz", "tar.sz"),
+        ("tzst", "tar.zst"),
+    ] {
+        let archive = &dir.join(format!("archive.{alias}"));
+        ouch!("-A", "c", before, archive);
+
+        // The same archive as with the full extensions
+        let renamed = &dir.join(format!("archive.{full}"));
+        fs::copy(archive, renamed).unwrap();
+        let out = &dir.join(format!("out_{full}"));
+        ouch!("-A", "d", renamed, "-d", out);
+        assert_same_directory(before, out.join("before"), false);
+
+        let out = &dir.join(format!("out_{alias}"));
+        ouch!("-A", "d", archive, "-d", out);
+        assert_same_directory(before, out.join("before"), false);
+    }
+}
+
+/// `.lz` and `.tar.lz` round-trip, and lzip members hold the same raw LZMA stream as the legacy
+/// format, which checks both ways against liblzma
+#[test]
+fn lzip_round_trip() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let contents: String = (0..20_000).map(|line| format!("line {line}\n")).collect();
+    fs::write(dir.join("file.txt"), &contents).unwrap();
+
+    let archive = &dir.join("file.txt.lz");
+    ouch!("-A", "c", dir.join("file.txt"), archive);
+    let lzip = fs::read(archive).unwrap();
+    assert!(lzip.starts_with(b"LZIP\x01"));
+
+    let out = &dir.join("out");
+    ouch!("-A", "d", archive, "-d", out);
+    assert_eq!(fs::read_to_string(out.join("file.txt")).unwrap(), contents);
+
+    // The LZMA stream of the member, with a legacy header for an unknown size
+    let dict_size = 1u32 << (lzip[5] & 0x1F);
+    let mut lzma = vec![0x5D];
+    lzma.extend(dict_size.to_le_bytes());
+    lzma.extend([0xFF; 8]);
+    lzma.extend(&lzip[6..lzip.len() - 20]);
+    let mut decoder =
+        xz2::read::XzDecoder::new_stream(&lzma[..], xz2::stream::Stream::new_lzma_decoder(u64::MAX).unwrap());
+    let mut decoded = String::new();
+    decoder.read_to_string(&mut decoded).unwrap();
+    assert_eq!(decoded, contents);
+
+    // A member around the LZMA stream of liblzma, concatenated twice
+    let options = xz2::stream::LzmaOptions::new_preset(6).unwrap();
+    let mut encoder =
+        xz2::write::XzEncoder::new_stream(vec![], xz2::stream::Stream::new_lzma_encoder(&options).unwrap());
+    encoder.write_all(contents.as_bytes()).unwrap();
+    let lzma = encoder.finish().unwrap();
+    let mut member = b"LZIP\x01\x17".to_vec();
+    member.extend(&lzma[13..]);
+    member.extend(crc32fast::hash(contents.as_bytes()).to_le_bytes());
+    member.extend((contents.len() as u64).to_le_bytes());
+    member.extend((member.len() as u64 + 8).to_le_bytes());
+    fs::write(dir.join("twice.txt.lz"), [member.clone(), member].concat()).unwrap();
+
+    ouch!("-A", "d", dir.join("twice.txt.lz"), "-d", out);
+    assert_eq!(fs::read_to_string(out.join("twice.txt")).unwrap(), contents.repeat(2));
+
+    // Archives chain with tar
+    let before = &dir.join("before");
+    fs::create_dir_all(before.join("sub")).unwrap();
+    fs::write(before.join("sub/a.txt"), &contents).unwrap();
+    for extension in ["tar.lz", "tlz"] {
+        let archive = &dir.join(format!("archive.{extension}"));
+        ouch!("-A", "c", before, archive);
+
+        let out = &dir.join(format!("out_{extension}"));
+        ouch!("-A", "d", archive, "-d", out);
+        assert_same_directory(before, out.join("before"), false);
+    }
+}
+
+/// Raw deflate and zlib streams round-trip with `--format`, and are read back by flate2
+#[test]
+fn deflate_and_zlib_round_trip() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let contents: String = (0..1_000).map(|line| format!("line {line}\n")).collect();
+    fs::write(dir.join("file.txt"), &contents).unwrap();
+
+    for format in ["deflate", "zlib"] {
+        let blob = &dir.join(format!("{format}.bin"));
+        ouch!("-A", "c", dir.join("file.txt"), blob, "--format", format);
+
+        let compressed = fs::read(blob).unwrap();
+        let mut decoded = String::new();
+        match format {
+            "deflate" => flate2::read::DeflateDecoder::new(&compressed[..]).read_to_string(&mut decoded),
+            _ => flate2::read::ZlibDecoder::new(&compressed[..]).read_to_string(&mut decoded),
+        }
+        .unwrap();
+        assert_eq!(decoded, contents);
+
+        let out = &dir.join(format!("out_{format}"));
+        ouch!("-A", "d", blob, "-d", out, "--format", format);
+        assert_eq!(fs::read_to_string(out.join(format!("{format}.bin"))).unwrap(), contents);
+    }
+
+    // A zlib blob written by another encoder, like a git object
+    let mut encoder = flate2::write::ZlibEncoder::new(vec![], flate2::Compression::best());
+    encoder.write_all(b"blob 5\0hello").unwrap();
+    fs::write(dir.join("object"), encoder.finish().unwrap()).unwrap();
+
+    let out = &dir.join("out_object");
+    ouch!("-A", "d", dir.join("object"), "-d", out, "--format", "zlib");
+    assert_eq!(fs::read(out.join("object")).unwrap(), b"blob 5\0hello");
+}
+
+/// Build an ar archive, `members` are (header name, data) pairs, with the long names already
+/// encoded in the header names and the data
+fn ar_archive(members: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut archive = b"!<arch>\n".to_vec();
+    for (name, data) in members {
+        let header = format!("{name:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n", 0, 0, 0, 100644, data.len());
+        archive.extend(header.as_bytes());
+        archive.extend(*data);
+        if data.len() % 2 == 1 {
+            archive.push(b'\n');
+        }
+    }
+    archive
+}
+
+/// The members of static libraries are listed and extracted, with GNU and BSD long names, and
+/// without their symbol tables
+#[test]
+fn list_and_extract_ar() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+
+    let library = &dir.join("libfoo.a");
+    fs::write(
+        library,
+        ar_archive(&[
+            ("/", b"\0\0\0\0"),
+            ("//", b"a_rather_long_object_name.o/\n"),
+            ("short.o/", b"short"),
+            ("/0", b"long"),
+            ("#1/12", b"bsd_named.o\0bsd"),
+        ]),
+    )
+    .unwrap();
+
+    let output = crate::utils::cargo_bin()
+        .args(["-A", "l"])
+        .arg(library)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let listing = String::from_utf8(output.stdout).unwrap();
+    let members: Vec<_> = listing
+        .lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .filter(|name| name.ends_with(".o"))
+        .collect();
+    assert_eq!(
+        members,
+        ["short.o", "a_rather_long_object_name.o", "bsd_named.o"],
+        "{listing}"
+    );
+
+    let out = &dir.join("out");
+    ouch!("-A", "d", library, "-d", out);
+    assert_eq!(fs::read(out.join("libfoo/short.o")).unwrap(), b"short");
+    assert_eq!(
+        fs::read(out.join("libfoo/a_rather_long_object_name.o")).unwrap(),
+        b"long"
+    );
+    assert_eq!(fs::read(out.join("libfoo/bsd_named.o")).unwrap(), b"bsd");
+    assert_eq!(fs::read_dir(out.join("libfoo")).unwrap().count(), 3);
+}
+
+/// The members of Debian packages are compressed tar archives, which are extracted in turn
+#[test]
+fn extract_deb_members() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let control = &dir.join("control");
+    fs::create_dir(control).unwrap();
+    fs::write(control.join("control"), "Package: foo\n").unwrap();
+    ouch!("-A", "c", control.join("control"), dir.join("control.tar.gz"));
+
+    let package = &dir.join("foo.deb");
+    fs::write(
+        package,
+        ar_archive(&[
+            ("debian-binary", b"2.0\n"),
+            ("control.tar.gz", &fs::read(dir.join("control.tar.gz")).unwrap()),
+        ]),
+    )
+    .unwrap();
+
+    let out = &dir.join("out");
+    ouch!("-A", "d", package, "-d", out);
+    assert_eq!(fs::read(out.join("foo/debian-binary")).unwrap(), b"2.0\n");
+
+    ouch!("-A", "d", out.join("foo/control.tar.gz"), "-d", out);
+    assert_eq!(fs::read_to_string(out.join("control")).unwrap(), "Package: foo\n");
+
+    // Detected from its signature when it has no extension
+    fs::rename(package, dir.join("package")).unwrap();
+    ouch!("-A", "test", dir.join("package"));
+}
+
+/// Zip entries over 4 GiB are stored with ZIP64 sizes, which are read back when testing the archive
+///
+/// Ignored by default, it compresses 4 GiB, run it with `cargo test -- --ignored`
+#[test]
+#[ignore]
+fn zip64_entry_over_4_gib() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let size = 4 * 1024 * 1024 * 1024 + 1;
+
+    // Sparse, it doesn't take any space on disk
+    let file = &dir.join("big.bin");
+    fs::File::create(file).unwrap().set_len(size).unwrap();
+
+    let archive = &dir.join("archive.zip");
+    ouch!("-A", "-q", "c", file, archive);
+
+    let mut zip_archive = zip::ZipArchive::new(fs::File::open(archive).unwrap()).unwrap();
+    assert_eq!(zip_archive.by_index(0).unwrap().size(), size);
+
+    ouch!("-A", "t", archive);
+}
+
+/// Zip archives chained with other formats are compressed in memory, the user is only asked
+/// before doing so if the inputs are larger than `--in-memory-limit`
+#[test]
+fn compress_in_memory_limit() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let file = &dir.join("file.txt");
+    fs::write(file, "x".repeat(1000)).unwrap();
+
+    let small = &dir.join("small.zip.gz");
+    let output = crate::utils::cargo_bin()
+        .args(["-A", "c", "--no", "--in-memory-limit", "1MiB"])
+        .arg(file)
+        .arg(small)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(!String::from_utf8(output.stderr).unwrap().contains("in-memory"));
+    assert!(small.exists());
+
+    // Answered "no" to the question
+    let large = &dir.join("large.zip.gz");
+    let output = crate::utils::cargo_bin()
+        .args(["-A", "c", "--no", "--in-memory-limit", "100"])
+        .arg(file)
+        .arg(large)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(4));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("in-memory"));
+    assert!(!large.exists());
+}
+
+/// `--auto-compress` adds a compression format to plain tar outputs, picked by sampling with `--best`
+#[test]
+fn compress_auto_compress() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    fs::create_dir(before).unwrap();
+    fs::write(before.join("file.txt"), "some text ".repeat(1000)).unwrap();
+
+    ouch!("-A", "c", before, dir.join("auto.tar"), "--auto-compress");
+    assert!(!dir.join("auto.tar").exists());
+    let after = &dir.join("after");
+    ouch!("-A", "d", dir.join("auto.tar.zst"), "-d", after);
+    assert_same_directory(before, after.join("before"), false);
+
+    ouch!("-A", "c", before, dir.join("best.tar"), "--auto-compress", "--best");
+    let outputs = ["best.tar.zst", "best.tar.gz", "best.tar.bz2", "best.tar.xz"];
+    assert_eq!(outputs.iter().filter(|name| dir.join(name).exists()).count(), 1);
+
+    // Without it, plain archives are left as they are, with a hint
+    let output = crate::utils::cargo_bin()
+        .arg("c")
+        .arg(before)
+        .arg(dir.join("plain.tar"))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("won't be compressed"), "{stderr}");
+}
+
+/// Zip archives compressed with a password can only be decompressed with the same password
+#[test]
+fn zip_password_roundtrip() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    fs::create_dir(before).unwrap();
+    fs::write(before.join("secret.txt"), "Testing 123\n").unwrap();
+    let archive = &dir.join("archive.zip");
+
+    crate::utils::cargo_bin()
+        .args(["-A", "c", "--password-stdin"])
+        .arg(before)
+        .arg(archive)
+        .write_stdin("hunter2\n")
+        .assert()
+        .success();
+
+    // Without a password, `--yes` can't answer the password question
+    crate::utils::cargo_bin()
+        .args(["-A", "--yes", "d"])
+        .arg(archive)
+        .arg("-d")
+        .arg(dir.join("no-password"))
+        .assert()
+        .failure();
+    crate::utils::cargo_bin()
+        .args(["-A", "--yes", "d", "--password", "wrong"])
+        .arg(archive)
+        .arg("-d")
+        .arg(dir.join("wrong-password"))
+        .assert()
+        .failure();
+
+    let after = &dir.join("after");
+    ouch!("-A", "d", archive, "-d", after, "--password", "hunter2");
+    assert_same_directory(before, after.join("before"), false);
+
+    // The password is asked for when it wasn't given
+    let prompted = &dir.join("prompted");
+    crate::utils::cargo_bin()
+        .args(["-A", "d"])
+        .arg(archive)
+        .arg("-d")
+        .arg(prompted)
+        .write_stdin("hunter2\n")
+        .assert()
+        .success();
+    assert_same_directory(before, prompted.join("before"), false);
+}
+
+/// The password read from `--password-file` stops at the end of its first line
+#[test]
+fn zip_password_file_roundtrip() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    fs::create_dir(before).unwrap();
+    fs::write(before.join("secret.txt"), "Testing 123\n").unwrap();
+    let archive = &dir.join("archive.zip");
+    let password_file = &dir.join("password");
+    fs::write(password_file, "hunter2\nnot the password\n").unwrap();
+
+    ouch!("-A", "c", "--password-file", password_file, before, archive);
+
+    // The trailing newline isn't part of the password
+    crate::utils::cargo_bin()
+        .args(["-A", "--yes", "d", "--password", "hunter2\n"])
+        .arg(archive)
+        .arg("-d")
+        .arg(dir.join("wrong-password"))
+        .assert()
+        .failure();
+
+    let after = &dir.join("after");
+    ouch!("-A", "d", archive, "-d", after, "--password-file", password_file);
+    assert_same_directory(before, after.join("before"), false);
+    ouch!("-A", "t", archive, "--password-file", password_file);
+
+    fs::write(password_file, "").unwrap();
+    crate::utils::cargo_bin()
+        .args(["-A", "d"])
+        .arg(archive)
+        .arg("--password-file")
+        .arg(password_file)
+        .assert()
+        .failure();
+}
+
+/// Zip archives encrypted with ZipCrypto by other tools can be decrypted
+#[test]
+fn unpack_zipcrypto() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let mut archive = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
+    archive.push("tests/data/testfile.zipcrypto.zip");
+
+    ouch!("-A", "d", archive, "-d", dir, "--password", "hunter2");
+    assert_eq!(fs::read_to_string(dir.join("testfile.txt")).unwrap(), "Testing 123\n");
+}
+
+/// Paths matching `--exclude` patterns are left out of the archive, excluded directories included
+#[test]
+fn compress_with_exclude() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let project = &dir.join("project");
+    fs::create_dir_all(project.join("src/logs")).unwrap();
+    fs::create_dir_all(project.join("node_modules/dep")).unwrap();
+    fs::write(project.join("main.rs"), "fn main() {}\n").unwrap();
+    fs::write(project.join("debug.log"), "log\n").unwrap();
+    fs::write(project.join("src/lib.rs"), "\n").unwrap();
+    fs::write(project.join("src/logs/today.log"), "log\n").unwrap();
+    fs::write(project.join("node_modules/dep/index.js"), "\n").unwrap();
+    let archive = &dir.join("project.tar.gz");
+    let after = &dir.join("after");
+
+    ouch!(
+        "-A",
+        "c",
+        project,
+        archive,
+        "--exclude",
+        "*.log",
+        "--exclude",
+        "node_modules"
+    );
+    ouch!("-A", "d", archive, "-d", after);
+
+    let after = &after.join("project");
+    assert!(after.join("main.rs").exists());
+    assert!(after.join("src/lib.rs").exists());
+    assert!(after.join("src/logs").is_dir());
+    assert!(!after.join("debug.log").exists());
+    assert!(!after.join("src/logs/today.log").exists());
+    assert!(!after.join("node_modules").exists());
+}
+
+/// With `--gitignore`, paths matched by `.gitignore` are skipped, even outside of a git repository
+#[test]
+fn compress_with_gitignore() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let project = &dir.join("project");
+    fs::create_dir_all(project.join("target/debug")).unwrap();
+    fs::create_dir_all(project.join("src")).unwrap();
+    fs::write(project.join(".gitignore"), "target/\n").unwrap();
+    fs::write(project.join("src/main.rs"), "fn main() {}\n").unwrap();
+    fs::write(project.join("target/debug/main"), "binary\n").unwrap();
+    let archive = &dir.join("project.tar.gz");
+    let after = &dir.join("after");
+
+    ouch!("-A", "--gitignore", "c", project, archive);
+    ouch!("-A", "d", archive, "-d", after);
+
+    let after = &after.join("project");
+    assert!(after.join("src/main.rs").exists());
+    assert!(after.join(".gitignore").exists());
+    assert!(!after.join("target").exists());
+}
+
+/// `--dry-run` lists what would be compressed without creating the output file
+#[test]
+fn compress_dry_run() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let project = &dir.join("project");
+    fs::create_dir_all(project.join("src")).unwrap();
+    fs::write(project.join("src/main.rs"), "fn main() {}\n").unwrap();
+    fs::write(project.join("debug.log"), "log\n").unwrap();
+    let archive = &dir.join("project.tar.gz");
+
+    let output = ouch!("-A", "c", project, archive, "--dry-run", "--exclude", "*.log");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(!archive.exists());
+    assert!(stdout.contains("project/src/main.rs"));
+    assert!(!stdout.contains("debug.log"));
+
+    // Named like the entries of the archive
+    let output = ouch!("-A", "c", project.join("src"), archive, "--dry-run", "--base-dir", dir);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.lines().any(|line| line == "project/src/main.rs"));
+    let output = ouch!("-A", "c", "src", archive, "--dry-run", "-C", project);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.lines().any(|line| line == "src/main.rs"));
+}
+
+/// `-` reads from stdin and writes to stdout, so ouch can be used in pipelines
+#[test]
+fn gzip_through_stdin_and_stdout() {
+    let content = "data piped through ouch\n".repeat(100);
+
+    let compressed = crate::utils::cargo_bin()
+        .args(["-A", "c", "-", "-", "--format", "gz"])
+        .write_stdin(content.clone())
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    // Gzip magic number
+    assert_eq!(&compressed[..2], &[0x1f, 0x8b]);
+
+    crate::utils::cargo_bin()
+        .args(["-A", "d", "-", "--format", "gz"])
+        .write_stdin(compressed)
+        .assert()
+        .success()
+        .stdout(content);
+
+    // Zip needs seeking, which pipes can't do
+    crate::utils::cargo_bin()
+        .args(["-A", "d", "-", "--format", "zip"])
+        .write_stdin("")
+        .assert()
+        .failure();
+}
+
+/// `--format` applies to every input of decompress, even if they have no extension
+#[test]
+fn decompress_multiple_files_with_format_flag() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    fs::create_dir(before).unwrap();
+    fs::write(before.join("file.txt"), "content\n").unwrap();
+    let blobs = [dir.join("blob1"), dir.join("blob2")];
+    for blob in &blobs {
+        ouch!("-A", "c", before, blob, "--format", "tar.gz");
+    }
+    let after = &dir.join("after");
+
+    ouch!("-A", "d", &blobs[0], &blobs[1], "--format", "tar.gz", "-d", after);
+
+    assert_eq!(fs::read_to_string(after.join("before/file.txt")).unwrap(), "content\n");
+}
+
+/// `--output-dir` places the output file in that directory, creating it if needed
+#[test]
+fn compress_with_output_dir() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    fs::create_dir(before).unwrap();
+    fs::write(before.join("file.txt"), "Testing 123\n").unwrap();
+    let output_dir = &dir.join("nested/backups");
+
+    ouch!("-A", "c", before, "archive.tar.gz", "--output-dir", output_dir);
+    let archive = &output_dir.join("archive.tar.gz");
+    assert!(archive.is_file());
+
+    let after = &dir.join("after");
+    ouch!("-A", "d", archive, "-d", after);
+    assert_same_directory(before, after.join("before"), false);
+
+    // An absolute output path conflicts with --output-dir
+    crate::utils::cargo_bin()
+        .args(["-A", "c"])
+        .arg(before)
+        .arg(dir.join("other.tar.gz"))
+        .arg("--output-dir")
+        .arg(output_dir)
+        .assert()
+        .failure();
+    assert!(!output_dir.join("other.tar.gz").exists());
+}
+
+/// `--checksum` writes a `sha256sum`-style file, which `--verify-checksum` checks before extracting
+#[test]
+fn checksum_roundtrip() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    fs::create_dir(before).unwrap();
+    fs::write(before.join("file.txt"), "Testing 123\n").unwrap();
+
+    for algorithm in ["sha256", "sha512", "md5"] {
+        let archive = &dir.join(format!("{algorithm}.tar.gz"));
+        ouch!("-A", "c", before, archive, "--checksum", algorithm);
+
+        let checksum_file = fs::read_to_string(dir.join(format!("{algorithm}.tar.gz.{algorithm}"))).unwrap();
+        let (digest, file_name) = checksum_file.trim_end().split_once("  ").unwrap();
+        assert!(digest.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_eq!(file_name, format!("{algorithm}.tar.gz"));
+
+        let after = &dir.join(format!("{algorithm}-after"));
+        ouch!("-A", "d", archive, "-d", after, "--verify-checksum");
+        assert_same_directory(before, after.join("before"), false);
+
+        // A modified archive fails the check, and nothing is extracted
+        fs::OpenOptions::new()
+            .append(true)
+            .open(archive)
+            .unwrap()
+            .write_all(b"junk")
+            .unwrap();
+        let corrupted = &dir.join(format!("{algorithm}-corrupted"));
+        crate::utils::cargo_bin()
+            .args(["-A", "d"])
+            .arg(archive)
+            .arg("-d")
+            .arg(corrupted)
+            .arg("--verify-checksum")
+            .assert()
+            .failure();
+        assert!(!corrupted.exists());
+    }
+}
+
+/// `convert` streams tar archives and compressed files, and goes through a temporary directory
+/// when the archive format changes
+#[test]
+fn convert_formats() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    fs::create_dir(before).unwrap();
+    fs::write(before.join("file.txt"), "Testing 123\n").unwrap();
+    fs::write(before.join(".hidden"), "Hidden\n").unwrap();
+
+    ouch!("-A", "c", before, dir.join("archive.tar.gz"));
+    for (input, output) in [
+        ("archive.tar.gz", "archive.tar.zst"),
+        ("archive.tar.zst", "archive.zip"),
+    ] {
+        ouch!("-A", "convert", dir.join(input), dir.join(output));
+
+        let after = &dir.join(format!("after-{output}"));
+        ouch!("-A", "d", dir.join(output), "-d", after);
+        assert_same_directory(before, after.join("before"), false);
+    }
+
+    let file = &dir.join("file.txt");
+    fs::write(file, "Testing 123\n").unwrap();
+    ouch!("-A", "c", file, dir.join("file.txt.gz"));
+    ouch!("-A", "recompress", dir.join("file.txt.gz"), dir.join("file.txt.bz2"));
+    let after = &dir.join("after-bz2");
+    ouch!("-A", "d", dir.join("file.txt.bz2"), "-d", after);
+    assert_eq!(fs::read(after.join("file.txt")).unwrap(), b"Testing 123\n");
+
+    // The temporary directories, hidden like `.tmpXXXXXX`, were removed
+    let leftovers: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .filter(|name| name.to_string_lossy().starts_with('.'))
+        .collect();
+    assert!(leftovers.is_empty(), "left behind: {leftovers:?}");
+}
+
+/// Extracted files keep the modification time stored in the archive, unless `--no-preserve-mtime` is given
+#[test]
+fn preserve_mtime() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    fs::create_dir(before).unwrap();
+    let file = &before.join("file.txt");
+    fs::write(file, "Testing 123\n").unwrap();
+    // An even number of seconds, zip stores them with a two seconds resolution
+    let old_mtime = FileTime::from_unix_time(1_000_000_000, 0);
+    set_file_mtime(file, old_mtime).unwrap();
+
+    for format in ["tar.gz", "zip", "7z", "cpio"] {
+        let archive = &dir.join(format!("archive.{format}"));
+        ouch!("-A", "c", before, archive);
+
+        let after = &dir.join(format!("after-{format}"));
+        ouch!("-A", "d", archive, "-d", after);
+        let mtime = FileTime::from_last_modification_time(&fs::metadata(after.join("before/file.txt")).unwrap());
+        assert!(
+            (mtime.unix_seconds() - old_mtime.unix_seconds()).abs() <= 1,
+            "{format}: {mtime} != {old_mtime}"
+        );
+
+        let after = &dir.join(format!("after-{format}-no-preserve"));
+        ouch!("-A", "d", archive, "-d", after, "--no-preserve-mtime");
+        let mtime = FileTime::from_last_modification_time(&fs::metadata(after.join("before/file.txt")).unwrap());
+        assert!(mtime.unix_seconds() > old_mtime.unix_seconds() + 1, "{format}: {mtime}");
+    }
+}
+
+/// `--split-size` writes numbered volumes, which are joined back when decompressing the first one
+#[test]
+fn split_size_roundtrip() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    fs::create_dir(before).unwrap();
+    let mut data = vec![0; 10_000];
+    SmallRng::seed_from_u64(42).fill(data.as_mut_slice());
+    fs::write(before.join("random"), &data).unwrap();
+
+    // zip archives are read with seeks across the volumes
+    for format in ["tar.gz", "zip"] {
+        let archive = &dir.join(format!("archive.{format}"));
+        let after = &dir.join(format!("after_{format}"));
+
+        ouch!("-A", "c", before, archive, "--split-size", "4KiB");
+
+        assert!(!archive.exists());
+        let volumes: Vec<_> = (1..)
+            .map(|n| dir.join(format!("archive.{format}.{n:03}")))
+            .take_while(|volume| volume.exists())
+            .collect();
+        assert_eq!(volumes.len(), 3);
+        assert!(volumes.iter().all(|volume| fs::metadata(volume).unwrap().len() <= 4096));
+
+        ouch!("-A", "d", &volumes[0], "-d", after);
+        assert_same_directory(before, after.join("before"), false);
+    }
+}
+
+/// `test` reads whole archives, failing if any of them is corrupted
+#[test]
+fn test_archive_integrity() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    fs::create_dir(before).unwrap();
+    let mut data = vec![0; 10_000];
+    SmallRng::seed_from_u64(42).fill(data.as_mut_slice());
+    fs::write(before.join("random"), &data).unwrap();
+
+    let archives: Vec<_> = ["tar.gz", "zip", "7z", "cpio.xz"]
+        .into_iter()
+        .map(|format| dir.join(format!("archive.{format}")))
+        .collect();
+    for archive in &archives {
+        ouch!("-A", "c", before, archive);
+        ouch!("-A", "test", archive);
+    }
+
+    // Flip bytes in the middle of the compressed data, where checksums catch it
+    for archive in &archives {
+        let mut bytes = fs::read(archive).unwrap();
+        let middle = bytes.len() / 2;
+        bytes[middle..middle + 4].iter_mut().for_each(|byte| *byte = !*byte);
+        fs::write(archive, bytes).unwrap();
+
+        crate::utils::cargo_bin()
+            .args(["-A", "test", "--yes"])
+            .arg(archive)
+            .assert()
+            .failure();
+    }
+}
+
+/// `list` shows sizes, with a compression ratio per entry only when the format stores it
+#[test]
+fn list_compression_ratio() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    fs::create_dir(before).unwrap();
+    fs::write(before.join("file.txt"), "Testing 123\n").unwrap();
+    let zip = &dir.join("archive.zip");
+    let tar = &dir.join("archive.tar");
+    ouch!("-A", "c", before, zip);
+    ouch!("-A", "c", before, tar);
+
+    let output = ouch!("-A", "l", zip);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains("before/file.txt  12 B -> 14 B (116.7%)"),
+        "unexpected listing: {stdout}"
+    );
+    let zip_size = fs::metadata(zip).unwrap().len();
+    assert!(stdout.contains(&format!("Total: 1 files, 12 B -> {zip_size} B")));
+
+    let output = ouch!("-A", "l", tar);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains("before/file.txt  12 B\n"),
+        "unexpected listing: {stdout}"
+    );
+    let output = ouch!("-A", "l", tar, "--human");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains("before/file.txt  12.00 B\n"),
+        "unexpected listing: {stdout}"
+    );
+}
+
+/// `list --json` prints a single flat array for all archives, even with `--tree`
+#[test]
+fn list_as_json() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    fs::create_dir(before).unwrap();
+    fs::write(before.join("file.txt"), "Testing 123\n").unwrap();
+    let zip = &dir.join("archive.zip");
+    let tar = &dir.join("archive.tar.gz");
+    ouch!("-A", "c", before, zip);
+    ouch!("-A", "c", before, tar);
+
+    let output = ouch!("-A", "l", zip, tar, "--json", "--tree");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<_> = stdout.lines().collect();
+
+    assert_eq!(lines.first(), Some(&"["), "unexpected listing: {stdout}");
+    assert_eq!(lines.last(), Some(&"]"), "unexpected listing: {stdout}");
+    let file_entries: Vec<_> = lines
+        .iter()
+        .filter(|line| line.contains(r#""path": "before/file.txt""#))
+        .collect();
+    assert_eq!(file_entries.len(), 2, "unexpected listing: {stdout}");
+    // Only zip knows the compressed size of each entry
+    assert!(file_entries[0].contains(r#""size": 12, "compressed_size": 14, "is_dir": false"#));
+    assert!(file_entries[1].contains(r#""size": 12, "compressed_size": null, "is_dir": false"#));
+
+    // A tar archive with no entries is just its end marker, two zeroed blocks
+    let empty = &dir.join("empty.tar");
+    fs::write(empty, [0; 1024]).unwrap();
+    let output = ouch!("-A", "l", empty, "--json");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "[]\n");
+}
@@ -1,14 +1,14 @@
-/// Snapshot tests for Ouch's output.
-///
-/// See CONTRIBUTING.md for a brief guide on how to use [`insta`] for these tests.
-/// [`insta`]: https://docs.rs/insta
+//! Snapshot tests for Ouch's output.
+//!
+//! See CONTRIBUTING.md for a brief guide on how to use [`insta`] for these tests.
+//! [`insta`]: https://docs.rs/insta
 
 #[macro_use]
 mod utils;
 
 use std::{ffi::OsStr, io, path::Path, process::Output};
 
-use insta::assert_display_snapshot as ui;
+use insta::assert_snapshot as ui;
 use regex::Regex;
 
 use crate::utils::create_files_in;
@@ -78,6 +78,23 @@ fn ui_test_err_decompress_missing_extension() {
     ui!(format!("{name}-3"), run_ouch("ouch decompress b.unknown", dir));
 }
 
+#[test]
+fn ui_test_err_decompress_invalid_format() {
+    let (_dropper, dir) = testdir().unwrap();
+
+    create_files_in(dir, &["blob"]);
+
+    let name = {
+        let suffix = if cfg!(feature = "unrar") {
+            "with_rar"
+        } else {
+            "without_rar"
+        };
+        format!("ui_test_err_decompress_invalid_format_{suffix}")
+    };
+    ui!(name, run_ouch("ouch decompress blob --format tar.foo", dir));
+}
+
 #[test]
 fn ui_test_err_missing_files() {
     let (_dropper, dir) = testdir().unwrap();
@@ -109,6 +126,23 @@ fn ui_test_ok_decompress() {
     ui!(run_ouch("ouch decompress output.zst", dir));
 }
 
+#[test]
+fn ui_test_err_decompress_encrypted_zip() {
+    let (_dropper, dir) = testdir().unwrap();
+
+    // prepare
+    create_files_in(dir, &["input"]);
+    run_ouch("ouch compress input output.zip --password hunter2", dir);
+
+    insta::with_settings!({filters => vec![
+        // the temporary directory that holds the decompressed files has a random name
+        (r"\.tmp\w+", ".tmp<RANDOM>"),
+    ]}, {
+        ui!(run_ouch("ouch decompress output.zip --yes", dir));
+        ui!(run_ouch("ouch decompress output.zip --yes --password wrong", dir));
+    });
+}
+
 #[test]
 fn ui_test_usage_help_flag() {
     insta::with_settings!({filters => vec![